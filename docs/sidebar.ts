@@ -15,6 +15,7 @@ export const sidebarItems: ISidebarSection[] = [
     text: "Guides",
     links: [
       { text: "Configuring Builds", href: "/docs/guides/configuring-builds" },
+      { text: "Error Codes and Messages", href: "/docs/guides/messages" },
     ],
   },
   {
@@ -27,6 +28,7 @@ export const sidebarItems: ISidebarSection[] = [
       },
       { text: "Procfile", href: "/docs/configuration/procfile" },
       { text: "Caching", href: "/docs/configuration/caching" },
+      { text: "Ignoring files", href: "/docs/configuration/ignore" },
     ],
   },
   { text: "CLI Reference", href: "/docs/cli" },
@@ -34,6 +36,7 @@ export const sidebarItems: ISidebarSection[] = [
     text: "Language Support",
     links: [
       { href: "/docs/providers/clojure", text: "Clojure" },
+      { href: "/docs/providers/cmake", text: "C/C++ (CMake)" },
       { href: "/docs/providers/cobol", text: "Cobol" },
       { href: "/docs/providers/crystal", text: "Crystal" },
       { href: "/docs/providers/csharp", text: "C#/.NET" },
@@ -41,16 +44,25 @@ export const sidebarItems: ISidebarSection[] = [
       { href: "/docs/providers/deno", text: "Deno" },
       { href: "/docs/providers/elixir", text: "Elixir" },
       { href: "/docs/providers/fsharp", text: "F#" },
+      { href: "/docs/providers/elm", text: "Elm" },
+      { href: "/docs/providers/flutter", text: "Flutter Web" },
       { href: "/docs/providers/go", text: "Go" },
       { href: "/docs/providers/haskell", text: "Haskell" },
+      { href: "/docs/providers/hugo", text: "Hugo" },
+      { href: "/docs/providers/haxe", text: "Haxe" },
       { href: "/docs/providers/java", text: "Java" },
+      { href: "/docs/providers/jekyll", text: "Jekyll" },
+      { href: "/docs/providers/meson", text: "Meson" },
       { href: "/docs/providers/node", text: "Node" },
       { href: "/docs/providers/php", text: "PHP" },
+      { href: "/docs/providers/purescript", text: "PureScript" },
       { href: "/docs/providers/python", text: "Python" },
+      { href: "/docs/providers/racket", text: "Racket" },
       { href: "/docs/providers/ruby", text: "Ruby" },
       { href: "/docs/providers/rust", text: "Rust" },
       { href: "/docs/providers/staticfile", text: "Staticfile" },
       { href: "/docs/providers/swift", text: "Swift" },
+      { href: "/docs/providers/vlang", text: "V" },
       { href: "/docs/providers/zig-lang", text: "Zig" },
     ],
   },
@@ -0,0 +1,4 @@
+(ns hello.core)
+
+(defn -main [& _args]
+  (println "Hello from Babashka"))
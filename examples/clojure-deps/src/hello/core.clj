@@ -0,0 +1,5 @@
+(ns hello.core
+  (:gen-class))
+
+(defn -main [& _args]
+  (println "Hello from Clojure deps.edn"))
@@ -0,0 +1 @@
+Console.WriteLine(Greeting.Greeter.Greet("C# solution"));
@@ -0,0 +1,2 @@
+await Deno.mkdir("dist", { recursive: true });
+await Deno.writeTextFile("dist/message.txt", "Hello from a Deno task");
@@ -0,0 +1 @@
+console.log(await Deno.readTextFile("dist/message.txt"));
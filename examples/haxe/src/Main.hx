@@ -0,0 +1,5 @@
+class Main {
+  static function main() {
+    trace("Hello from Haxe");
+  }
+}
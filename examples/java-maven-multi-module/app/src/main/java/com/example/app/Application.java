@@ -0,0 +1,9 @@
+package com.example.app;
+
+import com.example.core.Greeting;
+
+public class Application {
+  public static void main(String[] args) {
+    System.out.println(Greeting.message());
+  }
+}
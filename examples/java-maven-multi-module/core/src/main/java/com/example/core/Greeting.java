@@ -0,0 +1,7 @@
+package com.example.core;
+
+public class Greeting {
+  public static String message() {
+    return "Hello from a Maven module";
+  }
+}
@@ -0,0 +1 @@
+console.log(`${process.env.GREETING} on Node ${process.version}`);
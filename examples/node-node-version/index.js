@@ -0,0 +1 @@
+console.log(`Hello from Node ${process.versions.node.split(".")[0]}`);
@@ -0,0 +1,3 @@
+const { greeting } = require("@app/shared");
+
+console.log(greeting("pnpm workspace"));
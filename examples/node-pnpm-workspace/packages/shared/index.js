@@ -0,0 +1 @@
+exports.greeting = (name) => `Hello from ${name}`;
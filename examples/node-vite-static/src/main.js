@@ -0,0 +1 @@
+document.querySelector("#app").textContent = "Hello from Vite";
@@ -0,0 +1,4 @@
+<?php
+
+$redis = extension_loaded('redis') ? 'loaded' : 'missing';
+echo "Hello from PHP " . PHP_VERSION . " with redis " . $redis;
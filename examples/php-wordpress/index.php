@@ -0,0 +1,13 @@
+<?php
+/**
+ * Front to the WordPress application. This file doesn't do anything, but loads
+ * wp-blog-header.php which does and tells WordPress to load the theme.
+ */
+
+define('WP_USE_THEMES', true);
+
+if (file_exists(__DIR__ . '/wp-blog-header.php')) {
+    require __DIR__ . '/wp-blog-header.php';
+} else {
+    echo 'Hello from WordPress';
+}
@@ -0,0 +1,4 @@
+import numpy as np
+import requests
+
+print(f"Hello from Python-Conda with numpy {np.__version__} and requests {requests.__version__}")
@@ -0,0 +1,13 @@
+from fastapi import FastAPI
+
+app = FastAPI()
+
+
+@app.on_event("startup")
+async def startup():
+    print("Hello from Python-FastAPI")
+
+
+@app.get("/")
+async def root():
+    return {"message": "Hello from Python-FastAPI"}
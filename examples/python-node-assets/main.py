@@ -0,0 +1,2 @@
+print(open("static/message.txt").read())
+print("Hello from Python")
@@ -0,0 +1,3 @@
+import requests
+
+print(f"Hello from Python-Pipenv with requests {requests.__version__}")
@@ -0,0 +1,11 @@
+# frozen_string_literal: true
+
+require "sidekiq"
+
+class HelloJob
+  include Sidekiq::Job
+
+  def perform(name)
+    puts "Hello from Sidekiq, #{name}"
+  end
+end
@@ -0,0 +1,6 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    println!("Hello from rust with cached dependencies");
+    Ok(())
+}
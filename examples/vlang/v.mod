@@ -0,0 +1,7 @@
+Module {
+	name: 'hello'
+	description: 'Hello world in V'
+	version: '0.0.1'
+	license: 'MIT'
+	dependencies: []
+}
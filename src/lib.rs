@@ -21,27 +21,67 @@
 use crate::nixpacks::{
     app::App,
     builder::{
-        docker::{docker_image_builder::DockerImageBuilder, DockerBuilderOptions},
+        docker::{
+            docker_image_builder::DockerImageBuilder,
+            dockerfile_generation::{DockerfileGenerator, OutputDir},
+            warm_container::{WarmContainer, WarmContainerOptions},
+            DockerBuilderOptions,
+        },
         ImageBuilder,
     },
+    debug_bundle::DebugBundle,
     environment::Environment,
     logger::Logger,
-    nix::pkg::Pkg,
+    messages::{ExitCode, Message, MessageId},
+    nix::{create_nix_expressions_for_phases, pkg::Pkg},
     plan::{
+        dev_script::DEV_SCRIPT_NAME,
+        diff::PlanDifference,
         generator::{GeneratePlanOptions, NixpacksBuildPlanGenerator},
         BuildPlan, PlanGenerator,
     },
+    NIX_PACKS_VERSION,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use providers::{
-    clojure::ClojureProvider, cobol::CobolProvider, crystal::CrystalProvider,
-    csharp::CSharpProvider, dart::DartProvider, deno::DenoProvider, elixir::ElixirProvider,
-    fsharp::FSharpProvider, go::GolangProvider, haskell::HaskellStackProvider, java::JavaProvider,
-    node::NodeProvider, php::PhpProvider, python::PythonProvider, ruby::RubyProvider,
-    rust::RustProvider, staticfile::StaticfileProvider, swift::SwiftProvider, zig::ZigProvider,
+    clojure::ClojureProvider,
+    cmake::CMakeProvider,
+    cobol::CobolProvider,
+    crystal::CrystalProvider,
+    csharp::CSharpProvider,
+    dart::DartProvider,
+    deno::DenoProvider,
+    elixir::ElixirProvider,
+    elm::ElmProvider,
+    flutter::FlutterProvider,
+    fsharp::FSharpProvider,
+    go::GolangProvider,
+    haskell::{HaskellCabalProvider, HaskellStackProvider},
+    haxe::HaxeProvider,
+    hugo::HugoProvider,
+    java::JavaProvider,
+    jekyll::JekyllProvider,
+    meson::MesonProvider,
+    node::NodeProvider,
+    php::PhpProvider,
+    purescript::PureScriptProvider,
+    python::PythonProvider,
+    racket::RacketProvider,
+    ruby::RubyProvider,
+    rust::RustProvider,
+    staticfile::StaticfileProvider,
+    swift::SwiftProvider,
+    vlang::VProvider,
+    zig::ZigProvider,
     Provider,
 };
 
+use colored::Colorize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 mod chain;
 #[macro_use]
 pub mod nixpacks;
@@ -51,6 +91,7 @@ pub fn get_providers() -> &'static [&'static dyn Provider] {
     &[
         &CrystalProvider {},
         &CSharpProvider {},
+        &FlutterProvider {},
         &DartProvider {},
         &ElixirProvider {},
         &DenoProvider {},
@@ -58,16 +99,26 @@ pub fn get_providers() -> &'static [&'static dyn Provider] {
         &ClojureProvider {},
         &GolangProvider {},
         &HaskellStackProvider {},
+        &HaskellCabalProvider {},
+        &HaxeProvider {},
         &JavaProvider {},
         &PhpProvider {},
+        &PureScriptProvider {},
+        &HugoProvider {},
+        &JekyllProvider {},
+        &ElmProvider {},
         &RubyProvider {},
         &NodeProvider {},
         &PythonProvider {},
+        &RacketProvider {},
         &RustProvider {},
         &SwiftProvider {},
         &StaticfileProvider {},
+        &VProvider {},
         &ZigProvider {},
         &CobolProvider {},
+        &CMakeProvider {},
+        &MesonProvider {},
     ]
 }
 
@@ -85,6 +136,21 @@ pub fn generate_build_plan(
     Ok(plan)
 }
 
+/// Generate the plan, along with the providers used to build the app and why each is used,
+/// e.g. `detected from package.json`
+pub fn generate_build_plan_with_reasons(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<(BuildPlan, Vec<(String, String)>)> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+
+    generator.generate_plan_with_reasons(&app, &environment)
+}
+
 pub fn get_plan_providers(
     path: &str,
     envs: Vec<&str>,
@@ -98,6 +164,154 @@ pub fn get_plan_providers(
     generator.get_plan_providers(&app, &environment)
 }
 
+/// Every provider that detects the app, and the files each was detected from
+pub fn get_matching_providers(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<Vec<(String, Vec<String>)>> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+
+    generator.get_matching_providers(&app, &environment)
+}
+
+/// Generate the plan for the current source and compare it against a previously saved plan.
+/// The saved plan can be JSON or TOML, as output by `nixpacks plan`.
+pub fn verify_build_plan(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+    plan_file: &str,
+) -> Result<Vec<PlanDifference>> {
+    let contents = fs::read_to_string(plan_file)
+        .with_context(|| format!("Failed to read plan file `{}`", plan_file))?;
+    let saved_plan = match Path::new(plan_file).extension() {
+        Some(ext) if ext == "toml" => BuildPlan::from_toml(contents),
+        Some(ext) if ext == "json" => BuildPlan::from_json(contents),
+        _ => bail!(Message::new(MessageId::UnknownConfigFileType).arg("file", plan_file)),
+    }
+    .with_context(|| format!("Failed to parse plan file `{}`", plan_file))?;
+
+    let plan = generate_build_plan(path, envs, options)?;
+
+    saved_plan.diff(&plan)
+}
+
+/// Write a script that runs the plan locally, to `out` or to `dev.sh` in the app directory.
+/// An existing `dev.sh` is only replaced with `force`.
+pub fn create_dev_script(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+    out: Option<&str>,
+    force: bool,
+) -> Result<PathBuf> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let script_path = match out {
+        Some(out) => PathBuf::from(out),
+        None => app.source.join(DEV_SCRIPT_NAME),
+    };
+    if out.is_none() && !force && script_path.exists() {
+        bail!(
+            "{} already exists. Use --force to overwrite it, or --out to write the script to another path",
+            script_path.display()
+        );
+    }
+
+    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let plan = generator.generate_plan(&app, &environment)?;
+
+    // A script outside of the app directory has to change into it before running the phases
+    let script = plan.get_dev_script(out.map(|_| app.source.as_path()))?;
+    fs::write(&script_path, script).context("Writing dev script")?;
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::os::unix::prelude::PermissionsExt;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))
+            .context("Making dev script executable")?;
+    }
+
+    Ok(script_path)
+}
+
+/// Collect the plan, Dockerfile, Nix expressions, and provider detection results into a tarball
+/// that can be attached to bug reports. Environment variable values are never included.
+pub fn create_debug_bundle(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+    out: &str,
+) -> Result<PathBuf> {
+    get_debug_bundle(path, envs, options)?.write(out)
+}
+
+/// The files of the debug bundle. The values of all variables are redacted, whether they come from
+/// `--env`, a config file, or a provider, since any of them can hold secrets.
+pub fn get_debug_bundle(
+    path: &str,
+    envs: Vec<&str>,
+    options: &GeneratePlanOptions,
+) -> Result<DebugBundle> {
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+    let env_names = environment.get_variable_names();
+
+    let mut bundle = DebugBundle::new();
+    bundle.add_file(
+        "version.txt",
+        format!(
+            "nixpacks {}\n{} {}\n",
+            NIX_PACKS_VERSION,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+    );
+
+    let detection = get_providers()
+        .iter()
+        .map(|provider| {
+            let detected = provider.detect(&app, &environment)?;
+            Ok(format!(
+                "{}: {}",
+                provider.name(),
+                if detected { "detected" } else { "not detected" }
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    bundle.add_file("detection.txt", detection.join("\n"));
+
+    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), options.clone());
+    let providers = generator.get_plan_providers(&app, &environment)?;
+    bundle.add_file("providers.txt", providers.join("\n"));
+    bundle.add_file("env-names.txt", env_names.join("\n"));
+
+    let mut plan = generator.generate_plan(&app, &environment)?;
+    plan.redact_variables(|_| true);
+    bundle.add_file("plan.json", plan.to_json()?);
+
+    let dockerfile = plan.generate_dockerfile(
+        &DockerBuilderOptions::default(),
+        &Environment::default(),
+        &OutputDir::default(),
+        None,
+    )?;
+    bundle.add_file("Dockerfile", dockerfile);
+
+    for (name, expression) in
+        create_nix_expressions_for_phases(&plan.phases.clone().unwrap_or_default())
+    {
+        bundle.add_file(format!("nix/{}", name), expression);
+    }
+
+    Ok(bundle)
+}
+
 pub async fn create_docker_image(
     path: &str,
     envs: Vec<&str>,
@@ -119,10 +333,10 @@ pub async fn create_docker_image(
 
         let start = plan.start_phase.clone().unwrap_or_default();
         if start.cmd.is_none() && !build_options.no_error_without_start {
-            bail!("No start command could be found")
+            bail!(Message::new(MessageId::NoStartCommand))
         }
     } else {
-        println!("\nNixpacks was unable to generate a build plan for this app.\nPlease check the documentation for supported languages: https://nixpacks.com");
+        println!("\n{}", Message::new(MessageId::UnableToGeneratePlan));
         println!("\nThe contents of the app directory are:\n");
 
         for file in &app.paths {
@@ -134,7 +348,7 @@ pub async fn create_docker_image(
             );
         }
 
-        std::process::exit(1);
+        std::process::exit(ExitCode::DetectionFailed.code());
     }
 
     builder
@@ -143,3 +357,46 @@ pub async fn create_docker_image(
 
     Ok(())
 }
+
+/// Build the app once and keep it running in a container, syncing source changes into it
+/// and running only the affected phases again instead of rebuilding the image.
+pub async fn start_warm_container(
+    path: &str,
+    envs: Vec<&str>,
+    plan_options: &GeneratePlanOptions,
+    up_options: &WarmContainerOptions,
+) -> Result<()> {
+    println!(
+        "{}",
+        format!("\n {}\n", Message::new(MessageId::UpExperimental)).bright_yellow()
+    );
+
+    let app = App::new(path)?;
+    let environment = Environment::from_envs(envs)?;
+
+    let mut generator = NixpacksBuildPlanGenerator::new(get_providers(), plan_options.clone());
+    let mut plan = generator.generate_plan(&app, &environment)?;
+
+    // The phases are run again inside of the container, so it must be the build image
+    if let Some(start) = plan.start_phase.as_mut() {
+        start.run_image = None;
+    }
+
+    println!("{}", plan.get_build_string()?);
+
+    let build_options = DockerBuilderOptions {
+        name: Some(up_options.name.clone()),
+        ..Default::default()
+    };
+    let builder = DockerImageBuilder::new(Logger::new(), build_options);
+    builder
+        .create_image(app.source.to_str().unwrap(), &plan, &environment)
+        .await?;
+
+    let container = WarmContainer::new(&app.source, &plan, up_options.clone())?;
+    container.start(&up_options.name)?;
+    println!("\nWatching for changes. Logs are available with:");
+    println!("  docker logs -f {}", up_options.name);
+
+    container.watch().await
+}
@@ -1,22 +1,33 @@
 use anyhow::{bail, Result};
 use clap::{arg, Arg, Command};
 use nixpacks::{
-    create_docker_image, generate_build_plan, get_plan_providers,
+    create_debug_bundle, create_dev_script, create_docker_image, generate_build_plan_with_reasons,
+    get_matching_providers, get_plan_providers,
     nixpacks::{
-        builder::docker::DockerBuilderOptions,
+        builder::docker::{
+            nix_cache::NixCache, warm_container::WarmContainerOptions, DockerBuilderOptions,
+        },
+        debug_bundle::DEFAULT_DEBUG_BUNDLE_NAME,
+        environment::Environment,
+        git_source::{GitCheckout, GitSource},
+        messages::{ExitCode, Message, MessageId},
         nix::pkg::Pkg,
         plan::{
             generator::GeneratePlanOptions,
+            graph::GraphFormat,
             phase::{Phase, StartPhase},
+            schema::{get_plan_schema, validate_json_plan},
             BuildPlan,
         },
     },
+    start_warm_container, verify_build_plan,
 };
 use std::{
     collections::hash_map::DefaultHasher,
     env,
     hash::{Hash, Hasher},
     string::ToString,
+    time::Duration,
 };
 
 enum PlanFormat {
@@ -29,13 +40,24 @@ impl PlanFormat {
         match s {
             "json" => Ok(PlanFormat::Json),
             "toml" => Ok(PlanFormat::Toml),
-            _ => bail!("Invalid plan format"),
+            _ => bail!(Message::new(MessageId::InvalidPlanFormat)),
         }
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        // Include the stable message code so errors can be looked up across CLI and library
+        match e.chain().find_map(|cause| cause.downcast_ref::<Message>()) {
+            Some(message) => eprintln!("Error [{}]: {:?}", message.code(), e),
+            None => eprintln!("Error: {:?}", e),
+        }
+        std::process::exit(ExitCode::from_error(&e).code());
+    }
+}
+
+async fn run() -> Result<()> {
     const VERSION: &str = env!("CARGO_PKG_VERSION");
 
     let matches = Command::new("nixpacks")
@@ -51,12 +73,93 @@ async fn main() -> Result<()> {
                         .short('f')
                         .takes_value(true)
                         .help("json|toml. Specify the output format of the plan"),
+                )
+                .arg(
+                    Arg::new("graph")
+                        .long("graph")
+                        .takes_value(true)
+                        .help("dot|mermaid. Output a graph of the phases and Dockerfile stages instead of the plan"),
+                )
+                .arg(
+                    Arg::new("start-only")
+                        .long("start-only")
+                        .help("Output the command, image, working directory, user, and variables the container starts with instead of the plan"),
                 ),
         )
+        .subcommand(
+            Command::new("verify")
+                .about("Check that the plan generated for an app matches a previously saved plan")
+                .arg(arg!(<PLAN> "JSON or TOML plan file saved with `nixpacks plan`"))
+                .arg(arg!([PATH] "App source")),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print the JSON Schema that config files and plans are checked against"),
+        )
         .subcommand(
             Command::new("detect")
                 .about("List all of the providers that will be used to build the app")
-                .arg(arg!([PATH] "App source")),
+                .arg(arg!([PATH] "App source"))
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .help("List every provider that detects the app, and the files it was detected from"),
+                ),
+        )
+        .subcommand(
+            Command::new("debug-bundle")
+                .about("Collect the plan, Dockerfile, and provider detection results into a tarball for bug reports")
+                .arg(arg!([PATH] "App source"))
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .short('o')
+                        .help("Path of the tarball to write")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("dev-script")
+                .about("Generate a script that builds and runs an app locally without Docker")
+                .arg(arg!([PATH] "App source"))
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .short('o')
+                        .help("Path of the script to write. Defaults to dev.sh in the app directory")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .short('f')
+                        .help("Overwrite an existing dev.sh"),
+                ),
+        )
+        .subcommand(
+            Command::new("up")
+                .about("Experimental. Build an app once and keep it running in a container, syncing changes into it")
+                .arg(arg!([PATH] "App source"))
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .short('n')
+                        .help("Name for the image and container. Defaults to nixpacks-up-<app directory>")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("publish")
+                        .long("publish")
+                        .help("Ports to publish from the container, e.g. 3000:3000")
+                        .takes_value(true)
+                        .multiple_values(true),
+                )
+                .arg(
+                    Arg::new("poll-interval")
+                        .long("poll-interval")
+                        .help("Milliseconds between checks for changed files. Defaults to 500")
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             Command::new("build")
@@ -69,6 +172,12 @@ async fn main() -> Result<()> {
                         .help("Name for the built image")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::new("random-name")
+                        .long("random-name")
+                        .help("Use a random name for the built image instead of one derived from the app directory and plan")
+                        .conflicts_with("name"),
+                )
                 .arg(
                     Arg::new("out")
                         .long("out")
@@ -98,6 +207,12 @@ async fn main() -> Result<()> {
                         .takes_value(true)
                         .multiple_values(true),
                 )
+                .arg(
+                    Arg::new("label-from-file")
+                        .long("label-from-file")
+                        .help("JSON or TOML file of labels to add to the output image. Values can use {git_sha}, {plan_hash}, {providers}, and {build_time}")
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::new("platform")
                         .long("platform")
@@ -113,6 +228,28 @@ async fn main() -> Result<()> {
                         )
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::new("tmp-dir")
+                        .long("tmp-dir")
+                        .help("Directory to create the temporary build context in. Defaults to NIXPACKS_TMPDIR or the system temp directory")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("nix-cache")
+                        .long("nix-cache")
+                        .help("Binary cache to substitute Nix packages from, as cachix:<name> or attic:<endpoint>/<cache>. Defaults to NIXPACKS_NIX_CACHE. The token is read from NIXPACKS_NIX_CACHE_TOKEN")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("nix-cache-push")
+                        .long("nix-cache-push")
+                        .help("Push the installed Nix packages to the binary cache"),
+                )
+                .arg(
+                    Arg::new("nix-flakes")
+                        .long("nix-flakes")
+                        .help("Install the Nix packages from a flake with pinned inputs, using `nix profile install`. Defaults to NIXPACKS_NIX_FLAKES"),
+                )
                 .arg(
                     Arg::new("current-dir")
                         .long("current-dir")
@@ -183,6 +320,27 @@ async fn main() -> Result<()> {
                 .takes_value(true)
                 .global(true),
         )
+        .arg(
+            Arg::new("base_image")
+                .long("base-image")
+                .help("Image with Nix installed to build the app in, instead of the default base image")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("run_image")
+                .long("run-image")
+                .help("Image to run the app in, instead of the image it was built in")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("nixpkgs_archive")
+                .long("nixpkgs-archive")
+                .help("Nixpkgs revision to install the Nix packages of every phase from")
+                .takes_value(true)
+                .global(true),
+        )
         .arg(
             Arg::new("pkgs")
                 .long("pkgs")
@@ -226,9 +384,21 @@ async fn main() -> Result<()> {
         )
         .get_matches();
 
+    // `up` removes its container when interrupted, every other command stops right away
+    if matches.subcommand_name() != Some("up") {
+        tokio::spawn(async {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(ExitCode::Interrupted.code());
+            }
+        });
+    }
+
     let install_cmd = matches.value_of("install_cmd").map(|s| vec![s.to_string()]);
     let build_cmd = matches.value_of("build_cmd").map(|s| vec![s.to_string()]);
     let start_cmd = matches.value_of("start_cmd").map(ToString::to_string);
+    let base_image = matches.value_of("base_image").map(ToString::to_string);
+    let run_image = matches.value_of("run_image").map(ToString::to_string);
+    let nixpkgs_archive = matches.value_of("nixpkgs_archive").map(ToString::to_string);
     let pkgs = match matches.values_of("pkgs") {
         Some(values) => values.map(Pkg::new).collect::<Vec<_>>(),
         None => Vec::new(),
@@ -269,9 +439,20 @@ async fn main() -> Result<()> {
         let start = StartPhase::new(start_cmd);
         cli_plan.set_start_phase(start);
     }
+    if let Some(run_image) = run_image {
+        cli_plan
+            .start_phase
+            .get_or_insert_with(StartPhase::default)
+            .run_in_image(run_image);
+    }
+    cli_plan.build_image = base_image;
+    cli_plan.nixpkgs_archive = nixpkgs_archive;
 
     let json_plan = match matches.value_of("json-plan") {
-        Some(json) => Some(BuildPlan::from_json(json)?),
+        Some(json) => {
+            validate_json_plan(json)?;
+            Some(BuildPlan::from_json(json)?)
+        }
         None => None,
     };
 
@@ -290,10 +471,34 @@ async fn main() -> Result<()> {
 
     match &matches.subcommand() {
         Some(("plan", matches)) => {
-            let path = matches.value_of("PATH").unwrap_or(".");
+            let (path, _checkout) = get_source(matches.value_of("PATH").unwrap_or("."))?;
+            let path = path.as_str();
             let format = PlanFormat::from_str(matches.value_of("format").unwrap_or("json"))?;
 
-            let plan = generate_build_plan(path, envs, &options)?;
+            let (plan, reasons) = generate_build_plan_with_reasons(path, envs.clone(), &options)?;
+
+            // Printed to stderr so the plan can still be piped
+            if !reasons.is_empty() {
+                eprintln!("Providers:");
+                for (name, reason) in reasons {
+                    eprintln!("  {}: {}", name, reason);
+                }
+            }
+
+            if let Some(graph_format) = matches.value_of("graph") {
+                println!("{}", plan.get_graph(graph_format.parse::<GraphFormat>()?)?);
+                return Ok(());
+            }
+
+            if matches.is_present("start-only") {
+                let start = plan.resolved_start(&Environment::from_envs(envs)?);
+                let start_s = match format {
+                    PlanFormat::Json => serde_json::to_string_pretty(&start)?,
+                    PlanFormat::Toml => toml::to_string_pretty(&start)?,
+                };
+                println!("{}", start_s);
+                return Ok(());
+            }
 
             let plan_s = match format {
                 PlanFormat::Json => plan.to_json()?,
@@ -302,16 +507,110 @@ async fn main() -> Result<()> {
 
             println!("{}", plan_s);
         }
+        Some(("verify", matches)) => {
+            let (path, _checkout) = get_source(matches.value_of("PATH").unwrap_or("."))?;
+            let path = path.as_str();
+            let plan_file = matches.value_of("PLAN").unwrap();
+
+            let differences = verify_build_plan(path, envs, &options, plan_file)?;
+            if !differences.is_empty() {
+                for difference in &differences {
+                    println!("{}", difference);
+                }
+                bail!(Message::new(MessageId::PlanDrift)
+                    .arg("file", plan_file)
+                    .arg("count", &differences.len()));
+            }
+
+            println!("Plan matches {}", plan_file);
+        }
+        Some(("schema", _)) => {
+            println!("{}", serde_json::to_string_pretty(&get_plan_schema()?)?);
+        }
         Some(("detect", matches)) => {
-            let path = matches.value_of("PATH").unwrap_or(".");
+            let (path, _checkout) = get_source(matches.value_of("PATH").unwrap_or("."))?;
+            let path = path.as_str();
+
+            if matches.is_present("all") {
+                for (name, files) in get_matching_providers(path, envs, &options)? {
+                    if files.is_empty() {
+                        println!("{}", name);
+                    } else {
+                        println!("{}: {}", name, files.join(", "));
+                    }
+                }
+                return Ok(());
+            }
 
             let providers = get_plan_providers(path, envs, &options)?;
             println!("{}", providers.join(", "));
         }
-        Some(("build", matches)) => {
+        Some(("debug-bundle", matches)) => {
+            let (path, _checkout) = get_source(matches.value_of("PATH").unwrap_or("."))?;
+            let path = path.as_str();
+            let out = matches.value_of("out").unwrap_or(DEFAULT_DEBUG_BUNDLE_NAME);
+
+            let bundle_path = create_debug_bundle(path, envs, &options, out)?;
+            println!("Saved debug bundle to:");
+            println!("  {}", bundle_path.display());
+            println!("Environment variable values are not included in the bundle.");
+        }
+        Some(("dev-script", matches)) => {
             let path = matches.value_of("PATH").unwrap_or(".");
+
+            let out = matches.value_of("out");
+            let force = matches.is_present("force");
+
+            let script_path = create_dev_script(path, envs, &options, out, force)?;
+            println!("Saved dev script to:");
+            println!("  {}", script_path.display());
+        }
+        Some(("up", matches)) => {
+            let path = matches.value_of("PATH").unwrap_or(".");
+            let name = match matches.value_of("name") {
+                Some(name) => name.to_string(),
+                None => get_default_up_name(path)?,
+            };
+            let ports = matches
+                .values_of("publish")
+                .map(|values| values.map(ToString::to_string).collect::<Vec<_>>())
+                .unwrap_or_default();
+            let poll_interval = matches
+                .value_of("poll-interval")
+                .map(str::parse::<u64>)
+                .transpose()?
+                .unwrap_or(500);
+
+            let up_options = WarmContainerOptions {
+                name,
+                ports,
+                poll_interval: Duration::from_millis(poll_interval),
+            };
+
+            start_warm_container(path, envs, &options, &up_options).await?;
+
+            // The warm container only stops once it is interrupted
+            std::process::exit(ExitCode::Interrupted.code());
+        }
+        Some(("build", matches)) => {
+            let source = matches.value_of("PATH").unwrap_or(".");
+            let (path, checkout) = get_source(source)?;
+            let path = path.as_str();
             let name = matches.value_of("name").map(ToString::to_string);
+            let random_name = matches.is_present("random-name");
             let out_dir = matches.value_of("out").map(ToString::to_string);
+            let tmp_dir = matches
+                .value_of("tmp-dir")
+                .map(ToString::to_string)
+                .or_else(|| env::var("NIXPACKS_TMPDIR").ok());
+            let nix_cache = matches
+                .value_of("nix-cache")
+                .map(ToString::to_string)
+                .or_else(|| env::var("NIXPACKS_NIX_CACHE").ok())
+                .map(|cache| cache.parse::<NixCache>())
+                .transpose()?;
+            let nix_cache_push = matches.is_present("nix-cache-push");
+            let nix_flakes = matches.is_present("nix-flakes");
             let current_dir = matches.is_present("current-dir");
             let mut cache_key = matches.value_of("cache-key").map(ToString::to_string);
             let no_cache = matches.is_present("no-cache");
@@ -329,8 +628,12 @@ async fn main() -> Result<()> {
                 .map(ToString::to_string);
 
             // Default to absolute `path` of the source that is being built as the cache-key if not disabled
+            // Git sources are checked out to a new directory each time, so key them by the source itself
             if !no_cache && cache_key.is_none() {
-                cache_key = get_default_cache_key(path)?;
+                cache_key = match checkout {
+                    Some(_) => Some(hash_cache_key(source)),
+                    None => get_default_cache_key(path)?,
+                };
             }
 
             let print_dockerfile = matches.is_present("dockerfile");
@@ -344,6 +647,7 @@ async fn main() -> Result<()> {
                 .values_of("label")
                 .map(|values| values.map(ToString::to_string).collect::<Vec<_>>())
                 .unwrap_or_default();
+            let label_file = matches.value_of("label-from-file").map(ToString::to_string);
             let platform = matches
                 .values_of("platform")
                 .map(|values| values.map(ToString::to_string).collect::<Vec<_>>())
@@ -353,9 +657,12 @@ async fn main() -> Result<()> {
 
             let build_options = &DockerBuilderOptions {
                 name,
+                random_name,
                 tags,
                 labels,
+                label_file,
                 out_dir,
+                tmp_dir,
                 quiet: false,
                 cache_key,
                 no_cache,
@@ -367,6 +674,9 @@ async fn main() -> Result<()> {
                 no_error_without_start,
                 incremental_cache_image,
                 verbose,
+                nix_cache,
+                nix_cache_push,
+                nix_flakes,
             };
 
             create_docker_image(path, envs, &options, build_options).await?;
@@ -377,19 +687,45 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+fn get_default_up_name(path: &str) -> Result<String> {
+    let source = env::current_dir()?.join(path).canonicalize()?;
+    let dir_name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "-");
+
+    Ok(format!("nixpacks-up-{}", dir_name))
+}
+
+/// Check out git sources (`<url>#<ref>:<subdir>`) so they can be built like a local directory.
+/// The checkout is removed once it is dropped.
+fn get_source(source: &str) -> Result<(String, Option<GitCheckout>)> {
+    match GitSource::parse(source) {
+        Some(git_source) => {
+            let checkout = git_source.checkout()?;
+            Ok((
+                checkout.path().to_string_lossy().to_string(),
+                Some(checkout),
+            ))
+        }
+        None => Ok((source.to_string(), None)),
+    }
+}
+
 fn get_default_cache_key(path: &str) -> Result<Option<String>> {
     let current_dir = env::current_dir()?;
     let source = current_dir.join(path).canonicalize();
     if let Ok(source) = source {
-        let source_str = source.to_string_lossy().to_string();
-        let mut hasher = DefaultHasher::new();
-        source_str.hash(&mut hasher);
-
-        let encoded_source = base64::encode(hasher.finish().to_be_bytes())
-            .replace(|c: char| !c.is_alphanumeric(), "");
-
-        Ok(Some(encoded_source))
+        Ok(Some(hash_cache_key(&source.to_string_lossy())))
     } else {
         Ok(None)
     }
 }
+
+fn hash_cache_key(source: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+
+    base64::encode(hasher.finish().to_be_bytes()).replace(|c: char| !c.is_alphanumeric(), "")
+}
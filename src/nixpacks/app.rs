@@ -1,12 +1,17 @@
+use crate::nixpacks::{
+    files,
+    messages::{Message, MessageId},
+};
 use path_slash::PathBufExt;
-use std::collections::BTreeMap;
-use std::ffi::OsStr;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
+use std::sync::Arc;
 use std::{env, fs, path::PathBuf};
 
 use anyhow::{bail, Context, Result};
 use globset::Glob;
-use ignore::{DirEntry, WalkBuilder};
+use ignore::DirEntry;
 use regex::Regex;
 use serde::de::DeserializeOwned;
 
@@ -18,6 +23,12 @@ pub const ASSETS_DIR: &str = "/assets/";
 pub struct App {
     pub source: PathBuf,
     pub paths: Vec<PathBuf>,
+
+    /// Files and directories that are copied into the image, leaving out the ones `.nixpacksignore` or `.gitignore` ignore
+    files: Arc<BTreeSet<PathBuf>>,
+
+    /// Files and directories that were looked for and exist, for apps created with `recording`
+    matches: Option<RefCell<Vec<String>>>,
 }
 
 impl App {
@@ -28,15 +39,67 @@ impl App {
             .canonicalize()
             .context("Failed to read app source directory")?;
 
+        // Apps are detected from the same files that are copied into the image
+        let files = files::walk_app_files(&source)
+            .filter_map(Result::ok)
+            .map(DirEntry::into_path)
+            .filter(|path| path != &source)
+            .collect::<BTreeSet<_>>();
+
         let dir = fs::read_dir(source.clone()).context("Failed to read app source directory")?;
-        let paths: Vec<PathBuf> = dir.map(|path| path.unwrap().path()).collect();
+        let paths: Vec<PathBuf> = dir
+            .map(|path| path.unwrap().path())
+            .filter(|path| files.contains(path))
+            .collect();
+
+        Ok(App {
+            source,
+            paths,
+            files: Arc::new(files),
+            matches: None,
+        })
+    }
+
+    /// A copy of the app that remembers the existing files and directories it is asked about,
+    /// e.g. to show which files a provider was detected from
+    #[must_use]
+    pub fn recording(&self) -> App {
+        App {
+            matches: Some(RefCell::new(Vec::new())),
+            ..self.clone()
+        }
+    }
+
+    /// The files and directories a `recording` app was asked about that exist, relative to the app
+    pub fn into_matches(self) -> Vec<String> {
+        self.matches.map(RefCell::into_inner).unwrap_or_default()
+    }
 
-        Ok(App { source, paths })
+    fn add_matches<'a>(&self, paths: impl IntoIterator<Item = &'a Path>) {
+        if let Some(matches) = &self.matches {
+            let mut matches = matches.borrow_mut();
+            for path in paths {
+                let path = self
+                    .strip_source_path(path)
+                    .unwrap_or_else(|_| path.to_path_buf())
+                    .to_slash_lossy()
+                    .to_string();
+                if !matches.contains(&path) {
+                    matches.push(path);
+                }
+            }
+        }
     }
 
     /// Check if a file exists
     pub fn includes_file(&self, name: &str) -> bool {
-        self.source.join(name).is_file()
+        let path = self.source.join(name);
+        let included = path.is_file() && self.files.contains(&path);
+        if included {
+            self.add_matches([Path::new(name)]);
+        }
+
+        included
     }
 
     /// Returns a list of paths matching a glob pattern
@@ -44,13 +107,10 @@ impl App {
     /// # Errors
     /// Creating the Glob fails
     pub fn find_files(&self, pattern: &str) -> Result<Vec<PathBuf>> {
-        let directories = self
-            .find_glob(pattern)?
-            .into_iter()
-            .filter(|path| path.is_file())
-            .collect();
+        let files = self.find_glob_files(pattern)?;
+        self.add_matches(files.iter().map(PathBuf::as_path));
 
-        Ok(directories)
+        Ok(files)
     }
 
     /// Returns a list of paths matching a glob pattern
@@ -58,15 +118,24 @@ impl App {
     /// # Errors
     /// Creating the Glob fails
     pub fn find_directories(&self, pattern: &str) -> Result<Vec<PathBuf>> {
-        let directories = self
+        let directories: Vec<PathBuf> = self
             .find_glob(pattern)?
             .into_iter()
             .filter(|path| path.is_dir())
             .collect();
+        self.add_matches(directories.iter().map(PathBuf::as_path));
 
         Ok(directories)
     }
 
+    fn find_glob_files(&self, pattern: &str) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .find_glob(pattern)?
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect())
+    }
+
     fn find_glob(&self, pattern: &str) -> Result<Vec<PathBuf>> {
         let full_pattern = self.source.join(pattern);
 
@@ -75,18 +144,13 @@ impl App {
             None => return Ok(Vec::new()),
         };
 
-        let walker = WalkBuilder::new(&self.source)
-            // this includes hidden directories & files
-            .hidden(false)
-            .sort_by_file_name(OsStr::cmp)
-            .build();
         let glob = Glob::new(pattern_str)?.compile_matcher();
 
-        let relative_paths = walker
-            .into_iter()
-            .filter_map(Result::ok) // remove bad ones
-            .map(DirEntry::into_path) // convert to paths
+        let relative_paths = self
+            .files
+            .iter()
             .filter(|path| glob.is_match(path)) // find matches
+            .cloned()
             .collect();
 
         Ok(relative_paths)
@@ -117,7 +181,7 @@ impl App {
     }
 
     pub fn find_match(&self, re: &Regex, pattern: &str) -> Result<bool> {
-        let paths = match self.find_files(pattern) {
+        let paths = match self.find_glob_files(pattern) {
             Ok(v) => v,
             Err(_e) => return Ok(false),
         };
@@ -128,6 +192,7 @@ impl App {
             if let Some(p) = path_buf.to_str() {
                 let f = self.read_file(p)?;
                 if re.find(f.as_str()).is_some() {
+                    self.add_matches([path_buf.as_path()]);
                     return Ok(true);
                 }
             }
@@ -138,7 +203,13 @@ impl App {
 
     /// Check if a directory exists
     pub fn includes_directory(&self, name: &str) -> bool {
-        self.source.join(name).is_dir()
+        let path = self.source.join(name);
+        let included = path.is_dir() && self.files.contains(&path);
+        if included {
+            self.add_matches([Path::new(name)]);
+        }
+
+        included
     }
 
     #[cfg(target_os = "windows")]
@@ -197,7 +268,7 @@ impl App {
     pub fn strip_source_path(&self, abs_path: &Path) -> Result<PathBuf> {
         let source_str = match self.source.to_str() {
             Some(s) => s,
-            None => bail!("Failed to parse source path"),
+            None => bail!(Message::new(MessageId::InvalidSourcePath)),
         };
 
         // Strip source path from absolute path
@@ -237,6 +308,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_recording() -> Result<()> {
+        let app = App::new("./examples/node-npm")?;
+        let recording = app.recording();
+        assert!(
+            recording.includes_file("package.json")
+                && !recording.includes_file("yarn.lock")
+                && recording.has_match("*.ts")
+        );
+        assert_eq!(recording.into_matches(), vec!["package.json", "index.ts"]);
+
+        // Nothing is recorded by the app itself
+        assert!(app.includes_file("package.json"));
+        assert_eq!(app.into_matches(), Vec::<String>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignored_files() -> Result<()> {
+        let dir = tempdir::TempDir::new("nixpacks-app")?;
+        fs::write(dir.path().join(".gitignore"), "dist/\n*.log\n")?;
+        fs::write(dir.path().join("index.js"), "")?;
+        fs::write(dir.path().join("debug.log"), "")?;
+        fs::create_dir(dir.path().join("dist"))?;
+        fs::write(dir.path().join("dist/index.js"), "")?;
+
+        // Ignored files are left out of detection, like they are left out of the image
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert!(app.includes_file("index.js"));
+        assert!(!app.includes_file("debug.log"));
+        assert!(!app.includes_directory("dist"));
+        assert_eq!(app.find_files("**/*.js")?.len(), 1);
+        assert_eq!(app.paths.len(), 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_read_file() -> Result<()> {
         let app = App::new("./examples/node-npm")?;
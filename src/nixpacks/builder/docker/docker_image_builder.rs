@@ -1,18 +1,28 @@
-use super::{dockerfile_generation::DockerfileGenerator, DockerBuilderOptions, ImageBuilder};
+use super::{
+    dockerfile_generation::DockerfileGenerator, utils, DockerBuilderOptions, ImageBuilder,
+};
 use crate::nixpacks::{
-    builder::docker::{
-        dockerfile_generation::OutputDir,
-        file_server::FileServer,
-        incremental_cache::{IncrementalCache, IncrementalCacheDirs},
+    builder::{
+        docker::{
+            dockerfile_generation::OutputDir,
+            file_server::FileServer,
+            incremental_cache::{IncrementalCache, IncrementalCacheDirs},
+            labels::{self, LabelContext},
+            nix_cache::{NIX_CACHE_SECRET_ID, NIX_CACHE_TOKEN_ENV},
+        },
+        publish::{get_publisher, write_artifacts, Publisher},
     },
     environment::Environment,
     files,
     logger::Logger,
+    messages::{Message, MessageId},
     plan::BuildPlan,
 };
 use anyhow::{bail, Context, Ok, Result};
 use std::{
+    env,
     fs::{self, remove_dir_all, File},
+    path::Path,
     process::Command,
 };
 use tempdir::TempDir;
@@ -29,7 +39,11 @@ fn get_output_dir(app_src: &str, options: &DockerBuilderOptions) -> Result<Outpu
     } else if options.current_dir {
         OutputDir::new(app_src.into(), false)
     } else {
-        let tmp = TempDir::new("nixpacks").context("Creating a temp directory")?;
+        let tmp = match &options.tmp_dir {
+            Some(tmp_dir) => TempDir::new_in(tmp_dir, "nixpacks")
+                .context(format!("Creating a temp directory in {}", tmp_dir))?,
+            None => TempDir::new("nixpacks").context("Creating a temp directory")?,
+        };
         OutputDir::new(tmp.into_path(), true)
     }
 }
@@ -39,12 +53,23 @@ use async_trait::async_trait;
 #[async_trait]
 impl ImageBuilder for DockerImageBuilder {
     async fn create_image(&self, app_src: &str, plan: &BuildPlan, env: &Environment) -> Result<()> {
-        let id = Uuid::new_v4();
-
         let output = get_output_dir(app_src, &self.options)?;
-        let name = self.options.name.clone().unwrap_or_else(|| id.to_string());
+        let name = match &self.options.name {
+            Some(name) => name.clone(),
+            None if self.options.random_name => Uuid::new_v4().to_string(),
+            None => utils::get_default_image_name(app_src, plan)?,
+        };
         output.ensure_output_exists()?;
 
+        // Check the publish destinations before building, so a typo doesn't waste a build
+        let publishers = plan
+            .publish
+            .clone()
+            .unwrap_or_default()
+            .iter()
+            .map(|destination| get_publisher(destination))
+            .collect::<Result<Vec<_>>>()?;
+
         let incremental_cache = IncrementalCache::default();
         let incremental_cache_dirs = IncrementalCacheDirs::new(&output);
 
@@ -68,6 +93,13 @@ impl ImageBuilder for DockerImageBuilder {
             return Ok(());
         }
 
+        if let Err(e) = self.check_available_space(app_src, &output) {
+            if output.is_temp {
+                remove_dir_all(&output.root)?;
+            }
+            return Err(e);
+        }
+
         self.write_app(app_src, &output).context("Writing app")?;
         self.write_dockerfile(dockerfile, &output)
             .context("Writing Dockerfile")?;
@@ -76,15 +108,27 @@ impl ImageBuilder for DockerImageBuilder {
 
         // Only build if the --out flag was not specified
         if self.options.out_dir.is_none() {
-            let mut docker_build_cmd = self.get_docker_build_cmd(plan, name.as_str(), &output)?;
+            // Kept outside of the build context, so the values are not copied into the image with the app
+            let variables_dir =
+                TempDir::new("nixpacks-variables").context("Creating a temp directory")?;
+            let variables_file = variables_dir.path().join("variables.sh");
+            fs::write(
+                &variables_file,
+                utils::get_variables_file(&utils::get_secret_variables(plan)),
+            )
+            .context("Writing variables file")?;
+
+            let mut docker_build_cmd =
+                self.get_docker_build_cmd(app_src, plan, name.as_str(), &output, &variables_file)?;
 
             // Execute docker build
             let build_result = docker_build_cmd.spawn()?.wait().context("Building image")?;
             if !build_result.success() {
-                bail!("Docker build failed")
+                bail!(Message::new(MessageId::DockerBuildFailed))
             }
 
-            self.logger.log_section("Successfully Built!");
+            self.logger
+                .log_section(&Message::new(MessageId::BuildSuccessful).to_string());
             println!("\nRun:");
             println!("  docker run -it {}", name);
 
@@ -95,6 +139,10 @@ impl ImageBuilder for DockerImageBuilder {
                 )?;
             }
 
+            if !publishers.is_empty() {
+                self.publish_artifacts(plan, &name, &output, &publishers)?;
+            }
+
             if output.is_temp {
                 remove_dir_all(output.root)?;
             }
@@ -114,14 +162,16 @@ impl DockerImageBuilder {
 
     fn get_docker_build_cmd(
         &self,
+        app_src: &str,
         plan: &BuildPlan,
         name: &str,
         output: &OutputDir,
+        variables_file: &Path,
     ) -> Result<Command> {
         let mut docker_build_cmd = Command::new("docker");
 
         if docker_build_cmd.output().is_err() {
-            bail!("Please install Docker to build the app https://docs.docker.com/engine/install/")
+            bail!(Message::new(MessageId::DockerNotInstalled))
         }
 
         // Enable BuildKit for all builds
@@ -157,11 +207,40 @@ impl DockerImageBuilder {
                 .arg("BUILDKIT_INLINE_CACHE=1");
         }
 
-        // Add build environment variables
-        for (name, value) in &plan.variables.clone().unwrap_or_default() {
-            docker_build_cmd
-                .arg("--build-arg")
-                .arg(format!("{}={}", name, value));
+        // Add build environment variables. When there are too many for the command line, only their names are passed,
+        // and docker reads the values from its environment. Build-only variables are passed in a secret file instead
+        let variables = plan.all_variables();
+        let secret_variables = utils::get_secret_variables(plan);
+        if utils::exceeds_build_arg_limits(&variables) {
+            for (name, value) in &variables {
+                if secret_variables.contains_key(name) {
+                    continue;
+                }
+
+                if utils::is_docker_cli_variable(name) {
+                    docker_build_cmd
+                        .arg("--build-arg")
+                        .arg(format!("{}={}", name, value));
+                } else {
+                    docker_build_cmd
+                        .arg("--build-arg")
+                        .arg(name)
+                        .env(name, value);
+                }
+            }
+        } else {
+            for (name, value) in &variables {
+                docker_build_cmd
+                    .arg("--build-arg")
+                    .arg(format!("{}={}", name, value));
+            }
+        }
+        if !secret_variables.is_empty() {
+            docker_build_cmd.arg("--secret").arg(format!(
+                "id={},src={}",
+                utils::VARIABLES_SECRET_ID,
+                variables_file.display()
+            ));
         }
 
         // Add user defined tags and labels to the image
@@ -171,6 +250,19 @@ impl DockerImageBuilder {
         for l in self.options.labels.clone() {
             docker_build_cmd.arg("--label").arg(l);
         }
+        if let Some(label_file) = &self.options.label_file {
+            let context = LabelContext::new(app_src, plan)?;
+            for l in labels::get_labels_from_file(label_file, &context)? {
+                docker_build_cmd.arg("--label").arg(l);
+            }
+        }
+        // Pass the binary cache token as a secret so it is not stored in the image
+        if self.options.nix_cache.is_some() && env::var(NIX_CACHE_TOKEN_ENV).is_ok() {
+            docker_build_cmd.arg("--secret").arg(format!(
+                "id={},env={}",
+                NIX_CACHE_SECRET_ID, NIX_CACHE_TOKEN_ENV
+            ));
+        }
         for l in self.options.platform.clone() {
             docker_build_cmd.arg("--platform").arg(l);
         }
@@ -178,6 +270,28 @@ impl DockerImageBuilder {
         Ok(docker_build_cmd)
     }
 
+    /// Fail before copying the app if the temp directory is too small to hold it
+    fn check_available_space(&self, app_src: &str, output: &OutputDir) -> Result<()> {
+        if !output.is_temp {
+            return Ok(());
+        }
+
+        let available = match files::get_available_space(&output.root) {
+            Some(available) => available,
+            None => return Ok(()),
+        };
+        let required = files::get_dir_size(app_src).context("Calculating the size of the app")?;
+
+        if required > available {
+            bail!(Message::new(MessageId::NotEnoughDiskSpace)
+                .arg("dir", &output.root.display())
+                .arg("required", &(required / 1024 / 1024 + 1))
+                .arg("available", &(available / 1024 / 1024)));
+        }
+
+        Ok(())
+    }
+
     fn write_app(&self, app_src: &str, output: &OutputDir) -> Result<()> {
         if output.is_temp {
             files::recursive_copy_dir(app_src, &output.root)
@@ -186,6 +300,30 @@ impl DockerImageBuilder {
         }
     }
 
+    /// Upload the artifacts of the build to every publish destination
+    fn publish_artifacts(
+        &self,
+        plan: &BuildPlan,
+        name: &str,
+        output: &OutputDir,
+        publishers: &[Box<dyn Publisher>],
+    ) -> Result<()> {
+        let artifacts_dir = output.get_absolute_path("artifacts");
+        let files = write_artifacts(
+            plan,
+            name,
+            &output.get_absolute_path("Dockerfile"),
+            &artifacts_dir,
+        )?;
+
+        for publisher in publishers {
+            println!("\nPublishing artifacts to {}", publisher.destination());
+            publisher.publish(&artifacts_dir, &files)?;
+        }
+
+        Ok(())
+    }
+
     fn write_dockerfile(&self, dockerfile: String, output: &OutputDir) -> Result<()> {
         let dockerfile_path = output.get_absolute_path("Dockerfile");
         File::create(dockerfile_path.clone()).context("Creating Dockerfile file")?;
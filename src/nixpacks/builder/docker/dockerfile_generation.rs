@@ -3,26 +3,36 @@ use super::{
 };
 use crate::nixpacks::{
     app,
-    environment::Environment,
-    images::DEFAULT_BASE_IMAGE,
-    nix::{create_nix_expressions_for_phases, nix_file_names_for_phases},
+    environment::{Environment, EnvironmentVariables},
+    images::{is_shellless_image, DEFAULT_BASE_IMAGE},
+    messages::{Message, MessageId},
+    nix::{
+        check_nix_file_archives, create_nix_expressions_for_phases, create_nix_flakes_for_phases,
+        nix_file_name_for_phase, nix_file_names_for_phases, nix_flake_dir,
+        nix_local_files_for_file,
+    },
     plan::{
-        phase::{Phase, StartPhase},
+        phase::{Phase, Phases, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 use indoc::formatdoc;
 use path_slash::PathBufExt;
 use std::{
+    collections::BTreeSet,
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
+    process::Command,
 };
 
 const NIXPACKS_OUTPUT_DIR: &str = ".nixpacks";
 pub const APP_DIR: &str = "/app/";
 
+/// Profile the flakes are installed into, separate from the `nix-env` profile of the base image
+const NIX_FLAKE_PROFILE: &str = "/nix/var/nix/profiles/nixpacks";
+
 #[derive(Debug, Clone)]
 pub struct OutputDir {
     pub root: PathBuf,
@@ -105,20 +115,37 @@ impl DockerfileGenerator for BuildPlan {
     ) -> Result<String> {
         let plan = self;
 
-        let nix_file_names = nix_file_names_for_phases(&plan.phases.clone().unwrap_or_default());
+        let all_phases = plan.phases.clone().unwrap_or_default();
+        check_nix_file_archives(&all_phases)?;
+        let nix_file_names = nix_file_names_for_phases(&all_phases);
 
-        let mut nix_install_cmds: Vec<String> = Vec::new();
-        for name in nix_file_names {
-            let nix_file = output.get_relative_path(name);
+        let mut nix_install_cmds = nix_file_names
+            .iter()
+            .map(|name| get_nix_install_cmd(options, env, output, name, &all_phases))
+            .collect::<Result<Vec<_>>>()?;
 
-            let nix_file_path = nix_file
-                .to_slash()
-                .context("Failed to convert nix file path to slash path.")?;
+        // Install the binary cache client before any Nix packages so they can be substituted
+        let uses_nix = plan
+            .phases
+            .clone()
+            .unwrap_or_default()
+            .values()
+            .any(Phase::uses_nix);
+        if let Some(nix_cache) = options.nix_cache.as_ref().filter(|_| uses_nix) {
+            nix_install_cmds.insert(0, nix_cache.get_client_install_cmd());
+        }
+        if uses_nix && uses_nix_flakes(options, env) {
+            nix_install_cmds.insert(0, get_nix_flake_profile_cmds());
+        }
+
+        let base_image = plan
+            .build_image
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
 
-            nix_install_cmds.push(format!(
-                "COPY {nix_file_path} {nix_file_path}\nRUN nix-env -if {nix_file_path} && nix-collect-garbage -d",
-                nix_file_path = nix_file_path
-            ));
+        // Fail early when a custom base image doesn't have Nix to install the packages with
+        if !nix_file_names.is_empty() && base_image != DEFAULT_BASE_IMAGE {
+            nix_install_cmds.insert(0, get_nix_check_cmd(&base_image));
         }
         let nix_install_cmds = nix_install_cmds.join("\n");
 
@@ -127,31 +154,29 @@ impl DockerfileGenerator for BuildPlan {
             String::new()
         } else {
             format!(
-                "RUN apt-get update && apt-get install -y --no-install-recommends {}",
+                "RUN apt-get update && apt-get install -y --no-install-recommends {} && apt-get clean && rm -rf /var/lib/apt/lists/*",
                 apt_pkgs.join(" ")
             )
         };
 
+        let secret_variables = utils::get_secret_variables(plan);
+
+        // Build variables are only declared as build args so they are not set in the image
         let variables = plan.variables.clone().unwrap_or_default();
-        let args_string = if variables.is_empty() {
-            String::new()
-        } else {
-            format!(
-                "ARG {}\nENV {}",
-                // Pull the variables in from docker `--build-arg`
-                variables
-                    .iter()
-                    .map(|var| var.0.to_string())
-                    .collect::<Vec<_>>()
-                    .join(" "),
-                // Make the variables available at runtime
-                variables
-                    .iter()
-                    .map(|var| format!("{}=${}", var.0, var.0))
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            )
-        };
+        let build_variables = plan.build_variables.clone().unwrap_or_default();
+        let args_string = get_variables_cmd(
+            &variables
+                .keys()
+                .chain(
+                    build_variables
+                        .keys()
+                        .filter(|name| !variables.contains_key(*name)),
+                )
+                .cloned()
+                .collect::<Vec<_>>(),
+            &variables.keys().cloned().collect::<Vec<_>>(),
+            &secret_variables,
+        );
 
         let static_assets = plan.static_assets.clone().unwrap_or_default();
         let assets_copy_cmd = if static_assets.is_empty() {
@@ -166,6 +191,9 @@ impl DockerfileGenerator for BuildPlan {
 
         let phases = plan.get_sorted_phases()?;
 
+        // Nix files set with `nixFile` are installed right before the first phase that uses them
+        let mut installed_nix_files = BTreeSet::new();
+
         let dockerfile_phases = phases
             .into_iter()
             .map(|phase| {
@@ -176,21 +204,78 @@ impl DockerfileGenerator for BuildPlan {
                         phase.get_name()
                     ))?;
 
-                Ok(phase_dockerfile)
+                match (&phase.nix_file, nix_file_name_for_phase(&phase)) {
+                    (Some(_), Some(name)) if installed_nix_files.insert(name.clone()) => {
+                        Ok(format!(
+                            "{}\n{}",
+                            get_nix_install_cmd(options, env, output, &name, &all_phases)?,
+                            phase_dockerfile
+                        ))
+                    }
+                    _ => Ok(phase_dockerfile),
+                }
             })
             .collect::<Result<Vec<_>>>()?;
         let dockerfile_phases_str = dockerfile_phases.join("\n");
 
-        let start_phase_str = plan
+        // Build-only variables passed in the secret file are only loaded while the phase commands run
+        let dockerfile_phases_str = if secret_variables.is_empty() {
+            dockerfile_phases_str
+        } else {
+            mount_variables_secret(&dockerfile_phases_str)
+        };
+
+        let mut start_phase = plan.start_phase.clone().unwrap_or_default();
+        if start_phase.run_image.is_some() {
+            // Run images set with `--run-image` don't have the Nix packages of the build,
+            // so the program the start command runs is copied along with the Nix store paths it needs
+            let copies_nix_store = start_phase
+                .only_include_files
+                .as_ref()
+                .map_or(false, |files| files.iter().any(|file| file == "/nix"));
+            if start_phase.nix_closure.is_none() && !copies_nix_store {
+                if let Some(program) = get_start_program(&start_phase) {
+                    start_phase.add_nix_closure(program);
+                }
+            }
+        }
+        let start_phase_str =
+            start_phase.generate_dockerfile(options, env, output, file_server_config)?;
+
+        // Runtime variables are set in the final stage, after all of the phases have run.
+        // A separate run image starts without any of the build stage's variables, so they are declared again
+        let runs_in_separate_image = plan
             .start_phase
-            .clone()
-            .unwrap_or_default()
-            .generate_dockerfile(options, env, output, file_server_config)?;
+            .as_ref()
+            .map_or(false, |start| start.run_image.is_some());
+        let runtime_variables = plan.runtime_variables.clone().unwrap_or_default();
+        let start_variables = if runs_in_separate_image {
+            variables
+                .keys()
+                .chain(
+                    runtime_variables
+                        .keys()
+                        .filter(|name| !variables.contains_key(*name)),
+                )
+                .cloned()
+                .collect::<Vec<_>>()
+        } else {
+            runtime_variables.keys().cloned().collect::<Vec<_>>()
+        };
+        let start_phase_str = if start_variables.is_empty() {
+            start_phase_str
+        } else {
+            format!(
+                "{}\n{}",
+                start_phase_str,
+                get_variables_cmd(&start_variables, &start_variables, &secret_variables)
+            )
+        };
 
-        let base_image = plan
-            .build_image
-            .clone()
-            .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
+        let start_phase_str = match plan.get_exposed_port(env) {
+            Some(port) => format!("{}\nEXPOSE {}", start_phase_str, port),
+            None => start_phase_str,
+        };
 
         let dockerfile = formatdoc! {"
             FROM {base_image}
@@ -227,17 +312,31 @@ impl DockerfileGenerator for BuildPlan {
     ) -> Result<()> {
         self.write_assets(self, output).context("Writing assets")?;
 
-        let nix_expressions =
-            create_nix_expressions_for_phases(&self.phases.clone().unwrap_or_default());
+        let phases = self.phases.clone().unwrap_or_default();
+        let nix_flakes = uses_nix_flakes(options, env);
+        let nix_expressions = if nix_flakes {
+            create_nix_flakes_for_phases(&phases)
+        } else {
+            create_nix_expressions_for_phases(&phases)
+        };
 
+        let mut flake_dirs = Vec::new();
         for (name, nix_expression) in nix_expressions {
             let nix_path = output.get_absolute_path(name);
+            if let Some(parent) = nix_path.parent() {
+                fs::create_dir_all(parent).context("Creating Nix file directory")?;
+                flake_dirs.push(parent.to_path_buf());
+            }
             let mut nix_file = File::create(nix_path).context("Creating Nix environment file")?;
             nix_file
                 .write_all(nix_expression.as_bytes())
                 .context("Unable to write Nix expression")?;
         }
 
+        if nix_flakes && !flake_dirs.is_empty() {
+            lock_nix_flakes(&flake_dirs)?;
+        }
+
         for phase in self.get_sorted_phases()? {
             phase
                 .write_supporting_files(options, env, output)
@@ -248,8 +347,187 @@ impl DockerfileGenerator for BuildPlan {
     }
 }
 
+fn get_nix_check_cmd(base_image: &str) -> String {
+    let message = Message::new(MessageId::BaseImageMissingNix).arg("image", base_image);
+    format!(
+        "RUN command -v nix-env > /dev/null || (echo '{}' >&2 && exit 1)",
+        format!("Error [{}]: {}", message.code(), message).replace('\'', "'\\''")
+    )
+}
+
+/// Pull the variables in from docker `--build-arg` and set the ones needed at runtime in the image
+/// Declare `arg_names` as build args and set `env_names` in the image.
+/// Build-only variables in `secret_variables` are skipped, since they are loaded from the secret file instead.
+fn get_variables_cmd(
+    arg_names: &[String],
+    env_names: &[String],
+    secret_variables: &EnvironmentVariables,
+) -> String {
+    let arg_names = arg_names
+        .iter()
+        .filter(|name| !secret_variables.contains_key(*name))
+        .map(|name| name.as_str())
+        .collect::<Vec<_>>();
+    let env_names = env_names
+        .iter()
+        .filter(|name| !secret_variables.contains_key(*name))
+        .map(|name| format!("{}=${}", name, name))
+        .collect::<Vec<_>>();
+
+    let mut cmds = Vec::new();
+    if !arg_names.is_empty() {
+        cmds.push(format!("ARG {}", arg_names.join(" ")));
+    }
+    if !env_names.is_empty() {
+        cmds.push(format!("ENV {}", env_names.join(" ")));
+    }
+
+    cmds.join("\n")
+}
+
+/// The executable or app file the start command runs, e.g. `node` for `node server.js`
+fn get_start_program(start: &StartPhase) -> Option<String> {
+    let program = start.cmd.as_ref()?.split_whitespace().next()?;
+    if program.contains('=') || program.contains('$') {
+        return None;
+    }
+
+    if program.starts_with(APP_DIR) {
+        Some(program.replacen(APP_DIR, "./", 1))
+    } else {
+        Some(program.to_string())
+    }
+}
+
+/// Mount the BuildKit secret with the build-only variables in every `RUN` command of the phases,
+/// and source it with the shell. The default shell is restored afterwards, so nothing after the phases needs the secret
+fn mount_variables_secret(phases: &str) -> String {
+    let mount = format!(
+        "RUN --mount=type=secret,id={},required=true ",
+        utils::VARIABLES_SECRET_ID
+    );
+    let phases = phases
+        .lines()
+        .map(|line| match line.strip_prefix("RUN ") {
+            Some(cmd) => format!("{}{}", mount, cmd),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "SHELL [\"/bin/sh\", \"-c\", \". /run/secrets/{} && eval \\\"$0\\\"\"]\n{}\nSHELL [\"/bin/sh\", \"-c\"]",
+        utils::VARIABLES_SECRET_ID,
+        phases
+    )
+}
+
+/// Flakes are used with `--nix-flakes` or `NIXPACKS_NIX_FLAKES`
+fn uses_nix_flakes(options: &DockerBuilderOptions, env: &Environment) -> bool {
+    options.nix_flakes || env.is_config_variable_truthy("NIX_FLAKES")
+}
+
+/// Put the packages of the flake profile on the `PATH`, and load their libraries in login shells like the base image does
+fn get_nix_flake_profile_cmds() -> String {
+    format!(
+        "ENV PATH={profile}/bin:$PATH\nRUN printf '\\nfor i in {profile}/etc/profile.d/*.sh; do\\n if [ -r $i ]; then\\n . $i\\n fi\\ndone\\n' >> /root/.profile",
+        profile = NIX_FLAKE_PROFILE
+    )
+}
+
+/// Lock the inputs of the flakes with the Nix of the host, so their `flake.lock` is written to the output directory.
+/// Without Nix on the host, the inputs are locked when the flakes are installed during the build
+fn lock_nix_flakes(dirs: &[PathBuf]) -> Result<()> {
+    if Command::new("nix").arg("--version").output().is_err() {
+        println!("Warning: Nix is not installed, so the Nix flakes are locked during the build instead of in the output directory");
+        return Ok(());
+    }
+
+    for dir in dirs {
+        let status = Command::new("nix")
+            .args([
+                "--extra-experimental-features",
+                "nix-command flakes",
+                "flake",
+                "lock",
+            ])
+            .arg(format!("path:{}", dir.display()))
+            .status()
+            .context("Locking Nix flake")?;
+        if !status.success() {
+            bail!(Message::new(MessageId::NixFlakeLockFailed).arg("dir", &dir.display()));
+        }
+    }
+
+    Ok(())
+}
+
+fn get_nix_install_cmd(
+    options: &DockerBuilderOptions,
+    env: &Environment,
+    output: &OutputDir,
+    name: &str,
+    phases: &Phases,
+) -> Result<String> {
+    // Overlays and expressions from the app are imported from where they are copied to
+    let local_files = nix_local_files_for_file(phases, name);
+    let nix_flakes = uses_nix_flakes(options, env);
+
+    // Flakes are installed from their directory, which also holds the lock file
+    let name = if nix_flakes {
+        nix_flake_dir(name)
+    } else {
+        name.to_string()
+    };
+    let nix_file_path = output
+        .get_relative_path(name)
+        .to_slash()
+        .context("Failed to convert nix file path to slash path.")?
+        .to_string();
+
+    let install_cmd = if nix_flakes {
+        // Files outside of the flake can only be imported when evaluating impurely
+        let impure = if local_files.is_empty() {
+            ""
+        } else {
+            " --impure"
+        };
+        format!(
+            "nix --extra-experimental-features 'nix-command flakes' profile install --profile {profile}{impure} path:{APP_DIR}{nix_file_path} && nix-collect-garbage -d",
+            profile = NIX_FLAKE_PROFILE,
+            impure = impure,
+            APP_DIR = APP_DIR,
+            nix_file_path = nix_file_path
+        )
+    } else {
+        format!(
+            "nix-env -if {nix_file_path} && nix-collect-garbage -d",
+            nix_file_path = nix_file_path
+        )
+    };
+    let run_cmd = match &options.nix_cache {
+        Some(nix_cache) => nix_cache.wrap_install_cmd(&install_cmd, options.nix_cache_push),
+        None => format!("RUN {}", install_cmd),
+    };
+
+    let copy_cmds = local_files
+        .iter()
+        .chain(std::iter::once(&nix_file_path))
+        .map(|path| format!("COPY {path} {path}", path = path))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(format!(
+        "{copy_cmds}\n{run_cmd}",
+        copy_cmds = copy_cmds,
+        run_cmd = run_cmd
+    ))
+}
+
 impl BuildPlan {
     fn write_assets(&self, plan: &BuildPlan, output: &OutputDir) -> Result<()> {
+        plan.verify_asset_integrity()?;
+
         if let Some(assets) = &plan.static_assets {
             if !assets.is_empty() {
                 let static_assets_path = output.get_absolute_path("assets");
@@ -270,15 +548,6 @@ impl BuildPlan {
 
         Ok(())
     }
-
-    fn all_apt_packages(&self) -> Vec<String> {
-        self.phases
-            .clone()
-            .unwrap_or_default()
-            .values()
-            .flat_map(|phase| phase.apt_pkgs.clone().unwrap_or_default())
-            .collect()
-    }
 }
 
 impl DockerfileGenerator for StartPhase {
@@ -302,27 +571,68 @@ impl DockerfileGenerator for StartPhase {
                     APP_DIR,
                 );
 
+                // The base image runs commands through a login shell, like the build stage does.
+                // Other images run them with `sh`, unless they don't have a shell
+                let entrypoint = if run_image == DEFAULT_BASE_IMAGE {
+                    "ENTRYPOINT [\"/bin/bash\", \"-l\", \"-c\"]"
+                } else if is_shellless_image(run_image) {
+                    ""
+                } else {
+                    "ENTRYPOINT [\"/bin/sh\", \"-c\"]"
+                };
+
+                // There is no shell to run commands with in these images
+                let run_true = if is_shellless_image(run_image) {
+                    ""
+                } else {
+                    "RUN true"
+                };
+
+                // Only the Nix store paths the start command needs are copied, instead of all of `/nix`
+                let nix_closure = self.nix_closure.clone().unwrap_or_default();
+                let (collect_closure_cmd, copy_closure_cmd) = if nix_closure.is_empty() {
+                    (String::new(), String::new())
+                } else {
+                    (
+                        utils::get_nix_closure_command(&nix_closure, APP_DIR),
+                        format!("COPY --from=0 {} /", utils::NIX_CLOSURE_DIR),
+                    )
+                };
+
                 // RUN true to prevent a Docker bug https://github.com/moby/moby/issues/37965#issuecomment-426853382
                 formatdoc! {"
+                  {collect_closure_cmd}
                   # start
                   FROM {run_image}
+                  {entrypoint}
                   WORKDIR {APP_DIR}
                   COPY --from=0 /etc/ssl/certs /etc/ssl/certs
-                  RUN true
+                  {copy_closure_cmd}
+                  {run_true}
                   {copy_cmd}
                   {start_cmd}
                 ",
+                collect_closure_cmd=collect_closure_cmd,
                 run_image=run_image,
+                entrypoint=entrypoint,
+                copy_closure_cmd=copy_closure_cmd,
+                run_true=run_true,
                 APP_DIR=APP_DIR,
                 copy_cmd=copy_cmd,
                 start_cmd=start_cmd,}
             }
             None => {
+                let copy_cmd = match &self.only_include_files {
+                    Some(files) => utils::get_copy_command(files, APP_DIR),
+                    None => "COPY . /app".to_string(),
+                };
+
                 formatdoc! {"
                   # start
-                  COPY . /app
+                  {}
                   {}
                 ",
+                copy_cmd,
                 start_cmd}
             }
         };
@@ -387,22 +697,17 @@ impl DockerfileGenerator for Phase {
                 file_server_config,
             );
 
-            let run_commands = [
-                phase.cmds.clone().unwrap_or_default(),
-                cache_copy_out_command,
-            ]
-            .concat()
-            .iter()
-            .map(|s| format!("RUN {}", s))
-            .collect::<Vec<_>>()
-            .join("\n");
+            let run_commands = [phase.get_cmds_with_cleanup(), cache_copy_out_command]
+                .concat()
+                .iter()
+                .map(|s| format!("RUN {}", s))
+                .collect::<Vec<_>>()
+                .join("\n");
 
             format!("{}\n{}", cache_copy_in_command, run_commands)
         } else {
             phase
-                .cmds
-                .clone()
-                .unwrap_or_default()
+                .get_cmds_with_cleanup()
                 .iter()
                 .map(|s| format!("RUN {} {}", cache_mount, s))
                 .collect::<Vec<_>>()
@@ -439,6 +744,8 @@ impl DockerfileGenerator for Phase {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nixpacks::builder::docker::nix_cache::NixCache;
+    use crate::nixpacks::nix::NIXPKGS_ARCHIVE;
 
     #[test]
     fn test_phase_generation() {
@@ -458,6 +765,94 @@ mod tests {
         assert!(dockerfile.contains("echo test"));
     }
 
+    #[test]
+    fn test_phase_generation_with_cleanup() {
+        let mut phase = Phase::new("test");
+        phase.add_cmd("echo first");
+        phase.add_cmd("echo second");
+        phase.add_cleanup_cmd("rm -rf /tmp/cache");
+
+        let dockerfile = phase
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                Some(FileServerConfig::default()),
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("echo first\n"));
+        assert!(dockerfile.contains("echo second && rm -rf /tmp/cache"));
+    }
+
+    #[test]
+    fn test_start_generation_in_scratch_image() {
+        let mut start = StartPhase::new("./server");
+        start.run_in_scratch_image();
+        start.add_file_dependency("./bin/server");
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("FROM scratch"));
+        assert!(dockerfile.contains("COPY --from=0 /app/bin/server /app/"));
+        assert!(!dockerfile.contains("RUN"));
+        assert!(!dockerfile.contains("ENTRYPOINT"));
+    }
+
+    #[test]
+    fn test_start_generation_in_custom_run_image() {
+        let mut start = StartPhase::new("./server");
+        start.run_in_image("gcr.io/distroless/cc-debian11".to_string());
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("FROM gcr.io/distroless/cc-debian11"));
+        assert!(dockerfile.contains("COPY --from=0 /etc/ssl/certs /etc/ssl/certs"));
+        assert!(dockerfile.contains("COPY --from=0 /app/ /app/"));
+        assert!(!dockerfile.contains("RUN"));
+    }
+
+    #[test]
+    fn test_start_generation_with_nix_closure() {
+        let mut start = StartPhase::new("node server.js");
+        start.run_in_slim_image();
+        start.add_file_dependency("./.next/standalone");
+        start.add_nix_closure("node");
+
+        let dockerfile = start
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let collect_closure = dockerfile.find("RUN mkdir -p /nixpacks-closure").unwrap();
+        let run_stage = dockerfile.find("FROM debian:bullseye-slim").unwrap();
+        let copy_closure = dockerfile
+            .find("COPY --from=0 /nixpacks-closure /")
+            .unwrap();
+
+        assert!(collect_closure < run_stage);
+        assert!(run_stage < copy_closure);
+        assert!(!dockerfile.contains("COPY --from=0 /nix /nix"));
+    }
+
     #[test]
     fn test_plan_generation() {
         let mut plan = BuildPlan::default();
@@ -485,4 +880,445 @@ mod tests {
         assert!(dockerfile.contains("apt-get update"));
         assert!(dockerfile.contains("wget"));
     }
+
+    #[test]
+    fn test_plan_generation_with_nix_files() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+
+            [phases.build]
+            nixPkgs = ["python3"]
+            nixFile = "build/env.nix"
+            cmds = ["npm run build"]
+            dependsOn = ["setup"]
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let setup_install = dockerfile
+            .find("RUN nix-env -if .nixpacks/nixpkgs.nix")
+            .unwrap();
+        let build_install = dockerfile
+            .find("RUN nix-env -if .nixpacks/build/env.nix")
+            .unwrap();
+        let build_phase = dockerfile.find("# build phase").unwrap();
+
+        assert!(setup_install < build_install);
+        assert!(build_install < build_phase);
+        assert_eq!(dockerfile.matches("nix-env -if").count(), 2);
+    }
+
+    #[test]
+    fn test_plan_generation_with_conflicting_nix_file_archives() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            nixFile = "env.nix"
+
+            [phases.build]
+            nixPkgs = ["python3"]
+            nixFile = "env.nix"
+            nixpkgsArchive = "21de2b973f9fee595a7a1ac4693efff791245c34"
+            dependsOn = ["setup"]
+            "#,
+        )
+        .unwrap();
+
+        let err = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap_err();
+
+        let message = err.downcast_ref::<Message>().unwrap();
+        assert_eq!(message.id, MessageId::NixFileArchiveConflict);
+        assert_eq!(message.args["file"], "env.nix");
+        assert_eq!(
+            message.args["phases"],
+            format!(
+                "build (21de2b973f9fee595a7a1ac4693efff791245c34), setup ({})",
+                NIXPKGS_ARCHIVE
+            )
+        );
+    }
+
+    #[test]
+    fn test_plan_generation_with_nix_cache() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    nix_cache: Some(NixCache::Cachix {
+                        name: "my-org".to_string(),
+                    }),
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let client_install = dockerfile.find("RUN nix-env -iA cachix").unwrap();
+        let setup_install = dockerfile
+            .find("RUN --mount=type=secret,id=nix-cache-token")
+            .unwrap();
+
+        assert!(client_install < setup_install);
+        assert!(dockerfile.contains("cachix use my-org && nix-env -if .nixpacks/nixpkgs.nix"));
+        assert!(!dockerfile.contains("cachix push"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_nix_flakes() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    nix_flakes: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("COPY .nixpacks/nixpkgs .nixpacks/nixpkgs\n"));
+        assert!(dockerfile.contains("RUN nix --extra-experimental-features 'nix-command flakes' profile install --profile /nix/var/nix/profiles/nixpacks path:/app/.nixpacks/nixpkgs && nix-collect-garbage -d"));
+        assert!(dockerfile.contains("ENV PATH=/nix/var/nix/profiles/nixpacks/bin:$PATH\n"));
+        assert!(!dockerfile.contains("nix-env -if"));
+
+        // Flakes can also be enabled with a variable
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::from_envs(vec!["NIXPACKS_NIX_FLAKES=1"]).unwrap(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains("profile install --profile /nix/var/nix/profiles/nixpacks"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_nix_expressions() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            nixExpressions = ["nix/tools.nix"]
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains("COPY nix/tools.nix nix/tools.nix\nCOPY .nixpacks/nixpkgs.nix .nixpacks/nixpkgs.nix\nRUN nix-env -if"));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions {
+                    nix_flakes: true,
+                    ..Default::default()
+                },
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(dockerfile.contains(
+            "profile install --profile /nix/var/nix/profiles/nixpacks --impure path:/app/.nixpacks/nixpkgs"
+        ));
+    }
+
+    #[test]
+    fn test_plan_generation_with_base_image() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            buildImage = "registry.example.com/nix:latest"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.starts_with("FROM registry.example.com/nix:latest\n"));
+        let nix_check = dockerfile
+            .find("RUN command -v nix-env > /dev/null || (echo 'Error [NP0058]: Nix is not installed in the base image registry.example.com/nix:latest.")
+            .unwrap();
+        assert!(nix_check < dockerfile.find("RUN nix-env -if").unwrap());
+
+        // Without Nix packages, the base image doesn't need Nix
+        let plan = BuildPlan::from_toml(r#"buildImage = "debian:bullseye""#).unwrap();
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+        assert!(!dockerfile.contains("command -v nix-env"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_build_and_runtime_variables() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+
+            [buildVariables]
+            CI = "true"
+
+            [runtimeVariables]
+            PORT = "3000"
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let build_args = dockerfile
+            .find("ARG NODE_ENV CI\nENV NODE_ENV=$NODE_ENV\n")
+            .unwrap();
+        let build_phase = dockerfile.find("# build phase").unwrap();
+        let runtime_args = dockerfile.find("ARG PORT\nENV PORT=$PORT").unwrap();
+
+        assert!(build_args < build_phase);
+        assert!(build_phase < runtime_args);
+        assert!(!dockerfile.contains("CI=$CI"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_variables_in_run_image() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+
+            [runtimeVariables]
+            PORT = "3000"
+
+            [start]
+            cmd = "node server.js"
+            runImage = "debian:bullseye-slim"
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        let run_stage = dockerfile.find("FROM debian:bullseye-slim").unwrap();
+        let run_args = dockerfile
+            .find("ARG NODE_ENV PORT\nENV NODE_ENV=$NODE_ENV PORT=$PORT")
+            .unwrap();
+
+        assert!(run_stage < run_args);
+    }
+
+    #[test]
+    fn test_plan_generation_with_run_image_override() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+
+            [start]
+            cmd = "node index.js"
+            runImage = "debian:bullseye-slim"
+            "#,
+        )
+        .unwrap();
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(dockerfile.contains("$(command -v node)"));
+        assert!(dockerfile.contains("FROM debian:bullseye-slim\nENTRYPOINT [\"/bin/sh\", \"-c\"]"));
+        assert!(dockerfile.contains("COPY --from=0 /nixpacks-closure /"));
+        assert!(dockerfile.contains("CMD [\"node index.js\"]"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_exposed_port() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [start]
+            cmd = "npm start"
+            port = 3000
+            "#,
+        )
+        .unwrap();
+
+        let generate = |env: &Environment| {
+            plan.generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                env,
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap()
+        };
+
+        assert!(!generate(&Environment::default()).contains("EXPOSE"));
+        assert!(
+            generate(&Environment::from_envs(vec!["NIXPACKS_EXPOSE_PORT=1"]).unwrap())
+                .contains("EXPOSE 3000")
+        );
+    }
+
+    #[test]
+    fn test_plan_generation_with_many_variables() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        plan.add_variables(
+            (0..150)
+                .map(|i| (format!("VAR_{}", i), format!("value ${}", i)))
+                .collect(),
+        );
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        // The values are passed in the environment of `docker build`, and the variables are still set with ENV
+        assert!(!dockerfile.contains("value"));
+        assert!(dockerfile.contains("ARG VAR_0 VAR_1 "));
+        assert!(dockerfile.contains("ENV VAR_0=$VAR_0 VAR_1=$VAR_1 "));
+        assert!(!dockerfile.contains("--mount=type=secret"));
+    }
+
+    #[test]
+    fn test_plan_generation_with_many_build_variables() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "./server"
+            runImage = "debian:bullseye-slim"
+            "#,
+        )
+        .unwrap();
+        plan.add_variables(
+            (0..150)
+                .map(|i| (format!("VAR_{}", i), i.to_string()))
+                .collect(),
+        );
+        plan.add_build_variables(EnvironmentVariables::from([(
+            "NPM_TOKEN".to_string(),
+            "npm_secret".to_string(),
+        )]));
+
+        let dockerfile = plan
+            .generate_dockerfile(
+                &DockerBuilderOptions::default(),
+                &Environment::default(),
+                &OutputDir::default(),
+                None,
+            )
+            .unwrap();
+
+        assert!(!dockerfile.contains("npm_secret"));
+        assert!(!dockerfile.contains("NPM_TOKEN"));
+        assert!(dockerfile.contains(
+            "RUN --mount=type=secret,id=nixpacks-variables,required=true  npm run build"
+        ));
+
+        // The final stage has the plan variables, but neither the secret nor the build-only variables
+        let final_stage = &dockerfile[dockerfile.find("FROM debian:bullseye-slim").unwrap()..];
+        assert!(!final_stage.contains("nixpacks-variables"));
+        assert!(!final_stage.contains("SHELL"));
+        assert!(final_stage.contains("ENV VAR_0=$VAR_0 "));
+        assert!(final_stage.contains("CMD [\"./server\"]"));
+
+        assert_eq!(
+            utils::get_secret_variables(&plan),
+            EnvironmentVariables::from([("NPM_TOKEN".to_string(), "npm_secret".to_string())])
+        );
+    }
 }
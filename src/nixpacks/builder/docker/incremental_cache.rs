@@ -1,3 +1,4 @@
+use crate::nixpacks::messages::{Message, MessageId};
 use std::{
     fs::{self},
     path::PathBuf,
@@ -89,7 +90,7 @@ impl IncrementalCache {
                 .context("Create incremental cache image")?;
 
             if !result.success() {
-                bail!("Creating incremental cache image failed")
+                bail!(Message::new(MessageId::IncrementalCacheFailed))
             }
         }
 
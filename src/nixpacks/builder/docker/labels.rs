@@ -0,0 +1,143 @@
+use super::utils;
+use crate::nixpacks::plan::BuildPlan;
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Values that can be used in label templates as `{name}`
+#[derive(Debug, Clone, Default)]
+pub struct LabelContext {
+    pub git_sha: String,
+    pub plan_hash: String,
+    pub providers: String,
+    pub build_time: String,
+}
+
+impl LabelContext {
+    pub fn new(app_src: &str, plan: &BuildPlan) -> Result<Self> {
+        let providers = plan
+            .variables
+            .clone()
+            .unwrap_or_default()
+            .get("NIXPACKS_METADATA")
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Self {
+            git_sha: get_git_sha(app_src).unwrap_or_default(),
+            plan_hash: utils::get_plan_hash(plan)?,
+            providers,
+            build_time: format_rfc3339(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+        })
+    }
+
+    pub fn expand(&self, template: &str) -> String {
+        template
+            .replace("{git_sha}", &self.git_sha)
+            .replace("{plan_hash}", &self.plan_hash)
+            .replace("{providers}", &self.providers)
+            .replace("{build_time}", &self.build_time)
+    }
+}
+
+/// Read a JSON or TOML file of label names to templates
+pub fn read_label_templates<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, String>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Reading label file {}", path.display()))?;
+
+    let labels = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&contents)?,
+        Some("toml") => toml::from_str(&contents)?,
+        _ => bail!(
+            "Unknown label file type {}. Expected .json or .toml",
+            path.display()
+        ),
+    };
+
+    Ok(labels)
+}
+
+/// Read and expand the label templates, returning `name=value` pairs for `docker build --label`
+pub fn get_labels_from_file<P: AsRef<Path>>(
+    path: P,
+    context: &LabelContext,
+) -> Result<Vec<String>> {
+    Ok(read_label_templates(path)?
+        .iter()
+        .map(|(name, template)| format!("{}={}", name, context.expand(template)))
+        .collect())
+}
+
+fn get_git_sha(app_src: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(app_src)
+        .output()
+        .ok()?;
+
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp
+fn format_rfc3339(secs: u64) -> String {
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // Convert days since the epoch to a civil date (https://howardhinnant.github.io/date_algorithms.html)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(1_668_470_745), "2022-11-15T00:05:45Z");
+    }
+
+    #[test]
+    fn test_expand() {
+        let context = LabelContext {
+            git_sha: "abc123".to_string(),
+            plan_hash: "0011".to_string(),
+            providers: "node".to_string(),
+            build_time: "2022-11-15T00:05:45Z".to_string(),
+        };
+
+        assert_eq!(
+            context.expand("{providers}@{git_sha} ({plan_hash}) {build_time} {unknown}"),
+            "node@abc123 (0011) 2022-11-15T00:05:45Z {unknown}"
+        );
+    }
+}
@@ -1,13 +1,17 @@
+use self::nix_cache::NixCache;
 use super::ImageBuilder;
 
 #[derive(Clone, Default, Debug)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct DockerBuilderOptions {
     pub name: Option<String>,
+    pub random_name: bool,
     pub out_dir: Option<String>,
+    pub tmp_dir: Option<String>,
     pub print_dockerfile: bool,
     pub tags: Vec<String>,
     pub labels: Vec<String>,
+    pub label_file: Option<String>,
     pub quiet: bool,
     pub cache_key: Option<String>,
     pub no_cache: bool,
@@ -18,11 +22,17 @@ pub struct DockerBuilderOptions {
     pub no_error_without_start: bool,
     pub incremental_cache_image: Option<String>,
     pub verbose: bool,
+    pub nix_cache: Option<NixCache>,
+    pub nix_cache_push: bool,
+    pub nix_flakes: bool,
 }
 
 mod cache;
 pub mod docker_image_builder;
-mod dockerfile_generation;
+pub mod dockerfile_generation;
 pub mod file_server;
 pub mod incremental_cache;
+pub mod labels;
+pub mod nix_cache;
 pub mod utils;
+pub mod warm_container;
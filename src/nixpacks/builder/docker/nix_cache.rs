@@ -0,0 +1,156 @@
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+/// Environment variable holding the token used to authenticate with the binary cache
+pub const NIX_CACHE_TOKEN_ENV: &str = "NIXPACKS_NIX_CACHE_TOKEN";
+
+/// ID of the BuildKit secret the token is mounted as, so it never ends up in an image layer
+pub const NIX_CACHE_SECRET_ID: &str = "nix-cache-token";
+
+// The cache clients are installed from the 24.11 release, which packages both cachix and attic-client.
+// The release tag never moves, so the same client is installed by every build
+const NIX_CACHE_CLIENT_ARCHIVE: &str = "https://github.com/NixOS/nixpkgs/archive/24.11.tar.gz";
+
+const ATTIC_SERVER_NAME: &str = "nixpacks";
+
+/// A binary cache used to substitute Nix packages, and optionally to push newly built store paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NixCache {
+    /// `cachix:<name>`
+    Cachix { name: String },
+    /// `attic:<endpoint>/<cache>`
+    Attic { endpoint: String, cache: String },
+}
+
+impl FromStr for NixCache {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("cachix", name)) if !name.is_empty() => Ok(NixCache::Cachix {
+                name: name.to_string(),
+            }),
+            Some(("attic", url)) => match url.trim_end_matches('/').rsplit_once('/') {
+                Some((endpoint, cache)) if !cache.is_empty() && endpoint.contains("://") => {
+                    Ok(NixCache::Attic {
+                        endpoint: endpoint.to_string(),
+                        cache: cache.to_string(),
+                    })
+                }
+                _ => bail!(
+                    "Invalid attic cache {}. Expected attic:<endpoint>/<cache>",
+                    s
+                ),
+            },
+            _ => bail!(
+                "Invalid Nix cache {}. Expected cachix:<name> or attic:<endpoint>/<cache>",
+                s
+            ),
+        }
+    }
+}
+
+impl NixCache {
+    /// Dockerfile instruction that installs the cache client
+    pub fn get_client_install_cmd(&self) -> String {
+        let client = match self {
+            NixCache::Cachix { .. } => "cachix",
+            NixCache::Attic { .. } => "attic-client",
+        };
+
+        format!("RUN nix-env -iA {} -f {}", client, NIX_CACHE_CLIENT_ARCHIVE)
+    }
+
+    /// Wrap a Nix install command so packages are substituted from the cache,
+    /// and optionally pushed to it afterwards.
+    ///
+    /// Credentials only exist for the duration of the command and are removed before
+    /// the layer is committed.
+    pub fn wrap_install_cmd(&self, cmd: &str, push: bool) -> String {
+        let token = format!(
+            "NIX_CACHE_TOKEN=\"$(cat /run/secrets/{} 2>/dev/null || true)\"",
+            NIX_CACHE_SECRET_ID
+        );
+
+        let (configure, push_cmd, cleanup) = match self {
+            NixCache::Cachix { name } => (
+                format!(
+                    "if [ -n \"$NIX_CACHE_TOKEN\" ]; then export CACHIX_AUTH_TOKEN=\"$NIX_CACHE_TOKEN\"; fi && cachix use {}",
+                    name
+                ),
+                format!("cachix push {} $(nix-store -qR /root/.nix-profile)", name),
+                "rm -f /etc/nix/netrc /root/.config/nix/netrc",
+            ),
+            NixCache::Attic { endpoint, cache } => (
+                format!(
+                    "attic login {server} {} $NIX_CACHE_TOKEN && attic use {server}:{}",
+                    endpoint,
+                    cache,
+                    server = ATTIC_SERVER_NAME
+                ),
+                format!(
+                    "attic push {}:{} $(nix-store -qR /root/.nix-profile)",
+                    ATTIC_SERVER_NAME, cache
+                ),
+                "rm -rf /root/.config/attic /root/.config/nix/netrc",
+            ),
+        };
+
+        let cmds = if push {
+            vec![token, configure, cmd.to_string(), push_cmd]
+        } else {
+            vec![token, configure, cmd.to_string()]
+        };
+
+        format!(
+            "RUN --mount=type=secret,id={} {} && {}",
+            NIX_CACHE_SECRET_ID,
+            cmds.join(" && "),
+            cleanup
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nix_cache() {
+        assert_eq!(
+            "cachix:my-org".parse::<NixCache>().unwrap(),
+            NixCache::Cachix {
+                name: "my-org".to_string()
+            }
+        );
+        assert_eq!(
+            "attic:https://cache.example.com/my-cache"
+                .parse::<NixCache>()
+                .unwrap(),
+            NixCache::Attic {
+                endpoint: "https://cache.example.com".to_string(),
+                cache: "my-cache".to_string()
+            }
+        );
+
+        assert!("cachix:".parse::<NixCache>().is_err());
+        assert!("attic:my-cache".parse::<NixCache>().is_err());
+        assert!("s3://bucket".parse::<NixCache>().is_err());
+    }
+
+    #[test]
+    fn test_wrap_install_cmd() {
+        let cache = NixCache::Cachix {
+            name: "my-org".to_string(),
+        };
+
+        let cmd = cache.wrap_install_cmd("nix-env -if .nixpacks/nixpkgs.nix", false);
+        assert!(cmd.starts_with("RUN --mount=type=secret,id=nix-cache-token "));
+        assert!(cmd.contains("cachix use my-org && nix-env -if .nixpacks/nixpkgs.nix"));
+        assert!(!cmd.contains("cachix push"));
+        assert!(cmd.ends_with("&& rm -f /etc/nix/netrc /root/.config/nix/netrc"));
+
+        let cmd = cache.wrap_install_cmd("nix-env -if .nixpacks/nixpkgs.nix", true);
+        assert!(cmd.contains("&& cachix push my-org $(nix-store -qR /root/.nix-profile)"));
+    }
+}
@@ -1,4 +1,21 @@
 use super::cache::sanitize_cache_key;
+use crate::nixpacks::{environment::EnvironmentVariables, plan::BuildPlan};
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const DEFAULT_IMAGE_NAME: &str = "nixpacks-app";
+
+// Above these, passing every variable with `--build-arg` can exceed the OS argument length limit
+const MAX_BUILD_ARGS: usize = 100;
+const MAX_BUILD_ARGS_SIZE: usize = 64 * 1024;
+
+/// ID of the BuildKit secret the build-only variables above these limits are passed in
+pub const VARIABLES_SECRET_ID: &str = "nixpacks-variables";
+
+/// Variables the docker CLI reads itself, so they can't be handed to it through its environment
+const DOCKER_CLI_VARIABLES: &[&str] = &["PATH", "HOME", "TMPDIR"];
+const DOCKER_CLI_VARIABLE_PREFIXES: &[&str] = &["DOCKER_", "BUILDKIT_", "BUILDX_"];
 
 pub fn get_cache_mount(
     cache_key: &Option<String>,
@@ -24,29 +41,149 @@ pub fn get_cache_mount(
     }
 }
 
+/// Files in the app root are copied with a single `COPY`.
+/// Nested paths and directories are copied separately so they keep their place in the app.
 pub fn get_copy_command(files: &[String], app_dir: &str) -> String {
-    if files.is_empty() {
-        String::new()
-    } else {
-        format!("COPY {} {}", files.join(" "), app_dir)
+    let (nested, root): (Vec<_>, Vec<_>) = files
+        .iter()
+        .map(|file| {
+            file.strip_prefix("./")
+                .filter(|file| !file.is_empty())
+                .unwrap_or(file)
+        })
+        .partition(|file| file.contains('/'));
+
+    let mut cmds = Vec::new();
+    if !root.is_empty() {
+        cmds.push(format!("COPY {} {}", root.join(" "), app_dir));
     }
+    for path in nested {
+        let path = path.trim_end_matches('/');
+        cmds.push(format!(
+            "COPY {} {}/{}",
+            path,
+            app_dir.trim_end_matches('/'),
+            path
+        ));
+    }
+
+    cmds.join("\n")
 }
 
+/// App files are copied into the app directory in order, each with its own `COPY`,
+/// so files that rarely change are cached in their own layer when listed first.
+/// Absolute paths outside of the app, e.g. `/nix`, are copied to the same path first,
+/// since they are larger and change less often than the app files.
 pub fn get_copy_from_command(from: &str, files: &[String], app_dir: &str) -> String {
     if files.is_empty() {
-        format!("COPY --from=0 {} {}", app_dir, app_dir)
-    } else {
-        format!(
+        return format!("COPY --from=0 {} {}", app_dir, app_dir);
+    }
+
+    let (absolute, app_files): (Vec<_>, Vec<_>) = files
+        .iter()
+        .partition(|f| f.starts_with('/') && !f.starts_with(app_dir));
+
+    let mut cmds = Vec::new();
+    for path in absolute {
+        cmds.push(format!("COPY --from={} {} {}", from, path, path));
+    }
+    for file in app_files {
+        cmds.push(format!(
             "COPY --from={} {} {}",
             from,
-            files
-                .iter()
-                .map(|f| f.replace("./", app_dir))
-                .collect::<Vec<_>>()
-                .join(" "),
+            file.replace("./", app_dir),
             app_dir
-        )
+        ));
+    }
+
+    cmds.join("\n")
+}
+
+/// Directory in the build stage the Nix store paths a run image needs are collected in
+pub const NIX_CLOSURE_DIR: &str = "/nixpacks-closure";
+
+const NIX_STORE_PATH_PATTERN: &str = "/nix/store/[0-9a-z]{32}-[A-Za-z0-9+._?=-]+";
+
+/// Collect the Nix store paths that executables or app files reference, along with everything those paths depend on,
+/// so only they have to be copied into a run image.
+/// Entries starting with `/` or `./` are files, anything else is an executable on the `PATH`,
+/// which is linked into `/usr/local/bin`.
+pub fn get_nix_closure_command(entries: &[String], app_dir: &str) -> String {
+    let (files, executables): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .partition(|entry| entry.starts_with('/') || entry.starts_with("./"));
+
+    let bin_dir = format!("{}/usr/local/bin", NIX_CLOSURE_DIR);
+    let mut cmds = vec![format!("mkdir -p {}", bin_dir)];
+    let mut sources = Vec::new();
+    for executable in executables {
+        let link = format!("{}/{}", bin_dir, executable);
+        cmds.push(format!(
+            "ln -s \"$(readlink -f \"$(command -v {})\")\" {}",
+            executable, link
+        ));
+        sources.push(format!("readlink -f {}", link));
+    }
+    for file in files {
+        sources.push(format!(
+            "grep -rhoaE '{}' {}",
+            NIX_STORE_PATH_PATTERN,
+            file.replacen("./", app_dir, 1)
+        ));
+    }
+
+    cmds.push(format!(
+        "({}) | grep -oE '{}' | sort -u | while read -r path; do [ -e \"$path\" ] && echo \"$path\"; done | xargs -r nix-store -qR | xargs -r cp -a --parents -t {}",
+        sources.join("; "),
+        NIX_STORE_PATH_PATTERN,
+        NIX_CLOSURE_DIR
+    ));
+
+    format!("RUN {}", cmds.join(" && "))
+}
+
+/// Whether passing the variables as `--build-arg NAME=value` could exceed the command line length limit
+pub fn exceeds_build_arg_limits(variables: &EnvironmentVariables) -> bool {
+    let size: usize = variables
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+
+    variables.len() > MAX_BUILD_ARGS || size > MAX_BUILD_ARGS_SIZE
+}
+
+/// Build-only variables passed to the build in a BuildKit secret file instead of with `--build-arg`.
+/// This only happens for plans with many or large variables. The secret is only mounted while the
+/// phase commands run, so the values never end up in the Dockerfile or the image.
+pub fn get_secret_variables(plan: &BuildPlan) -> EnvironmentVariables {
+    if !exceeds_build_arg_limits(&plan.all_variables()) {
+        return EnvironmentVariables::new();
     }
+
+    let variables = plan.variables.clone().unwrap_or_default();
+    let runtime_variables = plan.runtime_variables.clone().unwrap_or_default();
+    plan.build_variables
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| !variables.contains_key(name) && !runtime_variables.contains_key(name))
+        .collect()
+}
+
+/// Variables the docker CLI needs itself, which are always passed as `--build-arg NAME=value`
+pub fn is_docker_cli_variable(name: &str) -> bool {
+    DOCKER_CLI_VARIABLES.contains(&name)
+        || DOCKER_CLI_VARIABLE_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// A shell script exporting the secret variables, which every phase command sources from the mounted secret
+pub fn get_variables_file(variables: &EnvironmentVariables) -> String {
+    variables
+        .iter()
+        .map(|(name, value)| format!("export {}={}\n", name, shell_words::quote(value)))
+        .collect()
 }
 
 pub fn get_exec_command(command: &str) -> String {
@@ -55,6 +192,39 @@ pub fn get_exec_command(command: &str) -> String {
     format!("CMD [\"{}\"]", params)
 }
 
+/// Create a stable image name from the app directory name and a hash of the plan (e.g. `myapp-3f9c2a`),
+/// so that repeated builds of the same app reuse the same tag.
+pub fn get_default_image_name(app_src: &str, plan: &BuildPlan) -> Result<String> {
+    let dir_name = Path::new(app_src)
+        .canonicalize()?
+        .file_name()
+        .map(|name| sanitize_image_name(&name.to_string_lossy()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_IMAGE_NAME.to_string());
+
+    let plan_hash = get_plan_hash(plan)?;
+
+    Ok(format!("{}-{}", dir_name, &plan_hash[..6]))
+}
+
+/// Hex encoded SHA-256 of the serialized plan, which is the same across Nixpacks versions and platforms
+pub fn get_plan_hash(plan: &BuildPlan) -> Result<String> {
+    Ok(format!("{:x}", Sha256::digest(plan.to_json()?.as_bytes())))
+}
+
+/// Docker image names may only contain lowercase alphanumerics and separators
+fn sanitize_image_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect::<String>()
+        .trim_matches(|c| matches!(c, '.' | '_' | '-'))
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +261,29 @@ mod tests {
             format!("COPY {} {}", files.join(" "), app_dir),
             get_copy_command(&files, app_dir)
         );
+
+        let files = vec![
+            "package.json".to_string(),
+            "packages/api/".to_string(),
+            "./packages/shared/index.js".to_string(),
+        ];
+        assert_eq!(
+            "COPY package.json /app/\nCOPY packages/api /app/packages/api\nCOPY packages/shared/index.js /app/packages/shared/index.js",
+            get_copy_command(&files, "/app/")
+        );
+    }
+
+    #[test]
+    fn test_get_nix_closure_command() {
+        assert_eq!(
+            get_nix_closure_command(&["node".to_string(), "./bin/server".to_string()], "/app/"),
+            "RUN mkdir -p /nixpacks-closure/usr/local/bin \
+            && ln -s \"$(readlink -f \"$(command -v node)\")\" /nixpacks-closure/usr/local/bin/node \
+            && (readlink -f /nixpacks-closure/usr/local/bin/node; grep -rhoaE '/nix/store/[0-9a-z]{32}-[A-Za-z0-9+._?=-]+' /app/bin/server) \
+            | grep -oE '/nix/store/[0-9a-z]{32}-[A-Za-z0-9+._?=-]+' | sort -u \
+            | while read -r path; do [ -e \"$path\" ] && echo \"$path\"; done \
+            | xargs -r nix-store -qR | xargs -r cp -a --parents -t /nixpacks-closure"
+        );
     }
 
     #[test]
@@ -104,9 +297,120 @@ mod tests {
             get_copy_from_command(from, &[], app_dir)
         );
         assert_eq!(
-            format!("COPY --from={} {} {}", from, files.join(" "), app_dir),
+            format!(
+                "COPY --from={from} file1 {app_dir}\nCOPY --from={from} file2 {app_dir}",
+                from = from,
+                app_dir = app_dir
+            ),
             get_copy_from_command(from, &files, app_dir)
         );
+        assert_eq!(
+            "COPY --from=0 /nix /nix\nCOPY --from=0 /app/.next/standalone /app/",
+            get_copy_from_command(
+                from,
+                &["./.next/standalone".to_string(), "/nix".to_string()],
+                "/app/"
+            )
+        );
+    }
+
+    #[test]
+    fn test_exceeds_build_arg_limits() {
+        let few = EnvironmentVariables::from([("A".to_string(), "1".to_string())]);
+        assert!(!exceeds_build_arg_limits(&few));
+
+        let many: EnvironmentVariables = (0..150)
+            .map(|i| (format!("VAR_{}", i), i.to_string()))
+            .collect();
+        assert!(exceeds_build_arg_limits(&many));
+
+        let large = EnvironmentVariables::from([("BIG".to_string(), "x".repeat(70 * 1024))]);
+        assert!(exceeds_build_arg_limits(&large));
+    }
+
+    #[test]
+    fn test_get_secret_variables() {
+        let mut plan = BuildPlan::default();
+        plan.add_build_variables(EnvironmentVariables::from([(
+            "NPM_TOKEN".to_string(),
+            "token".to_string(),
+        )]));
+        assert!(get_secret_variables(&plan).is_empty());
+
+        // Only build-only variables are passed in the secret
+        plan.add_variables(
+            (0..150)
+                .map(|i| (format!("VAR_{}", i), i.to_string()))
+                .collect(),
+        );
+        plan.add_runtime_variables(EnvironmentVariables::from([(
+            "PORT".to_string(),
+            "8080".to_string(),
+        )]));
+        assert_eq!(
+            get_secret_variables(&plan),
+            EnvironmentVariables::from([("NPM_TOKEN".to_string(), "token".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_is_docker_cli_variable() {
+        assert!(is_docker_cli_variable("PATH"));
+        assert!(is_docker_cli_variable("DOCKER_HOST"));
+        assert!(!is_docker_cli_variable("NODE_ENV"));
+    }
+
+    #[test]
+    fn test_get_variables_file() {
+        let variables = EnvironmentVariables::from([
+            ("CERT".to_string(), "line1\nline2".to_string()),
+            ("GREETING".to_string(), "it's $HOME".to_string()),
+        ]);
+        assert_eq!(
+            get_variables_file(&variables),
+            "export CERT='line1\nline2'\nexport GREETING='it'\\''s $HOME'\n"
+        );
+    }
+
+    #[test]
+    fn test_get_default_image_name() {
+        let plan = BuildPlan::default();
+        let name = get_default_image_name("./examples/node-npm", &plan).unwrap();
+
+        assert!(name.starts_with("node-npm-"));
+        assert_eq!(name.len(), "node-npm-".len() + 6);
+        assert_eq!(
+            name,
+            get_default_image_name("./examples/node-npm", &plan).unwrap()
+        );
+
+        let mut other_plan = BuildPlan::default();
+        other_plan.set_start_phase(crate::nixpacks::plan::phase::StartPhase::new("npm start"));
+        assert_ne!(
+            name,
+            get_default_image_name("./examples/node-npm", &other_plan).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_plan_hash() {
+        let plan = BuildPlan::default();
+        let hash = get_plan_hash(&plan).unwrap();
+
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash, get_plan_hash(&BuildPlan::default()).unwrap());
+
+        let mut other_plan = BuildPlan::default();
+        other_plan.set_start_phase(crate::nixpacks::plan::phase::StartPhase::new("npm start"));
+        assert_ne!(hash, get_plan_hash(&other_plan).unwrap());
+    }
+
+    #[test]
+    fn test_sanitize_image_name() {
+        assert_eq!(sanitize_image_name("my-app"), "my-app");
+        assert_eq!(sanitize_image_name("My App"), "my-app");
+        assert_eq!(sanitize_image_name("_app_"), "app");
     }
 
     #[test]
@@ -0,0 +1,397 @@
+use crate::nixpacks::{
+    environment::EnvironmentVariables,
+    files,
+    logger::Logger,
+    messages::{Message, MessageId},
+    plan::{phase::Phase, BuildPlan},
+};
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, SystemTime},
+};
+
+const APP_DIR: &str = "/app";
+const START_PID_FILE: &str = "/tmp/nixpacks-up.pid";
+
+#[derive(Clone, Debug)]
+pub struct WarmContainerOptions {
+    pub name: String,
+    pub ports: Vec<String>,
+    pub poll_interval: Duration,
+}
+
+/// Modification times of every file in the app, keyed by the path relative to the app
+pub type FileSnapshot = BTreeMap<PathBuf, SystemTime>;
+
+/// Files that differ between two snapshots
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FileChanges {
+    pub changed: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.changed.iter().chain(self.removed.iter())
+    }
+}
+
+/// A long running container with the plan's Nix environment installed.
+///
+/// Source changes are copied into the container, and only the phases affected
+/// by the changed files are run again before the start command is restarted.
+pub struct WarmContainer {
+    logger: Logger,
+    app_src: PathBuf,
+    phases: Vec<Phase>,
+    build_variables: EnvironmentVariables,
+    start_cmd: Option<String>,
+    options: WarmContainerOptions,
+}
+
+impl WarmContainer {
+    pub fn new(app_src: &Path, plan: &BuildPlan, options: WarmContainerOptions) -> Result<Self> {
+        Ok(Self {
+            logger: Logger::new(),
+            app_src: app_src.to_path_buf(),
+            phases: plan
+                .get_sorted_phases()?
+                .into_iter()
+                .filter(Phase::runs_docker_commands)
+                .collect(),
+            build_variables: plan
+                .build_variables
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(name, _)| {
+                    !plan
+                        .variables
+                        .as_ref()
+                        .map_or(false, |variables| variables.contains_key(name))
+                })
+                .collect(),
+            start_cmd: plan.start_phase.clone().unwrap_or_default().cmd,
+            options,
+        })
+    }
+
+    /// Start the container from an image built from the plan and run the start command
+    pub fn start(&self, image: &str) -> Result<()> {
+        // Remove a container left over from a previous session
+        self.remove();
+
+        let mut docker_run_cmd = Command::new("docker");
+        docker_run_cmd
+            .arg("run")
+            .arg("--detach")
+            .arg("--name")
+            .arg(&self.options.name);
+        for port in &self.options.ports {
+            docker_run_cmd.arg("--publish").arg(port);
+        }
+        docker_run_cmd.arg(image).arg("sleep infinity");
+
+        let status = docker_run_cmd
+            .stdout(Stdio::null())
+            .status()
+            .context("Starting warm container")?;
+        if !status.success() {
+            bail!(Message::new(MessageId::WarmContainerFailed).arg("name", &self.options.name));
+        }
+
+        self.restart_app()
+    }
+
+    /// Poll the app for changes until the process is interrupted, then remove the container
+    pub async fn watch(&self) -> Result<()> {
+        let mut snapshot = get_file_snapshot(&self.app_src)?;
+
+        loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => break,
+                () = tokio::time::sleep(self.options.poll_interval) => {}
+            }
+
+            let new_snapshot = get_file_snapshot(&self.app_src)?;
+            let changes = diff_file_snapshots(&snapshot, &new_snapshot);
+            snapshot = new_snapshot;
+            if changes.is_empty() {
+                continue;
+            }
+
+            if let Err(e) = self.apply_changes(&changes) {
+                eprintln!("{:?}", e);
+            }
+        }
+
+        self.logger.log_section("Stopping warm container");
+        self.remove();
+
+        Ok(())
+    }
+
+    fn apply_changes(&self, changes: &FileChanges) -> Result<()> {
+        self.logger.log_section(&format!(
+            "Syncing {} changed file(s)",
+            changes.changed.len() + changes.removed.len()
+        ));
+        self.sync_files(changes)?;
+
+        for phase in get_phases_to_rerun(&self.phases, changes) {
+            self.logger
+                .log_step(&format!("Running {}", phase.get_name()));
+            let cmds = phase.get_cmds_with_cleanup();
+            if !cmds.is_empty() {
+                self.exec_with_env(&cmds.join(" && "), &self.build_variables)?;
+            }
+        }
+
+        self.restart_app()
+    }
+
+    fn sync_files(&self, changes: &FileChanges) -> Result<()> {
+        for path in &changes.changed {
+            let container_path = get_container_path(path);
+            if let Some(parent) = Path::new(&container_path).parent() {
+                self.exec(&format!("mkdir -p '{}'", parent.display()))?;
+            }
+
+            let status = Command::new("docker")
+                .arg("cp")
+                .arg(self.app_src.join(path))
+                .arg(format!("{}:{}", self.options.name, container_path))
+                .status()
+                .context("Copying file into warm container")?;
+            if !status.success() {
+                bail!(Message::new(MessageId::WarmContainerFailed).arg("name", &self.options.name));
+            }
+        }
+
+        if !changes.removed.is_empty() {
+            let paths = changes
+                .removed
+                .iter()
+                .map(|path| format!("'{}'", get_container_path(path)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.exec(&format!("rm -f {}", paths))?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop the running start command, if any, and run it again in the background
+    fn restart_app(&self) -> Result<()> {
+        let start_cmd = match &self.start_cmd {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+
+        // The start command runs in its own process group so all of its children are stopped
+        self.exec(&format!(
+            "if [ -f {pid} ]; then kill -TERM -- -$(cat {pid}) 2>/dev/null; rm -f {pid}; fi",
+            pid = START_PID_FILE
+        ))?;
+
+        let status = Command::new("docker")
+            .arg("exec")
+            .arg("--detach")
+            .arg("--workdir")
+            .arg(APP_DIR)
+            .arg(&self.options.name)
+            .arg("setsid")
+            .arg("bash")
+            .arg("-l")
+            .arg("-c")
+            .arg(format!(
+                "echo $$ > {} && exec {} > /proc/1/fd/1 2> /proc/1/fd/2",
+                START_PID_FILE, start_cmd
+            ))
+            .status()
+            .context("Starting app in warm container")?;
+        if !status.success() {
+            bail!(Message::new(MessageId::WarmContainerFailed).arg("name", &self.options.name));
+        }
+
+        self.logger.log_step(&format!("Started {}", start_cmd));
+        Ok(())
+    }
+
+    fn exec(&self, cmd: &str) -> Result<()> {
+        self.exec_with_env(cmd, &EnvironmentVariables::new())
+    }
+
+    /// Build variables are not set in the image, so they are passed to the phase commands directly
+    fn exec_with_env(&self, cmd: &str, variables: &EnvironmentVariables) -> Result<()> {
+        let mut docker_exec_cmd = Command::new("docker");
+        docker_exec_cmd.arg("exec");
+        for (name, value) in variables {
+            docker_exec_cmd
+                .arg("--env")
+                .arg(format!("{}={}", name, value));
+        }
+
+        let status = docker_exec_cmd
+            .arg("--workdir")
+            .arg(APP_DIR)
+            .arg(&self.options.name)
+            .arg("bash")
+            .arg("-l")
+            .arg("-c")
+            .arg(cmd)
+            .status()
+            .context("Running command in warm container")?;
+
+        if !status.success() {
+            bail!(Message::new(MessageId::WarmContainerCommandFailed).arg("cmd", cmd));
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self) {
+        let _ = Command::new("docker")
+            .arg("rm")
+            .arg("--force")
+            .arg(&self.options.name)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+fn get_container_path(path: &Path) -> String {
+    format!("{}/{}", APP_DIR, path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Snapshot the app files that are copied into the image, which also leaves out the ones in `.dockerignore`
+pub fn get_file_snapshot(app_src: &Path) -> Result<FileSnapshot> {
+    let mut snapshot = FileSnapshot::new();
+
+    let walker = files::app_files_walker(app_src)
+        .add_custom_ignore_filename(".dockerignore")
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    for entry in walker.filter_map(Result::ok) {
+        if !entry.file_type().map_or(false, |t| t.is_file()) {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+        let path = entry.path().strip_prefix(app_src)?.to_path_buf();
+        snapshot.insert(path, modified);
+    }
+
+    Ok(snapshot)
+}
+
+pub fn diff_file_snapshots(old: &FileSnapshot, new: &FileSnapshot) -> FileChanges {
+    FileChanges {
+        changed: new
+            .iter()
+            .filter(|(path, modified)| old.get(*path) != Some(*modified))
+            .map(|(path, _)| path.clone())
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|path| !new.contains_key(*path))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Mirror Docker layer caching: the first phase that copies a changed file
+/// and every phase after it are run again.
+pub fn get_phases_to_rerun(phases: &[Phase], changes: &FileChanges) -> Vec<Phase> {
+    let first = phases.iter().position(|phase| {
+        let files = phase
+            .only_include_files
+            .clone()
+            .unwrap_or_else(|| vec![".".to_string()]);
+
+        changes
+            .paths()
+            .any(|path| files.iter().any(|file| path_is_included(path, file)))
+    });
+
+    match first {
+        Some(i) => phases[i..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+fn path_is_included(path: &Path, file: &str) -> bool {
+    let file = file.trim_start_matches("./").trim_end_matches('/');
+    file == "." || file.is_empty() || path.starts_with(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_phases() -> Vec<Phase> {
+        let mut install = Phase::install(Some("npm ci".to_string()));
+        install.only_include_files = Some(vec!["package.json".to_string(), "src/".to_string()]);
+        let build = Phase::build(Some("npm run build".to_string()));
+
+        vec![install, build]
+    }
+
+    fn changes(paths: &[&str]) -> FileChanges {
+        FileChanges {
+            changed: paths.iter().map(PathBuf::from).collect(),
+            removed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_diff_file_snapshots() {
+        let old = FileSnapshot::from([
+            (PathBuf::from("a"), SystemTime::UNIX_EPOCH),
+            (PathBuf::from("b"), SystemTime::UNIX_EPOCH),
+        ]);
+        let new = FileSnapshot::from([
+            (
+                PathBuf::from("a"),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1),
+            ),
+            (PathBuf::from("c"), SystemTime::UNIX_EPOCH),
+        ]);
+
+        assert_eq!(
+            diff_file_snapshots(&old, &new),
+            FileChanges {
+                changed: vec![PathBuf::from("a"), PathBuf::from("c")],
+                removed: vec![PathBuf::from("b")],
+            }
+        );
+        assert!(diff_file_snapshots(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_phases_to_rerun() {
+        let phases = get_phases();
+
+        let rerun = get_phases_to_rerun(&phases, &changes(&["index.js"]));
+        assert_eq!(
+            rerun.iter().map(Phase::get_name).collect::<Vec<_>>(),
+            vec!["build"]
+        );
+
+        let rerun = get_phases_to_rerun(&phases, &changes(&["package.json"]));
+        assert_eq!(rerun.len(), 2);
+
+        let rerun = get_phases_to_rerun(&phases, &changes(&["src/index.js"]));
+        assert_eq!(rerun.len(), 2);
+
+        assert!(get_phases_to_rerun(&phases, &FileChanges::default()).is_empty());
+    }
+}
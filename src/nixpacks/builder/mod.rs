@@ -3,6 +3,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 
 pub mod docker;
+pub mod publish;
 
 #[async_trait]
 pub trait ImageBuilder {
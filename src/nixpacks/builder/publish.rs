@@ -0,0 +1,311 @@
+use crate::nixpacks::{
+    messages::{Message, MessageId},
+    nix::NIXPKGS_ARCHIVE,
+    plan::BuildPlan,
+    NIX_PACKS_VERSION,
+};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::{fs, path::Path, process::Command};
+
+pub const PLAN_ARTIFACT: &str = "plan.json";
+pub const DOCKERFILE_ARTIFACT: &str = "Dockerfile";
+pub const SBOM_ARTIFACT: &str = "sbom.cdx.json";
+pub const REPORT_ARTIFACT: &str = "report.json";
+
+/// Write the artifacts of a build to `dir`, and return their names.
+///
+/// The values of all variables are redacted from the plan, since they can hold secrets.
+pub fn write_artifacts(
+    plan: &BuildPlan,
+    image: &str,
+    dockerfile: &Path,
+    dir: &Path,
+) -> Result<Vec<String>> {
+    fs::create_dir_all(dir).context("Creating artifacts directory")?;
+
+    let mut redacted_plan = plan.clone();
+    redacted_plan.redact_variables(|_| true);
+    fs::write(dir.join(PLAN_ARTIFACT), redacted_plan.to_json()?).context("Writing plan")?;
+
+    fs::copy(dockerfile, dir.join(DOCKERFILE_ARTIFACT)).context("Copying Dockerfile")?;
+
+    fs::write(
+        dir.join(SBOM_ARTIFACT),
+        serde_json::to_string_pretty(&get_sbom(plan, image)?)?,
+    )
+    .context("Writing SBOM")?;
+
+    fs::write(
+        dir.join(REPORT_ARTIFACT),
+        serde_json::to_string_pretty(&get_report(plan, image)?)?,
+    )
+    .context("Writing build report")?;
+
+    Ok(vec![
+        PLAN_ARTIFACT.to_string(),
+        DOCKERFILE_ARTIFACT.to_string(),
+        SBOM_ARTIFACT.to_string(),
+        REPORT_ARTIFACT.to_string(),
+    ])
+}
+
+/// A CycloneDX SBOM of the Nix and Apt packages the phases install
+fn get_sbom(plan: &BuildPlan, image: &str) -> Result<Value> {
+    let mut components: Vec<Value> = Vec::new();
+    let mut add_component = |component: Value| {
+        if !components.contains(&component) {
+            components.push(component);
+        }
+    };
+
+    for phase in plan.get_sorted_phases()? {
+        let archive = phase
+            .nixpkgs_archive
+            .clone()
+            .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+        for pkg in phase.nix_pkgs.iter().chain(phase.nix_libs.iter()).flatten() {
+            add_component(json!({
+                "type": "library",
+                "name": pkg,
+                "properties": [{ "name": "nixpacks:nixpkgsArchive", "value": archive }],
+            }));
+        }
+        for pkg in phase.apt_pkgs.iter().flatten() {
+            add_component(json!({
+                "type": "library",
+                "name": pkg,
+                "purl": format!("pkg:deb/debian/{}", pkg),
+            }));
+        }
+    }
+
+    Ok(json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.4",
+        "version": 1,
+        "metadata": {
+            "tools": [{ "name": "nixpacks", "version": NIX_PACKS_VERSION }],
+            "component": { "type": "container", "name": image },
+        },
+        "components": components,
+    }))
+}
+
+/// What was built: the image, the providers, the phases in the order they ran, and the start command
+fn get_report(plan: &BuildPlan, image: &str) -> Result<Value> {
+    let start = plan.start_phase.clone().unwrap_or_default();
+
+    Ok(json!({
+        "nixpacksVersion": NIX_PACKS_VERSION,
+        "image": image,
+        "providers": plan.providers.clone().unwrap_or_default(),
+        "buildImage": plan.build_image,
+        "runImage": start.run_image,
+        "phases": plan
+            .get_sorted_phases()?
+            .iter()
+            .map(|phase| phase.get_name())
+            .collect::<Vec<_>>(),
+        "startCmd": start.cmd,
+    }))
+}
+
+/// Uploads the artifacts of a build after it succeeds, e.g. the plan and the Dockerfile.
+///
+/// Publishers are chosen by the scheme of the destinations in the plan's `publish` list.
+pub trait Publisher {
+    fn destination(&self) -> &str;
+
+    /// Commands that upload `files` from `dir`
+    fn get_publish_cmds(&self, dir: &Path, files: &[String]) -> Vec<Command>;
+
+    fn publish(&self, dir: &Path, files: &[String]) -> Result<()> {
+        for mut cmd in self.get_publish_cmds(dir, files) {
+            let status = cmd
+                .status()
+                .context(format!("Running {:?}", cmd.get_program()))?;
+            if !status.success() {
+                bail!(Message::new(MessageId::PublishFailed).arg("destination", self.destination()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the publisher for a destination, e.g. `s3://bucket/builds`, `gs://bucket/builds`, or `oci://ghcr.io/org/app:plan`
+pub fn get_publisher(destination: &str) -> Result<Box<dyn Publisher>> {
+    let destination = destination.trim_end_matches('/').to_string();
+    if destination.starts_with("s3://") {
+        Ok(Box::new(S3Publisher { url: destination }))
+    } else if destination.starts_with("gs://") {
+        Ok(Box::new(GcsPublisher { url: destination }))
+    } else if let Some(reference) = destination.strip_prefix("oci://") {
+        Ok(Box::new(OciPublisher {
+            reference: reference.to_string(),
+        }))
+    } else {
+        bail!(Message::new(MessageId::UnknownPublishDestination).arg("destination", &destination))
+    }
+}
+
+/// Copies each file to `<url>/<file>` with the AWS CLI
+pub struct S3Publisher {
+    pub url: String,
+}
+
+impl Publisher for S3Publisher {
+    fn destination(&self) -> &str {
+        &self.url
+    }
+
+    fn get_publish_cmds(&self, dir: &Path, files: &[String]) -> Vec<Command> {
+        files
+            .iter()
+            .map(|file| {
+                let mut cmd = Command::new("aws");
+                cmd.args(["s3", "cp"])
+                    .arg(dir.join(file))
+                    .arg(format!("{}/{}", self.url, file));
+                cmd
+            })
+            .collect()
+    }
+}
+
+/// Copies each file to `<url>/<file>` with gsutil
+pub struct GcsPublisher {
+    pub url: String,
+}
+
+impl Publisher for GcsPublisher {
+    fn destination(&self) -> &str {
+        &self.url
+    }
+
+    fn get_publish_cmds(&self, dir: &Path, files: &[String]) -> Vec<Command> {
+        files
+            .iter()
+            .map(|file| {
+                let mut cmd = Command::new("gsutil");
+                cmd.arg("cp")
+                    .arg(dir.join(file))
+                    .arg(format!("{}/{}", self.url, file));
+                cmd
+            })
+            .collect()
+    }
+}
+
+/// Pushes all files as a single OCI artifact with ORAS
+pub struct OciPublisher {
+    pub reference: String,
+}
+
+impl Publisher for OciPublisher {
+    fn destination(&self) -> &str {
+        &self.reference
+    }
+
+    fn get_publish_cmds(&self, dir: &Path, files: &[String]) -> Vec<Command> {
+        // Files are pushed with relative paths, which ORAS uses as their titles
+        let mut cmd = Command::new("oras");
+        cmd.current_dir(dir)
+            .args(["push", &self.reference])
+            .args(files);
+        vec![cmd]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        debug_bundle::REDACTED_VALUE,
+        nix::pkg::Pkg,
+        plan::phase::{Phase, StartPhase},
+    };
+    use std::collections::BTreeMap;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_write_artifacts() -> Result<()> {
+        let tmp = TempDir::new("nixpacks-publish")?;
+        let dockerfile = tmp.path().join("Dockerfile");
+        fs::write(&dockerfile, "FROM scratch\n")?;
+
+        let mut setup = Phase::setup(None);
+        setup.add_nix_pkgs(&[Pkg::new("nodejs")]);
+        setup.add_apt_pkgs(vec!["libpq-dev".to_string()]);
+        let mut plan = BuildPlan::new(&[setup], Some(StartPhase::new("npm start")));
+        plan.add_variables(BTreeMap::from([(
+            "DATABASE_URL".to_string(),
+            "postgres://user:secret@db".to_string(),
+        )]));
+        plan.add_build_variables(BTreeMap::from([(
+            "NPM_TOKEN".to_string(),
+            "npm_secret".to_string(),
+        )]));
+
+        let dir = tmp.path().join("artifacts");
+        let files = write_artifacts(&plan, "my-app", &dockerfile, &dir)?;
+        assert_eq!(
+            files,
+            vec!["plan.json", "Dockerfile", "sbom.cdx.json", "report.json"]
+        );
+
+        // Variable values never leave the machine, only their names
+        let plan_json = fs::read_to_string(dir.join(PLAN_ARTIFACT))?;
+        assert!(plan_json.contains("DATABASE_URL"));
+        assert!(!plan_json.contains("secret"));
+        let published = BuildPlan::from_json(plan_json)?;
+        assert_eq!(published.variables.unwrap()["DATABASE_URL"], REDACTED_VALUE);
+
+        let sbom: Value = serde_json::from_str(&fs::read_to_string(dir.join(SBOM_ARTIFACT))?)?;
+        assert_eq!(sbom["metadata"]["component"]["name"], "my-app");
+        assert_eq!(sbom["components"][0]["name"], "nodejs");
+        assert_eq!(sbom["components"][1]["purl"], "pkg:deb/debian/libpq-dev");
+
+        let report: Value = serde_json::from_str(&fs::read_to_string(dir.join(REPORT_ARTIFACT))?)?;
+        assert_eq!(report["phases"], json!(["setup"]));
+        assert_eq!(report["startCmd"], "npm start");
+
+        Ok(())
+    }
+
+    fn get_cmd_strings(publisher: &dyn Publisher) -> Vec<String> {
+        publisher
+            .get_publish_cmds(Path::new("/out"), &["plan.json".to_string()])
+            .iter()
+            .map(|cmd| {
+                std::iter::once(cmd.get_program())
+                    .chain(cmd.get_args())
+                    .map(|arg| arg.to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_get_publisher() {
+        assert_eq!(
+            get_cmd_strings(get_publisher("s3://bucket/builds/").unwrap().as_ref()),
+            vec!["aws s3 cp /out/plan.json s3://bucket/builds/plan.json"]
+        );
+        assert_eq!(
+            get_cmd_strings(get_publisher("gs://bucket").unwrap().as_ref()),
+            vec!["gsutil cp /out/plan.json gs://bucket/plan.json"]
+        );
+        assert_eq!(
+            get_cmd_strings(
+                get_publisher("oci://ghcr.io/org/app:plan")
+                    .unwrap()
+                    .as_ref()
+            ),
+            vec!["oras push ghcr.io/org/app:plan plan.json"]
+        );
+        assert!(get_publisher("ftp://example.com").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::nixpacks::messages::{Message, MessageId};
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempdir::TempDir;
+
+pub const DEFAULT_DEBUG_BUNDLE_NAME: &str = "nixpacks-debug.tar.gz";
+pub const REDACTED_VALUE: &str = "[redacted]";
+
+/// A collection of files describing a build that can be attached to bug reports.
+///
+/// The bundle should never contain the values of environment variables, only their names.
+#[derive(Debug, Default)]
+pub struct DebugBundle {
+    files: BTreeMap<String, String>,
+}
+
+impl DebugBundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file<N: Into<String>, C: Into<String>>(&mut self, name: N, contents: C) {
+        self.files.insert(name.into(), contents.into());
+    }
+
+    pub fn file_names(&self) -> Vec<String> {
+        self.files.keys().cloned().collect()
+    }
+
+    pub fn get_file(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(String::as_str)
+    }
+
+    /// Write all files in the bundle to a gzipped tarball
+    pub fn write<P: AsRef<Path>>(&self, out: P) -> Result<PathBuf> {
+        let tmp = TempDir::new("nixpacks-debug").context("Creating a temp directory")?;
+        let bundle_dir = tmp.path().join("nixpacks-debug");
+
+        for (name, contents) in &self.files {
+            let path = bundle_dir.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .context(format!("Creating parent directory for {}", name))?;
+            }
+            fs::write(&path, contents).context(format!("Writing {}", name))?;
+        }
+
+        let out = std::env::current_dir()?.join(out);
+        let status = Command::new("tar")
+            .arg("-czf")
+            .arg(&out)
+            .arg("-C")
+            .arg(tmp.path())
+            .arg("nixpacks-debug")
+            .status()
+            .context("Running tar. Please make sure it is installed")?;
+
+        if !status.success() {
+            bail!(Message::new(MessageId::DebugBundleFailed).arg("file", &out.display()));
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names() {
+        let mut bundle = DebugBundle::new();
+        bundle.add_file("plan.json", "{}");
+        bundle.add_file("nix/nixpkgs.nix", "{ }: {}");
+
+        assert_eq!(
+            bundle.file_names(),
+            vec!["nix/nixpkgs.nix".to_string(), "plan.json".to_string()]
+        );
+    }
+}
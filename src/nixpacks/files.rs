@@ -1,14 +1,37 @@
 use anyhow::Result;
-use ignore::WalkBuilder;
-use std::{fs, io, path::Path};
+use ignore::{Walk, WalkBuilder};
+use std::{fs, io, path::Path, process::Command};
 
-pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) -> Result<()> {
-    let walker = WalkBuilder::new(&source)
+/// Patterns of app files to leave out of the image, in the same format as `.gitignore`
+pub const NIXPACKS_IGNORE_FILE: &str = ".nixpacksignore";
+
+/// Walk the files of an app that are copied into the image. Paths matching `.nixpacksignore`,
+/// or `.gitignore` when the app doesn't have one, are skipped
+pub fn walk_app_files<T: AsRef<Path>>(source: T) -> Walk {
+    app_files_walker(source).build()
+}
+
+/// A walker of the app files, which can be configured further before it is built
+pub fn app_files_walker<T: AsRef<Path>>(source: T) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(&source);
+    builder
         .follow_links(false)
         // this includes hidden directories & files
         .standard_filters(false)
-        .hidden(false)
-        .build();
+        .hidden(false);
+
+    if source.as_ref().join(NIXPACKS_IGNORE_FILE).is_file() {
+        builder.add_custom_ignore_filename(NIXPACKS_IGNORE_FILE);
+    } else {
+        // The app doesn't have to be a git repository for its `.gitignore` to apply
+        builder.git_ignore(true).require_git(false);
+    }
+
+    builder
+}
+
+pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) -> Result<()> {
+    let walker = walk_app_files(&source);
 
     for entry in walker {
         let entry = entry?;
@@ -38,3 +61,116 @@ pub fn recursive_copy_dir<T: AsRef<Path>, Q: AsRef<Path>>(source: T, dest: Q) ->
     }
     Ok(())
 }
+
+/// Total size in bytes of the files that `recursive_copy_dir` would copy
+pub fn get_dir_size<T: AsRef<Path>>(source: T) -> Result<u64> {
+    let walker = walk_app_files(source);
+
+    let mut size = 0;
+    for entry in walker {
+        let entry = entry?;
+        if entry
+            .file_type()
+            .map_or(false, |file_type| file_type.is_file())
+        {
+            size += entry.metadata()?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+/// Available space in bytes on the filesystem containing `path`.
+/// Returns `None` if it could not be determined (e.g. `df` is not installed).
+pub fn get_available_space<T: AsRef<Path>>(path: T) -> Option<u64> {
+    let output = Command::new("df")
+        .arg("-Pk")
+        .arg(path.as_ref())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_df_available(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the available kilobytes out of POSIX `df -Pk` output
+fn parse_df_available(output: &str) -> Option<u64> {
+    let line = output.lines().nth(1)?;
+    let available = line.split_whitespace().nth(3)?.parse::<u64>().ok()?;
+    Some(available * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse_df_available() {
+        let output = "Filesystem     1024-blocks  Used Available Capacity Mounted on\ntmpfs               65536  1024     64512       2% /tmp\n";
+        assert_eq!(parse_df_available(output), Some(64512 * 1024));
+        assert_eq!(parse_df_available(""), None);
+    }
+
+    fn copy_app(files: &[(&str, &str)]) -> Result<(TempDir, TempDir)> {
+        let source = TempDir::new("nixpacks-app")?;
+        for (path, contents) in files {
+            let path = source.path().join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, contents)?;
+        }
+
+        let dest = TempDir::new("nixpacks-copy")?;
+        recursive_copy_dir(source.path(), dest.path())?;
+
+        Ok((source, dest))
+    }
+
+    #[test]
+    fn test_copy_with_nixpacksignore() -> Result<()> {
+        let (_source, dest) = copy_app(&[
+            (".nixpacksignore", "node_modules\n*.log\n!keep.log\n"),
+            (".gitignore", "dist\n"),
+            ("index.js", ""),
+            ("node_modules/left-pad/index.js", ""),
+            ("debug.log", ""),
+            ("keep.log", ""),
+            ("dist/index.js", ""),
+        ])?;
+
+        assert!(dest.path().join("index.js").is_file());
+        assert!(!dest.path().join("node_modules").exists());
+        assert!(!dest.path().join("debug.log").exists());
+        assert!(dest.path().join("keep.log").is_file());
+
+        // `.gitignore` is only used without a `.nixpacksignore`
+        assert!(dest.path().join("dist/index.js").is_file());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_with_gitignore() -> Result<()> {
+        let (_source, dest) = copy_app(&[
+            (".gitignore", "dist/\n"),
+            ("index.js", ""),
+            ("dist/index.js", ""),
+        ])?;
+
+        assert!(dest.path().join("index.js").is_file());
+        assert!(dest.path().join(".gitignore").is_file());
+        assert!(!dest.path().join("dist").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_dir_size() -> Result<()> {
+        let size = get_dir_size("./examples/node")?;
+        assert!(size > 0);
+        Ok(())
+    }
+}
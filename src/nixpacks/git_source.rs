@@ -0,0 +1,256 @@
+// Code relating to building an app straight from a git repository
+//
+// Sources are written as `<url>#<ref>:<subdir>`, where both the ref and the subdir are optional.
+// Every remote is mirrored once into a bare repository in the Nixpacks cache directory,
+// so later builds only fetch new commits instead of cloning the whole repository again.
+
+use super::messages::{Message, MessageId};
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use tempdir::TempDir;
+
+const GIT_URL_PREFIXES: &[&str] = &["https://", "http://", "ssh://", "git://", "git@", "file://"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSource {
+    pub url: String,
+    pub git_ref: Option<String>,
+    pub subdir: Option<String>,
+}
+
+/// A checkout of a git source that is removed once dropped
+pub struct GitCheckout {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl GitCheckout {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl GitSource {
+    /// Parse `source` if it is a git URL, e.g. `https://github.com/org/repo#main:apps/web`
+    pub fn parse(source: &str) -> Option<GitSource> {
+        if !GIT_URL_PREFIXES
+            .iter()
+            .any(|prefix| source.starts_with(prefix))
+        {
+            return None;
+        }
+
+        let (url, fragment) = source.split_once('#').unwrap_or((source, ""));
+        let (git_ref, subdir) = fragment.split_once(':').unwrap_or((fragment, ""));
+        let subdir = subdir.trim_matches('/');
+
+        Some(GitSource {
+            url: url.to_string(),
+            git_ref: (!git_ref.is_empty()).then(|| git_ref.to_string()),
+            subdir: (!subdir.is_empty()).then(|| subdir.to_string()),
+        })
+    }
+
+    /// `NIXPACKS_CACHE_DIR`, or `nixpacks` in the user cache directory
+    pub fn default_cache_dir() -> PathBuf {
+        if let Ok(dir) = env::var("NIXPACKS_CACHE_DIR") {
+            return PathBuf::from(dir);
+        }
+
+        env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(|_| env::temp_dir())
+            .join("nixpacks")
+    }
+
+    /// Check out the ref and subdir of the source into a temporary directory
+    pub fn checkout(&self) -> Result<GitCheckout> {
+        self.checkout_with_cache(&GitSource::default_cache_dir())
+    }
+
+    pub fn checkout_with_cache(&self, cache_dir: &Path) -> Result<GitCheckout> {
+        let repo = self.update_cached_repo(cache_dir)?;
+
+        // Name the checkout after the app, since the default image name comes from the directory name
+        let dir = TempDir::new("nixpacks-git")?;
+        let path = dir.path().join(self.get_app_name());
+        fs::create_dir_all(&path)?;
+
+        let tree = format!(
+            "{}:{}",
+            self.git_ref.as_deref().unwrap_or("HEAD"),
+            self.subdir.as_deref().unwrap_or_default()
+        );
+        let mut archive = Command::new("git")
+            .arg("--git-dir")
+            .arg(&repo)
+            .args(["archive", "--format=tar", &tree])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Running git")?;
+        let extracted = Command::new("tar")
+            .arg("-x")
+            .arg("-C")
+            .arg(&path)
+            .stdin(archive.stdout.take().unwrap())
+            .status()
+            .context("Running tar")?;
+
+        if !archive.wait()?.success() || !extracted.success() {
+            bail!(Message::new(MessageId::GitSourceFailed)
+                .arg("action", &format!("check out {}", tree))
+                .arg("url", &self.url));
+        }
+
+        Ok(GitCheckout { _dir: dir, path })
+    }
+
+    /// Mirror the remote into the cache on first use, and fetch new commits afterwards
+    fn update_cached_repo(&self, cache_dir: &Path) -> Result<PathBuf> {
+        let repo = cache_dir.join("git").join(self.get_cache_name());
+
+        let (action, mut git_cmd) = if repo.join("HEAD").is_file() {
+            let mut cmd = Command::new("git");
+            cmd.arg("--git-dir")
+                .arg(&repo)
+                .args(["fetch", "--prune", "origin"]);
+            ("fetch", cmd)
+        } else {
+            fs::create_dir_all(cache_dir.join("git"))?;
+            let mut cmd = Command::new("git");
+            cmd.args(["clone", "--mirror", &self.url]).arg(&repo);
+            ("clone", cmd)
+        };
+
+        // Progress goes to stderr, so `nixpacks plan` output stays valid JSON
+        let status = git_cmd
+            .stdout(Stdio::null())
+            .status()
+            .context("Running git")?;
+        if !status.success() {
+            bail!(Message::new(MessageId::GitSourceFailed)
+                .arg("action", action)
+                .arg("url", &self.url));
+        }
+
+        Ok(repo)
+    }
+
+    /// The directory of the cached mirror, e.g. `repo-3f9c2a1b7d4e.git`. It is keyed on a hash of the whole URL,
+    /// since different URLs can have the same name, like `org/my-app` and `org-my/app`
+    fn get_cache_name(&self) -> String {
+        let url = self.url.trim_end_matches('/').trim_end_matches(".git");
+        let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+
+        format!("{}-{}.git", GitSource::get_app_name_from(url), &hash[..12])
+    }
+
+    fn get_app_name(&self) -> String {
+        GitSource::get_app_name_from(self.subdir.as_deref().unwrap_or(&self.url))
+    }
+
+    fn get_app_name_from(path: &str) -> String {
+        path.trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(|c| c == '/' || c == ':')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=nixpacks", "-c", "user.email=nixpacks@test"])
+            .args(args)
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_parse_git_source() {
+        assert_eq!(GitSource::parse("./examples/node"), None);
+        assert_eq!(
+            GitSource::parse("https://github.com/org/repo.git"),
+            Some(GitSource {
+                url: "https://github.com/org/repo.git".to_string(),
+                git_ref: None,
+                subdir: None
+            })
+        );
+        assert_eq!(
+            GitSource::parse("git@github.com:org/repo#v1.2.0:apps/web/"),
+            Some(GitSource {
+                url: "git@github.com:org/repo".to_string(),
+                git_ref: Some("v1.2.0".to_string()),
+                subdir: Some("apps/web".to_string())
+            })
+        );
+        assert_eq!(
+            GitSource::parse("https://github.com/org/repo#:apps/api"),
+            Some(GitSource {
+                url: "https://github.com/org/repo".to_string(),
+                git_ref: None,
+                subdir: Some("apps/api".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn test_cache_name() {
+        let cache_name = |source: &str| GitSource::parse(source).unwrap().get_cache_name();
+
+        assert!(cache_name("https://github.com/org/my-app").starts_with("my-app-"));
+        assert_ne!(
+            cache_name("https://github.com/org/my-app"),
+            cache_name("https://github.com/org-my/app")
+        );
+        assert_eq!(
+            cache_name("https://github.com/org/repo.git#main"),
+            cache_name("https://github.com/org/repo#v1:apps/web")
+        );
+    }
+
+    #[test]
+    fn test_checkout_git_source() -> Result<()> {
+        let remote = TempDir::new("nixpacks-git-remote")?;
+        fs::create_dir_all(remote.path().join("apps/web"))?;
+        fs::write(remote.path().join("apps/web/index.js"), "v1")?;
+        git(remote.path(), &["init", "-q", "-b", "main"]);
+        git(remote.path(), &["add", "."]);
+        git(remote.path(), &["commit", "-q", "-m", "v1"]);
+        git(remote.path(), &["tag", "v1"]);
+
+        let cache = TempDir::new("nixpacks-git-cache")?;
+        let source =
+            GitSource::parse(&format!("file://{}#v1:apps/web", remote.path().display())).unwrap();
+        let checkout = source.checkout_with_cache(cache.path())?;
+        assert!(checkout.path().ends_with("web"));
+        assert_eq!(fs::read_to_string(checkout.path().join("index.js"))?, "v1");
+
+        // New commits are fetched into the cached mirror
+        fs::write(remote.path().join("apps/web/index.js"), "v2")?;
+        git(remote.path(), &["commit", "-q", "-am", "v2"]);
+        let source =
+            GitSource::parse(&format!("file://{}#main:apps/web", remote.path().display())).unwrap();
+        let checkout = source.checkout_with_cache(cache.path())?;
+        assert_eq!(fs::read_to_string(checkout.path().join("index.js"))?, "v2");
+        assert_eq!(fs::read_dir(cache.path().join("git"))?.count(), 1);
+
+        Ok(())
+    }
+}
@@ -1,2 +1,29 @@
 pub const DEFAULT_BASE_IMAGE: &str = "ghcr.io/railwayapp/nixpacks:debian-1668470745";
 pub const DEBIAN_SLIM_IMAGE: &str = "debian:bullseye-slim";
+/// The empty image, for statically linked binaries
+pub const SCRATCH_IMAGE: &str = "scratch";
+/// CA certificates, timezone data, and users, but no shell or libc
+pub const DISTROLESS_STATIC_IMAGE: &str = "gcr.io/distroless/static-debian11";
+
+/// Images that commands can not be run in
+pub const SHELLLESS_IMAGES: &[&str] = &[SCRATCH_IMAGE, DISTROLESS_STATIC_IMAGE];
+
+/// Whether commands can not be run in an image. Only the `debug` tags of distroless images have a shell
+pub fn is_shellless_image(image: &str) -> bool {
+    SHELLLESS_IMAGES.contains(&image)
+        || (image.starts_with("gcr.io/distroless/") && !image.contains(":debug"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_shellless_image() {
+        assert!(is_shellless_image(SCRATCH_IMAGE));
+        assert!(is_shellless_image("gcr.io/distroless/cc-debian11"));
+        assert!(is_shellless_image("gcr.io/distroless/base:nonroot"));
+        assert!(!is_shellless_image("gcr.io/distroless/base:debug"));
+        assert!(!is_shellless_image(DEBIAN_SLIM_IMAGE));
+    }
+}
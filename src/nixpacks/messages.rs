@@ -0,0 +1,599 @@
+use anyhow::{bail, Result};
+use std::{collections::BTreeMap, collections::HashMap, fmt};
+
+/// Identifiers for all user-facing messages.
+///
+/// The code of a message never changes between releases, so platforms embedding Nixpacks
+/// can match on it and remap or translate the text with a [`MessageCatalog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageId {
+    NoStartCommand,
+    UnableToGeneratePlan,
+    InvalidPlanFormat,
+    ProviderNotFound,
+    ConfigFileNotFound,
+    UnknownConfigFileType,
+    DuplicatePhase,
+    CircularDependency,
+    InvalidSourcePath,
+    DockerNotInstalled,
+    DockerBuildFailed,
+    NotEnoughDiskSpace,
+    IncrementalCacheFailed,
+    DebugBundleFailed,
+    ExecutableNameNotFound,
+    SwiftVersionEmpty,
+    SwiftMainNotFound,
+    RubyVersionNotFound,
+    DjangoAppNotFound,
+    PyprojectInvalid,
+    ConfigEvaluationFailed,
+    ConfigFileTypeNotEnabled,
+    WarmContainerFailed,
+    WarmContainerCommandFailed,
+    PlanDrift,
+    NodeWorkspaceNotFound,
+    GitSourceFailed,
+    UnknownPublishDestination,
+    PublishFailed,
+    NodeVersionUnavailable,
+    AssetIntegrityMismatch,
+    UnknownProcess,
+    CeleryAppNotFound,
+    RubyVersionUnsupported,
+    SidekiqNotFound,
+    RustBinaryNotFound,
+    RustBinaryAmbiguous,
+    RustStaticBuildUnavailable,
+    GoVersionUnavailable,
+    GoCommandNotFound,
+    GoCommandAmbiguous,
+    MavenModuleNotFound,
+    JdkVersionUnavailable,
+    ElixirVersionUnavailable,
+    ErlangVersionUnavailable,
+    DotnetSdkUnavailable,
+    DotnetProjectNotFound,
+    DotnetProjectAmbiguous,
+    DenoVersionInvalid,
+    SwiftVersionUnavailable,
+    SwiftProductNotFound,
+    SwiftProductAmbiguous,
+    ZigVersionUnavailable,
+    ZigOptimizeInvalid,
+    ProcfileProcessNotFound,
+    UnknownPhaseDependency,
+    InvalidHook,
+    BaseImageMissingNix,
+    PlanSchemaMismatch,
+    NixFlakeLockFailed,
+    PythonVersionUnsatisfiable,
+    NixFileArchiveConflict,
+    RacketMainNotFound,
+    RacketMainAmbiguous,
+    MultipleProvidersExperimental,
+    ConfigFileExperimental,
+    UpExperimental,
+    RenamedNixPackage,
+    BuildSuccessful,
+}
+
+pub const ALL_MESSAGE_IDS: &[MessageId] = &[
+    MessageId::NoStartCommand,
+    MessageId::UnableToGeneratePlan,
+    MessageId::InvalidPlanFormat,
+    MessageId::ProviderNotFound,
+    MessageId::ConfigFileNotFound,
+    MessageId::UnknownConfigFileType,
+    MessageId::DuplicatePhase,
+    MessageId::CircularDependency,
+    MessageId::InvalidSourcePath,
+    MessageId::DockerNotInstalled,
+    MessageId::DockerBuildFailed,
+    MessageId::NotEnoughDiskSpace,
+    MessageId::IncrementalCacheFailed,
+    MessageId::DebugBundleFailed,
+    MessageId::ExecutableNameNotFound,
+    MessageId::SwiftVersionEmpty,
+    MessageId::SwiftMainNotFound,
+    MessageId::RubyVersionNotFound,
+    MessageId::DjangoAppNotFound,
+    MessageId::PyprojectInvalid,
+    MessageId::ConfigEvaluationFailed,
+    MessageId::ConfigFileTypeNotEnabled,
+    MessageId::WarmContainerFailed,
+    MessageId::WarmContainerCommandFailed,
+    MessageId::PlanDrift,
+    MessageId::NodeWorkspaceNotFound,
+    MessageId::GitSourceFailed,
+    MessageId::UnknownPublishDestination,
+    MessageId::PublishFailed,
+    MessageId::NodeVersionUnavailable,
+    MessageId::AssetIntegrityMismatch,
+    MessageId::UnknownProcess,
+    MessageId::CeleryAppNotFound,
+    MessageId::RubyVersionUnsupported,
+    MessageId::SidekiqNotFound,
+    MessageId::RustBinaryNotFound,
+    MessageId::RustBinaryAmbiguous,
+    MessageId::RustStaticBuildUnavailable,
+    MessageId::GoVersionUnavailable,
+    MessageId::GoCommandNotFound,
+    MessageId::GoCommandAmbiguous,
+    MessageId::MavenModuleNotFound,
+    MessageId::JdkVersionUnavailable,
+    MessageId::ElixirVersionUnavailable,
+    MessageId::ErlangVersionUnavailable,
+    MessageId::DotnetSdkUnavailable,
+    MessageId::DotnetProjectNotFound,
+    MessageId::DotnetProjectAmbiguous,
+    MessageId::DenoVersionInvalid,
+    MessageId::SwiftVersionUnavailable,
+    MessageId::SwiftProductNotFound,
+    MessageId::SwiftProductAmbiguous,
+    MessageId::ZigVersionUnavailable,
+    MessageId::ZigOptimizeInvalid,
+    MessageId::ProcfileProcessNotFound,
+    MessageId::UnknownPhaseDependency,
+    MessageId::InvalidHook,
+    MessageId::BaseImageMissingNix,
+    MessageId::PlanSchemaMismatch,
+    MessageId::NixFlakeLockFailed,
+    MessageId::PythonVersionUnsatisfiable,
+    MessageId::NixFileArchiveConflict,
+    MessageId::RacketMainNotFound,
+    MessageId::RacketMainAmbiguous,
+    MessageId::MultipleProvidersExperimental,
+    MessageId::ConfigFileExperimental,
+    MessageId::UpExperimental,
+    MessageId::RenamedNixPackage,
+    MessageId::BuildSuccessful,
+];
+
+impl MessageId {
+    /// Stable code for the message, e.g. `NP0001`
+    pub fn code(&self) -> &'static str {
+        match self {
+            MessageId::NoStartCommand => "NP0001",
+            MessageId::UnableToGeneratePlan => "NP0002",
+            MessageId::InvalidPlanFormat => "NP0003",
+            MessageId::ProviderNotFound => "NP0004",
+            MessageId::ConfigFileNotFound => "NP0005",
+            MessageId::UnknownConfigFileType => "NP0006",
+            MessageId::DuplicatePhase => "NP0007",
+            MessageId::CircularDependency => "NP0008",
+            MessageId::InvalidSourcePath => "NP0009",
+            MessageId::DockerNotInstalled => "NP0010",
+            MessageId::DockerBuildFailed => "NP0011",
+            MessageId::NotEnoughDiskSpace => "NP0012",
+            MessageId::IncrementalCacheFailed => "NP0013",
+            MessageId::DebugBundleFailed => "NP0014",
+            MessageId::ExecutableNameNotFound => "NP0015",
+            MessageId::SwiftVersionEmpty => "NP0016",
+            MessageId::SwiftMainNotFound => "NP0017",
+            MessageId::RubyVersionNotFound => "NP0018",
+            MessageId::DjangoAppNotFound => "NP0019",
+            MessageId::PyprojectInvalid => "NP0020",
+            MessageId::ConfigEvaluationFailed => "NP0021",
+            MessageId::ConfigFileTypeNotEnabled => "NP0022",
+            MessageId::WarmContainerFailed => "NP0023",
+            MessageId::WarmContainerCommandFailed => "NP0024",
+            MessageId::PlanDrift => "NP0025",
+            MessageId::NodeWorkspaceNotFound => "NP0026",
+            MessageId::GitSourceFailed => "NP0027",
+            MessageId::UnknownPublishDestination => "NP0028",
+            MessageId::PublishFailed => "NP0029",
+            MessageId::NodeVersionUnavailable => "NP0030",
+            MessageId::AssetIntegrityMismatch => "NP0031",
+            MessageId::UnknownProcess => "NP0032",
+            MessageId::CeleryAppNotFound => "NP0033",
+            MessageId::RubyVersionUnsupported => "NP0034",
+            MessageId::SidekiqNotFound => "NP0035",
+            MessageId::RustBinaryNotFound => "NP0036",
+            MessageId::RustBinaryAmbiguous => "NP0037",
+            MessageId::RustStaticBuildUnavailable => "NP0038",
+            MessageId::GoVersionUnavailable => "NP0039",
+            MessageId::GoCommandNotFound => "NP0040",
+            MessageId::GoCommandAmbiguous => "NP0041",
+            MessageId::MavenModuleNotFound => "NP0042",
+            MessageId::JdkVersionUnavailable => "NP0043",
+            MessageId::ElixirVersionUnavailable => "NP0044",
+            MessageId::ErlangVersionUnavailable => "NP0045",
+            MessageId::DotnetSdkUnavailable => "NP0046",
+            MessageId::DotnetProjectNotFound => "NP0047",
+            MessageId::DotnetProjectAmbiguous => "NP0048",
+            MessageId::DenoVersionInvalid => "NP0049",
+            MessageId::SwiftVersionUnavailable => "NP0050",
+            MessageId::SwiftProductNotFound => "NP0051",
+            MessageId::SwiftProductAmbiguous => "NP0052",
+            MessageId::ZigVersionUnavailable => "NP0053",
+            MessageId::ZigOptimizeInvalid => "NP0054",
+            MessageId::ProcfileProcessNotFound => "NP0055",
+            MessageId::UnknownPhaseDependency => "NP0056",
+            MessageId::InvalidHook => "NP0057",
+            MessageId::BaseImageMissingNix => "NP0058",
+            MessageId::PlanSchemaMismatch => "NP0059",
+            MessageId::NixFlakeLockFailed => "NP0061",
+            MessageId::PythonVersionUnsatisfiable => "NP0062",
+            MessageId::NixFileArchiveConflict => "NP0063",
+            MessageId::RacketMainNotFound => "NP0064",
+            MessageId::RacketMainAmbiguous => "NP0065",
+            MessageId::MultipleProvidersExperimental => "NP0101",
+            MessageId::ConfigFileExperimental => "NP0102",
+            MessageId::UpExperimental => "NP0103",
+            MessageId::RenamedNixPackage => "NP0104",
+            MessageId::BuildSuccessful => "NP0201",
+        }
+    }
+
+    /// Exit code of the CLI when an error with this message is returned
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            MessageId::NoStartCommand
+            | MessageId::UnableToGeneratePlan
+            | MessageId::ProviderNotFound
+            | MessageId::ExecutableNameNotFound
+            | MessageId::SwiftVersionEmpty
+            | MessageId::SwiftMainNotFound
+            | MessageId::RubyVersionNotFound
+            | MessageId::DjangoAppNotFound
+            | MessageId::PyprojectInvalid
+            | MessageId::NodeWorkspaceNotFound
+            | MessageId::NodeVersionUnavailable
+            | MessageId::CeleryAppNotFound
+            | MessageId::RubyVersionUnsupported
+            | MessageId::SidekiqNotFound
+            | MessageId::RustBinaryNotFound
+            | MessageId::RustBinaryAmbiguous
+            | MessageId::GoVersionUnavailable
+            | MessageId::GoCommandNotFound
+            | MessageId::GoCommandAmbiguous
+            | MessageId::MavenModuleNotFound
+            | MessageId::JdkVersionUnavailable
+            | MessageId::ElixirVersionUnavailable
+            | MessageId::ErlangVersionUnavailable
+            | MessageId::DotnetSdkUnavailable
+            | MessageId::DotnetProjectNotFound
+            | MessageId::DotnetProjectAmbiguous
+            | MessageId::DenoVersionInvalid
+            | MessageId::SwiftVersionUnavailable
+            | MessageId::SwiftProductNotFound
+            | MessageId::SwiftProductAmbiguous
+            | MessageId::ZigVersionUnavailable
+            | MessageId::ZigOptimizeInvalid
+            | MessageId::ProcfileProcessNotFound
+            | MessageId::PythonVersionUnsatisfiable
+            | MessageId::RacketMainNotFound
+            | MessageId::RacketMainAmbiguous => ExitCode::DetectionFailed,
+            MessageId::InvalidPlanFormat
+            | MessageId::ConfigFileNotFound
+            | MessageId::UnknownConfigFileType
+            | MessageId::DuplicatePhase
+            | MessageId::CircularDependency
+            | MessageId::ConfigEvaluationFailed
+            | MessageId::ConfigFileTypeNotEnabled
+            | MessageId::UnknownPublishDestination
+            | MessageId::AssetIntegrityMismatch
+            | MessageId::UnknownProcess
+            | MessageId::RustStaticBuildUnavailable
+            | MessageId::UnknownPhaseDependency
+            | MessageId::InvalidHook
+            | MessageId::PlanSchemaMismatch
+            | MessageId::NixFileArchiveConflict => ExitCode::PlanInvalid,
+            MessageId::PlanDrift => ExitCode::PlanDrift,
+            MessageId::DockerNotInstalled => ExitCode::DockerMissing,
+            MessageId::DockerBuildFailed
+            | MessageId::BaseImageMissingNix
+            | MessageId::NotEnoughDiskSpace
+            | MessageId::NixFlakeLockFailed
+            | MessageId::IncrementalCacheFailed => ExitCode::BuildFailed,
+            MessageId::PublishFailed => ExitCode::PushFailed,
+            MessageId::InvalidSourcePath
+            | MessageId::DebugBundleFailed
+            | MessageId::WarmContainerFailed
+            | MessageId::WarmContainerCommandFailed
+            | MessageId::GitSourceFailed
+            // Warnings and notices are not errors, but never exit successfully if returned as one
+            | MessageId::MultipleProvidersExperimental
+            | MessageId::ConfigFileExperimental
+            | MessageId::UpExperimental
+            | MessageId::RenamedNixPackage
+            | MessageId::BuildSuccessful => ExitCode::Error,
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<MessageId> {
+        ALL_MESSAGE_IDS.iter().find(|id| id.code() == code).copied()
+    }
+
+    /// The default English text. Arguments are referenced with `{name}`
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            MessageId::NoStartCommand => "No start command could be found",
+            MessageId::UnableToGeneratePlan => "Nixpacks was unable to generate a build plan for this app.\nPlease check the documentation for supported languages: https://nixpacks.com",
+            MessageId::InvalidPlanFormat => "Invalid plan format",
+            MessageId::ProviderNotFound => "Provider {name} not found",
+            MessageId::ConfigFileNotFound => "Config file {file} does not exist",
+            MessageId::UnknownConfigFileType => "Unknown file type: {file}",
+            MessageId::DuplicatePhase => "Multiple items with the same name: {name}",
+            MessageId::CircularDependency => "Circular dependency detected",
+            MessageId::InvalidSourcePath => "Failed to parse source path",
+            MessageId::DockerNotInstalled => "Please install Docker to build the app https://docs.docker.com/engine/install/",
+            MessageId::DockerBuildFailed => "Docker build failed",
+            MessageId::NotEnoughDiskSpace => "Not enough disk space to copy the app to {dir}. The build context needs about {required} MB but only {available} MB is available.\nUse --tmp-dir or NIXPACKS_TMPDIR to create the build context in a different directory.",
+            MessageId::IncrementalCacheFailed => "Creating incremental cache image failed",
+            MessageId::DebugBundleFailed => "Failed to create debug bundle {file}",
+            MessageId::ExecutableNameNotFound => "Unable to get executable name",
+            MessageId::SwiftVersionEmpty => "Your .swift-version file is empty",
+            MessageId::SwiftMainNotFound => "Your swift app doesn't have a main.swift file",
+            MessageId::RubyVersionNotFound => "Please specify ruby's version in .ruby-version file",
+            MessageId::DjangoAppNotFound => "Failed to find django application name!",
+            MessageId::PyprojectInvalid => "failed to load pyproject.toml",
+            MessageId::ConfigEvaluationFailed => "Failed to evaluate config file {file}:\n{error}",
+            MessageId::ConfigFileTypeNotEnabled => "Config file {file} can only be used when Nixpacks is built with the `experimental-config` feature",
+            MessageId::WarmContainerFailed => "Failed to run docker command for warm container {name}",
+            MessageId::WarmContainerCommandFailed => "Command failed in warm container: {cmd}",
+            MessageId::PlanDrift => "The generated plan does not match {file}. {count} value(s) changed",
+            MessageId::NodeWorkspaceNotFound => "Node workspace {name} not found. Available workspaces: {workspaces}",
+            MessageId::GitSourceFailed => "Failed to {action} git source {url}",
+            MessageId::UnknownPublishDestination => "Unknown publish destination {destination}. Use an s3://, gs://, or oci:// URL",
+            MessageId::PublishFailed => "Failed to publish build artifacts to {destination}",
+            MessageId::NodeVersionUnavailable => "Node {version} from {source} is not available. Available major versions: {versions}",
+            MessageId::AssetIntegrityMismatch => "Static asset {name} does not match its integrity hash {integrity}. Remove the hash from the plan if the asset was changed on purpose",
+            MessageId::UnknownProcess => "Unknown process {process}. Set {variable} to web or worker",
+            MessageId::CeleryAppNotFound => "Failed to find a Celery app for the worker process. Create one with `app = Celery(...)`",
+            MessageId::RubyVersionUnsupported => "Ruby {version} from {source} is not supported. Supported versions: {versions}",
+            MessageId::SidekiqNotFound => "The worker process runs Sidekiq, but sidekiq is not in the Gemfile",
+            MessageId::RustBinaryNotFound => "Rust binary {name} not found. Available binaries: {binaries}",
+            MessageId::RustBinaryAmbiguous => "Found multiple Rust binaries: {binaries}. Set NIXPACKS_RUST_BIN to the one to start",
+            MessageId::RustStaticBuildUnavailable => "NIXPACKS_RUST_STATIC needs a musl build, but {reason}",
+            MessageId::GoVersionUnavailable => "Go {version} from {source} is not available. Available versions: {versions}",
+            MessageId::GoCommandNotFound => "Go command {name} not found in cmd/. Available commands: {commands}",
+            MessageId::GoCommandAmbiguous => "Found multiple Go commands in cmd/: {commands}. Set NIXPACKS_GO_BIN to the one to build",
+            MessageId::MavenModuleNotFound => "Maven module {name} not found. Available modules: {modules}",
+            MessageId::JdkVersionUnavailable => "Java {version} from {source} is not available. Available versions: {versions}",
+            MessageId::ElixirVersionUnavailable => "Elixir {version} from {source} is not available. Available versions: {versions}",
+            MessageId::ErlangVersionUnavailable => "Erlang/OTP {version} from {source} is not available for this Elixir version. Available versions: {versions}",
+            MessageId::DotnetSdkUnavailable => "The .NET SDK {version} from {source} is not available. Available versions: {versions}",
+            MessageId::DotnetProjectNotFound => ".NET project {name} not found. Available projects: {projects}",
+            MessageId::DotnetProjectAmbiguous => "Found multiple .NET projects to start: {projects}. Set NIXPACKS_DOTNET_PROJECT to the one to build",
+            MessageId::DenoVersionInvalid => "Deno version {version} from {source} is not an exact version, such as 1.28.3",
+            MessageId::SwiftVersionUnavailable => "Swift {version} from {source} is not available. Available versions: {versions}",
+            MessageId::SwiftProductNotFound => "Swift product {name} not found. Available products: {products}",
+            MessageId::SwiftProductAmbiguous => "Found multiple Swift executable products: {products}. Set NIXPACKS_SWIFT_PRODUCT to the one to start",
+            MessageId::ZigVersionUnavailable => "Zig {version} from {source} is not available. Available versions: {versions}",
+            MessageId::ZigOptimizeInvalid => "Zig optimize mode {mode} is not valid. Use one of: {modes}",
+            MessageId::ProcfileProcessNotFound => "Process {process} not found in the Procfile. Available processes: {processes}",
+            MessageId::UnknownPhaseDependency => "Phase {phase} depends on unknown phase {dependency}. Available phases: {phases}",
+            MessageId::InvalidHook => "Hook {hook} does not run around a phase of the plan. Available hooks: {hooks}",
+            MessageId::BaseImageMissingNix => "Nix is not installed in the base image {image}. Use an image with nix-env on the PATH",
+            MessageId::PlanSchemaMismatch => "The plan does not match the schema from `nixpacks schema`:\n{errors}",
+            MessageId::NixFlakeLockFailed => "Failed to lock the Nix flake in {dir}",
+            MessageId::PythonVersionUnsatisfiable => "No available Python version satisfies {requirement} from {source}. Available versions: {versions}",
+            MessageId::NixFileArchiveConflict => "Phases {phases} install from the same Nix file {file} but set different Nixpkgs archives: {archives}",
+            MessageId::RacketMainNotFound => "Racket file {file} not found. Add a main.rkt, list the file to start in racket-launcher-libraries of info.rkt, or set NIXPACKS_RACKET_MAIN",
+            MessageId::RacketMainAmbiguous => "Found multiple Racket files: {files}. Set NIXPACKS_RACKET_MAIN to the one to start",
+            MessageId::MultipleProvidersExperimental => "Using multiple providers is experimental",
+            MessageId::ConfigFileExperimental => "Nixpacks file based configuration is experimental and may change",
+            MessageId::UpExperimental => "`nixpacks up` is experimental and may change",
+            MessageId::RenamedNixPackage => "Nix package {old} is not available in the pinned Nixpkgs archive. Using {new} instead",
+            MessageId::BuildSuccessful => "Successfully Built!",
+        }
+    }
+}
+
+/// Process exit codes of the CLI, one for each class of failure.
+///
+/// Like message codes, the values never change between releases so scripts wrapping
+/// Nixpacks can branch on them without parsing the error text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitCode {
+    Success,
+    /// Any failure without a more specific code
+    Error,
+    /// No provider matched the app, or a provider could not create a plan for it
+    DetectionFailed,
+    /// The plan or config file could not be parsed or resolved
+    PlanInvalid,
+    /// Docker is not installed or is not running
+    DockerMissing,
+    /// The image build failed
+    BuildFailed,
+    /// Pushing the image or Nix packages failed
+    PushFailed,
+    /// `nixpacks verify` found differences from the saved plan
+    PlanDrift,
+    /// The process was interrupted with Ctrl-C
+    Interrupted,
+}
+
+pub const ALL_EXIT_CODES: &[ExitCode] = &[
+    ExitCode::Success,
+    ExitCode::Error,
+    ExitCode::DetectionFailed,
+    ExitCode::PlanInvalid,
+    ExitCode::DockerMissing,
+    ExitCode::BuildFailed,
+    ExitCode::PushFailed,
+    ExitCode::PlanDrift,
+    ExitCode::Interrupted,
+];
+
+impl ExitCode {
+    /// Code 2 is skipped since it is used for invalid arguments,
+    /// and interrupts follow the shell convention of 128 + SIGINT
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Error => 1,
+            ExitCode::DetectionFailed => 3,
+            ExitCode::PlanInvalid => 4,
+            ExitCode::DockerMissing => 5,
+            ExitCode::BuildFailed => 6,
+            ExitCode::PushFailed => 7,
+            ExitCode::PlanDrift => 8,
+            ExitCode::Interrupted => 130,
+        }
+    }
+
+    /// The exit code for the first message found in the error chain
+    pub fn from_error(error: &anyhow::Error) -> ExitCode {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Message>())
+            .map_or(ExitCode::Error, |message| message.id.exit_code())
+    }
+}
+
+/// A user-facing message with the arguments needed to render it.
+///
+/// Errors returned by Nixpacks wrap a `Message` when possible,
+/// which can be retrieved with `error.downcast_ref::<Message>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub id: MessageId,
+    pub args: BTreeMap<String, String>,
+}
+
+impl Message {
+    pub fn new(id: MessageId) -> Self {
+        Self {
+            id,
+            args: BTreeMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn arg<N: Into<String>, V: fmt::Display + ?Sized>(mut self, name: N, value: &V) -> Self {
+        self.args.insert(name.into(), value.to_string());
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.id.code()
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", MessageCatalog::default().render(self))
+    }
+}
+
+impl std::error::Error for Message {}
+
+/// Maps message IDs to templates, falling back to the default English text
+#[derive(Debug, Clone, Default)]
+pub struct MessageCatalog {
+    templates: HashMap<MessageId, String>,
+}
+
+impl MessageCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_template<T: Into<String>>(&mut self, id: MessageId, template: T) {
+        self.templates.insert(id, template.into());
+    }
+
+    /// Create a catalog from a JSON object of message codes to templates,
+    /// e.g. `{ "NP0011": "Le build Docker a échoué" }`
+    pub fn from_json(json: &str) -> Result<Self> {
+        let codes: HashMap<String, String> = serde_json::from_str(json)?;
+
+        let mut catalog = Self::new();
+        for (code, template) in codes {
+            match MessageId::from_code(&code) {
+                Some(id) => catalog.set_template(id, template),
+                None => bail!("Unknown message code {}", code),
+            }
+        }
+
+        Ok(catalog)
+    }
+
+    pub fn render(&self, message: &Message) -> String {
+        let template = self
+            .templates
+            .get(&message.id)
+            .map_or_else(|| message.id.default_template(), String::as_str);
+
+        message
+            .args
+            .iter()
+            .fold(template.to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let codes = ALL_MESSAGE_IDS
+            .iter()
+            .map(MessageId::code)
+            .collect::<HashSet<_>>();
+        assert_eq!(codes.len(), ALL_MESSAGE_IDS.len());
+
+        for id in ALL_MESSAGE_IDS {
+            assert_eq!(MessageId::from_code(id.code()), Some(*id));
+        }
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let codes = ALL_EXIT_CODES
+            .iter()
+            .map(|code| code.code())
+            .collect::<HashSet<_>>();
+        assert_eq!(codes.len(), ALL_EXIT_CODES.len());
+
+        // An error never exits successfully, even with a message that is not an error
+        for id in ALL_MESSAGE_IDS {
+            assert_ne!(id.exit_code(), ExitCode::Success);
+        }
+
+        let err = anyhow::Error::new(Message::new(MessageId::DockerNotInstalled))
+            .context("Building image");
+        assert_eq!(ExitCode::from_error(&err), ExitCode::DockerMissing);
+        assert_eq!(
+            ExitCode::from_error(&anyhow::anyhow!("Unknown")),
+            ExitCode::Error
+        );
+    }
+
+    #[test]
+    fn test_render_default() {
+        let message = Message::new(MessageId::ProviderNotFound).arg("name", "cobol");
+        assert_eq!(message.to_string(), "Provider cobol not found");
+    }
+
+    #[test]
+    fn test_render_override() -> Result<()> {
+        let catalog =
+            MessageCatalog::from_json(r#"{ "NP0004": "Fournisseur {name} introuvable" }"#)?;
+        let message = Message::new(MessageId::ProviderNotFound).arg("name", "cobol");
+        assert_eq!(catalog.render(&message), "Fournisseur cobol introuvable");
+
+        assert!(MessageCatalog::from_json(r#"{ "NP9999": "" }"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_downcast_error() {
+        let err = anyhow::Error::new(Message::new(MessageId::DockerBuildFailed))
+            .context("Building image");
+        let message = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<Message>())
+            .unwrap();
+        assert_eq!(message.code(), "NP0011");
+    }
+}
@@ -1,11 +1,15 @@
 pub mod app;
 pub mod builder;
+pub mod debug_bundle;
 pub mod environment;
 mod files;
+pub mod git_source;
 pub mod images;
 pub mod logger;
+pub mod messages;
 pub mod nix;
 pub mod plan;
+pub mod scripts;
 #[macro_use]
 pub mod static_assets;
 
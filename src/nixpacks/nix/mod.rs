@@ -1,7 +1,12 @@
+use anyhow::{bail, Result};
 use indoc::formatdoc;
 use std::collections::{BTreeMap, BTreeSet};
 
-use crate::nixpacks::plan::phase::{Phase, Phases};
+use crate::nixpacks::{
+    builder::docker::dockerfile_generation::APP_DIR,
+    messages::{Message, MessageId},
+    plan::phase::{Phase, Phases},
+};
 
 pub mod pkg;
 
@@ -10,69 +15,174 @@ pub mod pkg;
 // https://github.com/NixOS/nixpkgs/commit/a0b7e70db7a55088d3de0cc370a59f9fbcc906c3
 pub const NIXPKGS_ARCHIVE: &str = "a0b7e70db7a55088d3de0cc370a59f9fbcc906c3";
 
+/// Libraries that prebuilt native binaries (built for glibc based distros) commonly link against,
+/// but that are not on the library path of the Nix environment
+pub const PREBUILT_BINARY_LIBS: &[&str] = &["stdenv.cc.cc.lib", "zlib"];
+
 #[derive(Eq, PartialEq, Default, Debug, Clone)]
 struct NixGroup {
     archive: Option<String>,
     pkgs: Vec<String>,
     libs: Vec<String>,
     overlays: Vec<String>,
+    expressions: Vec<String>,
 }
 
-fn group_nix_packages_by_archive(phases: &[Phase]) -> Vec<NixGroup> {
-    let mut archive_to_packages: BTreeMap<Option<String>, NixGroup> = BTreeMap::new();
+/// Group the Nix packages of all phases by the Nix file they are installed from
+fn group_nix_packages_by_file(phases: &[Phase]) -> BTreeMap<String, NixGroup> {
+    let mut file_to_packages: BTreeMap<String, NixGroup> = BTreeMap::new();
 
-    let groups = phases
-        .iter()
-        .filter(|phase| phase.uses_nix())
-        .map(|phase| NixGroup {
-            archive: phase.nixpkgs_archive.clone(),
-            pkgs: phase.nix_pkgs.clone().unwrap_or_default(),
-            libs: phase.nix_libs.clone().unwrap_or_default(),
-            overlays: phase.nix_overlays.clone().unwrap_or_default(),
-        });
-
-    for g in groups {
-        match archive_to_packages.get_mut(&g.archive) {
+    let groups = phases.iter().filter_map(|phase| {
+        nix_file_name_for_phase(phase).map(|file_name| {
+            (
+                file_name,
+                NixGroup {
+                    archive: phase.nixpkgs_archive.clone(),
+                    pkgs: phase.nix_pkgs.clone().unwrap_or_default(),
+                    libs: phase.nix_libs.clone().unwrap_or_default(),
+                    overlays: phase.nix_overlays.clone().unwrap_or_default(),
+                    expressions: phase.nix_expressions.clone().unwrap_or_default(),
+                },
+            )
+        })
+    });
+
+    for (file_name, g) in groups {
+        match file_to_packages.get_mut(&file_name) {
             Some(group) => {
                 group.pkgs.extend(g.pkgs);
                 group.libs.extend(g.libs);
                 group.overlays.extend(g.overlays);
+                group.expressions.extend(g.expressions);
             }
             None => {
-                archive_to_packages.insert(g.archive.clone(), g);
+                file_to_packages.insert(file_name, g);
             }
         }
     }
 
-    archive_to_packages
-        .values()
-        .map(std::clone::Clone::clone)
-        .collect()
+    file_to_packages
 }
 
-pub fn create_nix_expressions_for_phases(phases: &Phases) -> BTreeMap<String, String> {
-    let archive_to_packages = group_nix_packages_by_archive(
+/// The `flake.nix` of each Nix file of the phases, in a directory named after the file
+pub fn create_nix_flakes_for_phases(phases: &Phases) -> BTreeMap<String, String> {
+    group_nix_packages_by_file(
         &phases
             .values()
             .map(std::clone::Clone::clone)
             .collect::<Vec<_>>(),
-    );
+    )
+    .iter()
+    .map(|(file_name, g)| {
+        (
+            format!("{}/flake.nix", nix_flake_dir(file_name)),
+            nix_flake_for_group(file_name, g),
+        )
+    })
+    .collect()
+}
 
-    archive_to_packages
-        .iter()
-        .fold(BTreeMap::new(), |mut acc, g| {
-            acc.insert(nix_file_name(&g.archive), nix_expression_for_group(g));
-            acc
-        })
+/// Directory of the flake that replaces a Nix file, e.g. `nixpkgs-<archive>` for `nixpkgs-<archive>.nix`
+pub fn nix_flake_dir(file_name: &str) -> String {
+    file_name.trim_end_matches(".nix").to_string()
+}
+
+/// Files of the app that a Nix file imports, i.e. local overlays and expressions,
+/// which are copied into the image before the Nix file is installed
+pub fn nix_local_files_for_file(phases: &Phases, file_name: &str) -> Vec<String> {
+    let groups = group_nix_packages_by_file(&phases.values().cloned().collect::<Vec<_>>());
+    let files = match groups.get(file_name) {
+        Some(group) => group
+            .overlays
+            .iter()
+            .filter(|overlay| !is_remote(overlay))
+            .chain(group.expressions.iter())
+            .map(|path| path.trim_start_matches("./").to_string())
+            .collect::<BTreeSet<_>>(),
+        None => BTreeSet::new(),
+    };
+
+    files.into_iter().collect()
 }
 
+pub fn create_nix_expressions_for_phases(phases: &Phases) -> BTreeMap<String, String> {
+    group_nix_packages_by_file(
+        &phases
+            .values()
+            .map(std::clone::Clone::clone)
+            .collect::<Vec<_>>(),
+    )
+    .iter()
+    .map(|(file_name, g)| (file_name.clone(), nix_expression_for_group(file_name, g)))
+    .collect()
+}
+
+/// Names of the Nix files that are installed before any phase runs.
+/// Files set with a phase's `nixFile` are installed just before the first phase using them.
 pub fn nix_file_names_for_phases(phases: &Phases) -> Vec<String> {
-    let archives = phases
+    let file_names = phases
         .values()
-        .filter(|p| p.uses_nix())
-        .map(|p| p.nixpkgs_archive.clone())
+        .filter(|p| p.nix_file.is_none())
+        .filter_map(nix_file_name_for_phase)
         .collect::<BTreeSet<_>>();
-    archives.iter().map(nix_file_name).collect()
+    file_names.into_iter().collect()
+}
+
+/// Phases that share a `nixFile` are installed from the archive of the first one,
+/// so they must not set different archives
+pub fn check_nix_file_archives(phases: &Phases) -> Result<()> {
+    let mut files: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for (name, phase) in phases {
+        if let (Some(_), Some(file_name)) = (&phase.nix_file, nix_file_name_for_phase(phase)) {
+            let archive = phase
+                .nixpkgs_archive
+                .clone()
+                .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+            files
+                .entry(file_name)
+                .or_default()
+                .push((name.clone(), archive));
+        }
+    }
+
+    for (file_name, phases) in files {
+        let archives = phases
+            .iter()
+            .map(|(_, archive)| archive.clone())
+            .collect::<BTreeSet<_>>();
+        if archives.len() > 1 {
+            bail!(Message::new(MessageId::NixFileArchiveConflict)
+                .arg(
+                    "phases",
+                    &phases
+                        .iter()
+                        .map(|(name, archive)| format!("{} ({})", name, archive))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .arg("file", &file_name)
+                .arg(
+                    "archives",
+                    &archives.into_iter().collect::<Vec<_>>().join(", ")
+                ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Name of the Nix file the phase's packages are installed from, if it uses Nix
+pub fn nix_file_name_for_phase(phase: &Phase) -> Option<String> {
+    if !phase.uses_nix() {
+        return None;
+    }
+
+    Some(
+        phase
+            .nix_file
+            .clone()
+            .unwrap_or_else(|| nix_file_name(&phase.nixpkgs_archive)),
+    )
 }
 
 fn nix_file_name(archive: &Option<String>) -> String {
@@ -82,24 +192,36 @@ fn nix_file_name(archive: &Option<String>) -> String {
     }
 }
 
-fn nix_expression_for_group(group: &NixGroup) -> String {
+/// Name of the Nix environment, which must be unique across all installed files
+fn nix_env_name(file_name: &str, archive: &str) -> String {
+    if file_name == nix_file_name(&Some(archive.to_string())) || file_name == "nixpkgs.nix" {
+        format!("{}-env", archive)
+    } else {
+        let name = file_name
+            .trim_end_matches(".nix")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect::<String>();
+        format!("{}-env", name.trim_matches('-'))
+    }
+}
+
+fn nix_expression_for_group(file_name: &str, group: &NixGroup) -> String {
     let archive = group
         .archive
         .clone()
         .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
 
-    let mut pkgs = group.pkgs.clone();
-    pkgs.sort();
-    let pkgs = pkgs.join(" ");
-
-    let mut libs = group.libs.clone();
-    libs.sort();
-    let libs = libs.join(" ");
-
     let overlays_string = group
         .overlays
         .iter()
-        .map(|url| format!("(import (builtins.fetchTarball \"{}\"))", url))
+        .map(|overlay| {
+            if is_remote(overlay) {
+                format!("(import (builtins.fetchTarball \"{}\"))", overlay)
+            } else {
+                import_local_file(overlay)
+            }
+        })
         .collect::<Vec<String>>()
         .join("\n");
 
@@ -108,6 +230,93 @@ fn nix_expression_for_group(group: &NixGroup) -> String {
         archive
     );
 
+    formatdoc! {"
+            {{ }}:
+
+            let pkgs = {} {{ overlays = [ {} ]; }};
+            in with pkgs;
+              {}
+        ",
+        pkg_import,
+        overlays_string,
+        indent(&nix_env_for_group(file_name, group, &archive), 2),
+    }
+}
+
+/// A flake with the archive and overlays of the group as inputs, which are pinned in its lock file
+fn nix_flake_for_group(file_name: &str, group: &NixGroup) -> String {
+    let archive = group
+        .archive
+        .clone()
+        .unwrap_or_else(|| NIXPKGS_ARCHIVE.to_string());
+
+    // Only remote overlays are inputs, local ones are imported from the app
+    let remote_overlays = group
+        .overlays
+        .iter()
+        .filter(|overlay| is_remote(overlay))
+        .collect::<Vec<_>>();
+    let overlay_inputs = remote_overlays
+        .iter()
+        .enumerate()
+        .map(|(i, url)| format!("overlay{} = {{ url = \"{}\"; flake = false; }};", i, url))
+        .collect::<Vec<String>>();
+    let overlays_string = (0..remote_overlays.len())
+        .map(|i| format!("(import inputs.overlay{})", i))
+        .chain(
+            group
+                .overlays
+                .iter()
+                .filter(|overlay| !is_remote(overlay))
+                .map(|overlay| import_local_file(overlay)),
+        )
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let inputs = [
+        vec![format!(
+            "nixpkgs.url = \"github:NixOS/nixpkgs/{}\";",
+            archive
+        )],
+        overlay_inputs,
+    ]
+    .concat()
+    .join("\n");
+
+    formatdoc! {"
+            {{
+              inputs = {{
+                {}
+              }};
+
+              outputs = {{ nixpkgs, ... }}@inputs:
+                let
+                  forAllSystems = nixpkgs.lib.genAttrs [ \"x86_64-linux\" \"aarch64-linux\" ];
+                in {{
+                  packages = forAllSystems (system:
+                    let pkgs = import nixpkgs {{ inherit system; overlays = [ {} ]; }};
+                    in with pkgs; {{
+                      default = {};
+                    }});
+                }};
+            }}
+        ",
+        indent(&inputs, 4),
+        overlays_string,
+        indent(&nix_env_for_group(file_name, group, &archive), 10),
+    }
+}
+
+/// The environment of the packages and libraries of a group, evaluated `with pkgs`
+fn nix_env_for_group(file_name: &str, group: &NixGroup, archive: &str) -> String {
+    let mut pkgs = group.pkgs.clone();
+    pkgs.sort();
+    let pkgs = pkgs.join(" ");
+
+    let mut libs = group.libs.clone();
+    libs.sort();
+    let libs = libs.join(" ");
+
     // If the openssl library is added, set the OPENSSL_DIR and OPENSSL_LIB_DIR environment variables
     // In the future, we will probably want a generic way for providers to set variables based off Nix package locations
     let openssl_dirs = if libs.contains("openssl") {
@@ -119,39 +328,66 @@ fn nix_expression_for_group(group: &NixGroup) -> String {
         String::new()
     };
 
-    let name = format!("{}-env", archive);
-    let nix_expression = formatdoc! {"
-            {{ }}:
+    // Expressions are added to the environment whether they return a package or a list of them
+    let expressions = if group.expressions.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " ++ lib.flatten [ {} ]",
+            group
+                .expressions
+                .iter()
+                .map(|path| format!("({} {{ inherit pkgs; }})", import_local_file(path)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    };
 
-            let pkgs = {} {{ overlays = [ {} ]; }};
-            in with pkgs;
-              let
-                APPEND_LIBRARY_PATH = \"${{lib.makeLibraryPath [ {} ] }}\";
-                myLibraries = writeText \"libraries\" ''
-                  export LD_LIBRARY_PATH=\"${{APPEND_LIBRARY_PATH}}:$LD_LIBRARY_PATH\"
+    let name = nix_env_name(file_name, archive);
+    formatdoc! {"
+            let
+              APPEND_LIBRARY_PATH = \"${{lib.makeLibraryPath [ {} ] }}\";
+              myLibraries = writeText \"libraries\" ''
+                export LD_LIBRARY_PATH=\"${{APPEND_LIBRARY_PATH}}:$LD_LIBRARY_PATH\"
+                {}
+              '';
+            in
+              buildEnv {{
+                name = \"{name}\";
+                paths = [
+                  (runCommand \"{name}\" {{ }} ''
+                    mkdir -p $out/etc/profile.d
+                    cp ${{myLibraries}} $out/etc/profile.d/{name}.sh
+                  '')
                   {}
-                '';
-              in
-                buildEnv {{
-                  name = \"{name}\";
-                  paths = [
-                    (runCommand \"{name}\" {{ }} ''
-                      mkdir -p $out/etc/profile.d
-                      cp ${{myLibraries}} $out/etc/profile.d/{name}.sh
-                    '')
-                    {}
-                  ];
-                }}
-        ",
-        pkg_import,
-        overlays_string,
+                ]{};
+              }}",
         libs,
-        openssl_dirs,
+        indent(openssl_dirs.trim_end(), 4),
         pkgs,
+        expressions,
         name=name,
-    };
+    }
+}
+
+fn is_remote(overlay: &str) -> bool {
+    overlay.starts_with("https://") || overlay.starts_with("http://")
+}
+
+/// Import a file of the app from where it is copied to in the image
+fn import_local_file(path: &str) -> String {
+    format!(
+        "(import {}{})",
+        APP_DIR,
+        path.trim_start_matches("./").trim_start_matches('/')
+    )
+}
 
-    nix_expression
+/// Indent every line but the first, which is placed after existing text
+fn indent(text: &str, spaces: usize) -> String {
+    text.lines()
+        .collect::<Vec<_>>()
+        .join(&format!("\n{}", " ".repeat(spaces)))
 }
 
 #[cfg(test)]
@@ -159,7 +395,7 @@ mod tests {
     use super::{pkg::Pkg, *};
 
     #[test]
-    fn test_group_nix_packages_by_archive() {
+    fn test_group_nix_packages_by_file() {
         let mut setup1 = Phase::setup(Some(vec![Pkg::new("foo"), Pkg::new("bar")]));
         setup1.add_pkgs_libs(vec!["lib1".to_string()]);
 
@@ -168,25 +404,104 @@ mod tests {
 
         let setup3 = Phase::setup(Some(vec![Pkg::new("baz")]));
 
-        let groups = group_nix_packages_by_archive(&vec![setup1, setup2, setup3]);
-        assert_eq!(groups.len(), 2);
+        let mut build = Phase::build(None);
+        build.add_nix_pkgs(&[Pkg::new("qux")]);
+        build.nix_file = Some("build/env.nix".to_string());
+
+        let groups = group_nix_packages_by_file(&vec![setup1, setup2, setup3, build]);
+        assert_eq!(groups.len(), 3);
         assert_eq!(
-            groups[0],
+            groups["nixpkgs.nix"],
             NixGroup {
                 archive: None,
                 pkgs: vec!["foo".to_string(), "bar".to_string(), "baz".to_string()],
                 libs: vec!["lib1".to_string()],
-                overlays: vec![]
+                overlays: vec![],
+                expressions: vec![]
             }
         );
         assert_eq!(
-            groups[1],
+            groups["nixpkgs-archive2.nix"],
             NixGroup {
                 archive: Some("archive2".to_string()),
                 pkgs: vec!["hello".to_string(), "world".to_string()],
                 libs: vec![],
-                overlays: vec![]
+                overlays: vec![],
+                expressions: vec![]
+            }
+        );
+        assert_eq!(
+            groups["build/env.nix"],
+            NixGroup {
+                archive: None,
+                pkgs: vec!["qux".to_string()],
+                libs: vec![],
+                overlays: vec![],
+                expressions: vec![]
             }
         );
     }
+
+    #[test]
+    fn test_nix_flakes() {
+        let mut setup = Phase::setup(Some(vec![
+            Pkg::new("nodejs").from_overlay("https://example.com/overlay.tar.gz")
+        ]));
+        setup.nixpkgs_archive = Some("archive1".to_string());
+
+        let mut build = Phase::build(None);
+        build.add_nix_pkgs(&[Pkg::new("qux")]);
+        build.nix_file = Some("build/env.nix".to_string());
+
+        let phases = BTreeMap::from([("setup".to_string(), setup), ("build".to_string(), build)]);
+        let flakes = create_nix_flakes_for_phases(&phases);
+        assert_eq!(
+            flakes.keys().collect::<Vec<_>>(),
+            vec!["build/env/flake.nix", "nixpkgs-archive1/flake.nix"]
+        );
+
+        let flake = &flakes["nixpkgs-archive1/flake.nix"];
+        assert!(flake.contains("nixpkgs.url = \"github:NixOS/nixpkgs/archive1\";"));
+        assert!(flake.contains(
+            "overlay0 = { url = \"https://example.com/overlay.tar.gz\"; flake = false; };"
+        ));
+        assert!(flake.contains("overlays = [ (import inputs.overlay0) ];"));
+        assert!(flake.contains("nodejs\n"));
+        assert!(flakes["build/env/flake.nix"]
+            .contains(&format!("github:NixOS/nixpkgs/{}", NIXPKGS_ARCHIVE)));
+    }
+
+    #[test]
+    fn test_local_overlays_and_expressions() {
+        let mut setup = Phase::setup(Some(vec![Pkg::new("nodejs")]));
+        setup.nix_overlays = Some(vec![
+            "https://example.com/overlay.tar.gz".to_string(),
+            "./nix/overlay.nix".to_string(),
+        ]);
+        setup.nix_expressions = Some(vec!["nix/tools.nix".to_string()]);
+
+        let phases = BTreeMap::from([("setup".to_string(), setup)]);
+        assert_eq!(
+            nix_local_files_for_file(&phases, "nixpkgs.nix"),
+            vec!["nix/overlay.nix", "nix/tools.nix"]
+        );
+
+        let expression = &create_nix_expressions_for_phases(&phases)["nixpkgs.nix"];
+        assert!(expression.contains(
+            "(import (builtins.fetchTarball \"https://example.com/overlay.tar.gz\"))\n(import /app/nix/overlay.nix) ]"
+        ));
+        assert!(expression
+            .contains("] ++ lib.flatten [ ((import /app/nix/tools.nix) { inherit pkgs; }) ];"));
+
+        let flake = &create_nix_flakes_for_phases(&phases)["nixpkgs/flake.nix"];
+        assert!(flake
+            .contains("overlays = [ (import inputs.overlay0) (import /app/nix/overlay.nix) ];"));
+    }
+
+    #[test]
+    fn test_nix_env_name() {
+        assert_eq!(nix_env_name("nixpkgs.nix", "abc"), "abc-env");
+        assert_eq!(nix_env_name("nixpkgs-abc.nix", "abc"), "abc-env");
+        assert_eq!(nix_env_name("build/env.nix", "abc"), "build-env-env");
+    }
 }
@@ -1,6 +1,31 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Attributes that were renamed or removed in the pinned Nixpkgs archive, and their successors.
+///
+/// Add an entry here whenever `NIXPKGS_ARCHIVE` is bumped past the removal of a package,
+/// so plans and config files using the old name keep building.
+pub const RENAMED_PKGS: &[(&str, &str)] = &[
+    ("nodejs-10_x", "nodejs-12_x"),
+    ("python36", "python37"),
+    ("ruby_2_6", "ruby_2_7"),
+    ("go_1_15", "go_1_17"),
+];
+
+/// The successor of `name` in the pinned Nixpkgs archive if its top level attribute was renamed,
+/// e.g. `nodejs-10_x.pkgs.yarn` becomes `nodejs-12_x.pkgs.yarn`
+pub fn get_renamed_pkg(name: &str) -> Option<String> {
+    let (attr, rest) = match name.find('.') {
+        Some(index) => name.split_at(index),
+        None => (name, ""),
+    };
+
+    RENAMED_PKGS
+        .iter()
+        .find(|(old, _)| *old == attr)
+        .map(|(_, new)| format!("{}{}", new, rest))
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Pkg {
@@ -21,7 +46,7 @@ impl Pkg {
     pub fn to_nix_string(&self) -> String {
         match &self.overrides {
             Some(overrides) => {
-                let override_string = overrides
+                let override_string = Pkg::sorted_overrides(overrides)
                     .iter()
                     .map(|(name, value)| format!("{} = {};", name, value))
                     .collect::<Vec<_>>()
@@ -44,6 +69,13 @@ impl Pkg {
         self
     }
 
+    /// Overrides in a stable order, so the same package always results in the same Nix expression
+    fn sorted_overrides(overrides: &HashMap<String, String>) -> Vec<(&String, &String)> {
+        let mut sorted = overrides.iter().collect::<Vec<_>>();
+        sorted.sort();
+        sorted
+    }
+
     #[must_use]
     pub fn from_overlay(mut self, overlay: &str) -> Self {
         self.overlay = Some(overlay.to_string());
@@ -53,7 +85,7 @@ impl Pkg {
     pub fn to_pretty_string(&self) -> String {
         match &self.overrides {
             Some(overrides) => {
-                let override_string = overrides
+                let override_string = Pkg::sorted_overrides(overrides)
                     .iter()
                     .map(|(name, value)| format!("{} = {}", name, value))
                     .collect::<Vec<_>>()
@@ -74,6 +106,20 @@ mod tests {
         assert_eq!(Pkg::new("cowsay").to_nix_string(), "cowsay".to_string());
     }
 
+    #[test]
+    fn test_get_renamed_pkg() {
+        assert_eq!(
+            get_renamed_pkg("nodejs-10_x"),
+            Some("nodejs-12_x".to_string())
+        );
+        assert_eq!(
+            get_renamed_pkg("nodejs-10_x.pkgs.yarn"),
+            Some("nodejs-12_x.pkgs.yarn".to_string())
+        );
+        assert_eq!(get_renamed_pkg("nodejs-16_x"), None);
+        assert_eq!(get_renamed_pkg("python36Packages.pip"), None);
+    }
+
     #[test]
     fn test_pkg_single_override_to_string() {
         assert_eq!(
@@ -83,4 +129,15 @@ mod tests {
             "(cowsay.override { hello = hello_1.1; })".to_string()
         );
     }
+
+    #[test]
+    fn test_pkg_multiple_overrides_to_string() {
+        assert_eq!(
+            Pkg::new("rust")
+                .set_override("targets", "[\"wasm32\"]")
+                .set_override("extensions", "[\"rustfmt\"]")
+                .to_nix_string(),
+            "(rust.override { extensions = [\"rustfmt\"]; targets = [\"wasm32\"]; })".to_string()
+        );
+    }
 }
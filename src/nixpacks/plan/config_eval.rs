@@ -0,0 +1,42 @@
+use crate::nixpacks::{
+    app::App,
+    messages::{Message, MessageId},
+};
+use anyhow::{bail, Context, Result};
+use std::process::Command;
+
+/// Config files that are evaluated to JSON before being parsed into a plan
+pub const EVALUATED_CONFIG_FILES: &[&str] = &["nixpacks.ncl", "nixpacks.cue"];
+
+/// Evaluate a Nickel or CUE config file to JSON with the language's CLI.
+///
+/// The result has the same structure as `nixpacks.json`.
+pub fn evaluate_config_file(app: &App, file: &str) -> Result<String> {
+    let mut cmd = match file.rsplit('.').next() {
+        Some("ncl") => {
+            let mut cmd = Command::new("nickel");
+            cmd.args(["export", "--format", "json", file]);
+            cmd
+        }
+        Some("cue") => {
+            let mut cmd = Command::new("cue");
+            cmd.args(["export", "--out", "json", file]);
+            cmd
+        }
+        _ => bail!(Message::new(MessageId::UnknownConfigFileType).arg("file", file)),
+    };
+
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd
+        .current_dir(&app.source)
+        .output()
+        .with_context(|| format!("Running {}. Please make sure it is installed", program))?;
+
+    if !output.status.success() {
+        bail!(Message::new(MessageId::ConfigEvaluationFailed)
+            .arg("file", file)
+            .arg("error", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
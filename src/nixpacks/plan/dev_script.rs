@@ -0,0 +1,253 @@
+use super::BuildPlan;
+use crate::nixpacks::{
+    environment::EnvironmentVariables, nix::create_nix_expressions_for_phases, NIX_PACKS_VERSION,
+};
+use anyhow::Result;
+use indoc::formatdoc;
+use regex::Regex;
+use std::path::Path;
+
+pub const DEV_SCRIPT_NAME: &str = "dev.sh";
+
+/// Directories of the image that commands must not touch on the machine of the developer
+const IMAGE_ONLY_DIRS: &str = r#"(^|[\s'"=:;&|(])/(root|etc|usr|var|opt)/"#;
+
+impl BuildPlan {
+    /// Render a bash script that runs the plan locally, without Docker.
+    ///
+    /// The script builds the same Nix environment that would be installed in the image,
+    /// exports the plan variables, runs every phase in order, and then execs the start command.
+    /// Cleanup commands, the commands of the setup phase, and commands that write to directories
+    /// outside of the app only make sense in the image, so they are left out as comments.
+    /// The script runs in `app_dir`, or in its own directory if no app directory is given.
+    pub fn get_dev_script(&self, app_dir: Option<&Path>) -> Result<String> {
+        let export_variables = |variables: Option<EnvironmentVariables>| {
+            variables
+                .unwrap_or_default()
+                .iter()
+                .map(|(name, value)| format!("export {}={}", name, shell_quote(value)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let variables = [
+            export_variables(self.build_variables.clone()),
+            export_variables(self.variables.clone()),
+        ]
+        .into_iter()
+        .filter(|exports| !exports.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+        let runtime_variables = export_variables(self.runtime_variables.clone());
+
+        let nix_envs = create_nix_expressions_for_phases(&self.phases.clone().unwrap_or_default())
+            .iter()
+            .map(|(name, expression)| {
+                formatdoc! {"
+                    mkdir -p \"$(dirname .nixpacks/{name})\"
+                    cat > .nixpacks/{name} <<'NIXPACKS_NIX_EOF'
+                    {expression}
+                    NIXPACKS_NIX_EOF
+                    nix_env=$(nix-build --no-out-link .nixpacks/{name})
+                    use_nix_env \"$nix_env\"
+                    ",
+                    name = name,
+                    expression = expression.trim_end(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let apt_pkgs = self.all_apt_packages();
+        let apt_warning = if apt_pkgs.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "echo \"Warning: the following apt packages are not installed automatically: {}\"",
+                apt_pkgs.join(" ")
+            )
+        };
+
+        let image_only_re = Regex::new(IMAGE_ONLY_DIRS)?;
+        let phases = self
+            .get_sorted_phases()?
+            .iter()
+            .filter(|phase| phase.runs_docker_commands())
+            .map(|phase| {
+                let paths = phase
+                    .paths
+                    .clone()
+                    .unwrap_or_default()
+                    .iter()
+                    // Paths inside of the image app directory are relative to the script locally
+                    .map(|path| path.replacen("/app/", "$PWD/", 1))
+                    .map(|path| format!("export PATH=\"{}:$PATH\"", path))
+                    .collect::<Vec<_>>();
+                let cmds = phase
+                    .cmds
+                    .clone()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|cmd| {
+                        if phase.get_name() == "setup" || image_only_re.is_match(cmd) {
+                            format!("# Only runs in the image: {}", cmd.replace('\n', " "))
+                        } else {
+                            cmd.clone()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                format!(
+                    "# {} phase\n{}",
+                    phase.get_name(),
+                    [paths, cmds].concat().join("\n")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let start = match self.start_phase.clone().unwrap_or_default().cmd {
+            Some(cmd) if !runtime_variables.is_empty() => {
+                format!("{}\nexec {}", runtime_variables, cmd)
+            }
+            Some(cmd) => format!("exec {}", cmd),
+            None => "echo \"No start command could be found\"".to_string(),
+        };
+
+        let cd = match app_dir {
+            Some(app_dir) => format!("cd {}", shell_quote(&app_dir.to_string_lossy())),
+            None => "cd \"$(dirname \"${BASH_SOURCE[0]}\")\"".to_string(),
+        };
+
+        let script = formatdoc! {"
+            #!/usr/bin/env bash
+            # Generated by Nixpacks v{version}. Runs the build plan locally without Docker.
+            set -eo pipefail
+
+            {cd}
+            mkdir -p .nixpacks
+
+            if ! command -v nix-build > /dev/null; then
+              echo \"Nix is required to run this script https://nixos.org/download.html\"
+              exit 1
+            fi
+
+            use_nix_env() {{
+              export PATH=\"$1/bin:$PATH\"
+              for profile in \"$1\"/etc/profile.d/*.sh; do
+                if [ -f \"$profile\" ]; then . \"$profile\"; fi
+              done
+            }}
+
+            {variables}
+
+            {nix_envs}
+            {apt_warning}
+
+            {phases}
+
+            {start}
+            ",
+            version = NIX_PACKS_VERSION,
+            cd = cd,
+            variables = variables,
+            nix_envs = nix_envs,
+            apt_warning = apt_warning,
+            phases = phases,
+            start = start,
+        };
+
+        Ok(script)
+    }
+}
+
+/// Wrap a value in single quotes so that it is passed to the shell verbatim
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dev_script() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [variables]
+            HELLO = "it's me"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+            aptPkgs = ["wget"]
+
+            [phases.install]
+            cmds = ["npm ci"]
+            paths = ["/app/node_modules/.bin"]
+            dependsOn = ["setup"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+            dependsOn = ["install"]
+
+            [start]
+            cmd = "npm run start"
+            "#,
+        )
+        .unwrap();
+
+        let script = plan.get_dev_script(None).unwrap();
+
+        assert!(script.starts_with("#!/usr/bin/env bash"));
+        assert!(script.contains("export HELLO='it'\\''s me'"));
+        assert!(script.contains("nix_env=$(nix-build --no-out-link .nixpacks/nixpkgs.nix)"));
+        assert!(script.contains("wget"));
+        assert!(script
+            .contains("# install phase\nexport PATH=\"$PWD/node_modules/.bin:$PATH\"\nnpm ci"));
+        assert!(script.find("npm ci").unwrap() < script.find("npm run build").unwrap());
+        assert!(script.contains("exec npm run start"));
+    }
+
+    #[test]
+    fn test_dev_script_image_only_cmds() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["ruby"]
+            cmds = ["echo 'source /usr/local/rvm/scripts/rvm' >> /root/.profile"]
+            cleanupCmds = ["rm -rf /usr/local/rvm/src"]
+
+            [phases.install]
+            cmds = ["python -m venv /opt/venv", "bundle install"]
+            dependsOn = ["setup"]
+
+            [phases.build]
+            cmds = ["swift build"]
+            cleanupCmds = ["rm -rf ./.build"]
+            dependsOn = ["install"]
+            "#,
+        )
+        .unwrap();
+
+        let script = plan.get_dev_script(Some(Path::new("/src/my app"))).unwrap();
+        let run_lines = script
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .collect::<Vec<_>>();
+
+        assert!(script.contains("cd '/src/my app'"));
+        assert!(run_lines.iter().all(|line| !line.contains("rm -rf")));
+        assert!(run_lines
+            .iter()
+            .all(|line| !line.contains("/root/.profile")));
+        assert!(run_lines.iter().all(|line| !line.contains("/opt/venv")));
+        assert!(run_lines.contains(&"bundle install"));
+        assert!(run_lines.contains(&"swift build"));
+        assert!(script.contains("# Only runs in the image: python -m venv /opt/venv"));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("hello"), "'hello'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
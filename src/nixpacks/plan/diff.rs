@@ -0,0 +1,165 @@
+use super::BuildPlan;
+use anyhow::Result;
+use serde_json::Value;
+use std::fmt;
+
+/// A single value that differs between two plans, addressed by its path in the plan, e.g. `phases.build.cmds`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanDifference {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl fmt::Display for PlanDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanDifference::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            PlanDifference::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            PlanDifference::Changed { path, old, new } => {
+                write!(f, "~ {}: {} -> {}", path, old, new)
+            }
+        }
+    }
+}
+
+impl BuildPlan {
+    /// Everything that changed going from `self` to `other`.
+    /// Objects are compared key by key, while any other values are compared as a whole.
+    pub fn diff(&self, other: &BuildPlan) -> Result<Vec<PlanDifference>> {
+        let mut differences = Vec::new();
+        diff_values("", &self.to_value()?, &other.to_value()?, &mut differences);
+        Ok(differences)
+    }
+
+    fn to_value(&self) -> Result<Value> {
+        let mut plan = self.clone();
+        plan.remove_phase_names();
+        Ok(serde_json::to_value(&plan)?)
+    }
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, differences: &mut Vec<PlanDifference>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let key_path = join_path(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_values(&key_path, old_value, new_value, differences),
+                    None if !old_value.is_null() => differences.push(PlanDifference::Removed {
+                        path: key_path,
+                        value: old_value.clone(),
+                    }),
+                    None => {}
+                }
+            }
+
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) && !new_value.is_null() {
+                    differences.push(PlanDifference::Added {
+                        path: join_path(path, key),
+                        value: new_value.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Null, new) => differences.push(PlanDifference::Added {
+            path: path.to_string(),
+            value: new.clone(),
+        }),
+        (old, Value::Null) => differences.push(PlanDifference::Removed {
+            path: path.to_string(),
+            value: old.clone(),
+        }),
+        (old, new) if old != new => differences.push(PlanDifference::Changed {
+            path: path.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_same_plan() {
+        let plan = BuildPlan::from_toml(
+            r#"
+            [phases.build]
+            cmds = ["npm run build"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(plan.diff(&plan).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_plans() {
+        let old = BuildPlan::from_toml(
+            r#"
+            [variables]
+            NODE_ENV = "production"
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+        let new = BuildPlan::from_toml(
+            r#"
+            [phases.build]
+            cmds = ["yarn build"]
+            nixPkgs = ["nodejs"]
+
+            [start]
+            cmd = "npm start"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            old.diff(&new).unwrap(),
+            vec![
+                PlanDifference::Changed {
+                    path: "phases.build.cmds".to_string(),
+                    old: json!(["npm run build"]),
+                    new: json!(["yarn build"])
+                },
+                PlanDifference::Added {
+                    path: "phases.build.nixPkgs".to_string(),
+                    value: json!(["nodejs"])
+                },
+                PlanDifference::Removed {
+                    path: "variables".to_string(),
+                    value: json!({ "NODE_ENV": "production" })
+                },
+            ]
+        );
+    }
+}
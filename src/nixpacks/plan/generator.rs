@@ -4,13 +4,20 @@ use crate::{
     nixpacks::{
         app::App,
         environment::{Environment, EnvironmentVariables},
-        plan::{BuildPlan, PlanGenerator},
+        images::is_shellless_image,
+        messages::{Message, MessageId},
+        plan::{
+            schema::{validate_json_plan, validate_toml_plan},
+            BuildPlan, PlanGenerator,
+        },
     },
-    providers::{procfile::ProcfileProvider, Provider},
+    providers::{procfile::ProcfileProvider, tool_versions::MiseConfig, Provider},
 };
 use anyhow::{bail, Context, Ok, Result};
 use colored::Colorize;
 
+#[cfg(feature = "experimental-config")]
+use super::config_eval;
 use super::{
     merge::Mergeable,
     utils::{fill_auto_in_vec, remove_autos_from_vec},
@@ -31,15 +38,18 @@ pub struct NixpacksBuildPlanGenerator<'a> {
 
 impl<'a> PlanGenerator for NixpacksBuildPlanGenerator<'a> {
     fn generate_plan(&mut self, app: &App, environment: &Environment) -> Result<BuildPlan> {
-        // If the provider defines a build plan in the new format, use that
-        let plan = self.get_build_plan(app, environment)?;
+        let (plan, _) = self.generate_plan_with_reasons(app, environment)?;
 
         Ok(plan)
     }
 
     fn get_plan_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+        self.print_config_file_notice(app, env)?;
+
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
-        let providers = self.get_all_providers(app, env, plan_before_providers.providers)?;
+        let provider_env = get_provider_environment(env, &plan_before_providers);
+        let providers =
+            self.get_all_providers(app, &provider_env, plan_before_providers.providers)?;
 
         Ok(providers)
     }
@@ -53,47 +63,128 @@ impl NixpacksBuildPlanGenerator<'_> {
         NixpacksBuildPlanGenerator { providers, config }
     }
 
+    /// Generate the plan, along with why each provider of the plan is used,
+    /// e.g. `detected from package.json` or `set in providers`
+    pub fn generate_plan_with_reasons(
+        &mut self,
+        app: &App,
+        environment: &Environment,
+    ) -> Result<(BuildPlan, Vec<(String, String)>)> {
+        self.print_config_file_notice(app, environment)?;
+
+        // If the provider defines a build plan in the new format, use that
+        self.get_build_plan(app, environment)
+    }
+
     /// Get a build plan from the provider and by applying the config from the environment
-    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+    fn get_build_plan(
+        &self,
+        app: &App,
+        env: &Environment,
+    ) -> Result<(BuildPlan, Vec<(String, String)>)> {
         let plan_before_providers = self.get_plan_before_providers(app, env)?;
+        let provider_env = get_provider_environment(env, &plan_before_providers);
 
-        let provider_plan =
-            self.get_plan_from_providers(app, env, plan_before_providers.providers.clone())?;
+        let reasons =
+            self.get_provider_reasons(app, &provider_env, plan_before_providers.providers.clone())?;
+        let provider_plan = self.get_plan_from_providers(
+            app,
+            &provider_env,
+            reasons.iter().map(|(name, _)| name.clone()).collect(),
+        )?;
 
         let procfile_plan = (ProcfileProvider {})
-            .get_build_plan(app, env)?
+            .get_build_plan(app, &provider_env)?
             .unwrap_or_default();
 
+        let provider_plan = BuildPlan::merge_plans(&vec![provider_plan, procfile_plan]);
         let mut plan =
-            BuildPlan::merge_plans(&vec![provider_plan, procfile_plan, plan_before_providers]);
+            BuildPlan::merge_plans(&vec![provider_plan.clone(), plan_before_providers.clone()]);
+        order_config_phases(&mut plan, &provider_plan, &plan_before_providers)?;
+        plan.apply_hooks()?;
+
+        // A shell-less run image picked by a provider can't run a start command that was
+        // changed to more than a binary, e.g. by a Procfile, so that runs in the build image
+        let config_run_image = plan_before_providers
+            .start_phase
+            .as_ref()
+            .and_then(|start| start.run_image.as_ref());
+        if let Some(start) = plan.start_phase.as_mut() {
+            if config_run_image.is_none()
+                && start.run_image.as_deref().map_or(false, is_shellless_image)
+                && !start.runs_bare_binary()
+            {
+                start.run_image = None;
+            }
+        }
 
         if !env.get_variable_names().is_empty() {
             plan.add_variables(Environment::clone_variables(env));
         }
 
+        // Default `PORT` to the port the app listens on, unless it is already set
+        if let Some(port) = plan.get_exposed_port(env) {
+            if !plan.all_variables().contains_key("PORT") {
+                plan.add_runtime_variables(EnvironmentVariables::from([(
+                    "PORT".to_string(),
+                    port.to_string(),
+                )]));
+            }
+        }
+
         plan.pin();
 
-        Ok(plan)
+        for (old_pkg, new_pkg) in plan.rename_nix_pkgs() {
+            println!(
+                "{}",
+                format!(
+                    "\n {}\n",
+                    Message::new(MessageId::RenamedNixPackage)
+                        .arg("old", &old_pkg)
+                        .arg("new", &new_pkg)
+                )
+                .bright_yellow()
+            );
+        }
+
+        Ok((plan, reasons))
     }
 
     fn get_plan_before_providers(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        let mise_plan = NixpacksBuildPlanGenerator::get_mise_plan(app)?;
         let file_plan = self.read_file_plan(app, env)?;
-        let env_plan = BuildPlan::from_environment(env);
         let cli_plan = self.config.plan.clone().unwrap_or_default();
-        let plan_before_providers = BuildPlan::merge_plans(&vec![file_plan, env_plan, cli_plan]);
+
+        // `NIXPACKS_*` variables of the config configure the plan like the environment does
+        let config_plan = BuildPlan::merge_plans(&vec![
+            mise_plan.clone(),
+            file_plan.clone(),
+            cli_plan.clone(),
+        ]);
+        let env_plan = BuildPlan::from_environment(&get_provider_environment(env, &config_plan));
+        let plan_before_providers =
+            BuildPlan::merge_plans(&vec![mise_plan, file_plan, env_plan, cli_plan]);
 
         Ok(plan_before_providers)
     }
 
-    fn get_detected_providers(&self, app: &App, env: &Environment) -> Result<Vec<String>> {
+    /// The providers that detect the app, and the files each was detected from
+    fn get_detected_providers(
+        &self,
+        app: &App,
+        env: &Environment,
+    ) -> Result<Vec<(String, Vec<String>)>> {
         let mut providers = Vec::new();
 
         for provider in self.providers {
-            if provider.detect(app, env)? {
-                providers.push(provider.name().to_string());
+            let (detected, matches) = detect_provider(*provider, app, env)?;
+            if detected {
+                providers.push((provider.name().to_string(), matches));
 
-                // Only match a single provider... for now
-                break;
+                // Only match a single provider, unless the app uses every provider that matches
+                if !env.is_config_variable_truthy("POLYGLOT") {
+                    break;
+                }
             }
         }
 
@@ -107,30 +198,99 @@ impl NixpacksBuildPlanGenerator<'_> {
         env: &Environment,
         manually_providers: Option<Vec<String>>,
     ) -> Result<Vec<String>> {
-        let detected_providers = self.get_detected_providers(app, env)?;
-        let provider_names = remove_autos_from_vec(
+        Ok(self
+            .get_provider_reasons(app, env, manually_providers)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// The providers that will be used to create the plan, and why each is used,
+    /// e.g. `detected from package.json` or `set in providers`
+    fn get_provider_reasons(
+        &self,
+        app: &App,
+        env: &Environment,
+        manually_providers: Option<Vec<String>>,
+    ) -> Result<Vec<(String, String)>> {
+        let manually_providers = manually_providers.unwrap_or_else(|| vec!["...".to_string()]);
+
+        // Providers are only detected when the list includes them with `...`
+        let detected_providers = if manually_providers
+            .iter()
+            .any(|name| name == "..." || name == "@auto")
+        {
+            self.get_detected_providers(app, env)?
+        } else {
+            Vec::new()
+        };
+
+        let mut reasons: Vec<(String, String)> = Vec::new();
+        for name in remove_autos_from_vec(
             fill_auto_in_vec(
-                Some(detected_providers),
-                Some(manually_providers.unwrap_or_else(|| vec!["...".to_string()])),
+                Some(
+                    detected_providers
+                        .iter()
+                        .map(|(name, _)| name.clone())
+                        .collect(),
+                ),
+                Some(manually_providers.clone()),
             )
             .unwrap_or_default(),
-        );
+        ) {
+            if reasons.iter().any(|(used, _)| used == &name) {
+                continue;
+            }
+
+            let matches = detected_providers
+                .iter()
+                .find(|(detected, _)| detected == &name && !manually_providers.contains(&name))
+                .map(|(_, matches)| matches);
+            let reason = match matches {
+                Some(matches) if matches.is_empty() => "detected".to_string(),
+                Some(matches) => format!("detected from {}", matches.join(", ")),
+                None => "set in providers".to_string(),
+            };
+            reasons.push((name, reason));
+        }
+
+        Ok(reasons)
+    }
+
+    /// Every provider that detects the app, not only the ones used, and the files each was detected from
+    pub fn get_matching_providers(
+        &self,
+        app: &App,
+        env: &Environment,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let plan_before_providers = self.get_plan_before_providers(app, env)?;
+        let provider_env = get_provider_environment(env, &plan_before_providers);
+
+        let mut matching = Vec::new();
+        for provider in self.providers {
+            let (detected, matches) = detect_provider(*provider, app, &provider_env)?;
+            if detected {
+                matching.push((provider.name().to_string(), matches));
+            }
+        }
 
-        Ok(provider_names)
+        Ok(matching)
     }
 
     fn get_plan_from_providers(
         &self,
         app: &App,
         env: &Environment,
-        manual_providers: Option<Vec<String>>,
+        provider_names: Vec<String>,
     ) -> Result<BuildPlan> {
-        let provider_names = self.get_all_providers(app, env, manual_providers)?;
-
         if provider_names.len() > 1 {
             println!(
                 "{}",
-                "\n Using multiple providers is experimental\n".bright_yellow()
+                format!(
+                    "\n {}\n",
+                    Message::new(MessageId::MultipleProvidersExperimental)
+                )
+                .bright_yellow()
             );
         }
 
@@ -143,9 +303,13 @@ impl NixpacksBuildPlanGenerator<'_> {
             let provider = self.providers.iter().find(|p| p.name() == name);
             if let Some(provider) = provider {
                 if let Some(mut provider_plan) = provider.get_build_plan(app, env)? {
-                    // All but the first provider have their phases prefixed with their name
+                    // All but the first provider have their phases prefixed with their name,
+                    // and run before the build of the first provider
                     if count > 0 {
                         provider_plan.prefix_phases(provider.name());
+                        for phase in provider_plan.get_last_phases() {
+                            plan.add_dependency_between_phases("build", &phase);
+                        }
                     }
 
                     let metadata_string = provider
@@ -153,10 +317,15 @@ impl NixpacksBuildPlanGenerator<'_> {
                         .join_as_comma_separated(provider.name().to_owned());
                     metadata.push(metadata_string);
 
+                    // Every provider's toolchain is part of the build, so the largest minimums apply
+                    let provider_resources = provider_plan.resources.take();
                     plan = BuildPlan::merge(&provider_plan, &plan);
+                    if let Some(resources) = provider_resources {
+                        plan.add_resources(resources);
+                    }
                 }
             } else if name != "..." && name != "@auto" {
-                bail!("Provider {} not found", name);
+                bail!(Message::new(MessageId::ProviderNotFound).arg("name", &name));
             }
 
             count += 1;
@@ -172,12 +341,25 @@ impl NixpacksBuildPlanGenerator<'_> {
         Ok(plan)
     }
 
-    fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+    /// The `[env]` of a mise config as variables, which the config file and environment override
+    fn get_mise_plan(app: &App) -> Result<BuildPlan> {
+        let mut plan = BuildPlan::default();
+        if let Some((config, _)) = MiseConfig::from_app(app)? {
+            if !config.env.is_empty() {
+                plan.add_variables(config.env);
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /// The config file of the app, from `--config`, `NIXPACKS_CONFIG_FILE`, or the default file names
+    fn get_config_file(&self, app: &App, env: &Environment) -> Result<Option<String>> {
         let file_path = if let Some(file_path) = &self.config.config_file {
             Some(file_path.clone())
         } else if let Some(env_config_file) = env.get_config_variable("CONFIG_FILE") {
             if !app.includes_file(&env_config_file) {
-                bail!("Config file {} does not exist", env_config_file);
+                bail!(Message::new(MessageId::ConfigFileNotFound).arg("file", &env_config_file));
             }
 
             Some(env_config_file)
@@ -186,40 +368,140 @@ impl NixpacksBuildPlanGenerator<'_> {
         } else if app.includes_file("nixpacks.json") {
             Some("nixpacks.json".to_owned())
         } else {
-            None
+            NixpacksBuildPlanGenerator::find_evaluated_config_file(app)
         };
 
-        let plan =
-            if let Some(file_path) = file_path {
-                let filename = Path::new(&file_path);
-                let ext = filename.extension().unwrap_or_default();
-
-                let contents = app.read_file(file_path.as_str()).with_context(|| {
-                    format!("Failed to read Nixpacks config file `{}`", file_path)
-                })?;
-                let plan = if ext == "toml" {
-                    BuildPlan::from_toml(&contents)
-                } else if ext == "json" {
-                    BuildPlan::from_json(&contents)
-                } else {
-                    bail!("Unknown file type: {}", file_path)
-                };
-
-                Some(plan.with_context(|| {
-                    format!("Failed to parse Nixpacks config file `{}`", file_path)
-                })?)
-            } else {
-                None
-            };
+        Ok(file_path)
+    }
 
-        if plan.is_some() {
+    fn print_config_file_notice(&self, app: &App, env: &Environment) -> Result<()> {
+        if self.get_config_file(app, env)?.is_some() {
             println!(
                 "{}",
-                "\n Nixpacks file based configuration is experimental and may change\n"
-                    .bright_yellow()
+                format!("\n {}\n", Message::new(MessageId::ConfigFileExperimental)).bright_yellow()
             );
         }
 
+        Ok(())
+    }
+
+    fn read_file_plan(&self, app: &App, env: &Environment) -> Result<BuildPlan> {
+        let plan = if let Some(file_path) = self.get_config_file(app, env)? {
+            let filename = Path::new(&file_path);
+            let ext = filename.extension().unwrap_or_default();
+
+            let contents = app
+                .read_file(file_path.as_str())
+                .with_context(|| format!("Failed to read Nixpacks config file `{}`", file_path))?;
+            let plan = if ext == "toml" {
+                validate_toml_plan(&contents).and_then(|_| BuildPlan::from_toml(&contents))
+            } else if ext == "json" {
+                validate_json_plan(&contents).and_then(|_| BuildPlan::from_json(&contents))
+            } else if ext == "ncl" || ext == "cue" {
+                NixpacksBuildPlanGenerator::evaluate_config_file(app, &file_path).and_then(|json| {
+                    validate_json_plan(&json)?;
+                    BuildPlan::from_json(json)
+                })
+            } else {
+                bail!(Message::new(MessageId::UnknownConfigFileType).arg("file", &file_path))
+            };
+
+            Some(
+                plan.with_context(|| {
+                    format!("Failed to parse Nixpacks config file `{}`", file_path)
+                })?,
+            )
+        } else {
+            None
+        };
+
         Ok(plan.unwrap_or_default())
     }
+
+    #[cfg(feature = "experimental-config")]
+    fn find_evaluated_config_file(app: &App) -> Option<String> {
+        config_eval::EVALUATED_CONFIG_FILES
+            .iter()
+            .find(|file| app.includes_file(file))
+            .map(ToString::to_string)
+    }
+
+    #[cfg(not(feature = "experimental-config"))]
+    fn find_evaluated_config_file(_app: &App) -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "experimental-config")]
+    fn evaluate_config_file(app: &App, file_path: &str) -> Result<String> {
+        config_eval::evaluate_config_file(app, file_path)
+    }
+
+    #[cfg(not(feature = "experimental-config"))]
+    fn evaluate_config_file(_app: &App, file_path: &str) -> Result<String> {
+        bail!(Message::new(MessageId::ConfigFileTypeNotEnabled).arg("file", file_path))
+    }
+}
+
+/// Phases that only the config defines run after the phases of the providers, unless they set
+/// `dependsOn` or a provider phase depends on them. Every phase the config depends on must exist
+fn order_config_phases(
+    plan: &mut BuildPlan,
+    provider_plan: &BuildPlan,
+    config_plan: &BuildPlan,
+) -> Result<()> {
+    let last_phases = provider_plan.get_last_phases();
+    let provider_dependencies = provider_plan
+        .phases
+        .clone()
+        .unwrap_or_default()
+        .into_values()
+        .flat_map(|phase| phase.depends_on.unwrap_or_default())
+        .collect::<Vec<_>>();
+    let phase_names = plan
+        .phases
+        .clone()
+        .unwrap_or_default()
+        .into_keys()
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    for (name, config_phase) in config_plan.phases.clone().unwrap_or_default() {
+        if provider_plan.get_phase(&name).is_none() && !provider_dependencies.contains(&name) {
+            if let Some(phase) = plan.get_phase_mut(&name) {
+                if phase.depends_on.is_none() && !last_phases.is_empty() {
+                    phase.depends_on = Some(last_phases.clone());
+                }
+            }
+        }
+
+        for dependency in remove_autos_from_vec(config_phase.depends_on.unwrap_or_default()) {
+            if plan.get_phase(&dependency).is_none() {
+                bail!(Message::new(MessageId::UnknownPhaseDependency)
+                    .arg("phase", &name)
+                    .arg("dependency", &dependency)
+                    .arg("phases", &phase_names));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a provider detects the app, and the files of the app it looked for that exist
+fn detect_provider(
+    provider: &dyn Provider,
+    app: &App,
+    env: &Environment,
+) -> Result<(bool, Vec<String>)> {
+    let recording = app.recording();
+    let detected = provider.detect(&recording, env)?;
+    Ok((detected, recording.into_matches()))
+}
+
+/// Providers are configured with `NIXPACKS_*` variables from either the environment
+/// or the `variables` of the config file, with the environment taking precedence
+fn get_provider_environment(env: &Environment, plan: &BuildPlan) -> Environment {
+    let mut variables = plan.variables.clone().unwrap_or_default();
+    variables.extend(Environment::clone_variables(env));
+    Environment::new(variables)
 }
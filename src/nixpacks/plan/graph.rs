@@ -0,0 +1,374 @@
+use super::{phase::Phase, BuildPlan};
+use crate::nixpacks::images::DEFAULT_BASE_IMAGE;
+use anyhow::{bail, Result};
+use std::{collections::BTreeSet, str::FromStr};
+
+/// Format of the graph output by `nixpacks plan --graph`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => bail!("Invalid graph format {}. Expected dot or mermaid", s),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum NodeKind {
+    Phase,
+    File,
+    Start,
+}
+
+#[derive(Debug)]
+struct Node {
+    id: String,
+    label: Vec<String>,
+    kind: NodeKind,
+}
+
+#[derive(Debug)]
+struct Stage {
+    label: String,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+struct Edge {
+    from: String,
+    to: String,
+    label: Option<String>,
+    dashed: bool,
+}
+
+/// Intermediate representation of the phase DAG shared by all output formats
+#[derive(Debug, Default)]
+struct Graph {
+    stages: Vec<Stage>,
+    files: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl BuildPlan {
+    /// Render the phase DAG, the app files each phase copies, and the Dockerfile stages
+    pub fn get_graph(&self, format: GraphFormat) -> Result<String> {
+        let graph = self.build_graph()?;
+
+        Ok(match format {
+            GraphFormat::Dot => graph.to_dot(),
+            GraphFormat::Mermaid => graph.to_mermaid(),
+        })
+    }
+
+    fn build_graph(&self) -> Result<Graph> {
+        let phases = self.get_sorted_phases()?;
+        let phase_id = |name: &str| {
+            format!(
+                "phase_{}",
+                phases
+                    .iter()
+                    .position(|p| p.get_name() == name)
+                    .unwrap_or(0)
+            )
+        };
+
+        // Phases that copy the whole app are rebuilt whenever any file changes
+        let phase_files = |phase: &Phase| {
+            if phase.runs_docker_commands() {
+                phase
+                    .only_include_files
+                    .clone()
+                    .unwrap_or_else(|| vec![".".to_string()])
+            } else {
+                Vec::new()
+            }
+        };
+        let files = phases
+            .iter()
+            .flat_map(phase_files)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let file_id =
+            |file: &str| format!("file_{}", files.iter().position(|f| f == file).unwrap_or(0));
+
+        let mut graph = Graph::default();
+        let mut build_nodes = Vec::new();
+        let mut depended_on = BTreeSet::new();
+
+        for phase in &phases {
+            let id = phase_id(&phase.get_name());
+
+            let mut label = vec![phase.get_name()];
+            label.extend(phase.nix_pkgs.clone().unwrap_or_default());
+            label.extend(phase.apt_pkgs.clone().unwrap_or_default());
+            label.extend(phase.get_cmds_with_cleanup());
+            build_nodes.push(Node {
+                id: id.clone(),
+                label,
+                kind: NodeKind::Phase,
+            });
+
+            for dependency in phase.depends_on.clone().unwrap_or_default() {
+                depended_on.insert(dependency.clone());
+                graph.edges.push(Edge {
+                    from: phase_id(&dependency),
+                    to: id.clone(),
+                    label: None,
+                    dashed: false,
+                });
+            }
+
+            for file in phase_files(phase) {
+                graph.edges.push(Edge {
+                    from: file_id(&file),
+                    to: id.clone(),
+                    label: Some("COPY".to_string()),
+                    dashed: true,
+                });
+            }
+        }
+
+        graph.files = files
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, file)| Node {
+                id: format!("file_{}", i),
+                label: vec![file],
+                kind: NodeKind::File,
+            })
+            .collect();
+
+        let start = self.start_phase.clone().unwrap_or_default();
+        let start_node = Node {
+            id: "start".to_string(),
+            label: vec!["start".to_string(), start.cmd.unwrap_or_default()],
+            kind: NodeKind::Start,
+        };
+
+        // The last phases of the build lead to the start command
+        let copy_label = start
+            .run_image
+            .as_ref()
+            .map(|_| "COPY --from=0".to_string());
+        for phase in phases
+            .iter()
+            .filter(|phase| !depended_on.contains(&phase.get_name()))
+        {
+            graph.edges.push(Edge {
+                from: phase_id(&phase.get_name()),
+                to: start_node.id.clone(),
+                label: copy_label.clone(),
+                dashed: start.run_image.is_some(),
+            });
+        }
+
+        let build_image = self
+            .build_image
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
+        if let Some(run_image) = start.run_image {
+            graph.stages.push(Stage {
+                label: format!("build: {}", build_image),
+                nodes: build_nodes,
+            });
+            graph.stages.push(Stage {
+                label: format!("run: {}", run_image),
+                nodes: vec![start_node],
+            });
+        } else {
+            build_nodes.push(start_node);
+            graph.stages.push(Stage {
+                label: format!("build: {}", build_image),
+                nodes: build_nodes,
+            });
+        }
+
+        Ok(graph)
+    }
+}
+
+impl Graph {
+    fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph nixpacks {".to_string(),
+            "  rankdir=LR;".to_string(),
+            "  node [shape=box];".to_string(),
+        ];
+
+        for (i, stage) in self.stages.iter().enumerate() {
+            lines.push(format!("  subgraph cluster_{} {{", i));
+            lines.push(format!("    label=\"{}\";", escape_dot(&stage.label)));
+            for node in &stage.nodes {
+                lines.push(format!("    {}", node_to_dot(node)));
+            }
+            lines.push("  }".to_string());
+        }
+
+        for node in &self.files {
+            lines.push(format!("  {}", node_to_dot(node)));
+        }
+
+        for edge in &self.edges {
+            let mut attrs = Vec::new();
+            if let Some(label) = &edge.label {
+                attrs.push(format!("label=\"{}\"", escape_dot(label)));
+            }
+            if edge.dashed {
+                attrs.push("style=dashed".to_string());
+            }
+            let attrs = if attrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attrs.join(", "))
+            };
+
+            lines.push(format!("  {} -> {}{};", edge.from, edge.to, attrs));
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut lines = vec!["flowchart LR".to_string()];
+
+        for (i, stage) in self.stages.iter().enumerate() {
+            lines.push(format!(
+                "  subgraph stage_{}[\"{}\"]",
+                i,
+                escape_mermaid(&stage.label)
+            ));
+            for node in &stage.nodes {
+                lines.push(format!("    {}", node_to_mermaid(node)));
+            }
+            lines.push("  end".to_string());
+        }
+
+        for node in &self.files {
+            lines.push(format!("  {}", node_to_mermaid(node)));
+        }
+
+        for edge in &self.edges {
+            let arrow = if edge.dashed { "-.->" } else { "-->" };
+            match &edge.label {
+                Some(label) => lines.push(format!(
+                    "  {} {}|\"{}\"| {}",
+                    edge.from,
+                    arrow,
+                    escape_mermaid(label),
+                    edge.to
+                )),
+                None => lines.push(format!("  {} {} {}", edge.from, arrow, edge.to)),
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+fn node_to_dot(node: &Node) -> String {
+    let label = node
+        .label
+        .iter()
+        .map(|line| escape_dot(line))
+        .collect::<Vec<_>>()
+        .join("\\n");
+    let shape = match node.kind {
+        NodeKind::Phase => "",
+        NodeKind::File => ", shape=note",
+        NodeKind::Start => ", shape=oval",
+    };
+
+    format!("{} [label=\"{}\"{}];", node.id, label, shape)
+}
+
+fn node_to_mermaid(node: &Node) -> String {
+    let label = node
+        .label
+        .iter()
+        .map(|line| escape_mermaid(line))
+        .collect::<Vec<_>>()
+        .join("<br/>");
+
+    match node.kind {
+        NodeKind::Phase => format!("{}[\"{}\"]", node.id, label),
+        NodeKind::File => format!("{}[/\"{}\"/]", node.id, label),
+        NodeKind::Start => format!("{}([\"{}\"])", node.id, label),
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_plan() -> BuildPlan {
+        BuildPlan::from_toml(
+            r#"
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+
+            [phases.install]
+            cmds = ["npm ci"]
+            onlyIncludeFiles = ["package.json", "package-lock.json"]
+            dependsOn = ["setup"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+            dependsOn = ["install"]
+
+            [start]
+            cmd = "npm run start"
+            runImage = "ubuntu:jammy"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let dot = get_plan().get_graph(GraphFormat::Dot).unwrap();
+
+        assert!(dot.starts_with("digraph nixpacks {"));
+        assert!(dot.contains("label=\"run: ubuntu:jammy\";"));
+        assert!(dot.contains("phase_0 [label=\"setup\\nnodejs\"];"));
+        assert!(dot.contains("phase_0 -> phase_1;"));
+        assert!(dot.contains("file_2 [label=\"package.json\", shape=note];"));
+        assert!(dot.contains("file_2 -> phase_1 [label=\"COPY\", style=dashed];"));
+        assert!(dot.contains("file_0 [label=\".\", shape=note];"));
+        assert!(dot.contains("phase_2 -> start [label=\"COPY --from=0\", style=dashed];"));
+    }
+
+    #[test]
+    fn test_graph_mermaid() {
+        let mermaid = get_plan().get_graph(GraphFormat::Mermaid).unwrap();
+
+        assert!(mermaid.starts_with("flowchart LR"));
+        assert!(mermaid.contains("phase_1[\"install<br/>npm ci\"]"));
+        assert!(mermaid.contains("phase_0 --> phase_1"));
+        assert!(mermaid.contains("start([\"start<br/>npm run start\"])"));
+    }
+
+    #[test]
+    fn test_invalid_graph_format() {
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use super::{
     phase::{Phase, StartPhase},
+    resources::BuildResources,
     utils::fill_auto_in_vec,
     BuildPlan,
 };
+use crate::nixpacks::environment::EnvironmentVariables;
+use std::collections::BTreeMap;
 
 pub trait Mergeable {
     fn merge(c1: &Self, c2: &Self) -> Self;
@@ -15,6 +18,7 @@ impl Mergeable for BuildPlan {
 
         new_plan.providers = fill_auto_in_vec(new_plan.providers.clone(), plan2.providers.clone());
         new_plan.build_image = plan2.build_image.or(new_plan.build_image);
+        new_plan.nixpkgs_archive = plan2.nixpkgs_archive.or(new_plan.nixpkgs_archive);
 
         new_plan.static_assets = match (new_plan.static_assets, plan2.static_assets) {
             (None, assets) | (assets, None) => assets,
@@ -25,14 +29,12 @@ impl Mergeable for BuildPlan {
             }
         };
 
-        new_plan.variables = match (new_plan.variables, plan2.variables) {
-            (None, vars) | (vars, None) => vars,
-            (Some(vars1), Some(vars2)) => {
-                let mut vars = vars1;
-                vars.extend(vars2);
-                Some(vars)
-            }
-        };
+        new_plan.asset_integrity = merge_variables(new_plan.asset_integrity, plan2.asset_integrity);
+
+        new_plan.variables = merge_variables(new_plan.variables, plan2.variables);
+        new_plan.build_variables = merge_variables(new_plan.build_variables, plan2.build_variables);
+        new_plan.runtime_variables =
+            merge_variables(new_plan.runtime_variables, plan2.runtime_variables);
 
         if new_plan.phases.is_none() {
             new_plan.phases = plan2.phases;
@@ -60,23 +62,55 @@ impl Mergeable for BuildPlan {
             (Some(s1), Some(s2)) => Some(StartPhase::merge(&s1, &s2)),
         };
 
+        new_plan.resources = match (new_plan.resources, plan2.resources) {
+            (None, r) | (r, None) => r,
+            (Some(r1), Some(r2)) => Some(BuildResources::merge(&r1, &r2)),
+        };
+
+        new_plan.publish = fill_auto_in_vec(new_plan.publish, plan2.publish);
+
+        if let Some(hooks2) = plan2.hooks {
+            let hooks = new_plan.hooks.get_or_insert_with(BTreeMap::new);
+            for (hook, cmds) in hooks2 {
+                let merged = fill_auto_in_vec(hooks.remove(&hook), Some(cmds));
+                hooks.insert(hook, merged.unwrap_or_default());
+            }
+        }
+
         new_plan.resolve_phase_names();
         new_plan
     }
 }
 
+fn merge_variables(
+    vars1: Option<EnvironmentVariables>,
+    vars2: Option<EnvironmentVariables>,
+) -> Option<EnvironmentVariables> {
+    match (vars1, vars2) {
+        (None, vars) | (vars, None) => vars,
+        (Some(vars1), Some(vars2)) => {
+            let mut vars = vars1;
+            vars.extend(vars2);
+            Some(vars)
+        }
+    }
+}
+
 impl Mergeable for Phase {
     fn merge(c1: &Phase, c2: &Phase) -> Phase {
         let mut phase = c1.clone();
         let c2 = c2.clone();
         phase.nixpkgs_archive = c2.nixpkgs_archive.or_else(|| phase.nixpkgs_archive.clone());
+        phase.nix_file = c2.nix_file.or_else(|| phase.nix_file.clone());
 
         phase.cmds = fill_auto_in_vec(phase.cmds.clone(), c2.cmds);
+        phase.cleanup_cmds = fill_auto_in_vec(phase.cleanup_cmds.clone(), c2.cleanup_cmds);
         phase.depends_on = fill_auto_in_vec(phase.depends_on.clone(), c2.depends_on);
         phase.nix_pkgs = fill_auto_in_vec(phase.nix_pkgs.clone(), c2.nix_pkgs);
         phase.nix_libs = fill_auto_in_vec(phase.nix_libs.clone(), c2.nix_libs);
         phase.apt_pkgs = fill_auto_in_vec(phase.apt_pkgs.clone(), c2.apt_pkgs);
         phase.nix_overlays = fill_auto_in_vec(phase.nix_overlays.clone(), c2.nix_overlays);
+        phase.nix_expressions = fill_auto_in_vec(phase.nix_expressions.clone(), c2.nix_expressions);
         phase.only_include_files =
             fill_auto_in_vec(phase.only_include_files.clone(), c2.only_include_files);
         phase.cache_directories =
@@ -93,10 +127,12 @@ impl Mergeable for StartPhase {
         let c2 = c2.clone();
         start_phase.cmd = c2.cmd.or_else(|| start_phase.cmd.clone());
         start_phase.run_image = c2.run_image.or_else(|| start_phase.run_image.clone());
+        start_phase.port = c2.port.or(start_phase.port);
         start_phase.only_include_files = fill_auto_in_vec(
             start_phase.only_include_files.clone(),
             c2.only_include_files,
         );
+        start_phase.nix_closure = fill_auto_in_vec(start_phase.nix_closure.clone(), c2.nix_closure);
         start_phase
     }
 }
@@ -204,4 +240,81 @@ mod test {
             merged
         );
     }
+
+    #[test]
+    fn test_merge_resources() {
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [resources]
+                minMemoryMb = 4096
+                minCpus = 2
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [resources]
+                minMemoryMb = 1024
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            merged.resources,
+            Some(BuildResources::new(Some(1024), Some(2)))
+        );
+    }
+
+    #[test]
+    fn test_add_resources() {
+        let mut plan = BuildPlan::default();
+        plan.add_resources(BuildResources::new(Some(4096), None));
+        plan.add_resources(BuildResources::new(Some(2048), Some(2)));
+
+        // Provider minimums only ever raise the estimate
+        assert_eq!(
+            plan.resources,
+            Some(BuildResources::new(Some(4096), Some(2)))
+        );
+    }
+
+    #[test]
+    fn test_merge_build_and_runtime_variables() {
+        let merged = BuildPlan::merge(
+            &BuildPlan::from_toml(
+                r#"
+                [buildVariables]
+                CI = "true"
+
+                [runtimeVariables]
+                PORT = "3000"
+                "#,
+            )
+            .unwrap(),
+            &BuildPlan::from_toml(
+                r#"
+                [runtimeVariables]
+                PORT = "8080"
+                "#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            merged.build_variables,
+            Some(EnvironmentVariables::from([(
+                "CI".to_string(),
+                "true".to_string()
+            )]))
+        );
+        assert_eq!(
+            merged.runtime_variables,
+            Some(EnvironmentVariables::from([(
+                "PORT".to_string(),
+                "8080".to_string()
+            )]))
+        );
+    }
 }
@@ -1,22 +1,37 @@
 use self::{
     merge::Mergeable,
     phase::{Phase, Phases, StartPhase},
+    resources::BuildResources,
     topological_sort::topological_sort,
+    utils::remove_autos_from_vec,
 };
 use super::images::DEFAULT_BASE_IMAGE;
 use crate::nixpacks::{
     app::{App, StaticAssets},
+    debug_bundle::REDACTED_VALUE,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
+    scripts::{get_integrity, Script},
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
+use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 // pub mod config;
+#[cfg(feature = "experimental-config")]
+pub mod config_eval;
+pub mod dev_script;
+pub mod diff;
 pub mod generator;
+pub mod graph;
 pub mod merge;
 pub mod phase;
 pub mod pretty_print;
+pub mod resolved_start;
+pub mod resources;
+pub mod schema;
 mod topological_sort;
 mod utils;
 
@@ -26,7 +41,7 @@ pub trait PlanGenerator {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BuildPlan {
     pub providers: Option<Vec<String>>,
@@ -34,15 +49,35 @@ pub struct BuildPlan {
     #[serde(rename = "buildImage")]
     pub build_image: Option<String>,
 
+    /// Revision of Nixpkgs that every phase installs its Nix packages from
+    pub nixpkgs_archive: Option<String>,
+
     pub variables: Option<EnvironmentVariables>,
 
+    /// Only available while the phases run
+    pub build_variables: Option<EnvironmentVariables>,
+
+    /// Only available when the app starts
+    pub runtime_variables: Option<EnvironmentVariables>,
+
     #[serde(rename = "staticAssets")]
     pub static_assets: Option<StaticAssets>,
 
+    /// Integrity hash of each static asset, recorded when the plan is pinned
+    pub asset_integrity: Option<BTreeMap<String, String>>,
+
     pub phases: Option<Phases>,
 
     #[serde(rename = "start")]
     pub start_phase: Option<StartPhase>,
+
+    pub resources: Option<BuildResources>,
+
+    /// Where to upload the plan and Dockerfile after a successful build
+    pub publish: Option<Vec<String>>,
+
+    /// Commands to run around the commands of a phase, e.g. `pre_build` or `post_install`
+    pub hooks: Option<BTreeMap<String, Vec<String>>>,
 }
 
 impl BuildPlan {
@@ -87,7 +122,25 @@ impl BuildPlan {
         self.start_phase = Some(start_phase);
     }
 
+    /// Raise the estimated build resources of the plan to at least `resources`
+    pub fn add_resources(&mut self, resources: BuildResources) {
+        self.resources = Some(match &self.resources {
+            Some(existing) => BuildResources::max(existing, &resources),
+            None => resources,
+        });
+    }
+
+    /// Variables available in both stages replace any build or runtime only variable with the same name
     pub fn add_variables(&mut self, variables: EnvironmentVariables) {
+        for stage_variables in [&mut self.build_variables, &mut self.runtime_variables] {
+            if let Some(vars) = stage_variables.as_mut() {
+                vars.retain(|name, _| !variables.contains_key(name));
+                if vars.is_empty() {
+                    *stage_variables = None;
+                }
+            }
+        }
+
         match self.variables.as_mut() {
             Some(vars) => {
                 for (key, value) in &variables {
@@ -100,6 +153,62 @@ impl BuildPlan {
         }
     }
 
+    pub fn add_build_variables(&mut self, variables: EnvironmentVariables) {
+        self.build_variables
+            .get_or_insert_with(EnvironmentVariables::default)
+            .extend(variables);
+    }
+
+    pub fn add_runtime_variables(&mut self, variables: EnvironmentVariables) {
+        self.runtime_variables
+            .get_or_insert_with(EnvironmentVariables::default)
+            .extend(variables);
+    }
+
+    /// Every variable in the plan, whether it is used at build time, runtime, or both.
+    /// Variables available in both stages (such as those from `--env`) take precedence.
+    pub fn all_variables(&self) -> EnvironmentVariables {
+        let mut variables = self.build_variables.clone().unwrap_or_default();
+        variables.extend(self.runtime_variables.clone().unwrap_or_default());
+        variables.extend(self.variables.clone().unwrap_or_default());
+        variables
+    }
+
+    /// Replace the values of the variables `should_redact` matches, keeping their names,
+    /// so the plan can be shared without leaking secrets
+    pub fn redact_variables<F: Fn(&str) -> bool>(&mut self, should_redact: F) {
+        for variables in [
+            self.variables.as_mut(),
+            self.build_variables.as_mut(),
+            self.runtime_variables.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for (name, value) in variables.iter_mut() {
+                if should_redact(name) {
+                    *value = REDACTED_VALUE.to_string();
+                }
+            }
+        }
+    }
+
+    /// The port the app listens on, when `NIXPACKS_EXPOSE_PORT` is enabled in the plan variables or the environment.
+    /// A `PORT` that is set explicitly is used instead of the start port
+    pub fn get_exposed_port(&self, env: &Environment) -> Option<u16> {
+        let mut variables = self.all_variables();
+        variables.extend(Environment::clone_variables(env));
+        let variables = Environment::new(variables);
+        if !variables.is_config_variable_truthy("EXPOSE_PORT") {
+            return None;
+        }
+
+        variables
+            .get_variable("PORT")
+            .and_then(|port| port.trim().parse::<u16>().ok())
+            .or_else(|| self.start_phase.as_ref().and_then(|start| start.port))
+    }
+
     pub fn add_static_assets(&mut self, static_assets: StaticAssets) {
         match self.static_assets.as_mut() {
             Some(assets) => {
@@ -128,6 +237,16 @@ impl BuildPlan {
         self.phases.get_or_insert(BTreeMap::default()).remove(name)
     }
 
+    /// All of the apt packages that are installed across every phase
+    pub fn all_apt_packages(&self) -> Vec<String> {
+        self.phases
+            .clone()
+            .unwrap_or_default()
+            .values()
+            .flat_map(|phase| phase.apt_pkgs.clone().unwrap_or_default())
+            .collect()
+    }
+
     pub fn get_sorted_phases(&self) -> Result<Vec<Phase>> {
         let phases_with_names = self
             .phases
@@ -145,6 +264,23 @@ impl BuildPlan {
         Ok(res)
     }
 
+    /// The phases that no other phase depends on, which run last
+    pub fn get_last_phases(&self) -> Vec<String> {
+        let phases = self.phases.clone().unwrap_or_default();
+        phases
+            .keys()
+            .filter(|name| {
+                !phases.values().any(|phase| {
+                    phase
+                        .depends_on
+                        .as_ref()
+                        .map_or(false, |depends_on| depends_on.contains(name))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn get_phases_with_dependencies(&self, phase_name: &str) -> Phases {
         let p = self.get_phase(phase_name);
 
@@ -263,9 +399,93 @@ impl BuildPlan {
         }
 
         // Start
-        let start = env.get_config_variable("START_CMD").map(StartPhase::new);
+        let mut start = env.get_config_variable("START_CMD").map(StartPhase::new);
+        if let Some(run_image) = env.get_config_variable("RUN_IMAGE") {
+            start
+                .get_or_insert_with(StartPhase::default)
+                .run_in_image(run_image);
+        }
+
+        let mut plan = BuildPlan::new(&phases, start);
+        plan.build_image = env.get_config_variable("BASE_IMAGE");
+        plan.nixpkgs_archive = env.get_config_variable("NIXPKGS_ARCHIVE");
 
-        BuildPlan::new(&phases, start)
+        if let Some(providers) = env.get_config_variable("PROVIDERS") {
+            plan.providers = Some(split_env_string(providers.as_str()));
+        }
+
+        // Hooks, e.g. `NIXPACKS_PRE_BUILD_CMD`
+        let hook_re = Regex::new(r"^NIXPACKS_(((?:PRE|POST)_.+)_CMD)$").unwrap();
+        for name in env.get_variable_names() {
+            if let Some(captures) = hook_re.captures(&name) {
+                if let Some(cmd) = env.get_config_variable(&captures[1]) {
+                    plan.hooks
+                        .get_or_insert_with(BTreeMap::new)
+                        .insert(captures[2].to_lowercase(), vec![cmd]);
+                }
+            }
+        }
+
+        plan
+    }
+
+    /// Add the commands of the hooks before or after the commands of their phase
+    pub fn apply_hooks(&mut self) -> Result<()> {
+        let hooks = match self.hooks.take() {
+            Some(hooks) => hooks,
+            None => return Ok(()),
+        };
+
+        for (hook, cmds) in hooks {
+            let cmds = remove_autos_from_vec(cmds);
+            match hook.split_once('_') {
+                Some(("pre", "start")) if self.start_cmd().is_some() => {
+                    let start = self.start_phase.as_mut().unwrap();
+                    start.cmd = Some(
+                        cmds.into_iter()
+                            .chain(start.cmd.take())
+                            .collect::<Vec<_>>()
+                            .join(" && "),
+                    );
+                }
+                Some((position @ ("pre" | "post"), name)) if self.get_phase(name).is_some() => {
+                    let phase = self.get_phase_mut(name).unwrap();
+                    let phase_cmds = phase.cmds.take().unwrap_or_default();
+                    phase.cmds = Some(if position == "pre" {
+                        cmds.into_iter().chain(phase_cmds).collect()
+                    } else {
+                        phase_cmds.into_iter().chain(cmds).collect()
+                    });
+                }
+                _ => bail!(Message::new(MessageId::InvalidHook)
+                    .arg("hook", &hook)
+                    .arg("hooks", &self.get_hook_names().join(", "))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The hooks that can run around the phases of the plan
+    fn get_hook_names(&self) -> Vec<String> {
+        let mut hooks = self
+            .phases
+            .clone()
+            .unwrap_or_default()
+            .into_keys()
+            .flat_map(|name| [format!("pre_{}", name), format!("post_{}", name)])
+            .collect::<Vec<_>>();
+        if self.start_cmd().is_some() {
+            hooks.push("pre_start".to_string());
+        }
+
+        hooks
+    }
+
+    fn start_cmd(&self) -> Option<&String> {
+        self.start_phase
+            .as_ref()
+            .and_then(|start| start.cmd.as_ref())
     }
 
     pub fn pin(&mut self) {
@@ -277,12 +497,58 @@ impl BuildPlan {
         self.resolve_phase_names();
         let phases = self.phases.get_or_insert(Phases::default());
         for (_, phase) in phases.iter_mut() {
+            // Phases that need a specific archive, e.g. for a version of a package, keep it
+            if let Some(archive) = &self.nixpkgs_archive {
+                if phase.uses_nix() && phase.nixpkgs_archive.is_none() {
+                    phase.set_nix_archive(archive.clone());
+                }
+            }
             phase.pin();
         }
 
         if let Some(start) = &mut self.start_phase {
             start.pin();
         }
+
+        // Hashes already in the plan are kept, so assets changed after pinning are detected
+        if let Some(assets) = &self.static_assets {
+            let integrity = self.asset_integrity.get_or_insert_with(BTreeMap::new);
+            for (name, content) in assets {
+                integrity
+                    .entry(name.clone())
+                    .or_insert_with(|| get_integrity(content));
+            }
+        }
+    }
+
+    /// Check that every static asset still matches the integrity hash recorded for it
+    pub fn verify_asset_integrity(&self) -> Result<()> {
+        let assets = self.static_assets.clone().unwrap_or_default();
+        for (name, integrity) in self.asset_integrity.clone().unwrap_or_default() {
+            let matches = assets
+                .get(&name)
+                .map_or(false, |content| get_integrity(content) == integrity);
+            if !matches {
+                bail!(Message::new(MessageId::AssetIntegrityMismatch)
+                    .arg("name", &name)
+                    .arg("integrity", &integrity));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_script(&mut self, script: &Script) {
+        self.add_static_assets(script.to_static_assets());
+    }
+
+    /// Replace renamed Nix packages in all phases, returning the old and new names
+    pub fn rename_nix_pkgs(&mut self) -> Vec<(String, String)> {
+        self.phases
+            .iter_mut()
+            .flat_map(|phases| phases.values_mut())
+            .flat_map(Phase::rename_nix_pkgs)
+            .collect()
     }
 
     pub fn prefix_phases(&mut self, prefix: &str) {
@@ -343,12 +609,21 @@ mod test {
             "NIXPACKS_BUILD_CMD=yarn build",
             "NIXPACKS_BUILD_CACHE_DIRS=build/cache/dir",
             "NIXPACKS_START_CMD=yarn start",
+            "NIXPACKS_PRE_BUILD_CMD=yarn codegen",
+            "NIXPACKS_PROVIDERS=python,...",
+            "NIXPACKS_BASE_IMAGE=registry.example.com/nix:latest",
+            "NIXPACKS_RUN_IMAGE=debian:bullseye-slim",
+            "NIXPACKS_NIXPKGS_ARCHIVE=21de2b973f9fee595a7a1ac4693efff791245c34",
         ])
         .unwrap();
         let env_plan = BuildPlan::from_environment(&env);
 
         let result = BuildPlan::from_toml(
             r#"
+            providers = ["python", "..."]
+            buildImage = "registry.example.com/nix:latest"
+            nixpkgsArchive = "21de2b973f9fee595a7a1ac4693efff791245c34"
+
             [phases.setup]
             nixPkgs = ["cowsay", "sl", "..."]
             aptPkgs = ["foo", "bar", "..."]
@@ -366,6 +641,10 @@ mod test {
 
             [start]
             cmd = "yarn start"
+            runImage = "debian:bullseye-slim"
+
+            [hooks]
+            pre_build = ["yarn codegen"]
             "#,
         )
         .unwrap();
@@ -373,6 +652,64 @@ mod test {
         assert_eq!(result, env_plan);
     }
 
+    #[test]
+    fn test_apply_hooks() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [phases.install]
+            cmds = ["npm ci"]
+
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [start]
+            cmd = "npm start"
+
+            [hooks]
+            pre_build = ["npm run codegen"]
+            post_install = ["npx prisma generate", "..."]
+            pre_start = ["./migrate.sh"]
+            "#,
+        )
+        .unwrap();
+        plan.apply_hooks().unwrap();
+
+        assert_eq!(
+            plan.get_phase("install").unwrap().cmds,
+            Some(vec![
+                "npm ci".to_string(),
+                "npx prisma generate".to_string()
+            ])
+        );
+        assert_eq!(
+            plan.get_phase("build").unwrap().cmds,
+            Some(vec![
+                "npm run codegen".to_string(),
+                "npm run build".to_string()
+            ])
+        );
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("./migrate.sh && npm start".to_string())
+        );
+        assert_eq!(plan.hooks, None);
+
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            [phases.build]
+            cmds = ["npm run build"]
+
+            [hooks]
+            post_start = ["echo started"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.apply_hooks().unwrap_err().to_string(),
+            "Hook post_start does not run around a phase of the plan. Available hooks: pre_build, post_build"
+        );
+    }
+
     #[test]
     fn test_get_phases_with_dependencies() {
         let setup = Phase::new("setup");
@@ -394,6 +731,27 @@ mod test {
         assert_eq!(phases.len(), 3);
     }
 
+    #[test]
+    fn test_get_last_phases() {
+        let setup = Phase::new("setup");
+
+        let mut install = Phase::new("install");
+        install.depends_on_phase("setup");
+
+        let mut build = Phase::new("build");
+        build.depends_on_phase("install");
+
+        let mut release = Phase::new("release");
+        release.depends_on_phase("install");
+
+        let plan = BuildPlan::new(&vec![setup, install, build, release], None);
+
+        assert_eq!(
+            plan.get_last_phases(),
+            vec!["build".to_string(), "release".to_string()]
+        );
+    }
+
     #[test]
     fn test_pin_build_plan() {
         let mut plan = BuildPlan::from_toml(
@@ -418,6 +776,56 @@ mod test {
         assert!(plan.get_phase("setup").unwrap().nixpkgs_archive.is_some());
     }
 
+    #[test]
+    fn test_pin_nixpkgs_archive() {
+        let mut plan = BuildPlan::from_toml(
+            r#"
+            nixpkgsArchive = "21de2b973f9fee595a7a1ac4693efff791245c34"
+
+            [phases.setup]
+            nixPkgs = ["nodejs"]
+
+            [phases.python]
+            nixPkgs = ["python38"]
+            nixpkgsArchive = "bf744fe90419885eefced41b3e5ae442d732712d"
+
+            [phases.build]
+            cmds = ["yarn run build"]
+            "#,
+        )
+        .unwrap();
+
+        plan.pin();
+        assert_eq!(
+            plan.get_phase("setup").unwrap().nixpkgs_archive,
+            Some("21de2b973f9fee595a7a1ac4693efff791245c34".to_string())
+        );
+        assert_eq!(
+            plan.get_phase("python").unwrap().nixpkgs_archive,
+            Some("bf744fe90419885eefced41b3e5ae442d732712d".to_string())
+        );
+        assert_eq!(plan.get_phase("build").unwrap().nixpkgs_archive, None);
+    }
+
+    #[test]
+    fn test_asset_integrity() {
+        let mut plan = BuildPlan::default();
+        plan.add_static_assets(StaticAssets::from([(
+            "hello.sh".to_string(),
+            "echo hello".to_string(),
+        )]));
+        plan.pin();
+        assert!(plan.verify_asset_integrity().is_ok());
+
+        // Assets changed after the plan was pinned are rejected, even when it is pinned again
+        plan.add_static_assets(StaticAssets::from([(
+            "hello.sh".to_string(),
+            "echo goodbye".to_string(),
+        )]));
+        plan.pin();
+        assert!(plan.verify_asset_integrity().is_err());
+    }
+
     #[test]
     fn test_split_env_string() {
         assert_eq!(
@@ -1,7 +1,11 @@
 use crate::nixpacks::{
-    images::{DEBIAN_SLIM_IMAGE, DEFAULT_BASE_IMAGE},
-    nix::{pkg::Pkg, NIXPKGS_ARCHIVE},
+    images::{DEBIAN_SLIM_IMAGE, DEFAULT_BASE_IMAGE, DISTROLESS_STATIC_IMAGE, SCRATCH_IMAGE},
+    nix::{
+        pkg::{get_renamed_pkg, Pkg},
+        NIXPKGS_ARCHIVE, PREBUILT_BINARY_LIBS,
+    },
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
@@ -10,7 +14,7 @@ use super::utils::remove_autos_from_vec;
 pub type Phases = BTreeMap<String, Phase>;
 
 #[serde_with::skip_serializing_none]
-#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Phase {
     pub name: Option<String>,
@@ -24,16 +28,26 @@ pub struct Phase {
     #[serde(alias = "nixLibraries")]
     pub nix_libs: Option<Vec<String>>,
 
+    /// URLs of overlay tarballs, or paths of overlay files relative to the app
     pub nix_overlays: Option<Vec<String>>,
 
+    /// Paths of Nix files relative to the app, each a function of `{ pkgs }` to a package or list of packages
+    pub nix_expressions: Option<Vec<String>>,
+
     pub nixpkgs_archive: Option<String>,
 
+    /// Path of the Nix file the phase's packages are installed from, relative to the output directory
+    pub nix_file: Option<String>,
+
     #[serde(alias = "aptPackages")]
     pub apt_pkgs: Option<Vec<String>>,
 
     #[serde(alias = "commands")]
     pub cmds: Option<Vec<String>>,
 
+    #[serde(alias = "cleanupCommands")]
+    pub cleanup_cmds: Option<Vec<String>>,
+
     #[serde(rename = "onlyIncludeFiles")]
     pub only_include_files: Option<Vec<String>>,
 
@@ -45,12 +59,19 @@ pub struct Phase {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug)]
+#[derive(PartialEq, Eq, Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct StartPhase {
     pub cmd: Option<String>,
     pub run_image: Option<String>,
     pub only_include_files: Option<Vec<String>>,
+
+    /// Executables, or files copied from the build stage, whose Nix store dependencies are copied into the run image.
+    /// The executables are put on the `PATH` of the run image
+    pub nix_closure: Option<Vec<String>>,
+
+    /// Port the app listens on when `PORT` is not set
+    pub port: Option<u16>,
 }
 
 impl Phase {
@@ -122,6 +143,7 @@ impl Phase {
     pub fn uses_nix(&self) -> bool {
         !self.nix_pkgs.clone().unwrap_or_default().is_empty()
             || !self.nix_libs.clone().unwrap_or_default().is_empty()
+            || !self.nix_expressions.clone().unwrap_or_default().is_empty()
     }
 
     /// Whether or not the phase runs any docker commands
@@ -155,6 +177,20 @@ impl Phase {
         ));
     }
 
+    /// Add the libraries needed to run prebuilt native binaries (e.g. sharp, swc) to the library path
+    pub fn add_prebuilt_binary_compat(&mut self) {
+        let existing_libs = self.nix_libs.clone().unwrap_or_default();
+        let missing_libs = PREBUILT_BINARY_LIBS
+            .iter()
+            .map(|lib| (*lib).to_string())
+            .filter(|lib| !existing_libs.contains(lib))
+            .collect::<Vec<_>>();
+
+        if !missing_libs.is_empty() {
+            self.add_pkgs_libs(missing_libs);
+        }
+    }
+
     pub fn add_apt_pkgs(&mut self, new_pkgs: Vec<String>) {
         self.apt_pkgs = Some(add_multiple_to_option_vec(self.apt_pkgs.clone(), new_pkgs));
     }
@@ -163,6 +199,26 @@ impl Phase {
         self.cmds = Some(add_to_option_vec(self.cmds.clone(), cmd.into()));
     }
 
+    /// Add a command that is run in the same layer as the last command of the phase.
+    /// Useful for removing package manager caches and other files that should not end up in the image.
+    pub fn add_cleanup_cmd<S: Into<String>>(&mut self, cmd: S) {
+        self.cleanup_cmds = Some(add_to_option_vec(self.cleanup_cmds.clone(), cmd.into()));
+    }
+
+    /// The commands to run for this phase, with any cleanup commands chained onto the last one
+    pub fn get_cmds_with_cleanup(&self) -> Vec<String> {
+        let mut cmds = self.cmds.clone().unwrap_or_default();
+        let cleanup_cmds = self.cleanup_cmds.clone().unwrap_or_default();
+
+        if let Some(last) = cmds.last_mut() {
+            if !cleanup_cmds.is_empty() {
+                *last = format!("{} && {}", last, cleanup_cmds.join(" && "));
+            }
+        }
+
+        cmds
+    }
+
     pub fn add_file_dependency<S: Into<String>>(&mut self, file: S) {
         self.only_include_files = Some(add_to_option_vec(
             self.only_include_files.clone(),
@@ -185,17 +241,39 @@ impl Phase {
         self.nixpkgs_archive = Some(archive);
     }
 
+    /// Replace Nix packages that were renamed in the default archive with their successors.
+    /// Returns the old and new name of every replaced package
+    pub fn rename_nix_pkgs(&mut self) -> Vec<(String, String)> {
+        if self.nixpkgs_archive.as_deref() != Some(NIXPKGS_ARCHIVE) {
+            return Vec::new();
+        }
+
+        let mut renamed = Vec::new();
+        if let Some(pkgs) = &mut self.nix_pkgs {
+            for pkg in pkgs.iter_mut() {
+                if let Some(new_pkg) = get_renamed_pkg(pkg) {
+                    renamed.push((pkg.clone(), new_pkg.clone()));
+                    *pkg = new_pkg;
+                }
+            }
+        }
+
+        renamed
+    }
+
     pub fn pin(&mut self) {
         if self.uses_nix() && self.nixpkgs_archive.is_none() {
             self.nixpkgs_archive = Some(NIXPKGS_ARCHIVE.to_string());
         }
 
         self.cmds = pin_option_vec(&self.cmds);
+        self.cleanup_cmds = pin_option_vec(&self.cleanup_cmds);
         self.depends_on = pin_option_vec(&self.depends_on);
         self.nix_pkgs = pin_option_vec(&self.nix_pkgs);
         self.nix_libs = pin_option_vec(&self.nix_libs);
         self.apt_pkgs = pin_option_vec(&self.apt_pkgs);
         self.nix_overlays = pin_option_vec(&self.nix_overlays);
+        self.nix_expressions = pin_option_vec(&self.nix_expressions);
         self.only_include_files = pin_option_vec(&self.only_include_files);
         self.cache_directories = pin_option_vec(&self.cache_directories);
         self.paths = pin_option_vec(&self.paths);
@@ -210,6 +288,10 @@ impl StartPhase {
         }
     }
 
+    pub fn set_port(&mut self, port: u16) {
+        self.port = Some(port);
+    }
+
     pub fn run_in_image(&mut self, image_name: String) {
         self.run_image = Some(image_name);
     }
@@ -222,6 +304,24 @@ impl StartPhase {
         self.run_image = Some(DEBIAN_SLIM_IMAGE.to_string());
     }
 
+    /// Run in an empty image. Only works when the start command is a static binary
+    pub fn run_in_scratch_image(&mut self) {
+        self.run_image = Some(SCRATCH_IMAGE.to_string());
+    }
+
+    /// Run in a distroless image. Only works when the start command is a static binary
+    pub fn run_in_distroless_image(&mut self) {
+        self.run_image = Some(DISTROLESS_STATIC_IMAGE.to_string());
+    }
+
+    /// Whether the start command only runs an executable, e.g. `./out`, which works in an image without a shell
+    pub fn runs_bare_binary(&self) -> bool {
+        self.cmd.as_ref().map_or(false, |cmd| {
+            (cmd.starts_with("./") || cmd.starts_with('/'))
+                && !cmd.contains(|c: char| c.is_whitespace() || "$\"'`;&|<>*?(){}[]~\\".contains(c))
+        })
+    }
+
     pub fn add_file_dependency<S: Into<String>>(&mut self, file: S) {
         self.only_include_files = Some(add_to_option_vec(
             self.only_include_files.clone(),
@@ -229,8 +329,14 @@ impl StartPhase {
         ));
     }
 
+    /// Copy the parts of `/nix` an executable or a copied file needs into the run image, instead of all of it
+    pub fn add_nix_closure<S: Into<String>>(&mut self, path: S) {
+        self.nix_closure = Some(add_to_option_vec(self.nix_closure.clone(), path.into()));
+    }
+
     pub fn pin(&mut self) {
         self.only_include_files = pin_option_vec(&self.only_include_files);
+        self.nix_closure = pin_option_vec(&self.nix_closure);
     }
 }
 
@@ -0,0 +1,140 @@
+// What the container of a plan runs, as it is set in the final stage of the generated Dockerfile
+//
+// Deployment platforms can show or validate the start command with this, without parsing the Dockerfile.
+
+use super::BuildPlan;
+use crate::nixpacks::{
+    builder::docker::dockerfile_generation::APP_DIR,
+    environment::{Environment, EnvironmentVariables},
+    images::{is_shellless_image, DEFAULT_BASE_IMAGE},
+};
+use serde::Serialize;
+
+/// Images are built and run as root
+const DEFAULT_USER: &str = "root";
+
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedStart {
+    pub cmd: Option<String>,
+    pub image: String,
+    pub entrypoint: Vec<String>,
+    pub working_dir: String,
+    pub user: String,
+    pub port: Option<u16>,
+    pub variables: EnvironmentVariables,
+}
+
+impl BuildPlan {
+    /// The start command, image, working directory, user, and variables of the final image,
+    /// after the `--env` variables in `env` are applied
+    pub fn resolved_start(&self, env: &Environment) -> ResolvedStart {
+        let start = self.start_phase.clone().unwrap_or_default();
+        let build_image = self
+            .build_image
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_IMAGE.to_string());
+
+        // A separate run image gets the variables again, but none of the phase paths
+        let mut variables = EnvironmentVariables::new();
+        variables.extend(self.variables.clone().unwrap_or_default());
+        variables.extend(Environment::clone_variables(env));
+        if start.run_image.is_none() {
+            let paths = self
+                .get_sorted_phases()
+                .unwrap_or_default()
+                .into_iter()
+                .rev()
+                .flat_map(|phase| phase.paths.unwrap_or_default())
+                .collect::<Vec<_>>();
+            if !paths.is_empty() {
+                variables.insert("PATH".to_string(), format!("{}:$PATH", paths.join(":")));
+            }
+        }
+        variables.extend(self.runtime_variables.clone().unwrap_or_default());
+
+        let image = start.run_image.clone().unwrap_or(build_image);
+
+        // Only the default base image runs the command through a login shell in a separate run stage,
+        // other images run it with `sh` if they have a shell
+        let entrypoint = if start.run_image.is_none() || image == DEFAULT_BASE_IMAGE {
+            vec!["/bin/bash".to_string(), "-l".to_string(), "-c".to_string()]
+        } else if is_shellless_image(&image) {
+            Vec::new()
+        } else {
+            vec!["/bin/sh".to_string(), "-c".to_string()]
+        };
+
+        ResolvedStart {
+            cmd: start.cmd,
+            image,
+            entrypoint,
+            working_dir: APP_DIR.to_string(),
+            user: DEFAULT_USER.to_string(),
+            port: self.get_exposed_port(env),
+            variables,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        images::DEBIAN_SLIM_IMAGE,
+        plan::phase::{Phase, StartPhase},
+    };
+
+    #[test]
+    fn test_resolved_start() {
+        let mut install = Phase::install(Some("npm ci".to_string()));
+        install.add_path("/app/node_modules/.bin".to_string());
+        let mut start = StartPhase::new("npm start");
+        start.set_port(3000);
+        let mut plan = BuildPlan::new(&vec![install], Some(start));
+        plan.add_variables(EnvironmentVariables::from([(
+            "NODE_ENV".to_string(),
+            "production".to_string(),
+        )]));
+
+        let resolved =
+            plan.resolved_start(&Environment::from_envs(vec!["NIXPACKS_EXPOSE_PORT=1"]).unwrap());
+        assert_eq!(resolved.cmd, Some("npm start".to_string()));
+        assert_eq!(resolved.image, DEFAULT_BASE_IMAGE);
+        assert_eq!(resolved.entrypoint, vec!["/bin/bash", "-l", "-c"]);
+        assert_eq!(resolved.working_dir, "/app/");
+        assert_eq!(resolved.user, "root");
+        assert_eq!(resolved.port, Some(3000));
+        assert_eq!(resolved.variables["NODE_ENV"], "production");
+        assert_eq!(resolved.variables["NIXPACKS_EXPOSE_PORT"], "1");
+        assert_eq!(resolved.variables["PATH"], "/app/node_modules/.bin:$PATH");
+    }
+
+    #[test]
+    fn test_resolved_start_in_run_image() {
+        let mut start = StartPhase::new("./server");
+        start.run_in_slim_image();
+        let mut plan = BuildPlan::new(&vec![], Some(start));
+        plan.add_variables(EnvironmentVariables::from([(
+            "CARGO_PROFILE".to_string(),
+            "release".to_string(),
+        )]));
+        plan.add_runtime_variables(EnvironmentVariables::from([(
+            "ROCKET_ADDRESS".to_string(),
+            "0.0.0.0".to_string(),
+        )]));
+
+        let resolved = plan.resolved_start(&Environment::default());
+        assert_eq!(resolved.image, DEBIAN_SLIM_IMAGE);
+        assert_eq!(resolved.entrypoint, vec!["/bin/sh", "-c"]);
+        assert_eq!(resolved.port, None);
+        assert_eq!(
+            resolved.variables,
+            EnvironmentVariables::from([
+                ("CARGO_PROFILE".to_string(), "release".to_string()),
+                ("ROCKET_ADDRESS".to_string(), "0.0.0.0".to_string())
+            ])
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use super::merge::Mergeable;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Estimated minimum resources needed to build a plan.
+///
+/// Providers set these for toolchains that are known to be heavy so platforms
+/// can schedule the build on an appropriately sized runner.
+#[serde_with::skip_serializing_none]
+#[derive(PartialEq, Eq, Default, Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildResources {
+    pub min_memory_mb: Option<u64>,
+    pub min_cpus: Option<u32>,
+}
+
+impl BuildResources {
+    pub fn new(min_memory_mb: Option<u64>, min_cpus: Option<u32>) -> Self {
+        Self {
+            min_memory_mb,
+            min_cpus,
+        }
+    }
+
+    /// The larger of each minimum, for resources that several providers need at the same time
+    pub fn max(r1: &BuildResources, r2: &BuildResources) -> BuildResources {
+        BuildResources {
+            min_memory_mb: r1.min_memory_mb.max(r2.min_memory_mb),
+            min_cpus: r1.min_cpus.max(r2.min_cpus),
+        }
+    }
+}
+
+// Resources set by the user replace the minimums of the providers
+impl Mergeable for BuildResources {
+    fn merge(c1: &BuildResources, c2: &BuildResources) -> BuildResources {
+        BuildResources {
+            min_memory_mb: c2.min_memory_mb.or(c1.min_memory_mb),
+            min_cpus: c2.min_cpus.or(c1.min_cpus),
+        }
+    }
+}
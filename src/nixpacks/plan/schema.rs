@@ -0,0 +1,216 @@
+// The JSON Schema of build plans, which config files and `--json-plan` are checked against
+//
+// The schema is derived from `BuildPlan`, so it always matches what Nixpacks parses.
+// Editors can use the output of `nixpacks schema` to complete and check `nixpacks.json` files.
+
+use super::BuildPlan;
+use crate::nixpacks::messages::{Message, MessageId};
+use anyhow::{anyhow, bail, Result};
+use jsonschema::JSONSchema;
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+
+/// Other names that the fields of a phase are parsed from, from the `serde(alias)` attributes of `Phase`
+const PHASE_ALIASES: &[(&str, &str)] = &[
+    ("nixPkgs", "nixPackages"),
+    ("nixLibs", "nixLibraries"),
+    ("aptPkgs", "aptPackages"),
+    ("cmds", "commands"),
+    ("cleanupCmds", "cleanupCommands"),
+    ("paths", "envPaths"),
+];
+
+/// The JSON Schema of `BuildPlan`
+pub fn get_plan_schema() -> Result<Value> {
+    // Optional values are left out instead of being null, and without a `null` alternative
+    // errors point at the invalid field of a phase instead of the whole phase
+    let settings = SchemaSettings::draft07().with(|settings| {
+        settings.option_nullable = false;
+        settings.option_add_null_type = false;
+    });
+    let schema = settings
+        .into_generator()
+        .into_root_schema_for::<BuildPlan>();
+    let mut schema = serde_json::to_value(schema)?;
+
+    if let Some(properties) = schema
+        .pointer_mut("/definitions/Phase/properties")
+        .and_then(Value::as_object_mut)
+    {
+        for (name, alias) in PHASE_ALIASES {
+            if let Some(property) = properties.get(*name).cloned() {
+                properties.insert((*alias).to_string(), property);
+            }
+        }
+    }
+
+    // Editors read the schema of a `nixpacks.json` file from its `$schema`
+    schema["properties"]["$schema"] = json!({ "type": "string" });
+
+    // Misspelled fields are reported instead of being ignored
+    if let Some(definitions) = schema.get_mut("definitions").and_then(Value::as_object_mut) {
+        definitions
+            .values_mut()
+            .for_each(deny_additional_properties);
+    }
+    deny_additional_properties(&mut schema);
+
+    Ok(schema)
+}
+
+fn deny_additional_properties(schema: &mut Value) {
+    if let Some(object) = schema.as_object_mut() {
+        if object.contains_key("properties") {
+            object
+                .entry("additionalProperties")
+                .or_insert(Value::Bool(false));
+        }
+    }
+}
+
+/// Check a JSON plan against the schema. Every invalid value is listed with its JSON pointer, e.g. `/phases/build/cmds`
+pub fn validate_json_plan(json: &str) -> Result<()> {
+    validate_plan(&serde_json::from_str(json)?)
+}
+
+/// Check a TOML plan against the schema
+pub fn validate_toml_plan(toml: &str) -> Result<()> {
+    validate_plan(&toml::from_str(toml)?)
+}
+
+fn validate_plan(plan: &Value) -> Result<()> {
+    let schema = get_plan_schema()?;
+    let validator = JSONSchema::compile(&schema).map_err(|e| anyhow!(e.to_string()))?;
+
+    if let Err(errors) = validator.validate(plan) {
+        let errors = errors
+            .map(|error| {
+                let pointer = error.instance_path.to_string();
+                let pointer = if pointer.is_empty() {
+                    "/".to_string()
+                } else {
+                    pointer
+                };
+                format!("  {}: {}", pointer, error)
+            })
+            .collect::<Vec<_>>();
+
+        bail!(Message::new(MessageId::PlanSchemaMismatch).arg("errors", &errors.join("\n")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::plan::phase::Phase;
+
+    #[test]
+    fn test_valid_plan() {
+        assert!(validate_toml_plan(
+            r#"
+            providers = ["...", "python"]
+            buildImage = "registry.example.com/nix:latest"
+
+            [variables]
+            NODE_ENV = "production"
+
+            [phases.build]
+            cmds = ["...", "npm run build"]
+            dependsOn = ["install"]
+            nixPackages = ["cowsay"]
+
+            [start]
+            cmd = "npm start"
+            port = 3000
+
+            [hooks]
+            pre_build = ["npm run codegen"]
+            "#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_plan() {
+        let err = validate_json_plan(
+            r#"{
+                "providers": "python",
+                "phases": { "build": { "cmds": ["npm run build", 1] } },
+                "start": { "port": "3000" }
+            }"#,
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.starts_with("The plan does not match the schema"));
+        assert!(message.contains("  /providers: \"python\" is not of type \"array\""));
+        assert!(message.contains("  /phases/build/cmds/1: 1 is not of type \"string\""));
+        assert!(message.contains("  /start/port: \"3000\" is not of type \"integer\""));
+    }
+
+    #[test]
+    fn test_unknown_properties() {
+        let err = validate_json_plan(
+            r#"{
+                "$schema": "./nixpacks.schema.json",
+                "phase": { "build": { "cmds": ["npm run build"] } },
+                "phases": { "build": { "command": ["npm run build"] } },
+                "start": { "cmd": "npm start", "ports": 3000 }
+            }"#,
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(!message.contains("$schema"));
+        assert!(
+            message.contains("  /: Additional properties are not allowed ('phase' was unexpected)")
+        );
+        assert!(message.contains(
+            "  /phases/build: Additional properties are not allowed ('command' was unexpected)"
+        ));
+        assert!(message
+            .contains("  /start: Additional properties are not allowed ('ports' was unexpected)"));
+    }
+
+    #[test]
+    fn test_phase_aliases() {
+        for (name, alias) in PHASE_ALIASES {
+            let json = format!(
+                r#"{{ "phases": {{ "build": {{ "{}": ["value"] }} }} }}"#,
+                alias
+            );
+            assert!(
+                validate_json_plan(&json).is_ok(),
+                "{} is not in the schema",
+                alias
+            );
+
+            // Every alias parses into the same field as its name
+            let phase =
+                |key: &str| serde_json::from_value::<Phase>(json!({ key: ["value"] })).unwrap();
+            assert_eq!(
+                phase(alias),
+                phase(name),
+                "{} is not an alias of {}",
+                alias,
+                name
+            );
+            assert_ne!(phase(name), Phase::default());
+        }
+    }
+
+    #[test]
+    fn test_plan_schema() {
+        let schema = get_plan_schema().unwrap();
+        assert_eq!(
+            schema["properties"]["phases"]["additionalProperties"]["$ref"],
+            "#/definitions/Phase"
+        );
+        assert_eq!(
+            schema["definitions"]["StartPhase"]["properties"]["runImage"]["type"],
+            "string"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::nixpacks::messages::{Message, MessageId};
 use anyhow::{bail, Result};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -16,7 +17,7 @@ where
     let mut lookup = BTreeMap::<String, T>::new();
     for item in items.clone() {
         if lookup.contains_key(&item.get_name()) {
-            bail!("Multiple items with the same name: {}", item.get_name());
+            bail!(Message::new(MessageId::DuplicatePhase).arg("name", &item.get_name()));
         }
 
         lookup.insert(item.get_name(), item);
@@ -63,7 +64,7 @@ where
 
         // Circular dependency
         if no_deps.is_empty() {
-            bail!("Circular dependency detected");
+            bail!(Message::new(MessageId::CircularDependency));
         }
 
         indegree = new_indegree;
@@ -0,0 +1,16 @@
+#!/bin/bash
+# Install Ruby with RVM and make it the default
+#
+# Usage: install-ruby.sh <ruby version> <bundler gem, e.g. bundler:2.3.7>
+
+set -e
+
+ruby_version="$1"
+bundler="$2"
+
+curl -sSL https://get.rvm.io | bash -s stable
+. /etc/profile.d/rvm.sh
+
+rvm install "$ruby_version"
+rvm --default use "$ruby_version"
+gem install "$bundler"
@@ -0,0 +1,91 @@
+// Shell scripts that providers run from the assets directory, instead of inlining them in commands
+//
+// Keeping them in files keeps the generated Dockerfiles short and lets the scripts be tested on their own.
+// The version is part of the file name. Bump it whenever a script changes, so plans pinned to
+// an older script are easy to tell apart from the current one.
+
+use super::app::{StaticAssets, ASSETS_DIR};
+use sha2::{Digest, Sha256};
+
+pub struct Script {
+    pub name: &'static str,
+    pub version: u32,
+    pub content: &'static str,
+}
+
+pub const INSTALL_RUBY: Script = Script {
+    name: "install-ruby",
+    version: 1,
+    content: include_str!("install-ruby.sh"),
+};
+
+pub const START_NGINX: Script = Script {
+    name: "start-nginx",
+    version: 1,
+    content: include_str!("start-nginx.sh"),
+};
+
+impl Script {
+    /// Name of the script in the assets directory, e.g. `install-ruby.v1.sh`
+    pub fn file_name(&self) -> String {
+        format!("{}.v{}.sh", self.name, self.version)
+    }
+
+    /// Command that runs the script with `args`
+    pub fn get_cmd(&self, args: &[&str]) -> String {
+        let mut cmd = format!("bash {}{}", ASSETS_DIR, self.file_name());
+        for arg in args {
+            cmd.push(' ');
+            cmd.push_str(arg);
+        }
+        cmd
+    }
+
+    pub fn to_static_assets(&self) -> StaticAssets {
+        StaticAssets::from([(self.file_name(), self.content.to_string())])
+    }
+}
+
+/// Subresource integrity string of an asset, e.g. `sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=`
+pub fn get_integrity(content: &str) -> String {
+    format!(
+        "sha256-{}",
+        base64::encode(Sha256::digest(content.as_bytes()))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, process::Command};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_script_cmd() {
+        assert_eq!(
+            INSTALL_RUBY.get_cmd(&["3.1.2", "bundler:2.3.7"]),
+            "bash /assets/install-ruby.v1.sh 3.1.2 bundler:2.3.7"
+        );
+    }
+
+    #[test]
+    fn test_get_integrity() {
+        assert_eq!(
+            get_integrity(""),
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+    }
+
+    #[test]
+    fn test_scripts_are_valid_bash() {
+        let dir = TempDir::new("nixpacks-scripts").unwrap();
+        for script in [INSTALL_RUBY, START_NGINX] {
+            let path = dir.path().join(script.file_name());
+            let mut file = std::fs::File::create(&path).unwrap();
+            file.write_all(script.content.as_bytes()).unwrap();
+
+            let status = Command::new("bash").arg("-n").arg(&path).status().unwrap();
+            assert!(status.success(), "{} has a syntax error", script.name);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/bash
+# Serve static files with nginx, listening on $PORT when it is set
+#
+# Usage: start-nginx.sh <nginx.conf>
+
+conf="$1"
+
+if [[ -z "${PORT}" ]]; then
+  echo "Environment variable PORT not found. Using PORT 80"
+else
+  sed -i "s/0.0.0.0:80/$PORT/g" "$conf"
+fi
+
+exec nginx -c "$conf"
@@ -12,6 +12,10 @@ use anyhow::Result;
 use regex::Regex;
 
 const DEFAULT_JDK_PKG_NAME: &str = "jdk8";
+const UBERJAR_PATH: &str = "/app/target/*standalone.jar";
+const DEPSTAR_JAR_PATH: &str = "target/app-standalone.jar";
+const M2_CACHE_DIR: &str = "/root/.m2";
+const GITLIBS_CACHE_DIR: &str = "/root/.gitlibs";
 
 pub struct ClojureProvider {}
 
@@ -21,10 +25,24 @@ impl Provider for ClojureProvider {
     }
 
     fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("project.clj"))
+        Ok(app.includes_file("project.clj")
+            || app.includes_file("deps.edn")
+            || app.includes_file("bb.edn"))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        if app.includes_file("project.clj") {
+            ClojureProvider::get_lein_build_plan(app, env)
+        } else if ClojureProvider::uses_babashka(app)? {
+            ClojureProvider::get_babashka_build_plan(app)
+        } else {
+            ClojureProvider::get_deps_build_plan(app, env)
+        }
+    }
+}
+
+impl ClojureProvider {
+    fn get_lein_build_plan(app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = Phase::setup(Some(vec![
             Pkg::new("leiningen"),
             ClojureProvider::get_nix_jdk_package(app, env)?,
@@ -46,14 +64,90 @@ impl Provider for ClojureProvider {
         let mut build = Phase::build(Some(format!("{}; {}", build_cmd, move_file_cmd)));
         build.depends_on_phase("setup");
 
-        let start = StartPhase::new("bash -c \"java $JAVA_OPTS -jar /app/target/*standalone.jar\"");
+        let start = StartPhase::new(format!("bash -c \"java $JAVA_OPTS -jar {}\"", UBERJAR_PATH));
 
         let plan = BuildPlan::new(&vec![setup, build], Some(start));
         Ok(Some(plan))
     }
-}
 
-impl ClojureProvider {
+    fn get_deps_build_plan(app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![
+            Pkg::new("clojure"),
+            ClojureProvider::get_nix_jdk_package(app, env)?,
+        ]));
+
+        let (build_cmd, jar_path) = ClojureProvider::get_deps_build_cmd(app)?;
+        let mut build = Phase::build(Some(build_cmd));
+        build.depends_on_phase("setup");
+        build.add_cache_directory(M2_CACHE_DIR.to_string());
+        build.add_cache_directory(GITLIBS_CACHE_DIR.to_string());
+
+        let start = StartPhase::new(format!("bash -c \"java $JAVA_OPTS -jar {}\"", jar_path));
+
+        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        Ok(Some(plan))
+    }
+
+    /// Babashka is used when bb.edn declares a start task or there is no JVM project
+    fn uses_babashka(app: &App) -> Result<bool> {
+        if !app.includes_file("bb.edn") {
+            return Ok(false);
+        }
+
+        Ok(!app.includes_file("deps.edn") || ClojureProvider::has_bb_task(app, "start")?)
+    }
+
+    fn get_babashka_build_plan(app: &App) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![Pkg::new("babashka")]));
+
+        // Download the deps and pods declared in bb.edn into the image
+        let install = Phase::install(Some("bb prepare".to_string()));
+
+        let build = if ClojureProvider::has_bb_task(app, "build")? {
+            Some(Phase::build(Some("bb run build".to_string())))
+        } else {
+            None
+        };
+
+        let start = if ClojureProvider::has_bb_task(app, "start")? {
+            Some(StartPhase::new("bb run start"))
+        } else {
+            None
+        };
+
+        let phases = [Some(setup), Some(install), build]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let plan = BuildPlan::new(&phases, start);
+        Ok(Some(plan))
+    }
+
+    fn has_bb_task(app: &App, task: &str) -> Result<bool> {
+        // Task names are symbols followed by their definition, e.g. `start (shell "...")`
+        let task_regex = Regex::new(&format!(r#"(?m)(^|[\s{{]){}\s+[({{\["]"#, task))?;
+        Ok(task_regex.is_match(&app.read_file("bb.edn")?))
+    }
+
+    /// Build an uberjar with tools.build if there is a `build.clj`, otherwise with depstar
+    fn get_deps_build_cmd(app: &App) -> Result<(String, String)> {
+        if app.includes_file("build.clj") {
+            // Use the literal uber-file path from build.clj when there is one
+            let uber_file_regex = Regex::new(r#"uber-file\s+"([^"]+)""#)?;
+            let jar_path = match uber_file_regex.captures(&app.read_file("build.clj")?) {
+                Some(captures) => format!("/app/{}", captures[1].trim_start_matches("./")),
+                None => UBERJAR_PATH.to_string(),
+            };
+
+            return Ok(("clojure -T:build uber".to_string(), jar_path));
+        }
+
+        Ok((
+            format!("clojure -X:uberjar :jar {}", DEPSTAR_JAR_PATH),
+            format!("/app/{}", DEPSTAR_JAR_PATH),
+        ))
+    }
+
     fn get_custom_version(app: &App, env: &Environment) -> Result<String> {
         // Fetch version from configs
         let mut custom_version = env.get_config_variable("JDK_VERSION");
@@ -112,6 +206,35 @@ mod test {
     use crate::nixpacks::{app::App, environment::Environment, nix::pkg::Pkg};
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_deps_tools_build() -> Result<()> {
+        let app = App::new("./examples/clojure-deps")?;
+        assert!(ClojureProvider {}.detect(&app, &Environment::default())?);
+        assert_eq!(
+            ClojureProvider::get_deps_build_cmd(&app)?,
+            (
+                "clojure -T:build uber".to_string(),
+                "/app/target/clojure-deps-standalone.jar".to_string()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_babashka_tasks() -> Result<()> {
+        let app = App::new("./examples/clojure-babashka")?;
+        assert!(ClojureProvider::uses_babashka(&app)?);
+        assert!(ClojureProvider::has_bb_task(&app, "start")?);
+        assert!(!ClojureProvider::has_bb_task(&app, "build")?);
+
+        assert!(!ClojureProvider::uses_babashka(&App::new(
+            "./examples/clojure-deps"
+        )?)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_no_version() -> Result<()> {
         assert_eq!(
@@ -0,0 +1,96 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+
+const CMAKE_TARGET: &str = "CMAKE_TARGET";
+const CMAKE_BUILD_DIR: &str = "build";
+
+pub struct CMakeProvider {}
+
+impl Provider for CMakeProvider {
+    fn name(&self) -> &str {
+        "cmake"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("CMakeLists.txt"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![
+            Pkg::new("cmake"),
+            Pkg::new("gcc"),
+            Pkg::new("ninja"),
+        ]));
+
+        let target = CMakeProvider::get_target(app, env)?;
+
+        let mut build = Phase::build(Some(format!(
+            "cmake -B {dir} -G Ninja -DCMAKE_BUILD_TYPE=Release && cmake --build {dir}{target}",
+            dir = CMAKE_BUILD_DIR,
+            target = target
+                .as_ref()
+                .map(|target| format!(" --target {}", target))
+                .unwrap_or_default()
+        )));
+        build.depends_on_phase("setup");
+
+        let start =
+            target.map(|target| StartPhase::new(format!("./{}/{}", CMAKE_BUILD_DIR, target)));
+
+        let plan = BuildPlan::new(&vec![setup, build], start);
+        Ok(Some(plan))
+    }
+}
+
+impl CMakeProvider {
+    /// Use the target from the environment, otherwise the first executable in CMakeLists.txt
+    fn get_target(app: &App, env: &Environment) -> Result<Option<String>> {
+        if let Some(target) = env.get_config_variable(CMAKE_TARGET) {
+            return Ok(Some(target));
+        }
+
+        let contents = app
+            .read_file("CMakeLists.txt")
+            .context("Reading CMakeLists.txt")?;
+        let re = Regex::new(r"(?i)add_executable\s*\(\s*([\w.+-]+)")?;
+
+        Ok(re
+            .captures(&contents)
+            .and_then(|caps| caps.get(1))
+            .map(|m| m.as_str().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_target() -> Result<()> {
+        let app = App::new("./examples/cmake")?;
+
+        assert_eq!(
+            CMakeProvider::get_target(&app, &Environment::default())?,
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            CMakeProvider::get_target(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_CMAKE_TARGET=other"])?
+            )?,
+            Some("other".to_string())
+        );
+
+        Ok(())
+    }
+}
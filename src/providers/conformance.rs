@@ -0,0 +1,353 @@
+// Contract checks that every provider, built-in or not, is expected to pass
+//
+// Run them against example apps with the `provider_conformance!` macro:
+//
+//     nixpacks::provider_conformance!(MyProvider {}, my_app => "./examples/my-app");
+
+use super::Provider;
+use crate::nixpacks::{app::App, environment::Environment, plan::BuildPlan};
+use anyhow::{bail, Result};
+use regex::Regex;
+use std::{collections::BTreeMap, fs, path::PathBuf, time::SystemTime};
+use walkdir::WalkDir;
+
+/// Commands that are available in every image, or are part of the shell
+const BASE_COMMANDS: &[&str] = &[
+    "[", "[[", "bash", "cd", "chmod", "cp", "echo", "env", "exec", "export", "ls", "mkdir", "mv",
+    "rm", "sed", "sh", "source", "test", "true", ".",
+];
+
+/// Commands installed by packages whose name differs from the command
+const PACKAGE_COMMANDS: &[(&str, &[&str])] = &[
+    ("nodejs", &["node", "npm", "npx", "corepack"]),
+    ("jdk", &["java"]),
+    ("babashka", &["bb"]),
+    ("elixir", &["mix", "iex"]),
+    ("python", &["pip"]),
+    ("php", &["php-fpm"]),
+    ("dotnet-sdk", &["dotnet"]),
+];
+
+/// Directories added to the `PATH` that always contain the same commands
+const PATH_COMMANDS: &[(&str, &[&str])] = &[
+    ("/root/.corepack/bin", &["yarn", "pnpm"]),
+    ("/usr/local/rvm/rubies/", &["ruby", "gem", "rake", "bundle"]),
+];
+
+/// Directories added to the `PATH` that contain the binaries of the app's dependencies,
+/// with the files that list those dependencies
+const DEPENDENCY_PATHS: &[(&str, &[&str])] = &[
+    ("node_modules/.bin", &["package.json"]),
+    (
+        "/opt/venv/bin",
+        &["requirements.txt", "pyproject.toml", "Pipfile"],
+    ),
+    ("/usr/local/rvm/gems/", &["Gemfile", "Gemfile.lock"]),
+];
+
+/// Check that `provider` keeps its contract for `app`, returning every violation in one error.
+///
+/// - `detect` does not modify the app and returns the same result every time
+/// - detected apps get a plan with a `setup` phase
+/// - the start command only runs commands that the plan installs
+/// - the plan and the metadata can be serialized
+pub fn check_conformance(provider: &dyn Provider, app: &App, env: &Environment) -> Result<()> {
+    let mut violations = Vec::new();
+
+    let files_before = get_file_snapshot(app);
+    let detected = provider.detect(app, env)?;
+    if get_file_snapshot(app) != files_before {
+        violations.push("detect() modified the app directory".to_string());
+    }
+    if provider.detect(app, env)? != detected {
+        violations.push("detect() returned different results for the same app".to_string());
+    }
+
+    if detected {
+        for value in provider.metadata(app, env)?.values.unwrap_or_default() {
+            if value.is_empty() || value.contains(|c: char| c == ',' || c.is_whitespace()) {
+                violations.push(format!(
+                    "metadata value `{}` can't be stored in the comma separated metadata",
+                    value
+                ));
+            }
+        }
+
+        if let Some(plan) = provider.get_build_plan(app, env)? {
+            violations.extend(check_plan(&plan, app)?);
+        }
+    }
+
+    if !violations.is_empty() {
+        bail!(
+            "Provider {} does not conform for {}:\n  - {}",
+            provider.name(),
+            app.source.display(),
+            violations.join("\n  - ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Panic with the violations of `provider` for the app at `path`
+pub fn assert_conformance(provider: &dyn Provider, path: &str, envs: Vec<&str>) {
+    let app = App::new(path).unwrap();
+    let env = Environment::from_envs(envs).unwrap();
+    if let Err(e) = check_conformance(provider, &app, &env) {
+        panic!("{}", e);
+    }
+}
+
+fn check_plan(plan: &BuildPlan, app: &App) -> Result<Vec<String>> {
+    let mut violations = Vec::new();
+
+    if plan.get_phase("setup").is_none() {
+        violations.push("the plan has no setup phase".to_string());
+    }
+
+    if let Some(cmd) = plan
+        .start_phase
+        .as_ref()
+        .and_then(|start| start.cmd.as_ref())
+    {
+        for command in get_commands(cmd) {
+            if !is_installed(plan, app, &command)? {
+                violations.push(format!(
+                    "the start command runs `{}`, which is not installed by the plan",
+                    command
+                ));
+            }
+        }
+    }
+
+    if &BuildPlan::from_json(plan.to_json()?)? != plan {
+        violations.push("the plan changes when serialized to JSON and back".to_string());
+    }
+
+    Ok(violations)
+}
+
+/// The first word of every command in a shell command line, skipping variable assignments
+fn get_commands(cmd: &str) -> Vec<String> {
+    cmd.split(|c| c == '&' || c == '|' || c == ';')
+        .filter_map(|command| {
+            command
+                .trim()
+                .trim_start_matches('(')
+                .split_whitespace()
+                .find(|word| !word.contains('='))
+                .map(ToString::to_string)
+        })
+        .collect()
+}
+
+/// Whether a Nix or apt package of the plan, or a directory the plan adds to the `PATH`, provides `command`
+fn is_installed(plan: &BuildPlan, app: &App, command: &str) -> Result<bool> {
+    // Paths, variables, and commands that are always available
+    if command.contains('/') || command.starts_with('$') || BASE_COMMANDS.contains(&command) {
+        return Ok(true);
+    }
+
+    let phases = plan.phases.clone().unwrap_or_default();
+
+    // Commands of the packages installed by any phase, e.g. `mix` for `beam.packages.erlangR25.elixir_1_13`
+    let provides_command = |pkg: &str| {
+        pkg.split('.').map(get_package_base_name).any(|name| {
+            name == command
+                || PACKAGE_COMMANDS
+                    .iter()
+                    .any(|(pkg, commands)| name == *pkg && commands.contains(&command))
+        })
+    };
+    let mut pkgs = phases.values().flat_map(|phase| {
+        [phase.nix_pkgs.clone(), phase.apt_pkgs.clone()]
+            .into_iter()
+            .flatten()
+            .flatten()
+    });
+    if pkgs.any(|pkg| provides_command(&pkg)) {
+        return Ok(true);
+    }
+
+    let command_re = Regex::new(&format!(
+        r#"(^|[^\w-]){}($|[^\w-])"#,
+        regex::escape(command)
+    ))?;
+    for phase in phases.values() {
+        let cmds = phase.cmds.clone().unwrap_or_default();
+        for path in phase.paths.clone().unwrap_or_default() {
+            if PATH_COMMANDS
+                .iter()
+                .any(|(dir, commands)| path.contains(dir) && commands.contains(&command))
+            {
+                return Ok(true);
+            }
+
+            // Binaries of the app's dependencies, or of packages the phase installs into the directory
+            for (_, files) in DEPENDENCY_PATHS
+                .iter()
+                .filter(|(dir, _)| path.contains(dir))
+            {
+                if files
+                    .iter()
+                    .any(|file| command_re.is_match(&app.read_file(file).unwrap_or_default()))
+                    || cmds
+                        .iter()
+                        .any(|cmd| get_pip_packages(cmd).iter().any(|pkg| pkg == command))
+                {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// The packages of every `pip install` in a command, e.g. `poetry` for `pip install poetry==1.1.13`
+fn get_pip_packages(cmd: &str) -> Vec<String> {
+    cmd.split(|c| c == '&' || c == '|' || c == ';')
+        .filter_map(|command| command.trim().strip_prefix("pip install "))
+        .flat_map(|args| {
+            args.split_whitespace()
+                .filter(|arg| !arg.starts_with('-'))
+                .map(|arg| {
+                    arg.split(|c| "=<>~![@".contains(c))
+                        .next()
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The name of a package without its version or attribute path, e.g. `nodejs` for `nodejs-16_x`
+/// and `python` for `python38`
+fn get_package_base_name(pkg: &str) -> String {
+    let name = pkg
+        .trim_start_matches('(')
+        .split('.')
+        .next()
+        .unwrap_or_default();
+    let name = match name.find(|c: char| c == '_' || c.is_ascii_digit()) {
+        Some(index) => &name[..index],
+        None => name,
+    };
+    name.trim_end_matches('-').to_string()
+}
+
+fn get_file_snapshot(app: &App) -> BTreeMap<PathBuf, (u64, Option<SystemTime>)> {
+    WalkDir::new(&app.source)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = fs::metadata(entry.path()).ok()?;
+            Some((
+                entry.into_path(),
+                (metadata.len(), metadata.modified().ok()),
+            ))
+        })
+        .collect()
+}
+
+/// Generate a test for each app that checks the provider keeps its contract with `check_conformance`
+#[macro_export]
+macro_rules! provider_conformance {
+    ($provider:expr, $($name:ident => $path:expr),+ $(,)?) => {
+        $(
+            #[test]
+            fn $name() {
+                $crate::providers::conformance::assert_conformance(&$provider, $path, Vec::new());
+            }
+        )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nixpacks::{
+        nix::pkg::Pkg,
+        plan::phase::{Phase, StartPhase},
+    };
+
+    struct NoSetupProvider {}
+
+    impl Provider for NoSetupProvider {
+        fn name(&self) -> &str {
+            "no-setup"
+        }
+
+        fn detect(&self, _app: &App, _env: &Environment) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn get_build_plan(&self, _app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+            Ok(Some(BuildPlan::new(
+                &vec![Phase::build(Some("make".to_string()))],
+                Some(StartPhase::new("cowsay hello")),
+            )))
+        }
+    }
+
+    #[test]
+    fn test_get_commands() {
+        assert_eq!(
+            get_commands("RACK_ENV=production bundle exec puma && (php-fpm & nginx -c x)"),
+            vec!["bundle", "php-fpm", "nginx"]
+        );
+    }
+
+    #[test]
+    fn test_get_package_base_name() {
+        assert_eq!(get_package_base_name("nodejs-16_x"), "nodejs");
+        assert_eq!(get_package_base_name("python38"), "python");
+        assert_eq!(get_package_base_name("php81Packages.composer"), "php");
+        assert_eq!(get_package_base_name("dotnet-sdk"), "dotnet-sdk");
+        assert_eq!(
+            get_package_base_name("(php81.withExtensions (pe: pe.enabled))"),
+            "php"
+        );
+    }
+
+    #[test]
+    fn test_is_installed() -> Result<()> {
+        let mut install = Phase::install(Some("npm install cowsay".to_string()));
+        install.add_nix_pkgs(&[Pkg::new("nodejs-16_x")]);
+        install.add_path("/app/node_modules/.bin".to_string());
+        install.add_path("/opt/venv/bin".to_string());
+        let plan = BuildPlan::new(&vec![install], None);
+
+        let app = App::new("./examples/shell-hello")?;
+        assert!(is_installed(&plan, &app, "npm")?);
+        assert!(!is_installed(&plan, &app, "cowsay")?);
+        assert!(!is_installed(&plan, &app, "gunicorn")?);
+
+        // Dependencies of the app are on the `PATH`
+        let app = App::new("./examples/python-django")?;
+        assert!(is_installed(&plan, &app, "gunicorn")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_pip_packages() {
+        assert_eq!(
+            get_pip_packages(". /opt/venv/bin/activate && pip install poetry==$VERSION uv"),
+            vec!["poetry", "uv"]
+        );
+    }
+
+    #[test]
+    fn test_non_conforming_provider() {
+        let app = App::new("./examples/shell-hello").unwrap();
+        let err = check_conformance(&NoSetupProvider {}, &app, &Environment::default())
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("no setup phase"));
+        assert!(err.contains("runs `cowsay`"));
+    }
+}
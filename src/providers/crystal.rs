@@ -4,6 +4,7 @@ use super::Provider;
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
@@ -42,7 +43,7 @@ impl Provider for CrystalProvider {
             "./bin/{}",
             target_names
                 .get(0)
-                .ok_or_else(|| anyhow::anyhow!("Unable to get executable name"))?
+                .ok_or_else(|| Message::new(MessageId::ExecutableNameNotFound))?
         ));
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
@@ -1,8 +1,10 @@
-use super::Provider;
+use super::{
+    dotnet::{get_dotnet_sdk_pkg, get_project_file, get_restore_and_publish, get_solution_file},
+    Provider,
+};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
-    nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
@@ -23,24 +25,29 @@ impl Provider for CSharpProvider {
         Ok(!app.find_files("*.csproj")?.is_empty())
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
-        let setup = Phase::setup(Some(vec![Pkg::new("dotnet-sdk")]));
-        let install = Phase::install(Some("dotnet restore".to_string()));
-        let build = Phase::build(Some(format!(
-            "dotnet publish --no-restore -c Release -o {}",
-            ARTIFACT_DIR
-        )));
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let solution = get_solution_file(app)?;
+        let project_file = &get_project_file(app, env, "csproj", solution.as_deref())?;
+        let setup = Phase::setup(Some(vec![get_dotnet_sdk_pkg(app, project_file)?]));
+
+        let (install, build) =
+            get_restore_and_publish(app, solution.as_deref(), project_file, ARTIFACT_DIR)?;
 
-        let csproj = &app.find_files("*.csproj")?[0].with_extension("");
+        let csproj = project_file.with_extension("");
         let project_name = csproj
             .file_name()
             .context("Invalid file_name")?
             .to_str()
             .context("Invalid project_name")?;
-        let start = StartPhase::new(format!("./{}/{}", ARTIFACT_DIR, project_name));
+        let mut start = StartPhase::new(format!("./{}/{}", ARTIFACT_DIR, project_name));
+        start.set_port(3000);
 
         let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
-        plan.add_variables(EnvironmentVariables::from([
+        plan.add_variables(EnvironmentVariables::from([(
+            "DOTNET_ROOT".to_string(),
+            "/nix/var/nix/profiles/default/".to_string(),
+        )]));
+        plan.add_runtime_variables(EnvironmentVariables::from([
             (
                 "ASPNETCORE_ENVIRONMENT".to_string(),
                 "Production".to_string(),
@@ -49,10 +56,6 @@ impl Provider for CSharpProvider {
                 "ASPNETCORE_URLS".to_string(),
                 "http://0.0.0.0:3000".to_string(),
             ),
-            (
-                "DOTNET_ROOT".to_string(),
-                "/nix/var/nix/profiles/default/".to_string(),
-            ),
         ]));
 
         Ok(Some(plan))
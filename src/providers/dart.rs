@@ -30,17 +30,35 @@ impl Provider for DartProvider {
         Ok(app.includes_file("pubspec.yaml"))
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let setup = Phase::setup(Some(vec![Pkg::new(DEFAULT_DART_PKG_NAME)]));
 
         let mut install = Phase::install(Some("dart pub get".to_string()));
         install.add_file_dependency("pubspec.yaml".to_string());
 
         let pubspec = DartProvider::get_pubspec(app)?;
-        let build = Phase::build(Some(format!("dart compile exe bin/{}.dart", pubspec.name)));
+        let (build, start) = if env.is_config_variable_truthy("DART_JIT") {
+            // Apps using dart:mirrors only run on the VM, so they keep the SDK
+            (
+                Phase::build(Some(format!(
+                    "dart compile kernel bin/{name}.dart -o bin/{name}.dill",
+                    name = pubspec.name
+                ))),
+                StartPhase::new(format!("dart bin/{}.dill", pubspec.name)),
+            )
+        } else {
+            // The native executable is linked against the glibc from Nix, so that is copied along with it
+            let exe = format!("./bin/{}.exe", pubspec.name);
+            let mut start = StartPhase::new(format!("./{}.exe", pubspec.name));
+            start.run_in_slim_image();
+            start.add_file_dependency(exe.clone());
+            start.add_nix_closure(exe);
 
-        let pubspec = DartProvider::get_pubspec(app)?;
-        let start = StartPhase::new(format!("./bin/{}.exe", pubspec.name));
+            (
+                Phase::build(Some(format!("dart compile exe bin/{}.dart", pubspec.name))),
+                start,
+            )
+        };
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
         Ok(Some(plan))
@@ -66,4 +84,36 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jit() -> Result<()> {
+        let plan = DartProvider {}
+            .get_build_plan(
+                &App::new("./examples/dart")?,
+                &Environment::from_envs(vec!["NIXPACKS_DART_JIT=1"])?,
+            )?
+            .unwrap();
+        let start = plan.start_phase.unwrap();
+
+        assert_eq!(start.cmd, Some("dart bin/console_simple.dill".to_string()));
+        assert_eq!(start.run_image, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_native_executable() -> Result<()> {
+        let plan = DartProvider {}
+            .get_build_plan(&App::new("./examples/dart")?, &Environment::default())?
+            .unwrap();
+        let start = plan.start_phase.unwrap();
+
+        assert_eq!(start.cmd, Some("./console_simple.exe".to_string()));
+        assert_eq!(
+            start.nix_closure,
+            Some(vec!["./bin/console_simple.exe".to_string()])
+        );
+
+        Ok(())
+    }
 }
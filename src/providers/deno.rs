@@ -1,30 +1,35 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
-    environment::Environment,
+    environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use path_slash::PathBufExt;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-pub struct DenoTasks {
-    pub start: Option<String>,
-}
-
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct DenoJson {
-    pub tasks: Option<DenoTasks>,
+    #[serde(rename = "$schema")]
+    pub schema: Option<String>,
+    pub tasks: Option<BTreeMap<String, String>>,
 }
 
+// The directory `deno upgrade` installs a pinned version to, ahead of the Nix package in the PATH
+const DENO_BIN_DIR: &str = "/root/.deno/bin";
+
+// Deno's default DENO_DIR is a cache mount during the build, so modules are copied into the image for the app
+const DENO_CACHE_DIR: &str = "/root/.cache/deno";
+const DENO_DIR: &str = "/app/.deno";
+
 pub struct DenoProvider {}
 
 impl Provider for DenoProvider {
@@ -42,17 +47,41 @@ impl Provider for DenoProvider {
             || app.find_match(&re, "**/*.{tsx,ts,js,jsx}")?)
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
-        let setup = Phase::setup(Some(vec![Pkg::new("deno")]));
+        let mut setup = Phase::setup(Some(vec![Pkg::new("deno")]));
+        if let Some(version) = DenoProvider::get_deno_version(app, env)? {
+            setup.add_cmd(format!(
+                "mkdir -p {dir} && deno upgrade --version {version} --output {dir}/deno",
+                dir = DENO_BIN_DIR,
+                version = version
+            ));
+            setup.add_path(DENO_BIN_DIR.to_string());
+        }
         plan.add_phase(setup);
 
+        let mut build = Phase::build(None);
+        build.depends_on_phase("setup");
         if let Some(build_cmd) = DenoProvider::get_build_cmd(app)? {
-            let mut build = Phase::build(Some(build_cmd));
-            build.depends_on_phase("setup");
-            plan.add_phase(build);
+            build.add_cmd(build_cmd);
         };
+        if DenoProvider::has_task(app, "build")? {
+            build.add_cmd("deno task build");
+        }
+        if build.cmds.is_some() {
+            build.add_cmd(format!(
+                "mkdir -p {dir} {cache} && cp -r {cache}/. {dir}",
+                dir = DENO_DIR,
+                cache = DENO_CACHE_DIR
+            ));
+            build.add_cache_directory(DENO_CACHE_DIR);
+            plan.add_phase(build);
+            plan.add_runtime_variables(EnvironmentVariables::from([(
+                "DENO_DIR".to_string(),
+                DENO_DIR.to_string(),
+            )]));
+        }
 
         if let Some(start_cmd) = DenoProvider::get_start_cmd(app)? {
             let start = StartPhase::new(start_cmd);
@@ -78,15 +107,9 @@ impl DenoProvider {
     }
 
     fn get_start_cmd(app: &App) -> Result<Option<String>> {
-        // First check for a deno.json and see if we can rip the start command from there
-        if app.includes_file("deno.json") {
-            let deno_json: DenoJson = app.read_json("deno.json")?;
-
-            if let Some(tasks) = deno_json.tasks {
-                if let Some(start) = tasks.start {
-                    return Ok(Some(start));
-                }
-            }
+        // First check for a start task in deno.json
+        if DenoProvider::has_task(app, "start")? {
+            return Ok(Some("deno task start".to_string()));
         }
 
         // Barring that, just try and start the index with sane defaults
@@ -101,6 +124,68 @@ impl DenoProvider {
         }
     }
 
+    fn get_deno_json(app: &App) -> Result<Option<DenoJson>> {
+        if app.includes_file("deno.json") {
+            return Ok(Some(app.read_json("deno.json")?));
+        }
+
+        if app.includes_file("deno.jsonc") {
+            // Drop the comments on their own lines, which is where they usually are
+            let content = app
+                .read_file("deno.jsonc")?
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("//"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let deno_json = serde_json::from_str(&content).context("Error reading deno.jsonc")?;
+            return Ok(Some(deno_json));
+        }
+
+        Ok(None)
+    }
+
+    fn has_task(app: &App, name: &str) -> Result<bool> {
+        let tasks = DenoProvider::get_deno_json(app)?.and_then(|deno_json| deno_json.tasks);
+
+        Ok(tasks.map_or(false, |tasks| tasks.contains_key(name)))
+    }
+
+    /// The version from `NIXPACKS_DENO_VERSION`, `.dvmrc`, `.tool-versions`, or the `$schema` of deno.json,
+    /// e.g. `https://deno.land/x/deno@v1.28.3/cli/schemas/config-file.v1.json`
+    fn get_deno_version(app: &App, env: &Environment) -> Result<Option<String>> {
+        let (version, source) = if let Some(version) = env.get_config_variable("DENO_VERSION") {
+            (version, "NIXPACKS_DENO_VERSION")
+        } else if app.includes_file(".dvmrc") {
+            (app.read_file(".dvmrc")?, ".dvmrc")
+        } else if let Some(pinned) = ToolVersions::from_app(app)?.get("deno") {
+            pinned
+        } else {
+            let schema_version = DenoProvider::get_deno_json(app)?
+                .and_then(|deno_json| deno_json.schema)
+                .and_then(|schema| {
+                    Regex::new(r"deno@v?(\d+\.\d+\.\d+)")
+                        .ok()?
+                        .captures(&schema)
+                        .map(|captures| captures[1].to_string())
+                });
+
+            match schema_version {
+                Some(version) => (version, "deno.json"),
+                None => return Ok(None),
+            }
+        };
+
+        // `deno upgrade` only installs exact versions
+        let version = version.trim().trim_start_matches('v');
+        if !Regex::new(r"^\d+\.\d+\.\d+$")?.is_match(version) {
+            bail!(Message::new(MessageId::DenoVersionInvalid)
+                .arg("version", version)
+                .arg("source", source));
+        }
+
+        Ok(Some(version.to_string()))
+    }
+
     // Find the first index.ts or index.js file to run
     fn get_start_file(app: &App) -> Result<Option<PathBuf>> {
         // Find the first index.ts or index.js file to run
@@ -114,3 +199,34 @@ impl DenoProvider {
         Ok(Some(relative_path_to_index))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deno_version() -> Result<()> {
+        let app = App::new("./examples/deno-tasks")?;
+        assert_eq!(
+            DenoProvider::get_deno_version(&app, &Environment::default())?,
+            Some("1.28.3".to_string())
+        );
+
+        let err = DenoProvider::get_deno_version(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_DENO_VERSION=1.28"])?,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Deno version 1.28 from NIXPACKS_DENO_VERSION is not an exact version, such as 1.28.3"
+        );
+
+        assert_eq!(
+            DenoProvider::get_deno_version(&App::new("./examples/deno")?, &Environment::default())?,
+            None
+        );
+
+        Ok(())
+    }
+}
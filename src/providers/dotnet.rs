@@ -0,0 +1,375 @@
+use std::path::{Path, PathBuf};
+
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    messages::{Message, MessageId},
+    nix::pkg::Pkg,
+    plan::phase::Phase,
+};
+use anyhow::{bail, Result};
+use regex::Regex;
+
+const DEFAULT_DOTNET_SDK_PKG_NAME: &str = "dotnet-sdk";
+const AVAILABLE_DOTNET_SDK_VERSIONS: &[u32] = &[6, 7];
+
+/// The .NET SDK for a C# or F# project, from the SDK version of `global.json`
+/// or the target framework of the project file, e.g. `net7.0`
+pub fn get_dotnet_sdk_pkg(app: &App, project_file: &Path) -> Result<Pkg> {
+    if let Some(version) = get_global_json_sdk_version(app)? {
+        // An SDK pinned in global.json has to be installed with the same major version
+        return match parse_major_version(&version)
+            .filter(|major| AVAILABLE_DOTNET_SDK_VERSIONS.contains(major))
+        {
+            Some(major) => Ok(Pkg::new(&format!("dotnet-sdk_{}", major))),
+            None => bail!(unavailable_message(&version, "global.json")),
+        };
+    }
+
+    let project = app.read_file(&project_file.to_string_lossy())?;
+    let target_frameworks = Regex::new(r"<TargetFrameworks?>\s*([^<]+?)\s*</TargetFrameworks?>")?
+        .captures(&project)
+        .map(|captures| captures[1].to_string());
+    let target_framework = match target_frameworks {
+        Some(target_frameworks) => target_frameworks,
+        None => return Ok(Pkg::new(DEFAULT_DOTNET_SDK_PKG_NAME)),
+    };
+
+    // The newest framework of a multi-targeting project needs the newest SDK
+    let major = target_framework
+        .split(';')
+        .filter_map(|framework| parse_major_version(framework.trim()))
+        .max();
+
+    // Newer SDKs can still build older frameworks, such as netcoreapp3.1
+    match major.and_then(|major| {
+        AVAILABLE_DOTNET_SDK_VERSIONS
+            .iter()
+            .find(|available| **available >= major)
+    }) {
+        Some(available) => Ok(Pkg::new(&format!("dotnet-sdk_{}", available))),
+        None if major.is_none() => Ok(Pkg::new(DEFAULT_DOTNET_SDK_PKG_NAME)),
+        None => {
+            let source = app.strip_source_path(project_file)?;
+            bail!(unavailable_message(
+                &target_framework,
+                &source.to_string_lossy()
+            ))
+        }
+    }
+}
+
+/// The solution file at the root of the app, which is restored instead of a single project
+pub fn get_solution_file(app: &App) -> Result<Option<PathBuf>> {
+    let solution = app
+        .find_files("*.sln")?
+        .into_iter()
+        .find(|path| path.parent() == Some(app.source.as_path()));
+
+    Ok(solution)
+}
+
+/// The project to publish and start. In a solution, this is the project set with `NIXPACKS_DOTNET_PROJECT`,
+/// or the only project that builds an executable
+pub fn get_project_file(
+    app: &App,
+    env: &Environment,
+    extension: &str,
+    solution: Option<&Path>,
+) -> Result<PathBuf> {
+    // Projects of other languages in the solution are built by their own provider
+    let mut projects = match solution {
+        Some(solution) => get_solution_projects(app, solution, extension)?,
+        None => Vec::new(),
+    };
+    if projects.is_empty() {
+        projects = app.find_files(&format!("*.{}", extension))?;
+    }
+    let names = projects
+        .iter()
+        .map(|project| get_project_name(project))
+        .collect::<Vec<_>>();
+
+    if let Some(selected) = env.get_config_variable("DOTNET_PROJECT") {
+        let selected_path = app.source.join(&selected);
+        return match projects
+            .iter()
+            .zip(&names)
+            .find(|(project, name)| **name == selected || **project == selected_path)
+        {
+            Some((project, _)) => Ok(project.clone()),
+            None => bail!(Message::new(MessageId::DotnetProjectNotFound)
+                .arg("name", &selected)
+                .arg("projects", &names.join(", "))),
+        };
+    }
+
+    if solution.is_none() || projects.len() <= 1 {
+        return match projects.into_iter().next() {
+            Some(project) => Ok(project),
+            None => bail!(Message::new(MessageId::DotnetProjectNotFound)
+                .arg("name", &format!("*.{}", extension))
+                .arg("projects", "")),
+        };
+    }
+
+    // Libraries and test projects of the solution can't be started
+    let executable_re = Regex::new(
+        r#"<OutputType>\s*(Win)?Exe\s*</OutputType>|Sdk="Microsoft\.NET\.Sdk\.(Web|Worker)""#,
+    )?;
+    let mut executables = Vec::new();
+    for project in &projects {
+        if executable_re.is_match(&app.read_file(&project.to_string_lossy())?) {
+            executables.push(project.clone());
+        }
+    }
+
+    match executables.len() {
+        0 => Ok(projects.remove(0)),
+        1 => Ok(executables.remove(0)),
+        _ => bail!(Message::new(MessageId::DotnetProjectAmbiguous).arg(
+            "projects",
+            &executables
+                .iter()
+                .map(|project| get_project_name(project))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// The install phase that restores the dependencies, and the build phase that publishes the project to `artifact_dir`.
+/// A solution is restored as a whole, and only the chosen project is published
+pub fn get_restore_and_publish(
+    app: &App,
+    solution: Option<&Path>,
+    project_file: &Path,
+    artifact_dir: &str,
+) -> Result<(Phase, Phase)> {
+    let phases = match solution {
+        Some(solution) => (
+            Phase::install(Some(format!(
+                "dotnet restore {}",
+                get_relative_path(app, solution)?
+            ))),
+            Phase::build(Some(format!(
+                "dotnet publish {} --no-restore -c Release -o {}",
+                get_relative_path(app, project_file)?,
+                artifact_dir
+            ))),
+        ),
+        None => (
+            Phase::install(Some("dotnet restore".to_string())),
+            Phase::build(Some(format!(
+                "dotnet publish --no-restore -c Release -o {}",
+                artifact_dir
+            ))),
+        ),
+    };
+
+    Ok(phases)
+}
+
+/// The path of a project or solution relative to the app, for the `dotnet` commands
+pub fn get_relative_path(app: &App, path: &Path) -> Result<String> {
+    let relative_path = app.strip_source_path(path)?;
+
+    Ok(relative_path.to_string_lossy().replace('\\', "/"))
+}
+
+/// The name of a project, which is also the name of the executable it builds
+pub fn get_project_name(project: &Path) -> String {
+    project
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// The projects with the extension listed in a solution,
+/// e.g. `Project("{...}") = "Api", "src\Api\Api.csproj", "{...}"`
+fn get_solution_projects(app: &App, solution: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let content = app.read_file(&solution.to_string_lossy())?;
+    let solution_dir = solution.parent().unwrap_or(&app.source);
+
+    let project_re = Regex::new(r#"(?m)^Project\("[^"]*"\)\s*=\s*"[^"]*",\s*"([^"]+)""#)?;
+    let projects = project_re
+        .captures_iter(&content)
+        .map(|captures| solution_dir.join(captures[1].replace('\\', "/")))
+        .filter(|project| {
+            project.is_file() && project.extension().and_then(|ext| ext.to_str()) == Some(extension)
+        })
+        .collect();
+
+    Ok(projects)
+}
+
+fn get_global_json_sdk_version(app: &App) -> Result<Option<String>> {
+    if !app.includes_file("global.json") {
+        return Ok(None);
+    }
+
+    // global.json may contain comments, so it isn't parsed as JSON
+    let global_json = app.read_file("global.json")?;
+    let version = Regex::new(r#""sdk"\s*:\s*\{[^}]*"version"\s*:\s*"([^"]+)""#)?
+        .captures(&global_json)
+        .map(|captures| captures[1].to_string());
+
+    Ok(version)
+}
+
+/// The major version of an SDK version or target framework, e.g. `7` for `7.0.100`, `net7.0`, or `netcoreapp3.1`
+fn parse_major_version(version: &str) -> Option<u32> {
+    let captures = Regex::new(r"^(?:net|netcoreapp)?(\d+)\.\d+")
+        .ok()?
+        .captures(version)?;
+
+    captures[1].parse::<u32>().ok()
+}
+
+fn unavailable_message(version: &str, source: &str) -> Message {
+    Message::new(MessageId::DotnetSdkUnavailable)
+        .arg("version", version)
+        .arg("source", source)
+        .arg(
+            "versions",
+            &AVAILABLE_DOTNET_SDK_VERSIONS
+                .iter()
+                .map(|version| format!("{}.0", version))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn create_app(project: &str, global_json: Option<&str>) -> Result<(TempDir, App)> {
+        let dir = TempDir::new("nixpacks-dotnet")?;
+        fs::write(dir.path().join("app.csproj"), project)?;
+        if let Some(global_json) = global_json {
+            fs::write(dir.path().join("global.json"), global_json)?;
+        }
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok((dir, app))
+    }
+
+    #[test]
+    fn test_target_framework() -> Result<()> {
+        let (dir, app) = create_app(
+            "<PropertyGroup><TargetFrameworks>net6.0;net7.0</TargetFrameworks></PropertyGroup>",
+            None,
+        )?;
+        assert_eq!(
+            get_dotnet_sdk_pkg(&app, &dir.path().join("app.csproj"))?,
+            Pkg::new("dotnet-sdk_7")
+        );
+
+        let (dir, app) = create_app(
+            "<PropertyGroup><TargetFramework>netcoreapp3.1</TargetFramework></PropertyGroup>",
+            None,
+        )?;
+        assert_eq!(
+            get_dotnet_sdk_pkg(&app, &dir.path().join("app.csproj"))?,
+            Pkg::new("dotnet-sdk_6")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_json() -> Result<()> {
+        let (dir, app) = create_app(
+            "<PropertyGroup><TargetFramework>net6.0</TargetFramework></PropertyGroup>",
+            Some("{\n  // The SDK to build with\n  \"sdk\": { \"version\": \"7.0.100\", \"rollForward\": \"latestFeature\" }\n}"),
+        )?;
+        assert_eq!(
+            get_dotnet_sdk_pkg(&app, &dir.path().join("app.csproj"))?,
+            Pkg::new("dotnet-sdk_7")
+        );
+
+        let (dir, app) = create_app(
+            "<PropertyGroup><TargetFramework>net6.0</TargetFramework></PropertyGroup>",
+            Some("{ \"sdk\": { \"version\": \"5.0.408\" } }"),
+        )?;
+        let err = get_dotnet_sdk_pkg(&app, &dir.path().join("app.csproj")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The .NET SDK 5.0.408 from global.json is not available. Available versions: 6.0, 7.0"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_solution_project() -> Result<()> {
+        let app = App::new("./examples/csharp-solution")?;
+        let solution = get_solution_file(&app)?.unwrap();
+        assert_eq!(get_relative_path(&app, &solution)?, "csharp-solution.sln");
+
+        // The library can't be started, so the app is chosen
+        let project = get_project_file(&app, &Environment::default(), "csproj", Some(&solution))?;
+        assert_eq!(get_relative_path(&app, &project)?, "App/App.csproj");
+
+        let project = get_project_file(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_DOTNET_PROJECT=Greeting"])?,
+            "csproj",
+            Some(&solution),
+        )?;
+        assert_eq!(
+            get_relative_path(&app, &project)?,
+            "Greeting/Greeting.csproj"
+        );
+
+        let err = get_project_file(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_DOTNET_PROJECT=Worker"])?,
+            "csproj",
+            Some(&solution),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ".NET project Worker not found. Available projects: App, Greeting"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguous_solution_project() -> Result<()> {
+        let dir = TempDir::new("nixpacks-dotnet")?;
+        let mut solution = String::new();
+        for name in ["Api", "Worker"] {
+            fs::create_dir(dir.path().join(name))?;
+            fs::write(
+                dir.path().join(name).join(format!("{}.csproj", name)),
+                "<Project Sdk=\"Microsoft.NET.Sdk.Web\"></Project>",
+            )?;
+            solution.push_str(&format!(
+                "Project(\"{{FAE04EC0}}\") = \"{0}\", \"{0}\\{0}.csproj\", \"{{1}}\"\nEndProject\n",
+                name
+            ));
+        }
+        fs::write(dir.path().join("app.sln"), solution)?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        let err = get_project_file(
+            &app,
+            &Environment::default(),
+            "csproj",
+            get_solution_file(&app)?.as_deref(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Found multiple .NET projects to start: Api, Worker. Set NIXPACKS_DOTNET_PROJECT to the one to build"
+        );
+
+        Ok(())
+    }
+}
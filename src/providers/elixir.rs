@@ -1,17 +1,30 @@
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::Result;
-use regex::{Match, Regex};
+use anyhow::{bail, Result};
+use regex::Regex;
+
 const DEFAULT_ELIXIR_PKG_NAME: &str = "elixir";
 
+// Elixir minor versions, with the oldest and newest Erlang/OTP release they run on
+const ELIXIR_OTP_COMPATIBILITY: &[(u32, u32, u32)] = &[
+    (9, 20, 22),
+    (10, 21, 23),
+    (11, 21, 24),
+    (12, 22, 24),
+    (13, 22, 25),
+    (14, 23, 25),
+];
+const AVAILABLE_OTP_VERSIONS: &[u32] = &[23, 24, 25];
+
 pub struct ElixirProvider {}
 
 impl Provider for ElixirProvider {
@@ -26,13 +39,16 @@ impl Provider for ElixirProvider {
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
+        let mix_exs_content = app.read_file("mix.exs")?;
+        let is_phoenix = mix_exs_content.contains(":phoenix");
+
         plan.add_variables(EnvironmentVariables::from([(
             "MIX_ENV".to_string(),
             "prod".to_string(),
         )]));
 
-        let elixir_pkg = ElixirProvider::get_nix_elixir_package(app, env)?;
-        let setup_phase = Phase::setup(Some(vec![elixir_pkg]));
+        let elixir_pkgs = ElixirProvider::get_nix_elixir_packages(app, env)?;
+        let setup_phase = Phase::setup(Some(elixir_pkgs));
         plan.add_phase(setup_phase);
 
         // Install Phase
@@ -43,20 +59,49 @@ impl Provider for ElixirProvider {
 
         // Build Phase
         let mut build_phase = Phase::build(Some("mix compile".to_string()));
-        let mix_exs_content = app.read_file("mix.exs")?;
 
         if mix_exs_content.contains("assets.deploy") {
             build_phase.add_cmd("mix assets.deploy".to_string());
+        } else if is_phoenix {
+            build_phase.add_cmd("mix phx.digest".to_string());
         }
 
         if mix_exs_content.contains("postgrex") && mix_exs_content.contains("ecto") {
             build_phase.add_cmd("mix ecto.migrate");
             build_phase.add_cmd("mix run priv/repo/seeds.exs");
         }
-        plan.add_phase(build_phase);
 
         // Start Phase
-        let start_phase = StartPhase::new("mix phx.server".to_string());
+        let release = if ElixirProvider::uses_releases(app, &mix_exs_content) {
+            ElixirProvider::get_release_name(&mix_exs_content)?
+        } else {
+            None
+        };
+        let mut start_phase = match release {
+            Some(release) => {
+                build_phase.add_cmd("mix release --overwrite");
+
+                // Only the release, with its Erlang runtime and the Nix store paths that links to, is copied to the run image
+                let release_dir = format!("./_build/prod/rel/{}", release);
+                let mut start_phase = StartPhase::new(format!("/app/bin/{} start", release));
+                start_phase.run_in_slim_image();
+                start_phase.add_file_dependency(release_dir.clone());
+                start_phase.add_nix_closure(release_dir);
+
+                // Releases of Phoenix apps only start the endpoint when this is set
+                if is_phoenix {
+                    plan.add_runtime_variables(EnvironmentVariables::from([(
+                        "PHX_SERVER".to_string(),
+                        "true".to_string(),
+                    )]));
+                }
+                start_phase
+            }
+            None => StartPhase::new("mix phx.server".to_string()),
+        };
+        plan.add_phase(build_phase);
+
+        start_phase.set_port(4000);
         plan.set_start_phase(start_phase);
 
         Ok(Some(plan))
@@ -64,57 +109,282 @@ impl Provider for ElixirProvider {
 }
 
 impl ElixirProvider {
-    fn get_nix_elixir_package(app: &App, env: &Environment) -> Result<Pkg> {
-        fn as_default(v: Option<Match>) -> &str {
-            match v {
-                Some(m) => m.as_str(),
-                None => "_",
+    /// Apps are only built as releases when they configure one, or read their config at runtime like releases do
+    fn uses_releases(app: &App, mix_exs_content: &str) -> bool {
+        mix_exs_content.contains("releases:") || app.includes_file("config/runtime.exs")
+    }
+
+    /// The release that `mix release` builds by default, which is named after the app
+    fn get_release_name(mix_exs_content: &str) -> Result<Option<String>> {
+        let release = Regex::new(r"app:\s*:(\w+)")?
+            .captures(mix_exs_content)
+            .map(|captures| captures[1].to_string());
+
+        Ok(release)
+    }
+
+    /// The Elixir package, and the Erlang/OTP it is built with when a version is requested
+    fn get_nix_elixir_packages(app: &App, env: &Environment) -> Result<Vec<Pkg>> {
+        let elixir = match ElixirProvider::get_requested_elixir_version(app, env)? {
+            Some((version, source)) => {
+                let minor = Regex::new(r"^\D*1\.(\d+)")?
+                    .captures(version.trim())
+                    .and_then(|captures| captures[1].parse::<u32>().ok());
+                // The requirement of mix.exs, e.g. `~> 1.11`, is a lower bound, while other sources pin a version
+                let is_requirement = source == "mix.exs";
+                let compatibility = ELIXIR_OTP_COMPATIBILITY
+                    .iter()
+                    .find(|(elixir_minor, _, _)| {
+                        minor.map_or(false, |minor| {
+                            if is_requirement {
+                                *elixir_minor >= minor
+                            } else {
+                                *elixir_minor == minor
+                            }
+                        })
+                    });
+
+                match compatibility {
+                    Some(compatibility) => Some(*compatibility),
+                    // Versions without a number, such as `latest`, and requirements newer than
+                    // the available versions use the default package
+                    None if is_requirement || !version.contains(|c: char| c.is_ascii_digit()) => {
+                        None
+                    }
+                    None => bail!(Message::new(MessageId::ElixirVersionUnavailable)
+                        .arg("version", version.trim())
+                        .arg("source", source)
+                        .arg(
+                            "versions",
+                            &ELIXIR_OTP_COMPATIBILITY
+                                .iter()
+                                .map(|(minor, _, _)| format!("1.{}", minor))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )),
+                }
             }
-        }
+            None => None,
+        };
 
-        let mix_exs_content = app.read_file("mix.exs")?;
-        let custom_version = env.get_config_variable("ELIXIR_VERSION");
+        // The Erlang/OTP releases that the Elixir version runs on
+        let compatible_otp_versions = AVAILABLE_OTP_VERSIONS
+            .iter()
+            .copied()
+            .filter(|otp| match elixir {
+                Some((_, min_otp, max_otp)) => (min_otp..=max_otp).contains(otp),
+                None => true,
+            })
+            .collect::<Vec<_>>();
 
-        let mix_elixir_version_regex = Regex::new(r#"(elixir:[\s].*[> ])([0-9|\.]*)"#)?;
+        let otp = match ElixirProvider::get_requested_otp_version(app, env)? {
+            Some((version, source)) => {
+                let major = Regex::new(r"^\D*(\d+)")?
+                    .captures(version.trim())
+                    .and_then(|captures| captures[1].parse::<u32>().ok());
 
-        // If not from env variable, get it from the .elixir-version file then try to parse from mix.exs
-        let custom_version = if custom_version.is_some() {
-            custom_version
-        } else if custom_version.is_none() && app.includes_file(".elixir-version") {
-            Some(app.read_file(".elixir-version")?)
-        } else {
-            mix_elixir_version_regex
-                .captures(&mix_exs_content)
-                .map(|c| c.get(2).unwrap().as_str().to_owned())
+                match major.filter(|major| compatible_otp_versions.contains(major)) {
+                    Some(major) => Some(major),
+                    None => bail!(Message::new(MessageId::ErlangVersionUnavailable)
+                        .arg("version", version.trim())
+                        .arg("source", source)
+                        .arg(
+                            "versions",
+                            &compatible_otp_versions
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )),
+                }
+            }
+            // The newest release the Elixir version supports, instead of the default pairing
+            None if elixir.is_some() => compatible_otp_versions.last().copied(),
+            None => None,
+        };
+
+        let pkgs = match (elixir, otp) {
+            (Some((minor, _, _)), Some(otp)) => vec![
+                Pkg::new(&format!("beam.packages.erlangR{}.elixir_1_{}", otp, minor)),
+                Pkg::new(&format!("beam.interpreters.erlangR{}", otp)),
+            ],
+            (None, Some(otp)) => vec![
+                Pkg::new(&format!("beam.packages.erlangR{}.elixir", otp)),
+                Pkg::new(&format!("beam.interpreters.erlangR{}", otp)),
+            ],
+            // Versions older than the available Erlang/OTP releases are built with the default
+            (Some((minor, _, _)), None) => vec![Pkg::new(&format!("elixir_1_{}", minor))],
+            (None, None) => vec![Pkg::new(DEFAULT_ELIXIR_PKG_NAME)],
         };
 
-        // If it's still none, return default
-        if custom_version.is_none() {
-            return Ok(Pkg::new(DEFAULT_ELIXIR_PKG_NAME));
+        Ok(pkgs)
+    }
+
+    /// The Elixir version from `NIXPACKS_ELIXIR_VERSION`, `.elixir-version`, `.tool-versions`, or `mix.exs`
+    fn get_requested_elixir_version(
+        app: &App,
+        env: &Environment,
+    ) -> Result<Option<(String, &'static str)>> {
+        if let Some(version) = env.get_config_variable("ELIXIR_VERSION") {
+            return Ok(Some((version, "NIXPACKS_ELIXIR_VERSION")));
+        }
+
+        if app.includes_file(".elixir-version") {
+            return Ok(Some((app.read_file(".elixir-version")?, ".elixir-version")));
         }
-        let custom_version = custom_version.unwrap();
 
-        // Regex for reading Elixir versions (e.g. 1.8 or 1.12)
-        let elixir_version_regex =
-            Regex::new(r#"^(?:[\sa-zA-Z-"']*)(\d*)(?:\.*)(\d*)(?:\.*\d*)(?:["']?)$"#)?;
+        if let Some(pinned) = ToolVersions::from_app(app)?.get("elixir") {
+            return Ok(Some(pinned));
+        }
+
+        let mix_exs_content = app.read_file("mix.exs")?;
+        let version = Regex::new(r#"(elixir:[\s].*[> ])([0-9|\.]*)"#)?
+            .captures(&mix_exs_content)
+            .map(|c| (c[2].to_string(), "mix.exs"));
 
-        // Capture matches
-        let matches = elixir_version_regex.captures(custom_version.as_str().trim());
+        Ok(version)
+    }
 
-        // If no matches, just use default
-        if matches.is_none() {
-            return Ok(Pkg::new(DEFAULT_ELIXIR_PKG_NAME));
+    /// The Erlang/OTP version from `NIXPACKS_ERLANG_VERSION`, `.tool-versions`, or an Elixir version such as `1.14.2-otp-25`
+    fn get_requested_otp_version(
+        app: &App,
+        env: &Environment,
+    ) -> Result<Option<(String, &'static str)>> {
+        if let Some(version) = env.get_config_variable("ERLANG_VERSION") {
+            return Ok(Some((version, "NIXPACKS_ERLANG_VERSION")));
         }
-        let matches = matches.unwrap();
-        let parsed_version = (as_default(matches.get(1)), as_default(matches.get(2)));
-
-        // Match major and minor versions
-        match parsed_version {
-            ("1", "9") => Ok(Pkg::new("elixir_1_9")),
-            ("1", "10") => Ok(Pkg::new("elixir_1_10")),
-            ("1", "11") => Ok(Pkg::new("elixir_1_11")),
-            ("1", "12") => Ok(Pkg::new("elixir_1_12")),
-            _ => Ok(Pkg::new(DEFAULT_ELIXIR_PKG_NAME)),
+
+        if let Some(pinned) = ToolVersions::from_app(app)?.get("erlang") {
+            return Ok(Some(pinned));
         }
+
+        let otp_re = Regex::new(r"-otp-(\d+)")?;
+        let version = ElixirProvider::get_requested_elixir_version(app, env)?.and_then(
+            |(version, source)| {
+                otp_re
+                    .captures(&version)
+                    .map(|captures| (captures[1].to_string(), source))
+            },
+        );
+
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn create_app(tool_versions: &str) -> Result<(TempDir, App)> {
+        let dir = TempDir::new("nixpacks-elixir")?;
+        fs::write(
+            dir.path().join("mix.exs"),
+            "def project do\n  [app: :hello, elixir: \"~> 1.11\"]\nend\n",
+        )?;
+        fs::write(dir.path().join(".tool-versions"), tool_versions)?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok((dir, app))
+    }
+
+    #[test]
+    fn test_uses_releases() -> Result<()> {
+        let (_dir, app) = create_app("")?;
+        assert!(!ElixirProvider::uses_releases(&app, "[app: :hello]"));
+        assert!(ElixirProvider::uses_releases(
+            &app,
+            "[app: :hello, releases: [hello: [include_executables_for: [:unix]]]]"
+        ));
+        assert!(ElixirProvider::uses_releases(
+            &App::new("./examples/elixir_no_ecto")?,
+            "[app: :elixir_no_ecto]"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_versions() -> Result<()> {
+        let (_dir, app) = create_app("erlang 23.3.4\nelixir 1.13.4-otp-23\n")?;
+        assert_eq!(
+            ElixirProvider::get_nix_elixir_packages(&app, &Environment::default())?,
+            vec![
+                Pkg::new("beam.packages.erlangR23.elixir_1_13"),
+                Pkg::new("beam.interpreters.erlangR23")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_otp_from_elixir_version() -> Result<()> {
+        let (_dir, app) = create_app("nodejs 18.12.1\n")?;
+        assert_eq!(
+            ElixirProvider::get_nix_elixir_packages(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_ELIXIR_VERSION=1.12.3-otp-23"])?
+            )?,
+            vec![
+                Pkg::new("beam.packages.erlangR23.elixir_1_12"),
+                Pkg::new("beam.interpreters.erlangR23")
+            ]
+        );
+
+        // Without an Erlang/OTP version, the newest release that runs the version of mix.exs is used
+        assert_eq!(
+            ElixirProvider::get_nix_elixir_packages(&app, &Environment::default())?,
+            vec![
+                Pkg::new("beam.packages.erlangR24.elixir_1_11"),
+                Pkg::new("beam.interpreters.erlangR24")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mix_exs_requirement() -> Result<()> {
+        let dir = TempDir::new("nixpacks-elixir")?;
+        let mix_exs = |requirement: &str| -> Result<Vec<Pkg>> {
+            fs::write(
+                dir.path().join("mix.exs"),
+                format!(
+                    "def project do\n  [app: :hello, elixir: \"{}\"]\nend\n",
+                    requirement
+                ),
+            )?;
+            let app = App::new(&dir.path().to_string_lossy())?;
+            ElixirProvider::get_nix_elixir_packages(&app, &Environment::default())
+        };
+
+        // The oldest available version that satisfies the requirement
+        assert_eq!(mix_exs("~> 1.7")?, vec![Pkg::new("elixir_1_9")]);
+        assert_eq!(mix_exs("~> 1.15")?, vec![Pkg::new(DEFAULT_ELIXIR_PKG_NAME)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unavailable_versions() -> Result<()> {
+        let (_dir, app) = create_app("elixir 1.15.7\n")?;
+        let err =
+            ElixirProvider::get_nix_elixir_packages(&app, &Environment::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Elixir 1.15.7 from .tool-versions is not available. Available versions: 1.9, 1.10, 1.11, 1.12, 1.13, 1.14"
+        );
+
+        let (_dir, app) = create_app("elixir 1.14.2\nerlang 22.3\n")?;
+        let err =
+            ElixirProvider::get_nix_elixir_packages(&app, &Environment::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Erlang/OTP 22.3 from .tool-versions is not available for this Elixir version. Available versions: 23, 24, 25"
+        );
+
+        Ok(())
     }
 }
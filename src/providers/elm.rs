@@ -0,0 +1,59 @@
+use super::{node::NodeProvider, staticfile::StaticfileProvider, Provider};
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{phase::Phase, BuildPlan},
+};
+use anyhow::Result;
+
+const ELM_CACHE_DIR: &str = "/root/.elm";
+const ELM_OUTPUT_DIR: &str = "dist";
+
+pub struct ElmProvider {}
+
+impl Provider for ElmProvider {
+    fn name(&self) -> &str {
+        "elm"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("elm.json"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let mut pkgs = vec![Pkg::new("elmPackages.elm")];
+        let mut phases = Vec::new();
+
+        // Node is only needed when the app uses npm packages (e.g. bundlers or ports)
+        if app.includes_file("package.json") {
+            pkgs.extend(NodeProvider::get_nix_packages(app, env)?);
+
+            let mut install = Phase::install(NodeProvider::get_install_command(app));
+            install.add_path("/app/node_modules/.bin".to_string());
+            phases.push(install);
+        }
+
+        let mut build = Phase::build(Some(format!(
+            "elm make src/Main.elm --optimize --output {}/index.html",
+            ELM_OUTPUT_DIR
+        )));
+        build.add_cache_directory(ELM_CACHE_DIR.to_string());
+        if phases.is_empty() {
+            build.depends_on_phase("setup");
+        }
+        phases.push(build);
+
+        let setup = StaticfileProvider::get_setup_phase(pkgs);
+        phases.insert(0, setup);
+
+        // The compiled app is served with nginx
+        let start = StaticfileProvider::get_start_phase(app);
+        let static_assets = StaticfileProvider::get_static_assets(app, env, Some(ELM_OUTPUT_DIR))?;
+
+        let mut plan = BuildPlan::new(&phases, Some(start));
+        plan.add_static_assets(static_assets);
+
+        Ok(Some(plan))
+    }
+}
@@ -0,0 +1,76 @@
+use super::{staticfile::StaticfileProvider, Provider};
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{phase::Phase, BuildPlan},
+};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const FLUTTER_WEB_OUTPUT_DIR: &str = "build/web";
+
+#[derive(Deserialize, Debug, Default)]
+pub struct FlutterPubspec {
+    pub dependencies: Option<BTreeMap<String, serde_yaml::Value>>,
+}
+
+pub struct FlutterProvider {}
+
+impl Provider for FlutterProvider {
+    fn name(&self) -> &str {
+        "flutter"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("pubspec.yaml") && FlutterProvider::uses_flutter(app))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = StaticfileProvider::get_setup_phase(vec![Pkg::new("flutter")]);
+
+        let mut install = Phase::install(Some("flutter pub get".to_string()));
+        install.add_file_dependency("pubspec.yaml".to_string());
+        install.add_cache_directory("/root/.pub-cache".to_string());
+
+        let build = Phase::build(Some("flutter build web --release".to_string()));
+
+        // The compiled web app is served with nginx
+        let start = StaticfileProvider::get_start_phase(app);
+        let static_assets =
+            StaticfileProvider::get_static_assets(app, env, Some(FLUTTER_WEB_OUTPUT_DIR))?;
+
+        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        plan.add_static_assets(static_assets);
+
+        Ok(Some(plan))
+    }
+}
+
+impl FlutterProvider {
+    fn uses_flutter(app: &App) -> bool {
+        app.read_yaml::<FlutterPubspec>("pubspec.yaml")
+            .unwrap_or_default()
+            .dependencies
+            .unwrap_or_default()
+            .contains_key("flutter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uses_flutter() -> Result<()> {
+        assert!(FlutterProvider::uses_flutter(&App::new(
+            "./examples/flutter-web"
+        )?));
+        assert!(!FlutterProvider::uses_flutter(&App::new(
+            "./examples/dart"
+        )?));
+
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
+use regex::Regex;
 
 pub struct GolangProvider {}
 
@@ -31,7 +33,11 @@ impl Provider for GolangProvider {
         let mut plan = BuildPlan::default();
 
         let go_mod = self.read_go_mod_if_exists(app)?;
-        let nix_pkg = GolangProvider::get_nix_golang_pkg(go_mod.as_ref())?;
+        let nix_pkg = GolangProvider::get_nix_golang_pkg(
+            go_mod.as_ref(),
+            &ToolVersions::from_app(app)?,
+            env,
+        )?;
         plan.add_phase(Phase::setup(Some(vec![Pkg::new(&nix_pkg)])));
 
         if app.includes_file("go.mod") {
@@ -40,10 +46,25 @@ impl Provider for GolangProvider {
             plan.add_phase(install);
         }
 
+        let cgo = GolangProvider::uses_cgo(app, env)?;
+
+        // Without cgo the binary is static, so the symbol table and debug info can be stripped
+        let build_cmd = if cgo {
+            "go build".to_string()
+        } else {
+            "go build -ldflags=\"-s -w\"".to_string()
+        };
+
         let mut build = if app.includes_file("go.mod") {
-            Phase::build(Some(format!("go build -o {}", BINARY_NAME)))
+            match GolangProvider::get_command(app, env)? {
+                Some(command) => Phase::build(Some(format!(
+                    "{} -o {} ./cmd/{}",
+                    build_cmd, BINARY_NAME, command
+                ))),
+                None => Phase::build(Some(format!("{} -o {}", build_cmd, BINARY_NAME))),
+            }
         } else if app.includes_file("main.go") {
-            Phase::build(Some(format!("go build -o {} main.go", BINARY_NAME)))
+            Phase::build(Some(format!("{} -o {} main.go", build_cmd, BINARY_NAME)))
         } else {
             Phase::build(None)
         };
@@ -55,18 +76,18 @@ impl Provider for GolangProvider {
 
         if has_go_files {
             let mut start = StartPhase::new(format!("./{}", BINARY_NAME));
-            let cgo = env.get_variable("CGO_ENABLED").unwrap_or("0");
 
-            // Only run in a new image if CGO_ENABLED=0 (default)
-            if cgo != "1" {
-                start.run_in_slim_image();
+            // Only run in a new image if CGO_ENABLED=0 (default). The image has no shell,
+            // so a start command other than the binary runs in the build image instead
+            if !cgo {
+                start.run_in_distroless_image();
             }
             plan.set_start_phase(start);
         }
 
-        plan.add_variables(EnvironmentVariables::from([(
+        plan.add_build_variables(EnvironmentVariables::from([(
             "CGO_ENABLED".to_string(),
-            "0".to_string(),
+            if cgo { "1" } else { "0" }.to_string(),
         )]));
 
         Ok(Some(plan))
@@ -82,38 +103,155 @@ impl GolangProvider {
         }
     }
 
-    pub fn get_nix_golang_pkg(go_mod_contents: Option<&String>) -> Result<String> {
-        if go_mod_contents.is_some() {
-            let mut lines = go_mod_contents.as_ref().unwrap().lines();
-            let go_version_line = lines.find(|line| line.trim().starts_with("go"));
+    /// The names of the `cmd/<name>/main.go` entrypoints
+    pub fn get_commands(app: &App) -> Result<Vec<String>> {
+        let mut commands = app
+            .find_files("cmd/*/main.go")?
+            .iter()
+            .filter_map(|path| path.parent()?.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        commands.sort();
+
+        Ok(commands)
+    }
+
+    /// The command in `cmd/` to build, selected with `NIXPACKS_GO_BIN`.
+    ///
+    /// Without it, a `main.go` at the root is built, or otherwise the only command.
+    pub fn get_command(app: &App, env: &Environment) -> Result<Option<String>> {
+        let commands = GolangProvider::get_commands(app)?;
+
+        if let Some(name) = env.get_config_variable("GO_BIN") {
+            if !commands.contains(&name) {
+                bail!(Message::new(MessageId::GoCommandNotFound)
+                    .arg("name", &name)
+                    .arg("commands", &commands.join(", ")));
+            }
+
+            return Ok(Some(name));
+        }
 
-            if let Some(go_version_line) = go_version_line {
-                let go_version = go_version_line.split_whitespace().nth(1).unwrap();
+        if app.includes_file("main.go") {
+            return Ok(None);
+        }
 
-                if let Some(nix_pkg) = version_number_to_pkg(go_version) {
-                    return Ok(nix_pkg);
-                }
+        match commands.len() {
+            0 | 1 => Ok(commands.into_iter().next()),
+            _ => {
+                bail!(Message::new(MessageId::GoCommandAmbiguous)
+                    .arg("commands", &commands.join(", ")))
             }
         }
+    }
+
+    /// Whether the app is built with cgo, because `CGO_ENABLED=1` is set or a file imports `C`
+    pub fn uses_cgo(app: &App, env: &Environment) -> Result<bool> {
+        if let Some(cgo) = env.get_variable("CGO_ENABLED") {
+            return Ok(cgo == "1");
+        }
+
+        // `import "C"`, or `"C"` in a grouped `import ( ... )`
+        let import_c_re = Regex::new(r#"import\s*(?:\(\s*(?:[^)]*\s)?)?"C""#).unwrap();
+        app.find_match(&import_c_re, "**/*.go")
+    }
+
+    /// The Go version from `NIXPACKS_GO_VERSION`, `.tool-versions`, or the `go` directive of `go.mod`,
+    /// and where it was found
+    pub fn get_requested_go_version(
+        go_mod_contents: Option<&String>,
+        tool_versions: &ToolVersions,
+        env: &Environment,
+    ) -> Option<(String, &'static str)> {
+        if let Some(version) = env.get_config_variable("GO_VERSION") {
+            return Some((version, "NIXPACKS_GO_VERSION"));
+        }
+
+        if let Some(pinned) = tool_versions.get("go") {
+            return Some(pinned);
+        }
+
+        let go_directive_re = Regex::new(r"(?m)^\s*go\s+(\S+)\s*$").unwrap();
+        go_mod_contents
+            .and_then(|contents| go_directive_re.captures(contents))
+            .map(|captures| (captures[1].to_string(), "go.mod"))
+    }
 
-        Ok(DEFAULT_GO_PKG_NAME.to_string())
+    pub fn get_nix_golang_pkg(
+        go_mod_contents: Option<&String>,
+        tool_versions: &ToolVersions,
+        env: &Environment,
+    ) -> Result<String> {
+        let (go_version, source) =
+            match GolangProvider::get_requested_go_version(go_mod_contents, tool_versions, env) {
+                Some(requested) => requested,
+                None => return Ok(DEFAULT_GO_PKG_NAME.to_string()),
+            };
+
+        // The `go` directive is the minimum version the module needs, while the other sources pin a version
+        let nix_pkg = if source == "go.mod" {
+            minimum_version_to_pkg(&go_version)
+        } else {
+            version_number_to_pkg(&go_version)
+        };
+
+        match nix_pkg {
+            Some(nix_pkg) => Ok(nix_pkg),
+            None => bail!(Message::new(MessageId::GoVersionUnavailable)
+                .arg("version", &go_version)
+                .arg("source", source)
+                .arg(
+                    "versions",
+                    &AVAILABLE_GO_VERSIONS
+                        .iter()
+                        .map(|(version, _)| *version)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+        }
     }
 }
 
+/// Patch releases use the package of their minor version, e.g. `1.18.3` uses `1.18`
 fn version_number_to_pkg(version: &str) -> Option<String> {
-    let matched_version = AVAILABLE_GO_VERSIONS.iter().find(|(v, _)| v == &version);
+    let minor_version = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+    let matched_version = AVAILABLE_GO_VERSIONS
+        .iter()
+        .find(|(v, _)| v == &minor_version);
 
     matched_version.map(|(_, pkg)| (*pkg).to_string())
 }
 
+/// The oldest available version that is at least `version`, e.g. `1.16` uses `1.17`
+fn minimum_version_to_pkg(version: &str) -> Option<String> {
+    let requested = parse_minor_version(version)?;
+    AVAILABLE_GO_VERSIONS
+        .iter()
+        .find(|(v, _)| parse_minor_version(v).map_or(false, |available| available >= requested))
+        .map(|(_, pkg)| (*pkg).to_string())
+}
+
+fn parse_minor_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |minor| minor.parse().ok())?;
+    Some((major, minor))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::fs;
+    use tempdir::TempDir;
 
     #[test]
     fn test_no_go_mod() -> Result<()> {
         assert_eq!(
-            GolangProvider::get_nix_golang_pkg(None)?,
+            GolangProvider::get_nix_golang_pkg(
+                None,
+                &ToolVersions::default(),
+                &Environment::default()
+            )?,
             DEFAULT_GO_PKG_NAME.to_string()
         );
 
@@ -127,7 +265,11 @@ mod test {
         "#;
 
         assert_eq!(
-            GolangProvider::get_nix_golang_pkg(Some(&go_mod_contents.to_string()))?,
+            GolangProvider::get_nix_golang_pkg(
+                Some(&go_mod_contents.to_string()),
+                &ToolVersions::default(),
+                &Environment::default()
+            )?,
             "go_1_18".to_string()
         );
 
@@ -135,16 +277,181 @@ mod test {
     }
 
     #[test]
-    fn test_fallback_on_invalid_version() -> Result<()> {
+    fn test_go_mod_with_requirements() -> Result<()> {
         let go_mod_contents = r#"
-            go 1.8
-        "#;
+module example.com/app
+
+go 1.17.5
+
+require (
+	golang.org/x/net v0.1.0
+)
+"#;
 
         assert_eq!(
-            GolangProvider::get_nix_golang_pkg(Some(&go_mod_contents.to_string()))?,
-            DEFAULT_GO_PKG_NAME.to_string()
+            GolangProvider::get_nix_golang_pkg(
+                Some(&go_mod_contents.to_string()),
+                &ToolVersions::default(),
+                &Environment::default()
+            )?,
+            "go".to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_from_tool_versions() -> Result<()> {
+        assert_eq!(
+            GolangProvider::get_nix_golang_pkg(
+                Some(&"go 1.17".to_string()),
+                &ToolVersions::parse("golang 1.18.3\n"),
+                &Environment::default()
+            )?,
+            "go_1_18".to_string()
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_version_from_environment_variable() -> Result<()> {
+        assert_eq!(
+            GolangProvider::get_nix_golang_pkg(
+                Some(&"go 1.17".to_string()),
+                &ToolVersions::default(),
+                &Environment::from_envs(vec!["NIXPACKS_GO_VERSION=1.18"])?
+            )?,
+            "go_1_18".to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_go_mod_minimum_version() -> Result<()> {
+        assert_eq!(
+            GolangProvider::get_nix_golang_pkg(
+                Some(&"go 1.8".to_string()),
+                &ToolVersions::default(),
+                &Environment::default()
+            )?,
+            "go".to_string()
+        );
+        assert_eq!(
+            GolangProvider::get_nix_golang_pkg(
+                Some(&"go 1.17.5".to_string()),
+                &ToolVersions::default(),
+                &Environment::default()
+            )?,
+            "go".to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_on_unavailable_version() -> Result<()> {
+        let err = GolangProvider::get_nix_golang_pkg(
+            Some(&"go 1.19".to_string()),
+            &ToolVersions::default(),
+            &Environment::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Go 1.19 from go.mod is not available. Available versions: 1.17, 1.18"
+        );
+
+        let err = GolangProvider::get_nix_golang_pkg(
+            Some(&"go 1.17".to_string()),
+            &ToolVersions::default(),
+            &Environment::from_envs(vec!["NIXPACKS_GO_VERSION=1.16"])?,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Go 1.16 from NIXPACKS_GO_VERSION is not available. Available versions: 1.17, 1.18"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_uses_cgo() -> Result<()> {
+        assert!(!GolangProvider::uses_cgo(
+            &App::new("./examples/go")?,
+            &Environment::default()
+        )?);
+        assert!(GolangProvider::uses_cgo(
+            &App::new("./examples/go")?,
+            &Environment::from_envs(vec!["CGO_ENABLED=1"])?
+        )?);
+
+        let dir = TempDir::new("nixpacks-go")?;
+        fs::write(
+            dir.path().join("main.go"),
+            "package main\n\n// #include <stdio.h>\nimport \"C\"\n\nfunc main() {}\n",
+        )?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert!(GolangProvider::uses_cgo(&app, &Environment::default())?);
+        assert!(!GolangProvider::uses_cgo(
+            &app,
+            &Environment::from_envs(vec!["CGO_ENABLED=0"])?
+        )?);
+
+        fs::write(
+            dir.path().join("main.go"),
+            "package main\n\n// #include <stdio.h>\nimport (\n\t\"fmt\"\n\t\"C\"\n)\n\nfunc main() {}\n",
+        )?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert!(GolangProvider::uses_cgo(&app, &Environment::default())?);
+
+        fs::write(
+            dir.path().join("main.go"),
+            "package main\n\nimport (\n\t\"fmt\"\n\t\"Crypto\"\n)\n\nfunc main() {}\n",
+        )?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert!(!GolangProvider::uses_cgo(&app, &Environment::default())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_command() -> Result<()> {
+        let app = App::new("./examples/go-cmd")?;
+        assert_eq!(
+            GolangProvider::get_commands(&app)?,
+            vec!["api".to_string(), "worker".to_string()]
+        );
+        assert_eq!(
+            GolangProvider::get_command(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_GO_BIN=worker"])?
+            )?,
+            Some("worker".to_string())
+        );
+        assert_eq!(
+            GolangProvider::get_command(&App::new("./examples/go-mod")?, &Environment::default())?,
+            None
+        );
+
+        let err = GolangProvider::get_command(&app, &Environment::default())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Found multiple Go commands in cmd/: api, worker. Set NIXPACKS_GO_BIN to the one to build"
+        );
+
+        let err = GolangProvider::get_command(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_GO_BIN=cron"])?,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("Available commands: api, worker"));
+
+        Ok(())
+    }
 }
@@ -2,19 +2,26 @@ use super::Provider;
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
+        resources::BuildResources,
         BuildPlan,
     },
 };
 use anyhow::Result;
+use regex::Regex;
 use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::env::consts::ARCH;
 
 const STACK_CACHE_DIR: &str = "/root/.stack";
 const STACK_WORK_CACHE_DIR: &str = ".stack-work";
+// GHC regularly runs out of memory compiling dependencies on small runners
+const GHC_MIN_MEMORY_MB: u64 = 4096;
+const CABAL_CACHE_DIR: &str = "/root/.cabal";
+const CABAL_DIST_CACHE_DIR: &str = "dist-newstyle";
 
 pub struct HaskellStackProvider {}
 
@@ -63,16 +70,80 @@ impl Provider for HaskellStackProvider {
 
         let name = exe_names
             .get(0)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get executable name"))?;
+            .ok_or_else(|| Message::new(MessageId::ExecutableNameNotFound))?;
 
         let start = StartPhase::new(format!("/root/.local/bin/{}", name));
 
-        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        plan.add_resources(BuildResources::new(Some(GHC_MIN_MEMORY_MB), None));
 
         Ok(Some(plan))
     }
 }
 
+pub struct HaskellCabalProvider {}
+
+impl Provider for HaskellCabalProvider {
+    fn name(&self) -> &str {
+        "haskell-cabal"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        // Projects with a package.yaml or stack.yaml are built with Stack
+        if app.includes_file("package.yaml") || app.includes_file("stack.yaml") {
+            return Ok(false);
+        }
+
+        Ok(
+            (app.includes_file("cabal.project") || app.has_match("*.cabal"))
+                && app.has_match("**/*.hs"),
+        )
+    }
+
+    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![Pkg::new("ghc"), Pkg::new("cabal-install")]));
+
+        let mut install = Phase::install(Some("cabal update".to_string()));
+        install.add_cmd("cabal build --only-dependencies");
+        install.add_cache_directory(CABAL_CACHE_DIR.to_string());
+
+        let name = HaskellCabalProvider::get_executable_name(app)?
+            .ok_or_else(|| Message::new(MessageId::ExecutableNameNotFound))?;
+
+        // The dist-newstyle directory is cached so the executable is copied out of it
+        let mut build = Phase::build(Some(format!("cabal build {}", name)));
+        build.add_cmd(format!(
+            "mkdir -p /root/.local/bin && cp \"$(cabal list-bin {})\" /root/.local/bin/",
+            name
+        ));
+        build.add_cache_directory(CABAL_CACHE_DIR.to_string());
+        build.add_cache_directory(CABAL_DIST_CACHE_DIR.to_string());
+
+        let start = StartPhase::new(format!("/root/.local/bin/{}", name));
+
+        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        plan.add_resources(BuildResources::new(Some(GHC_MIN_MEMORY_MB), None));
+
+        Ok(Some(plan))
+    }
+}
+
+impl HaskellCabalProvider {
+    /// Name of the first executable declared in the project's `.cabal` files
+    fn get_executable_name(app: &App) -> Result<Option<String>> {
+        let re = Regex::new(r"(?mi)^executable\s+(\S+)")?;
+
+        for path in app.find_files("**/*.cabal")? {
+            let contents = app.read_file(&app.strip_source_path(&path)?.to_string_lossy())?;
+            if let Some(captures) = re.captures(&contents) {
+                return Ok(Some(captures[1].to_string()));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
 #[derive(Deserialize)]
 #[allow(clippy::zero_sized_map_values)]
 struct HaskellStackPackageYaml {
@@ -81,3 +152,28 @@ struct HaskellStackPackageYaml {
 
 #[derive(Deserialize)]
 struct HaskellStackExecutableDefinition {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cabal_executable_name() -> Result<()> {
+        let app = App::new("./examples/haskell-cabal")?;
+        assert!(HaskellCabalProvider {}.detect(&app, &Environment::default())?);
+        assert_eq!(
+            HaskellCabalProvider::get_executable_name(&app)?,
+            Some("haskell-cabal".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stack_project_is_not_cabal() -> Result<()> {
+        let app = App::new("./examples/haskell-stack")?;
+        assert!(!HaskellCabalProvider {}.detect(&app, &Environment::default())?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::Result;
+use path_slash::PathBufExt;
+use regex::Regex;
+
+const HAXE_HXML: &str = "HAXE_HXML";
+const DEFAULT_HXML: &str = "build.hxml";
+const HAXELIB_PATH: &str = "/root/haxelib";
+
+#[derive(Debug, PartialEq, Eq)]
+enum HaxeTarget {
+    Node(String),
+    HashLink(String),
+}
+
+pub struct HaxeProvider {}
+
+impl Provider for HaxeProvider {
+    fn name(&self) -> &str {
+        "haxe"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file(DEFAULT_HXML) || app.includes_file("haxelib.json"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let hxml = match HaxeProvider::get_hxml(app, env)? {
+            Some(hxml) => hxml,
+            None => return Ok(None),
+        };
+        let target = HaxeProvider::get_target(app, &hxml)?;
+
+        let mut pkgs = vec![Pkg::new("haxe")];
+        match target {
+            Some(HaxeTarget::Node(_)) => pkgs.push(Pkg::new("nodejs")),
+            Some(HaxeTarget::HashLink(_)) => pkgs.push(Pkg::new("hashlink")),
+            None => {}
+        }
+        let setup = Phase::setup(Some(pkgs));
+
+        // Libraries listed in the hxml with `-lib` are installed before compiling
+        let install = Phase::install(Some(format!(
+            "mkdir -p {path} && haxelib setup {path} && haxelib install --always {hxml}",
+            path = HAXELIB_PATH,
+            hxml = hxml
+        )));
+
+        let build = Phase::build(Some(format!("haxe {}", hxml)));
+
+        let start = target.map(|target| match target {
+            HaxeTarget::Node(output) => StartPhase::new(format!("node {}", output)),
+            HaxeTarget::HashLink(output) => StartPhase::new(format!("hl {}", output)),
+        });
+
+        let mut plan = BuildPlan::new(&vec![setup, install, build], start);
+        plan.add_build_variables(EnvironmentVariables::from([(
+            "HAXELIB_PATH".to_string(),
+            HAXELIB_PATH.to_string(),
+        )]));
+
+        Ok(Some(plan))
+    }
+}
+
+impl HaxeProvider {
+    /// Use the hxml file from the environment, then `build.hxml`, then any hxml in the app root
+    fn get_hxml(app: &App, env: &Environment) -> Result<Option<String>> {
+        if let Some(hxml) = env.get_config_variable(HAXE_HXML) {
+            return Ok(Some(hxml));
+        }
+
+        if app.includes_file(DEFAULT_HXML) {
+            return Ok(Some(DEFAULT_HXML.to_string()));
+        }
+
+        let hxml = match app.find_files("*.hxml")?.first() {
+            Some(path) => app
+                .strip_source_path(path)?
+                .to_slash()
+                .map(|p| p.to_string()),
+            None => None,
+        };
+
+        Ok(hxml)
+    }
+
+    /// The Node.js or HashLink output of the hxml. Other targets have no start command
+    fn get_target(app: &App, hxml: &str) -> Result<Option<HaxeTarget>> {
+        let contents = app.read_file(hxml)?;
+        let re = Regex::new(r"(?m)^\s*--?(js|hl)\s+(\S+)")?;
+
+        Ok(re.captures(&contents).map(|caps| {
+            let output = caps[2].to_string();
+            match &caps[1] {
+                "hl" => HaxeTarget::HashLink(output),
+                _ => HaxeTarget::Node(output),
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_target() -> Result<()> {
+        let app = App::new("./examples/haxe")?;
+
+        assert_eq!(
+            HaxeProvider::get_target(&app, "build.hxml")?,
+            Some(HaxeTarget::Node("bin/main.js".to_string()))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use super::{staticfile::StaticfileProvider, Provider};
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{phase::Phase, BuildPlan},
+};
+use anyhow::Result;
+
+const HUGO_OUTPUT_DIR: &str = "public";
+
+pub struct HugoProvider {}
+
+impl Provider for HugoProvider {
+    fn name(&self) -> &str {
+        "hugo"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(
+            (app.includes_file("config.toml") || app.includes_file("hugo.toml"))
+                && app.includes_directory("content"),
+        )
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = StaticfileProvider::get_setup_phase(vec![Pkg::new("hugo")]);
+
+        let mut build = Phase::build(Some("hugo --minify".to_string()));
+        build.depends_on_phase("setup");
+
+        // The site is generated into `public/` and served with nginx
+        let start = StaticfileProvider::get_start_phase(app);
+        let static_assets = StaticfileProvider::get_static_assets(app, env, Some(HUGO_OUTPUT_DIR))?;
+
+        let mut plan = BuildPlan::new(&vec![setup, build], Some(start));
+        plan.add_static_assets(static_assets);
+
+        Ok(Some(plan))
+    }
+}
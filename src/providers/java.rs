@@ -1,18 +1,32 @@
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
+        resources::BuildResources,
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use regex::{Match, Regex};
 
 pub struct JavaProvider {}
 
+const DEFAULT_JDK_PKG_NAME: &str = "jdk";
+const AVAILABLE_JDK_VERSIONS: &[u32] = &[8, 11, 17];
+
+// Layers of a Spring Boot jar, from the least to the most frequently changing
+const SPRING_BOOT_LAYERS: &[&str] = &[
+    "dependencies",
+    "spring-boot-loader",
+    "snapshot-dependencies",
+    "application",
+];
+const SPRING_BOOT_LAYERS_DIR: &str = "layers";
+
 impl Provider for JavaProvider {
     fn name(&self) -> &str {
         "java"
@@ -27,13 +41,24 @@ impl Provider for JavaProvider {
             || app.includes_file("pom.scala")
             || app.includes_file("pom.yaml")
             || app.includes_file("pom.yml")
-            || app.includes_file("gradlew"))
+            || app.includes_file("gradlew")
+            || app.includes_file("build.gradle")
+            || app.includes_file("build.gradle.kts"))
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut setup: Phase;
         let mut build = if self.is_using_gradle(app) {
-            let pkgs = self.get_jdk_and_gradle_pkgs(app)?;
+            let mut pkgs = self.get_jdk_and_gradle_pkgs(app)?;
+
+            // A requested Java version replaces the JDK that matches the Gradle version
+            if self.get_requested_jdk_version(app, env)?.is_some() {
+                let jdk_pkg = self.get_jdk_pkg(app, env)?;
+                pkgs = vec![
+                    jdk_pkg.clone(),
+                    pkgs[1].clone().set_override("java", &jdk_pkg.name),
+                ];
+            }
             setup = Phase::setup(Some(pkgs));
 
             let mut build = Phase::build(None);
@@ -45,22 +70,59 @@ impl Provider for JavaProvider {
             }
 
             build.add_cmd(format!("{} build -x check", gradle_exe));
+            // Spring Boot 2.5+ also builds a `-plain.jar` without dependencies, which `build/libs/*.jar` would match
+            build.add_cmd("rm -f build/libs/*-plain.jar");
             build.add_cache_directory("/root/.gradle");
             build
         } else {
-            setup = Phase::setup(Some(vec![Pkg::new("jdk")]));
-            setup.add_nix_pkgs(&[Pkg::new("maven")]);
+            let jdk_pkg = self.get_jdk_pkg(app, env)?;
+
+            // Maven is wrapped with its own JDK, so it has to compile with the same one
+            let maven_pkg = if jdk_pkg.name == DEFAULT_JDK_PKG_NAME {
+                Pkg::new("maven")
+            } else {
+                Pkg::new("maven").set_override("jdk", &jdk_pkg.name)
+            };
+
+            setup = Phase::setup(Some(vec![jdk_pkg]));
+            setup.add_nix_pkgs(&[maven_pkg]);
             let mvn_exe = self.get_maven_exe(app);
-            let mut build = Phase::build(Some(format!("{mvn_exe} -DoutputFile=target/mvn-dependency-list.log -B -DskipTests clean dependency:list install", 
-                mvn_exe=mvn_exe
+
+            // Only build the module and the modules it depends on
+            let projects = match self.get_maven_module(app, env)? {
+                Some(module) => format!(" -pl {} -am", module),
+                None => String::new(),
+            };
+
+            let mut build = Phase::build(Some(format!("{mvn_exe}{projects} -DoutputFile=target/mvn-dependency-list.log -B -DskipTests clean dependency:list install", 
+                mvn_exe=mvn_exe,
+                projects=projects
             )));
             build.add_cache_directory(".m2/repository");
             build
         };
-        let start = StartPhase::new(self.get_start_cmd(app)?);
         build.depends_on = Some(vec!["setup".to_string()]);
 
-        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        let start = match self.get_spring_boot_launcher(app, env)? {
+            Some(launcher) => {
+                build.add_cmd(format!(
+                    "java -Djarmode=layertools -jar {} extract --destination {}",
+                    self.get_jar_path(app, env)?,
+                    SPRING_BOOT_LAYERS_DIR
+                ));
+                self.get_layered_start(app, env, launcher)?
+            }
+            None => StartPhase::new(self.get_start_cmd(app, env)?),
+        };
+
+        let mut plan = BuildPlan::new(&vec![setup, build], Some(start));
+
+        if self.is_using_gradle(app) {
+            if let Some(memory_mb) = self.get_gradle_memory_mb(app)? {
+                plan.add_resources(BuildResources::new(Some(memory_mb), None));
+            }
+        }
+
         Ok(Some(plan))
     }
 }
@@ -85,16 +147,127 @@ impl JavaProvider {
         }
     }
 
-    fn get_start_cmd(&self, app: &App) -> Result<String> {
+    /// The requested Java version, and where it was found
+    fn get_requested_jdk_version(
+        &self,
+        app: &App,
+        env: &Environment,
+    ) -> Result<Option<(String, &'static str)>> {
+        if let Some(version) = env.get_config_variable("JDK_VERSION") {
+            return Ok(Some((version, "NIXPACKS_JDK_VERSION")));
+        }
+
+        if app.includes_file(".sdkmanrc") {
+            let sdkmanrc = app.read_file(".sdkmanrc")?;
+            if let Some(captures) = Regex::new(r"(?m)^\s*java\s*=\s*(\S+)")?.captures(&sdkmanrc) {
+                return Ok(Some((captures[1].to_string(), ".sdkmanrc")));
+            }
+        }
+
+        if let Some(pinned) = ToolVersions::from_app(app)?.get("java") {
+            return Ok(Some(pinned));
+        }
+
+        // The toolchain of the `java` extension, e.g. `languageVersion = JavaLanguageVersion.of(17)`
+        if self.is_using_gradle(app) {
+            let toolchain_re = Regex::new(r"JavaLanguageVersion\.of\(\s*(\d+)\s*\)")?;
+            if let Some(captures) = toolchain_re.captures(&self.read_gradle_build_file(app)?) {
+                return Ok(Some((captures[1].to_string(), "the Gradle toolchain")));
+            }
+        }
+
+        if app.includes_file("pom.xml") {
+            let pom_file = app.read_file("pom.xml")?;
+            for property in ["maven.compiler.release", "java.version"] {
+                let re = Regex::new(&format!(
+                    r"<{}>\s*([^<$\s]+)\s*</{}>",
+                    regex::escape(property),
+                    regex::escape(property)
+                ))?;
+                if let Some(captures) = re.captures(&pom_file) {
+                    return Ok(Some((captures[1].to_string(), "pom.xml")));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The lowest available JDK that can build the requested Java version
+    fn get_jdk_pkg(&self, app: &App, env: &Environment) -> Result<Pkg> {
+        let (version, source) = match self.get_requested_jdk_version(app, env)? {
+            Some(requested) => requested,
+            None => return Ok(Pkg::new(DEFAULT_JDK_PKG_NAME)),
+        };
+
+        // Versions are written as 1.8, 17, 17.0.5-tem, or with the distribution first for asdf, e.g. temurin-17.0.5+8
+        let major = Regex::new(r"^(?:[a-zA-Z][a-zA-Z0-9]*-)*(?:1\.)?(\d+)")?
+            .captures(version.trim())
+            .and_then(|captures| captures[1].parse::<u32>().ok());
+
+        match major.and_then(|major| {
+            AVAILABLE_JDK_VERSIONS
+                .iter()
+                .find(|available| **available >= major)
+        }) {
+            Some(available) => Ok(Pkg::new(&format!("jdk{}", available))),
+            None => bail!(Message::new(MessageId::JdkVersionUnavailable)
+                .arg("version", &version)
+                .arg("source", source)
+                .arg(
+                    "versions",
+                    &AVAILABLE_JDK_VERSIONS
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+        }
+    }
+
+    /// The modules listed in the root `pom.xml`
+    fn get_maven_modules(&self, app: &App) -> Result<Vec<String>> {
+        let pom_file = app.read_file("pom.xml").unwrap_or_default();
+        let modules = Regex::new(r"<module>\s*([^<\s]+)\s*</module>")?
+            .captures_iter(&pom_file)
+            .map(|captures| captures[1].to_string())
+            .collect();
+
+        Ok(modules)
+    }
+
+    /// The module of a multi-module project to build and start, from `NIXPACKS_MAVEN_MODULE`
+    fn get_maven_module(&self, app: &App, env: &Environment) -> Result<Option<String>> {
+        let module = match env.get_config_variable("MAVEN_MODULE") {
+            Some(module) => module.trim_end_matches('/').to_string(),
+            None => return Ok(None),
+        };
+
+        if !app.includes_file(&format!("{}/pom.xml", module)) {
+            bail!(Message::new(MessageId::MavenModuleNotFound)
+                .arg("name", &module)
+                .arg("modules", &self.get_maven_modules(app)?.join(", ")));
+        }
+
+        Ok(Some(module))
+    }
+
+    fn get_start_cmd(&self, app: &App, env: &Environment) -> Result<String> {
         let cmd = if self.is_using_gradle(app) {
             format!(
                 "java $JAVA_OPTS -jar {} build/libs/*.jar",
                 self.get_gradle_port_config(app)?
             )
+        } else if let Some(module) = self.get_maven_module(app, env)? {
+            format!(
+                "java {} $JAVA_OPTS -jar {}/target/*jar",
+                self.get_port_config(app, Some(&module)),
+                module
+            )
         } else if app.includes_file("pom.xml") {
             format!(
                 "java {} $JAVA_OPTS -jar target/*jar",
-                self.get_port_config(app)
+                self.get_port_config(app, None)
             )
         } else {
             "java $JAVA_OPTS -jar target/*jar".to_string()
@@ -103,11 +276,114 @@ impl JavaProvider {
         Ok(cmd)
     }
 
+    fn get_jar_path(&self, app: &App, env: &Environment) -> Result<String> {
+        let jar_path = if self.is_using_gradle(app) {
+            "build/libs/*.jar".to_string()
+        } else if let Some(module) = self.get_maven_module(app, env)? {
+            format!("{}/target/*jar", module)
+        } else {
+            "target/*jar".to_string()
+        };
+
+        Ok(jar_path)
+    }
+
+    /// The `JarLauncher` class if the app uses a Spring Boot version with layered jars (2.4 or later)
+    fn get_spring_boot_launcher(&self, app: &App, env: &Environment) -> Result<Option<&str>> {
+        let (file_content, version_re) = if self.is_using_gradle(app) {
+            (
+                self.read_gradle_build_file(app)?,
+                Regex::new(r#"org\.springframework\.boot['"]\)?\s+version\s+['"](\d+)\.(\d+)"#)?,
+            )
+        } else {
+            let mut pom_file = app.read_file("pom.xml").unwrap_or_default();
+            if let Some(module) = self.get_maven_module(app, env)? {
+                pom_file.push_str(
+                    &app.read_file(&format!("{}/pom.xml", module))
+                        .unwrap_or_default(),
+                );
+            }
+            (
+                pom_file,
+                Regex::new(
+                    r"<artifactId>spring-boot-(?:starter-parent|dependencies)</artifactId>\s*<version>(\d+)\.(\d+)",
+                )?,
+            )
+        };
+
+        let version = version_re.captures(&file_content).and_then(|captures| {
+            Some((
+                captures[1].parse::<u32>().ok()?,
+                captures[2].parse::<u32>().ok()?,
+            ))
+        });
+
+        let launcher = match version {
+            Some(version) if version >= (3, 2) => {
+                Some("org.springframework.boot.loader.launch.JarLauncher")
+            }
+            Some(version) if version >= (2, 4) => {
+                Some("org.springframework.boot.loader.JarLauncher")
+            }
+            _ => None,
+        };
+
+        Ok(launcher)
+    }
+
+    /// Run the extracted jar in the base image, with each layer copied separately
+    /// so the dependencies stay cached when only the application changes
+    fn get_layered_start(
+        &self,
+        app: &App,
+        env: &Environment,
+        launcher: &str,
+    ) -> Result<StartPhase> {
+        let port_config = if self.is_using_gradle(app) {
+            self.get_gradle_port_config(app)?
+        } else {
+            self.get_port_config(app, self.get_maven_module(app, env)?.as_deref())
+        };
+
+        let mut start = StartPhase::new(format!("java {} $JAVA_OPTS {}", port_config, launcher));
+        start.run_in_default_image();
+        for layer in SPRING_BOOT_LAYERS {
+            start.add_file_dependency(format!("./{}/{}", SPRING_BOOT_LAYERS_DIR, layer));
+        }
+        start.add_file_dependency("/nix");
+
+        Ok(start)
+    }
+
     fn is_using_gradle(&self, app: &App) -> bool {
         app.includes_file("gradlew")
+            || app.includes_file("build.gradle")
+            || app.includes_file("build.gradle.kts")
     }
 
-    fn get_gradle_port_config(&self, app: &App) -> Result<String> {
+    /// The memory that `org.gradle.jvmargs` in `gradle.properties` gives the Gradle daemon with `-Xmx`
+    fn get_gradle_memory_mb(&self, app: &App) -> Result<Option<u64>> {
+        if !app.includes_file("gradle.properties") {
+            return Ok(None);
+        }
+
+        let properties = app.read_file("gradle.properties")?;
+        let memory = Regex::new(r"(?m)^\s*org\.gradle\.jvmargs\s*=.*-Xmx(\d+)([kKmMgG]?)")?
+            .captures(&properties)
+            .and_then(|captures| {
+                let size = captures[1].parse::<u64>().ok()?;
+                Some(match captures[2].to_ascii_lowercase().as_str() {
+                    "g" => size * 1024,
+                    "m" => size,
+                    "k" => size / 1024,
+                    _ => size / 1024 / 1024,
+                })
+            });
+
+        Ok(memory)
+    }
+
+    fn read_gradle_build_file(&self, app: &App) -> Result<String> {
         let file_content = if app.includes_file("build.gradle") {
             app.read_file("build.gradle")?
         } else if app.includes_file("build.gradle.kts") {
@@ -116,6 +392,12 @@ impl JavaProvider {
             String::new()
         };
 
+        Ok(file_content)
+    }
+
+    fn get_gradle_port_config(&self, app: &App) -> Result<String> {
+        let file_content = self.read_gradle_build_file(app)?;
+
         let is_spring_boot = file_content.contains("org.springframework.boot:spring-boot")
             || file_content.contains("spring-boot-gradle-plugin")
             || file_content.contains("org.springframework.boot")
@@ -130,8 +412,16 @@ impl JavaProvider {
         Ok(port_arg)
     }
 
-    fn get_port_config(&self, app: &App) -> String {
-        let pom_file = app.read_file("pom.xml").unwrap_or_default();
+    fn get_port_config(&self, app: &App, module: Option<&str>) -> String {
+        // Frameworks are often only in the parent pom of the module, so check both
+        let mut pom_file = app.read_file("pom.xml").unwrap_or_default();
+        if let Some(module) = module {
+            pom_file.push_str(
+                &app.read_file(&format!("{}/pom.xml", module))
+                    .unwrap_or_default(),
+            );
+        }
+
         if pom_file.contains("<groupId>org.wildfly.swarm") {
             "-Dswarm.http.port=$PORT".to_string()
         } else if pom_file.contains("<groupId>org.springframework.boot")
@@ -196,3 +486,140 @@ impl JavaProvider {
         Ok(pkgs)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_jdk_from_pom() -> Result<()> {
+        assert_eq!(
+            JavaProvider {}
+                .get_jdk_pkg(&App::new("./examples/java-maven")?, &Environment::default())?,
+            Pkg::new("jdk8")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_spring_boot_launcher() -> Result<()> {
+        let java = JavaProvider {};
+        let env = Environment::default();
+        assert_eq!(
+            java.get_spring_boot_launcher(
+                &App::new("./examples/java-maven-spring-boot-layered")?,
+                &env
+            )?,
+            Some("org.springframework.boot.loader.JarLauncher")
+        );
+        assert_eq!(
+            java.get_spring_boot_launcher(&App::new("./examples/java-maven")?, &env)?,
+            None
+        );
+        assert_eq!(
+            java.get_spring_boot_launcher(&App::new("./examples/java-spring-boot")?, &env)?,
+            None
+        );
+        assert_eq!(
+            java.get_spring_boot_launcher(
+                &App::new("./examples/java-gradle-spring-boot-layered")?,
+                &env
+            )?,
+            Some("org.springframework.boot.loader.JarLauncher")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jdk_from_tool_versions() -> Result<()> {
+        let dir = TempDir::new("nixpacks-java")?;
+        fs::write(dir.path().join("pom.xml"), "<project></project>")?;
+        fs::write(dir.path().join(".tool-versions"), "java temurin-17.0.5+8\n")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        assert_eq!(
+            JavaProvider {}.get_jdk_pkg(&app, &Environment::default())?,
+            Pkg::new("jdk17")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jdk_from_sdkmanrc() -> Result<()> {
+        let dir = TempDir::new("nixpacks-java")?;
+        fs::write(
+            dir.path().join("pom.xml"),
+            "<properties><maven.compiler.release>11</maven.compiler.release></properties>",
+        )?;
+        fs::write(dir.path().join(".sdkmanrc"), "java=17.0.5-tem\n")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        assert_eq!(
+            JavaProvider {}.get_jdk_pkg(&app, &Environment::default())?,
+            Pkg::new("jdk17")
+        );
+        assert_eq!(
+            JavaProvider {}.get_jdk_pkg(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_JDK_VERSION=9"])?
+            )?,
+            Pkg::new("jdk11")
+        );
+
+        let err = JavaProvider {}
+            .get_jdk_pkg(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_JDK_VERSION=21"])?,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Java 21 from NIXPACKS_JDK_VERSION is not available. Available versions: 8, 11, 17"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jdk_from_gradle_toolchain() -> Result<()> {
+        assert_eq!(
+            JavaProvider {}.get_jdk_pkg(
+                &App::new("./examples/java-gradle-toolchain")?,
+                &Environment::default()
+            )?,
+            Pkg::new("jdk17")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gradle_memory() -> Result<()> {
+        let java = JavaProvider {};
+        assert_eq!(
+            java.get_gradle_memory_mb(&App::new("./examples/java-gradle-toolchain")?)?,
+            Some(2048)
+        );
+        assert_eq!(
+            java.get_gradle_memory_mb(&App::new("./examples/java-gradle-hello-world")?)?,
+            None
+        );
+
+        let dir = TempDir::new("nixpacks-java")?;
+        fs::write(
+            dir.path().join("gradle.properties"),
+            "# org.gradle.jvmargs=-Xmx4g\norg.gradle.jvmargs=-XX:MaxMetaspaceSize=512m -Xmx1536m\n",
+        )?;
+        assert_eq!(
+            java.get_gradle_memory_mb(&App::new(&dir.path().to_string_lossy())?)?,
+            Some(1536)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use super::{ruby::RubyProvider, staticfile::StaticfileProvider, Provider};
+use crate::nixpacks::{
+    app::App,
+    environment::{Environment, EnvironmentVariables},
+    nix::pkg::Pkg,
+    plan::BuildPlan,
+};
+use anyhow::Result;
+
+const JEKYLL_OUTPUT_DIR: &str = "_site";
+
+pub struct JekyllProvider {}
+
+impl Provider for JekyllProvider {
+    fn name(&self) -> &str {
+        "jekyll"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("_config.yml")
+            && app.includes_file("Gemfile")
+            && app.read_file("Gemfile")?.contains("jekyll"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        // Ruby and the gems are installed the same way as any other Ruby app
+        let mut plan = match (RubyProvider {}).get_build_plan(app, env)? {
+            Some(plan) => plan,
+            None => return Ok(None),
+        };
+
+        if let Some(setup) = plan.get_phase_mut("setup") {
+            setup.add_nix_pkgs(&[Pkg::new("nginx")]);
+            setup.add_cmd("mkdir /etc/nginx/ /var/log/nginx/ /var/cache/nginx/");
+        }
+
+        if let Some(build) = plan.get_phase_mut("build") {
+            build.add_cmd("bundle exec jekyll build");
+        }
+        plan.add_build_variables(EnvironmentVariables::from([(
+            "JEKYLL_ENV".to_string(),
+            "production".to_string(),
+        )]));
+
+        // The generated `_site/` is served with nginx instead of running Ruby
+        plan.set_start_phase(StaticfileProvider::get_start_phase(app));
+        plan.add_static_assets(StaticfileProvider::get_static_assets(
+            app,
+            env,
+            Some(JEKYLL_OUTPUT_DIR),
+        )?);
+
+        Ok(Some(plan))
+    }
+}
@@ -0,0 +1,91 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+
+const MESON_TARGET: &str = "MESON_TARGET";
+const MESON_BUILD_DIR: &str = "build";
+
+pub struct MesonProvider {}
+
+impl Provider for MesonProvider {
+    fn name(&self) -> &str {
+        "meson"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("meson.build"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![
+            Pkg::new("meson"),
+            Pkg::new("ninja"),
+            Pkg::new("gcc"),
+            Pkg::new("pkg-config"),
+        ]));
+
+        let mut build = Phase::build(Some(format!(
+            "meson setup {dir} --buildtype=release && ninja -C {dir}",
+            dir = MESON_BUILD_DIR
+        )));
+        build.depends_on_phase("setup");
+
+        let start = MesonProvider::get_executable(app, env)?
+            .map(|name| StartPhase::new(format!("./{}/{}", MESON_BUILD_DIR, name)));
+
+        let plan = BuildPlan::new(&vec![setup, build], start);
+        Ok(Some(plan))
+    }
+}
+
+impl MesonProvider {
+    /// Use the executable from the environment, otherwise the first one declared in meson.build
+    fn get_executable(app: &App, env: &Environment) -> Result<Option<String>> {
+        if let Some(target) = env.get_config_variable(MESON_TARGET) {
+            return Ok(Some(target));
+        }
+
+        let contents = app
+            .read_file("meson.build")
+            .context("Reading meson.build")?;
+        let re = Regex::new(r#"executable\s*\(\s*['"]([^'"]+)['"]"#)?;
+
+        Ok(re
+            .captures(&contents)
+            .and_then(|caps| caps.get(1))
+            .map(|m| m.as_str().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_executable() -> Result<()> {
+        let app = App::new("./examples/meson")?;
+
+        assert_eq!(
+            MesonProvider::get_executable(&app, &Environment::default())?,
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            MesonProvider::get_executable(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_MESON_TARGET=other"])?
+            )?,
+            Some("other".to_string())
+        );
+
+        Ok(())
+    }
+}
@@ -2,24 +2,37 @@ use crate::nixpacks::{app::App, environment::Environment, plan::BuildPlan};
 use anyhow::Result;
 
 pub mod clojure;
+pub mod cmake;
 pub mod cobol;
+pub mod conformance;
 pub mod crystal;
 pub mod csharp;
 pub mod dart;
 pub mod deno;
+pub mod dotnet;
 pub mod elixir;
+pub mod elm;
+pub mod flutter;
 pub mod fsharp;
 pub mod go;
 pub mod haskell;
+pub mod haxe;
+pub mod hugo;
 pub mod java;
+pub mod jekyll;
+pub mod meson;
 pub mod node;
 pub mod php;
 pub mod procfile;
+pub mod purescript;
 pub mod python;
+pub mod racket;
 pub mod ruby;
 pub mod rust;
 pub mod staticfile;
 pub mod swift;
+pub mod tool_versions;
+pub mod vlang;
 pub mod zig;
 
 pub trait Provider {
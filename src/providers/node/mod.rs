@@ -1,44 +1,62 @@
-use self::{nx::Nx, turborepo::Turborepo};
-use super::Provider;
+use self::{
+    native::NativeAddons,
+    next::NextStandalone,
+    nx::Nx,
+    static_site::StaticSite,
+    turborepo::{Turborepo, TURBO_CACHE_DIR},
+    workspace::NodeWorkspace,
+};
+use super::{staticfile::StaticfileProvider, tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
+        resources::BuildResources,
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use path_slash::PathExt;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 
+mod native;
+mod next;
 mod nx;
+mod static_site;
 mod turborepo;
+mod version;
+mod workspace;
 
 pub const NODE_OVERLAY: &str = "https://github.com/railwayapp/nix-npm-overlay/archive/main.tar.gz";
 
+const DEFAULT_NODE_VERSION: u32 = 16;
 const DEFAULT_NODE_PKG_NAME: &str = "nodejs-16_x";
 const AVAILABLE_NODE_VERSIONS: &[u32] = &[14, 16, 18];
 
 const YARN_CACHE_DIR: &str = "/usr/local/share/.cache/yarn/v6";
+const YARN_BERRY_CACHE_DIR: &str = ".yarn/cache";
 const PNPM_CACHE_DIR: &str = "/root/.cache/pnpm";
+const PNPM_STORE_DIR: &str = "/root/.local/share/pnpm/store";
 const NPM_CACHE_DIR: &str = "/root/.npm";
 const BUN_CACHE_DIR: &str = "/root/.bun";
 const CYPRESS_CACHE_DIR: &str = "/root/.cache/Cypress";
 const NODE_MODULES_CACHE_DIR: &str = "node_modules/.cache";
+const COREPACK_BIN_DIR: &str = "/root/.corepack/bin";
+const NEXT_MIN_CPUS: u32 = 2;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 #[serde(untagged)]
 pub enum Workspaces {
     Array(Vec<String>),
     Unknown(Value),
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
 pub struct PackageJson {
     pub name: Option<String>,
     pub scripts: Option<HashMap<String, String>>,
@@ -51,12 +69,25 @@ pub struct PackageJson {
     pub project_type: Option<String>,
 
     pub workspaces: Option<Workspaces>,
+
+    #[serde(rename = "packageManager")]
+    pub package_manager: Option<String>,
+
+    pub volta: Option<Volta>,
+}
+
+/// Tools pinned with [Volta](https://volta.sh)
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+pub struct Volta {
+    pub node: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct Yarnrc {
     #[serde(rename = "yarnPath")]
     pub yarn_path: Option<String>,
+    #[serde(rename = "nodeLinker")]
+    pub node_linker: Option<String>,
 }
 
 #[derive(Default, Debug)]
@@ -75,7 +106,7 @@ impl Provider for NodeProvider {
         // Setup
         let mut setup = Phase::setup(Some(NodeProvider::get_nix_packages(app, env)?));
 
-        if NodeProvider::uses_node_dependency(app, "puppeteer") {
+        if NativeAddons::uses_package(app, "puppeteer") {
             // https://gist.github.com/winuxue/cfef08e2f5fe9dfc16a1d67a4ad38a01
             setup.add_apt_pkgs(vec![
                 "libnss3".to_string(),
@@ -90,13 +121,43 @@ impl Provider for NodeProvider {
                 "libxshmfence1".to_string(),
                 "libglu1".to_string(),
             ]);
-        } else if NodeProvider::uses_node_dependency(app, "canvas") {
+        } else if NativeAddons::uses_package(app, "canvas") {
             setup.add_pkgs_libs(vec!["libuuid".to_string(), "libGL".to_string()]);
         }
 
+        let native_addon_pkgs = NativeAddons::get_nix_pkgs(app);
+        if !native_addon_pkgs.is_empty() {
+            setup.add_nix_pkgs(&native_addon_pkgs);
+        }
+
+        if NativeAddons::uses_prebuilt_binaries(app) {
+            setup.add_prebuilt_binary_compat();
+        }
+
+        // Use the exact package manager version pinned in `packageManager` instead of the Nix package
+        if let Some((name, version)) = NodeProvider::get_corepack_package_manager(app) {
+            setup.add_cmd(format!(
+                "mkdir -p {dir} && corepack enable --install-directory {dir} {name} && corepack prepare {name}@{version} --activate",
+                dir = COREPACK_BIN_DIR,
+                name = name,
+                version = version
+            ));
+            setup.add_path(COREPACK_BIN_DIR.to_string());
+        }
+
         // Install
-        let mut install = Phase::install(NodeProvider::get_install_command(app));
-        install.add_cache_directory(NodeProvider::get_package_manager_cache_dir(app));
+        let workspace = NodeWorkspace::get_target(app, env)?;
+        let install_cmd = NodeProvider::get_install_command(app).map(|cmd| match &workspace {
+            Some(workspace) => workspace.get_install_cmd(app, cmd),
+            None => cmd,
+        });
+        let mut install = Phase::install(install_cmd);
+        if let Some(cache_dir) = NodeProvider::get_package_manager_cache_dir(app) {
+            install.add_cache_directory(cache_dir);
+        }
+        if NodeProvider::get_package_manager(app) == "pnpm" {
+            install.add_cache_directory(PNPM_STORE_DIR.to_string());
+        }
         install.add_path("/app/node_modules/.bin".to_string());
 
         // Cypress cache directory
@@ -110,6 +171,7 @@ impl Provider for NodeProvider {
 
         // Next build cache directories
         let next_cache_dirs = NodeProvider::find_next_packages(app)?;
+        let uses_next = !next_cache_dirs.is_empty();
         for dir in next_cache_dirs {
             let next_cache_dir = ".next/cache";
             build.add_cache_directory(if dir.is_empty() {
@@ -122,11 +184,72 @@ impl Provider for NodeProvider {
         // Node modules cache directory
         build.add_cache_directory((*NODE_MODULES_CACHE_DIR).to_string());
 
+        // Turborepo cache directory, so unchanged packages are not built again
+        if Turborepo::is_turborepo(app) {
+            build.add_cache_directory(TURBO_CACHE_DIR.to_string());
+        }
+
+        // Nx task cache directory
+        if Nx::is_nx_monorepo(app, env) {
+            build.add_cache_directory(Nx::get_nx_cache_dir(app));
+        }
+
         // Start
-        let start = NodeProvider::get_start_cmd(app, env)?.map(StartPhase::new);
+        let mut start = NodeProvider::get_start_cmd(app, env)?.map(|cmd| {
+            let mut start = StartPhase::new(cmd);
+            start.set_port(3000);
+            start
+        });
+
+        // Only copy the Next.js standalone server into the final image
+        let next_standalone = workspace.is_none() && NextStandalone::is_standalone(app);
+        if next_standalone {
+            NextStandalone::add_copy_cmds(app, &mut build);
+            start = Some(NextStandalone::get_start_phase(app));
+        }
+
+        // Serve the output of static site builds with nginx instead of a development server
+        let static_output_dir = if workspace.is_none() && !next_standalone {
+            StaticSite::get_output_dir(app)
+        } else {
+            None
+        };
+        if static_output_dir.is_some() {
+            StaticfileProvider::add_nginx(&mut setup);
+            start = Some(StaticfileProvider::get_start_phase(app));
+        }
+
+        // Only copy the files needed by the selected workspace package into the image
+        if let Some(workspace) = &workspace {
+            if NodeWorkspace::can_copy_only_needed_files(app) {
+                let files = workspace.get_needed_files(app)?;
+                setup.only_include_files = Some(Vec::new());
+                install.only_include_files = Some(files.clone());
+                build.only_include_files = Some(files.clone());
+                if let Some(start) = start.as_mut() {
+                    start.only_include_files = Some(files);
+                }
+            }
+        }
 
         let mut plan = BuildPlan::new(&vec![setup, install, build], start);
         plan.add_variables(NodeProvider::get_node_environment_variables());
+        plan.add_build_variables(NodeProvider::get_node_build_variables());
+        if next_standalone {
+            plan.add_runtime_variables(NextStandalone::get_runtime_variables());
+        }
+        if let Some(output_dir) = static_output_dir {
+            plan.add_static_assets(StaticfileProvider::get_static_assets(
+                app,
+                env,
+                Some(&output_dir),
+            )?);
+        }
+
+        // Next.js builds compile and optimize pages in parallel
+        if uses_next {
+            plan.add_resources(BuildResources::new(None, Some(NEXT_MIN_CPUS)));
+        }
 
         Ok(Some(plan))
     }
@@ -134,8 +257,12 @@ impl Provider for NodeProvider {
 
 impl NodeProvider {
     pub fn get_node_environment_variables() -> EnvironmentVariables {
+        EnvironmentVariables::from([("NODE_ENV".to_string(), "production".to_string())])
+    }
+
+    /// Install dev dependencies and run tools non-interactively, without leaking either setting into the app
+    pub fn get_node_build_variables() -> EnvironmentVariables {
         EnvironmentVariables::from([
-            ("NODE_ENV".to_string(), "production".to_string()),
             ("NPM_CONFIG_PRODUCTION".to_string(), "false".to_string()),
             ("CI".to_string(), "true".to_string()),
         ])
@@ -153,6 +280,10 @@ impl NodeProvider {
     }
 
     pub fn get_build_cmd(app: &App, env: &Environment) -> Result<Option<String>> {
+        if let Some(workspace) = NodeWorkspace::get_target(app, env)? {
+            return Ok(workspace.get_script_cmd(app, "build"));
+        }
+
         if Nx::is_nx_monorepo(app, env) {
             if let Some(nx_build_cmd) = Nx::get_nx_build_cmd(app, env) {
                 return Ok(Some(nx_build_cmd));
@@ -177,6 +308,21 @@ impl NodeProvider {
         let executor = NodeProvider::get_executor(app);
         let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
 
+        if let Some(workspace) = NodeWorkspace::get_target(app, env)? {
+            if let Some(start_cmd) = workspace.get_script_cmd(app, "start") {
+                return Ok(Some(start_cmd));
+            }
+
+            return Ok(workspace.package_json.main.map(|main| {
+                format!(
+                    "{} {}/{}",
+                    executor,
+                    workspace.dir,
+                    main.trim_start_matches("./")
+                )
+            }));
+        }
+
         if Nx::is_nx_monorepo(app, env) {
             if let Some(nx_start_cmd) = Nx::get_nx_start_cmd(app, env)? {
                 return Ok(Some(nx_start_cmd));
@@ -210,51 +356,85 @@ impl NodeProvider {
         Ok(None)
     }
 
-    /// Parses the package.json engines field and returns a Nix package if available
-    pub fn get_nix_node_pkg(
+    /// The Node version requested by `NIXPACKS_NODE_VERSION`, `engines.node`, `.nvmrc`, `.node-version`,
+    /// `.tool-versions`, or Volta,
+    /// along with where it was found
+    fn get_requested_node_version(
         package_json: &PackageJson,
         app: &App,
         environment: &Environment,
-    ) -> Result<Pkg> {
-        let env_node_version = environment.get_config_variable("NODE_VERSION");
+    ) -> Result<Option<(String, &'static str)>> {
+        if let Some(version) = environment.get_config_variable("NODE_VERSION") {
+            return Ok(Some((version, "NIXPACKS_NODE_VERSION")));
+        }
 
-        let pkg_node_version = package_json
+        if let Some(version) = package_json
             .engines
-            .clone()
-            .and_then(|engines| engines.get("node").cloned());
+            .as_ref()
+            .and_then(|engines| engines.get("node"))
+        {
+            return Ok(Some((version.clone(), "engines.node")));
+        }
 
-        let nvmrc_node_version = if app.includes_file(".nvmrc") {
-            let nvmrc = app.read_file(".nvmrc")?;
-            Some(nvmrc.trim().replace('v', ""))
-        } else {
-            None
-        };
+        for file in [".nvmrc", ".node-version"] {
+            if app.includes_file(file) {
+                let version = app.read_file(file)?.trim().to_string();
+                if !version.is_empty() {
+                    return Ok(Some((version, file)));
+                }
+            }
+        }
 
-        let node_version = env_node_version.or(pkg_node_version).or(nvmrc_node_version);
+        if let Some(pinned) = ToolVersions::from_app(app)?.get("node") {
+            return Ok(Some(pinned));
+        }
 
-        let node_version = match node_version {
-            Some(node_version) => node_version,
-            None => return Ok(Pkg::new(DEFAULT_NODE_PKG_NAME)),
-        };
+        Ok(package_json
+            .volta
+            .as_ref()
+            .and_then(|volta| volta.node.clone())
+            .map(|version| (version, "volta.node")))
+    }
 
-        // Any version will work, use latest
-        if node_version == "*" {
-            return Ok(Pkg::new(DEFAULT_NODE_PKG_NAME));
+    /// Returns the Nix package of the default major version if it matches the requested version,
+    /// or otherwise the lowest available major version that does
+    pub fn get_nix_node_pkg(
+        package_json: &PackageJson,
+        app: &App,
+        environment: &Environment,
+    ) -> Result<Pkg> {
+        let (node_version, source) =
+            match NodeProvider::get_requested_node_version(package_json, app, environment)? {
+                Some(requested) => requested,
+                None => return Ok(Pkg::new(DEFAULT_NODE_PKG_NAME)),
+            };
+
+        // nvm aliases for the newest release
+        if ["node", "latest", "current"].contains(&node_version.as_str()) {
+            let newest = AVAILABLE_NODE_VERSIONS.iter().max().unwrap();
+            return Ok(Pkg::new(&format!("nodejs-{}_x", newest)));
         }
 
-        // This also supports 18.x.x, or any number in place of the x.
-        let re = Regex::new(r"^(\d*)(?:\.?(?:\d*|[xX]?)?)(?:\.?(?:\d*|[xX]?)?)").unwrap();
-        if let Some(node_pkg) = parse_regex_into_pkg(&re, &node_version) {
-            return Ok(Pkg::new(node_pkg.as_str()));
-        }
+        // Aliases like `lts/*` can't be matched to a major, so use the default
+        let ranges = match version::parse_major_ranges(&node_version) {
+            Some(ranges) => ranges,
+            None => return Ok(Pkg::new(DEFAULT_NODE_PKG_NAME)),
+        };
 
-        // Parse `>=14.10.3 <16` into nodejs-14_x
-        let re = Regex::new(r"^>=(\d+)").unwrap();
-        if let Some(node_pkg) = parse_regex_into_pkg(&re, &node_version) {
-            return Ok(Pkg::new(node_pkg.as_str()));
+        match version::find_matching_major(&ranges, AVAILABLE_NODE_VERSIONS, DEFAULT_NODE_VERSION) {
+            Some(major) => Ok(Pkg::new(&format!("nodejs-{}_x", major))),
+            None => bail!(Message::new(MessageId::NodeVersionUnavailable)
+                .arg("version", &node_version)
+                .arg("source", source)
+                .arg(
+                    "versions",
+                    &AVAILABLE_NODE_VERSIONS
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
         }
-
-        Ok(Pkg::new(DEFAULT_NODE_PKG_NAME))
     }
 
     pub fn get_package_manager(app: &App) -> String {
@@ -287,15 +467,18 @@ impl NodeProvider {
         let mut install_cmd = "npm i".to_string();
         let package_manager = NodeProvider::get_package_manager(app);
         if package_manager == "pnpm" {
-            install_cmd = "pnpm i --frozen-lockfile".to_string();
+            install_cmd = format!(
+                "pnpm install --frozen-lockfile --store-dir {}",
+                PNPM_STORE_DIR
+            );
         } else if package_manager == "yarn" {
-            if app.includes_file(".yarnrc.yml") {
-                install_cmd = "yarn set version berry && yarn install --check-cache".to_string();
-                let yarnrc_yml: Yarnrc = app.read_yaml(".yarnrc.yml").unwrap_or_default();
-                if let Some(path) = yarnrc_yml.yarn_path {
-                    install_cmd =
-                        format!("yarn set version ./{} && yarn install --check-cache", path);
-                }
+            if NodeProvider::uses_yarn_berry(app) {
+                install_cmd = match NodeProvider::get_yarn_berry_version(app) {
+                    Some(version) => {
+                        format!("yarn set version {} && yarn install --immutable", version)
+                    }
+                    None => "yarn install --immutable".to_string(),
+                };
             } else {
                 install_cmd = "yarn install --frozen-lockfile".to_string();
             }
@@ -308,23 +491,71 @@ impl NodeProvider {
         Some(install_cmd)
     }
 
-    fn get_package_manager_cache_dir(app: &App) -> String {
+    fn get_package_manager_cache_dir(app: &App) -> Option<String> {
         let package_manager = NodeProvider::get_package_manager(app);
         if package_manager == "yarn" {
-            (*YARN_CACHE_DIR).to_string()
+            if !NodeProvider::uses_yarn_berry(app) {
+                Some((*YARN_CACHE_DIR).to_string())
+            } else if NodeProvider::uses_yarn_pnp(app) {
+                // Plug'n'Play loads packages from the cache archives at runtime, so they must stay in the image
+                None
+            } else {
+                Some(YARN_BERRY_CACHE_DIR.to_string())
+            }
         } else if package_manager == "pnpm" {
-            (*PNPM_CACHE_DIR).to_string()
+            Some((*PNPM_CACHE_DIR).to_string())
         } else if package_manager == "bun" {
-            (*BUN_CACHE_DIR).to_string()
+            Some((*BUN_CACHE_DIR).to_string())
         } else {
-            (*NPM_CACHE_DIR).to_string()
+            Some((*NPM_CACHE_DIR).to_string())
         }
     }
 
-    fn get_executor(app: &App) -> String {
+    /// Yarn 2+ is used if there is a `.yarnrc.yml` or a committed Yarn release
+    pub fn uses_yarn_berry(app: &App) -> bool {
+        app.includes_file(".yarnrc.yml") || app.includes_directory(".yarn/releases")
+    }
+
+    /// Plug'n'Play is the default linker for Yarn 2+
+    pub fn uses_yarn_pnp(app: &App) -> bool {
+        if !NodeProvider::uses_yarn_berry(app) {
+            return false;
+        }
+
+        let yarnrc: Yarnrc = app.read_yaml(".yarnrc.yml").unwrap_or_default();
+        !matches!(yarnrc.node_linker.as_deref(), Some("node-modules" | "pnpm"))
+    }
+
+    /// The Yarn release to set from `yarnPath` or `.yarn/releases`.
+    /// A release pinned in `packageManager` is already activated by corepack.
+    fn get_yarn_berry_version(app: &App) -> Option<String> {
+        let yarnrc: Yarnrc = app.read_yaml(".yarnrc.yml").unwrap_or_default();
+        if let Some(path) = yarnrc.yarn_path {
+            return Some(format!("./{}", path.trim_start_matches("./")));
+        }
+
+        if NodeProvider::get_corepack_package_manager(app).is_some() {
+            return None;
+        }
+
+        let release = app
+            .find_files(".yarn/releases/*.cjs")
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|path| app.strip_source_path(&path).ok());
+        match release {
+            Some(path) => Some(format!("./{}", path.to_slash_lossy())),
+            None => Some("berry".to_string()),
+        }
+    }
+
+    pub fn get_executor(app: &App) -> String {
         let package_manager = NodeProvider::get_package_manager(app);
         if package_manager == *"bun" {
             "bun"
+        } else if NodeProvider::uses_yarn_pnp(app) {
+            // Dependencies can only be resolved with the Plug'n'Play loader that `yarn node` sets up
+            "yarn node"
         } else {
             "node"
         }
@@ -342,6 +573,7 @@ impl NodeProvider {
 
         let pm_pkg: Pkg;
         let mut pkgs = Vec::<Pkg>::new();
+        let uses_corepack = NodeProvider::get_corepack_package_manager(app).is_some();
 
         let package_manager = NodeProvider::get_package_manager(app);
         if package_manager != "bun" {
@@ -367,20 +599,44 @@ impl NodeProvider {
                 pm_pkg = Pkg::new("npm-8_x");
             }
         };
-        pkgs.push(pm_pkg.from_overlay(NODE_OVERLAY));
+        // A pinned package manager is installed with corepack during the setup phase
+        if !uses_corepack {
+            pkgs.push(pm_pkg.from_overlay(NODE_OVERLAY));
+        }
 
         Ok(pkgs)
     }
 
-    pub fn uses_node_dependency(app: &App, dependency: &str) -> bool {
-        [
-            "package.json",
-            "package-lock.json",
-            "yarn.lock",
-            "pnpm-lock.yaml",
-        ]
-        .iter()
-        .any(|file| app.read_file(file).unwrap_or_default().contains(dependency))
+    /// The version of a package manager pinned in the `packageManager` field of package.json,
+    /// e.g. `7.14.0` for `"packageManager": "pnpm@7.14.0+sha256.abc"`
+    pub fn get_pinned_package_manager_version(app: &App, name: &str) -> Option<String> {
+        let package_json: PackageJson = app.read_json("package.json").ok()?;
+        let package_manager = package_json.package_manager?;
+
+        let (pm_name, version) = package_manager.split_once('@')?;
+        let version = version.split('+').next().unwrap_or_default();
+        let is_valid_version = !version.is_empty()
+            && version
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+
+        if pm_name == name && is_valid_version {
+            Some(version.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// The npm, Yarn, or pnpm version pinned in `packageManager` for the package manager in use.
+    /// Corepack ships with Node and installs that exact version.
+    pub fn get_corepack_package_manager(app: &App) -> Option<(String, String)> {
+        let package_manager = NodeProvider::get_package_manager(app);
+        if !["npm", "yarn", "pnpm"].contains(&package_manager.as_str()) {
+            return None;
+        }
+
+        let version = NodeProvider::get_pinned_package_manager_version(app, &package_manager)?;
+        Some((package_manager, version))
     }
 
     pub fn find_next_packages(app: &App) -> Result<Vec<String>> {
@@ -459,26 +715,6 @@ impl NodeProvider {
     }
 }
 
-fn version_number_to_pkg(version: u32) -> String {
-    if AVAILABLE_NODE_VERSIONS.contains(&version) {
-        format!("nodejs-{}_x", version)
-    } else {
-        DEFAULT_NODE_PKG_NAME.to_string()
-    }
-}
-
-fn parse_regex_into_pkg(re: &Regex, node_version: &str) -> Option<String> {
-    let matches: Vec<_> = re.captures_iter(node_version).collect();
-    if let Some(captures) = matches.get(0) {
-        match captures[1].parse::<u32>() {
-            Ok(version) => return Some(version_number_to_pkg(version)),
-            Err(_e) => {}
-        }
-    }
-
-    None
-}
-
 #[cfg(test)]
 mod test {
     use std::collections::BTreeMap;
@@ -705,20 +941,119 @@ mod test {
 
     #[test]
     fn test_engine_invalid_version() -> Result<()> {
-        // this test now defaults to lts
+        let err = NodeProvider::get_nix_node_pkg(
+            &PackageJson {
+                name: Some(String::default()),
+                engines: Some(engines_node("15")),
+                ..Default::default()
+            },
+            &App::new("examples/node")?,
+            &Environment::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node 15 from engines.node is not available. Available major versions: 14, 16, 18"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_from_node_version_file() -> Result<()> {
+        assert_eq!(
+            NodeProvider::get_nix_node_pkg(
+                &PackageJson::default(),
+                &App::new("examples/node-node-version")?,
+                &Environment::default()
+            )?,
+            Pkg::new("nodejs-18_x")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_from_volta() -> Result<()> {
         assert_eq!(
             NodeProvider::get_nix_node_pkg(
                 &PackageJson {
-                    name: Some(String::default()),
-                    engines: Some(engines_node("15")),
+                    volta: Some(Volta {
+                        node: Some("14.19.0".to_string())
+                    }),
                     ..Default::default()
                 },
                 &App::new("examples/node")?,
                 &Environment::default()
-            )?
-            .name,
-            DEFAULT_NODE_PKG_NAME
+            )?,
+            Pkg::new("nodejs-14_x")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_package_manager_version() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-package-manager")?;
+        assert_eq!(
+            NodeProvider::get_pinned_package_manager_version(&app, "pnpm"),
+            Some("7.14.0".to_string())
+        );
+        assert_eq!(
+            NodeProvider::get_pinned_package_manager_version(&app, "yarn"),
+            None
+        );
+        assert_eq!(
+            NodeProvider::get_pinned_package_manager_version(
+                &App::new("./examples/node-pnpm")?,
+                "pnpm"
+            ),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_corepack_package_manager() -> Result<()> {
+        assert_eq!(
+            NodeProvider::get_corepack_package_manager(&App::new(
+                "./examples/node-pnpm-package-manager"
+            )?),
+            Some(("pnpm".to_string(), "7.14.0".to_string()))
+        );
+        assert_eq!(
+            NodeProvider::get_corepack_package_manager(&App::new(
+                "./examples/node-yarn-berry-node-modules"
+            )?),
+            Some(("yarn".to_string(), "3.2.4".to_string()))
         );
+        assert_eq!(
+            NodeProvider::get_corepack_package_manager(&App::new("./examples/node-pnpm")?),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_yarn_berry_linker() -> Result<()> {
+        let pnp = App::new("./examples/node-yarn-berry")?;
+        assert!(NodeProvider::uses_yarn_pnp(&pnp));
+        assert_eq!(NodeProvider::get_package_manager_cache_dir(&pnp), None);
+        assert_eq!(NodeProvider::get_executor(&pnp), "yarn node");
+
+        let node_modules = App::new("./examples/node-yarn-berry-node-modules")?;
+        assert!(NodeProvider::uses_yarn_berry(&node_modules));
+        assert!(!NodeProvider::uses_yarn_pnp(&node_modules));
+        assert_eq!(
+            NodeProvider::get_install_command(&node_modules),
+            Some("yarn install --immutable".to_string())
+        );
+
+        assert!(!NodeProvider::uses_yarn_pnp(&App::new(
+            "./examples/node-yarn"
+        )?));
 
         Ok(())
     }
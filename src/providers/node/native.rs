@@ -0,0 +1,137 @@
+// Code relating to dependencies with native addons
+//
+// Packages like sharp or swc download a prebuilt binary built for glibc based systems, which needs the libraries
+// it links against on the library path. Packages like bcrypt or sqlite3 fall back to compiling their addon with
+// node-gyp when no prebuilt binary matches the platform, which needs Python, a C/C++ compiler, make, and the
+// libraries they link against.
+
+use crate::nixpacks::{app::App, nix::pkg::Pkg};
+use regex::Regex;
+
+/// Patterns matching a dependency `{}` in package.json, or an installed package in each lockfile.
+/// Peer dependencies of other packages, which are not installed, do not match
+const DEPENDENCY_PATTERNS: &[(&str, &str)] = &[
+    ("package.json", r#""{}"\s*:"#),
+    ("package-lock.json", r#"node_modules/{}""#),
+    ("yarn.lock", r#"(?m)^"?{}@"#),
+    ("pnpm-lock.yaml", r#"(?m)^\s+/?{}[/@]"#),
+];
+
+const NODE_GYP_TOOLCHAIN: &[&str] = &["python3", "gcc", "gnumake"];
+
+/// How a native dependency gets its binary
+enum NativeBinary {
+    /// A prebuilt binary is downloaded
+    Prebuilt,
+    /// The addon is built with node-gyp, linking against these extra Nix packages
+    NodeGyp(&'static [&'static str]),
+}
+
+const NATIVE_DEPENDENCIES: &[(&str, NativeBinary)] = &[
+    ("sharp", NativeBinary::Prebuilt),
+    ("@swc/core", NativeBinary::Prebuilt),
+    // Downloads the `@next/swc` binary for the platform
+    ("next", NativeBinary::Prebuilt),
+    ("lightningcss", NativeBinary::Prebuilt),
+    ("@parcel/watcher", NativeBinary::Prebuilt),
+    ("bcrypt", NativeBinary::NodeGyp(&[])),
+    (
+        "canvas",
+        NativeBinary::NodeGyp(&[
+            "cairo",
+            "pango",
+            "libpng",
+            "libjpeg",
+            "giflib",
+            "librsvg",
+            "pixman",
+            "pkg-config",
+        ]),
+    ),
+    ("sqlite3", NativeBinary::NodeGyp(&["sqlite"])),
+    ("better-sqlite3", NativeBinary::NodeGyp(&[])),
+];
+
+pub struct NativeAddons {}
+
+impl NativeAddons {
+    /// The node-gyp toolchain and libraries for all native addon dependencies of the app
+    pub fn get_nix_pkgs(app: &App) -> Vec<Pkg> {
+        let mut pkgs: Vec<&str> = Vec::new();
+        for (dependency, binary) in NATIVE_DEPENDENCIES {
+            let libs = match binary {
+                NativeBinary::NodeGyp(libs) if NativeAddons::uses_package(app, dependency) => libs,
+                _ => continue,
+            };
+
+            for name in NODE_GYP_TOOLCHAIN.iter().chain(libs.iter()) {
+                if !pkgs.contains(name) {
+                    pkgs.push(name);
+                }
+            }
+        }
+
+        pkgs.into_iter().map(Pkg::new).collect()
+    }
+
+    /// Whether the app depends on a package that downloads a prebuilt native binary
+    pub fn uses_prebuilt_binaries(app: &App) -> bool {
+        NATIVE_DEPENDENCIES.iter().any(|(dependency, binary)| {
+            matches!(binary, NativeBinary::Prebuilt) && NativeAddons::uses_package(app, dependency)
+        })
+    }
+
+    /// Whether the app depends on the package `name`, directly or through another package.
+    /// Only whole names match, so `bcrypt` does not match `bcrypt-pbkdf`
+    pub fn uses_package(app: &App, name: &str) -> bool {
+        DEPENDENCY_PATTERNS.iter().any(|(file, pattern)| {
+            let re = Regex::new(&pattern.replace("{}", &regex::escape(name))).unwrap();
+            app.read_file(file)
+                .map(|contents| re.is_match(&contents))
+                .unwrap_or(false)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_native_addon_pkgs() {
+        let pkgs = NativeAddons::get_nix_pkgs(&App::new("./examples/node-canvas").unwrap());
+        assert!(pkgs.contains(&Pkg::new("gnumake")));
+        assert!(pkgs.contains(&Pkg::new("cairo")));
+        assert!(!pkgs.contains(&Pkg::new("vips")));
+        assert!(!NativeAddons::uses_prebuilt_binaries(
+            &App::new("./examples/node-canvas").unwrap()
+        ));
+
+        // Only lists `bcrypt-pbkdf`, and `canvas` as an optional peer dependency of jsdom
+        assert!(NativeAddons::get_nix_pkgs(&App::new("./examples/node-nx").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_uses_package() -> anyhow::Result<()> {
+        let dir = TempDir::new("nixpacks-node-native")?;
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{ "dependencies": { "sharp-cli": "^2.1.0", "next": "13.0.0" } }"#,
+        )?;
+        fs::write(
+            dir.path().join("package-lock.json"),
+            r#"{ "packages": { "node_modules/sharp-cli": {}, "node_modules/@next/swc-linux-x64-gnu": {} } }"#,
+        )?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        assert!(NativeAddons::uses_package(&app, "next"));
+        assert!(NativeAddons::uses_package(&app, "@next/swc-linux-x64-gnu"));
+        assert!(!NativeAddons::uses_package(&app, "sharp"));
+        assert!(!NativeAddons::uses_package(&app, "@next/swc"));
+        assert!(NativeAddons::uses_prebuilt_binaries(&app));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+// Code relating to Next.js standalone output
+//
+// With `output: 'standalone'`, `next build` traces the files the server needs into `.next/standalone`.
+// Only that directory and the Nix store paths Node needs are copied into the final image,
+// instead of the whole app and its `node_modules`.
+
+use super::NodeProvider;
+use crate::nixpacks::{
+    app::App,
+    environment::EnvironmentVariables,
+    plan::phase::{Phase, StartPhase},
+};
+use regex::Regex;
+
+const NEXT_CONFIG_FILES: &[&str] = &["next.config.js", "next.config.mjs", "next.config.ts"];
+const STANDALONE_DIR: &str = ".next/standalone";
+
+pub struct NextStandalone {}
+
+impl NextStandalone {
+    pub fn is_standalone(app: &App) -> bool {
+        let re = Regex::new(r#"output\s*:\s*["'`]standalone["'`]"#).unwrap();
+        NEXT_CONFIG_FILES.iter().any(|file| {
+            app.includes_file(file)
+                && app
+                    .read_file(file)
+                    .map(|config| re.is_match(&config))
+                    .unwrap_or(false)
+        })
+    }
+
+    /// The standalone server does not serve `.next/static` or `public`, so copy them next to it
+    pub fn add_copy_cmds(app: &App, build: &mut Phase) {
+        build.add_cmd(format!(
+            "cp -r .next/static {}/.next/static",
+            STANDALONE_DIR
+        ));
+        if app.includes_directory("public") {
+            build.add_cmd(format!("cp -r public {}/public", STANDALONE_DIR));
+        }
+    }
+
+    /// Run `server.js` in the base image, with only the standalone output and the Nix store paths of Node
+    pub fn get_start_phase(app: &App) -> StartPhase {
+        let executor = NodeProvider::get_executor(app);
+        let mut start = StartPhase::new(format!("{} server.js", executor));
+        start.set_port(3000);
+        start.run_in_default_image();
+        start.add_file_dependency(format!("./{}", STANDALONE_DIR));
+        if let Some(runtime) = executor.split_whitespace().next() {
+            start.add_nix_closure(runtime);
+        }
+        start
+    }
+
+    /// The standalone server listens on `HOSTNAME`, which Docker sets to the container ID
+    pub fn get_runtime_variables() -> EnvironmentVariables {
+        EnvironmentVariables::from([("HOSTNAME".to_string(), "0.0.0.0".to_string())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_standalone() {
+        assert!(NextStandalone::is_standalone(
+            &App::new("./examples/node-next-standalone").unwrap()
+        ));
+        assert!(!NextStandalone::is_standalone(
+            &App::new("./examples/node").unwrap()
+        ));
+    }
+}
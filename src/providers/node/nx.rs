@@ -12,6 +12,18 @@ use crate::nixpacks::{app::App, environment::Environment};
 pub struct NxJson {
     #[serde(alias = "defaultProject")]
     pub default_project: Option<String>,
+    #[serde(alias = "workspaceLayout")]
+    pub workspace_layout: Option<WorkspaceLayout>,
+    #[serde(alias = "cacheDirectory")]
+    pub cache_directory: Option<String>,
+    #[serde(alias = "tasksRunnerOptions")]
+    pub tasks_runner_options: Option<Value>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Deserialize)]
+pub struct WorkspaceLayout {
+    #[serde(alias = "appsDir")]
+    pub apps_dir: Option<String>,
 }
 
 #[derive(Debug, Serialize, PartialEq, Eq, Deserialize)]
@@ -47,6 +59,10 @@ pub struct Configuration {
 pub struct Nx {}
 
 const NX_APP_NAME_ENV_VAR: &str = "NX_APP_NAME";
+const DEFAULT_APPS_DIR: &str = "apps";
+
+// Local cache of Nx 16+. Earlier versions cache in `node_modules/.cache/nx`
+const DEFAULT_NX_CACHE_DIR: &str = ".nx/cache";
 
 impl Nx {
     pub fn is_nx_monorepo(app: &App, env: &Environment) -> bool {
@@ -71,8 +87,42 @@ impl Nx {
         None
     }
 
+    /// Directory of the Nx apps, `apps` unless changed with `workspaceLayout.appsDir`
+    fn get_nx_apps_dir(app: &App) -> String {
+        app.read_json::<NxJson>("nx.json")
+            .ok()
+            .and_then(|nx_json| nx_json.workspace_layout)
+            .and_then(|layout| layout.apps_dir)
+            .unwrap_or_else(|| DEFAULT_APPS_DIR.to_string())
+    }
+
+    /// The Nx task cache, which may be moved with `cacheDirectory` in `nx.json` or the task runner options
+    pub fn get_nx_cache_dir(app: &App) -> String {
+        let nx_json = match app.read_json::<NxJson>("nx.json") {
+            Ok(nx_json) => nx_json,
+            Err(_) => return DEFAULT_NX_CACHE_DIR.to_string(),
+        };
+
+        let runner_cache_dir = nx_json
+            .tasks_runner_options
+            .as_ref()
+            .and_then(|options| options.pointer("/default/options/cacheDirectory"))
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+
+        nx_json
+            .cache_directory
+            .or(runner_cache_dir)
+            .map(|dir| dir.trim_start_matches("./").to_string())
+            .unwrap_or_else(|| DEFAULT_NX_CACHE_DIR.to_string())
+    }
+
     pub fn get_nx_project_json_for_app(app: &App, nx_app_name: &String) -> Result<ProjectJson> {
-        let project_path = format!("./apps/{}/project.json", nx_app_name);
+        let project_path = format!(
+            "./{}/{}/project.json",
+            Nx::get_nx_apps_dir(app),
+            nx_app_name
+        );
         app.read_json::<ProjectJson>(&project_path)
     }
 
@@ -86,7 +136,7 @@ impl Nx {
             }
         }
 
-        Ok(format!("dist/apps/{}", nx_app_name))
+        Ok(format!("dist/{}/{}", Nx::get_nx_apps_dir(app), nx_app_name))
     }
 
     pub fn get_nx_build_cmd(app: &App, env: &Environment) -> Option<String> {
@@ -130,3 +180,85 @@ impl Nx {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn create_app(nx_json: &str) -> Result<(TempDir, App)> {
+        let dir = TempDir::new("nixpacks-nx")?;
+        fs::write(dir.path().join("nx.json"), nx_json)?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok((dir, app))
+    }
+
+    #[test]
+    fn test_nx_cache_dir() -> Result<()> {
+        let app = App::new("./examples/node-nx")?;
+        assert_eq!(Nx::get_nx_cache_dir(&app), ".nx/cache");
+
+        let app = App::new("./examples/node")?;
+        assert_eq!(Nx::get_nx_cache_dir(&app), ".nx/cache");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nx_cache_directory() -> Result<()> {
+        let (_dir, app) = create_app(r#"{ "cacheDirectory": "./tmp/nx-cache" }"#)?;
+        assert_eq!(Nx::get_nx_cache_dir(&app), "tmp/nx-cache");
+
+        // The top-level `cacheDirectory` takes precedence over the task runner options
+        let (_dir, app) = create_app(
+            r#"{
+                "cacheDirectory": "tmp/nx-cache",
+                "tasksRunnerOptions": { "default": { "options": { "cacheDirectory": "runner-cache" } } }
+            }"#,
+        )?;
+        assert_eq!(Nx::get_nx_cache_dir(&app), "tmp/nx-cache");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nx_tasks_runner_cache_directory() -> Result<()> {
+        let (_dir, app) = create_app(
+            r#"{
+                "tasksRunnerOptions": {
+                    "default": {
+                        "runner": "nx/tasks-runners/default",
+                        "options": { "cacheDirectory": "./node_modules/.cache/nx" }
+                    }
+                }
+            }"#,
+        )?;
+        assert_eq!(Nx::get_nx_cache_dir(&app), "node_modules/.cache/nx");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nx_apps_dir() -> Result<()> {
+        let (dir, app) = create_app(
+            r#"{ "defaultProject": "web", "workspaceLayout": { "appsDir": "packages" } }"#,
+        )?;
+        fs::create_dir_all(dir.path().join("packages/web"))?;
+        fs::write(
+            dir.path().join("packages/web/project.json"),
+            r#"{ "targets": { "build": { "executor": "@nrwl/next:build" } } }"#,
+        )?;
+        let app = App::new(&app.source.to_string_lossy())?;
+
+        assert_eq!(Nx::get_nx_apps_dir(&app), "packages");
+        assert!(Nx::get_nx_project_json_for_app(&app, &"web".to_string()).is_ok());
+        assert!(Nx::is_nx_monorepo(&app, &Environment::default()));
+
+        let (_dir, app) = create_app(r#"{ "defaultProject": "web" }"#)?;
+        assert_eq!(Nx::get_nx_apps_dir(&app), "apps");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+// Code relating to frontend frameworks that build a purely static site
+//
+// Astro, SvelteKit with adapter-static, Vite, and Create React App builds only produce HTML, CSS, and JS.
+// Their `start` script runs a development or preview server, so the output is served with nginx instead.
+
+use super::{NodeProvider, PackageJson};
+use crate::nixpacks::app::App;
+use regex::Regex;
+use std::collections::HashSet;
+
+const ASTRO_CONFIG_FILES: &[&str] = &[
+    "astro.config.mjs",
+    "astro.config.js",
+    "astro.config.ts",
+    "astro.config.cjs",
+];
+const SVELTE_CONFIG_FILES: &[&str] = &["svelte.config.js", "svelte.config.mjs"];
+const VITE_CONFIG_FILES: &[&str] = &[
+    "vite.config.js",
+    "vite.config.mjs",
+    "vite.config.ts",
+    "vite.config.mts",
+];
+
+// Frameworks built on Vite that run their own server
+const VITE_SERVER_FRAMEWORKS: &[&str] = &[
+    "astro",
+    "@sveltejs/kit",
+    "nuxt",
+    "@remix-run/dev",
+    "@solidjs/start",
+    "@builder.io/qwik-city",
+    "vite-plugin-ssr",
+    "vike",
+];
+
+pub struct StaticSite {}
+
+impl StaticSite {
+    /// The output directory of an app that builds a static site and has no start script
+    /// of its own, other than the framework's development or preview server
+    pub fn get_output_dir(app: &App) -> Option<String> {
+        let package_json: PackageJson = app.read_json("package.json").ok()?;
+        let scripts = package_json.scripts.clone().unwrap_or_default();
+        if let Some(start) = scripts.get("start") {
+            if !StaticSite::is_dev_server_script(start) {
+                return None;
+            }
+        }
+
+        let deps = NodeProvider::get_deps_from_package_json(&package_json);
+        let build_script = scripts.get("build").cloned().unwrap_or_default();
+
+        if deps.contains("astro") {
+            StaticSite::detect_astro(app)
+        } else if deps.contains("@sveltejs/kit") {
+            StaticSite::detect_sveltekit(app, &deps)
+        } else if deps.contains("react-scripts") {
+            Some("build".to_string())
+        } else if deps.contains("vite") {
+            StaticSite::detect_vite(app, &deps, &build_script)
+        } else {
+            None
+        }
+    }
+
+    /// Astro builds a static site unless it is configured with server or hybrid rendering
+    fn detect_astro(app: &App) -> Option<String> {
+        let config = StaticSite::read_config(app, ASTRO_CONFIG_FILES);
+        let server_output = Regex::new(r#"output\s*:\s*["'](server|hybrid)["']"#).unwrap();
+        if server_output.is_match(&config) {
+            return None;
+        }
+
+        Some(StaticSite::get_config_string(&config, "outDir").unwrap_or_else(|| "dist".to_string()))
+    }
+
+    /// SvelteKit only builds a static site with `@sveltejs/adapter-static`
+    fn detect_sveltekit(app: &App, deps: &HashSet<String>) -> Option<String> {
+        if !deps.contains("@sveltejs/adapter-static") {
+            return None;
+        }
+
+        let config = StaticSite::read_config(app, SVELTE_CONFIG_FILES);
+        Some(StaticSite::get_config_string(&config, "pages").unwrap_or_else(|| "build".to_string()))
+    }
+
+    /// A `vite build` of a plain Vite app, e.g. React or Vue, without server rendering
+    fn detect_vite(app: &App, deps: &HashSet<String>, build_script: &str) -> Option<String> {
+        if VITE_SERVER_FRAMEWORKS.iter().any(|dep| deps.contains(*dep))
+            || !build_script.contains("vite build")
+            || build_script.contains("--ssr")
+        {
+            return None;
+        }
+
+        let config = StaticSite::read_config(app, VITE_CONFIG_FILES);
+        Some(StaticSite::get_config_string(&config, "outDir").unwrap_or_else(|| "dist".to_string()))
+    }
+
+    /// Whether a start script only runs a development or preview server, e.g. `vite preview` or `react-scripts start`
+    fn is_dev_server_script(script: &str) -> bool {
+        Regex::new(
+            r"^(react-scripts start|vite( dev| serve| preview)?|astro (dev|preview)|svelte-kit preview|serve)(\s|$)",
+        )
+        .unwrap()
+        .is_match(script.trim())
+    }
+
+    fn read_config(app: &App, files: &[&str]) -> String {
+        files
+            .iter()
+            .find(|file| app.includes_file(file))
+            .and_then(|file| app.read_file(file).ok())
+            .unwrap_or_default()
+    }
+
+    /// The string value of `key` in a JS config file, e.g. `dist` for `outDir: './dist/'`
+    fn get_config_string(config: &str, key: &str) -> Option<String> {
+        let re = Regex::new(&format!(r#"{}\s*:\s*["'`]([^"'`]+)["'`]"#, key)).unwrap();
+        re.captures(config).map(|captures| {
+            captures[1]
+                .trim_start_matches("./")
+                .trim_end_matches('/')
+                .to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_dev_server_script() {
+        assert!(StaticSite::is_dev_server_script("react-scripts start"));
+        assert!(StaticSite::is_dev_server_script("vite preview --port 3000"));
+        assert!(StaticSite::is_dev_server_script("vite"));
+        assert!(StaticSite::is_dev_server_script("serve -s dist"));
+        assert!(!StaticSite::is_dev_server_script("node server.js"));
+        assert!(!StaticSite::is_dev_server_script("vite-node server.ts"));
+    }
+
+    #[test]
+    fn test_get_config_string() {
+        assert_eq!(
+            StaticSite::get_config_string("build: { outDir: './public/' }", "outDir"),
+            Some("public".to_string())
+        );
+        assert_eq!(
+            StaticSite::get_config_string("export default {}", "outDir"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_static_site() {
+        assert_eq!(
+            StaticSite::get_output_dir(&App::new("./examples/node-vite-static").unwrap()),
+            Some("dist".to_string())
+        );
+        assert_eq!(
+            StaticSite::get_output_dir(&App::new("./examples/node").unwrap()),
+            None
+        );
+    }
+}
@@ -11,8 +11,13 @@ use crate::{
 
 use super::{NodeProvider, PackageJson};
 
+/// Local cache of Turborepo 1.13+. Earlier versions cache in `node_modules/.cache/turbo`
+pub const TURBO_CACHE_DIR: &str = ".turbo/cache";
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TurboJson {
+    // Turborepo 2 renamed `pipeline` to `tasks`
+    #[serde(alias = "tasks")]
     pub pipeline: HashMap<String, Value>,
 }
 
@@ -56,10 +61,15 @@ impl Turborepo {
     ) -> Result<Option<String>, Box<dyn Error>> {
         let turbo_cfg = Turborepo::get_config(app)?;
         let dlx = NodeProvider::get_package_manager_dlx_command(app);
-        if let Some(build_cmd) = Turborepo::get_build_cmd(&turbo_cfg) {
+
+        // Only build the app and the workspace packages it depends on
+        if let Some(app_name) = Turborepo::get_app_name(env) {
+            return Ok(Some(format!(
+                "{} turbo run build --filter={}",
+                dlx, app_name
+            )));
+        } else if let Some(build_cmd) = Turborepo::get_build_cmd(&turbo_cfg) {
             return Ok(Some(build_cmd));
-        } else if let Some(app_name) = Turborepo::get_app_name(env) {
-            return Ok(Some(format!("{} turbo run {}:build", dlx, app_name)));
         };
         Ok(None)
     }
@@ -89,9 +99,15 @@ impl Turborepo {
                 },
                 &name,
             )? {
+                // pnpm selects workspace packages with `--filter` instead of `--workspace`
+                let workspace_flag = if pkg_manager == "pnpm" {
+                    "--filter"
+                } else {
+                    "--workspace"
+                };
                 return Ok(Some(format!(
-                    "{} --workspace {} run start",
-                    pkg_manager, name
+                    "{} {} {} run start",
+                    pkg_manager, workspace_flag, name
                 )));
             }
             println!("Warning: Turborepo app `{}` not found", name);
@@ -118,3 +134,28 @@ impl Turborepo {
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_turborepo_build_cmd() {
+        let app = App::new("./examples/node-turborepo").unwrap();
+
+        assert_eq!(
+            Turborepo::get_actual_build_cmd(&app, &Environment::default()).unwrap(),
+            Some("npx turbo run build".to_string())
+        );
+
+        let env = Environment::new(BTreeMap::from([(
+            "NIXPACKS_TURBO_APP_NAME".to_string(),
+            "web".to_string(),
+        )]));
+        assert_eq!(
+            Turborepo::get_actual_build_cmd(&app, &env).unwrap(),
+            Some("npx turbo run build --filter=web".to_string())
+        );
+    }
+}
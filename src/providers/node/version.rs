@@ -0,0 +1,133 @@
+// Code relating to matching Node version ranges to the available Nix packages
+//
+// Only major versions can be installed, so every range is reduced to the majors it allows.
+
+use regex::Regex;
+
+/// Inclusive range of major versions
+type MajorRange = (u32, u32);
+
+/// The majors allowed by a version or semver range, e.g. `18`, `16.x`, `^18.1.0`, `>=14.2 <16`, or `14 || 16`.
+/// Returns None if the range can't be parsed.
+pub fn parse_major_ranges(range: &str) -> Option<Vec<MajorRange>> {
+    // Allow spaces after operators, e.g. `>= 14`
+    let range = Regex::new(r"([<>=^~])\s+")
+        .unwrap()
+        .replace_all(range.trim(), "$1")
+        .to_string();
+
+    range
+        .split("||")
+        .map(|set| parse_comparator_set(set.trim()))
+        .collect()
+}
+
+/// `default` if it is allowed by any of `ranges`, otherwise the lowest allowed major of `available`
+pub fn find_matching_major(ranges: &[MajorRange], available: &[u32], default: u32) -> Option<u32> {
+    let is_allowed = |major: &u32| ranges.iter().any(|(min, max)| min <= major && major <= max);
+    if is_allowed(&default) {
+        return Some(default);
+    }
+
+    available.iter().copied().find(is_allowed)
+}
+
+fn parse_comparator_set(set: &str) -> Option<MajorRange> {
+    // Hyphen ranges, e.g. `14 - 16`
+    if let Some((from, to)) = set.split_once(" - ") {
+        let (from, _) = parse_partial_version(from.trim())?;
+        let (to, _) = parse_partial_version(to.trim())?;
+        return Some((from.unwrap_or(0), to.unwrap_or(u32::MAX)));
+    }
+
+    set.split_whitespace()
+        .map(parse_comparator)
+        .try_fold((0, u32::MAX), |(min, max), comparator| {
+            comparator.map(|(c_min, c_max)| (min.max(c_min), max.min(c_max)))
+        })
+}
+
+fn parse_comparator(comparator: &str) -> Option<MajorRange> {
+    let (op, version) = match comparator.find(|c: char| !"<>=^~".contains(c)) {
+        Some(index) => comparator.split_at(index),
+        None => (comparator, ""),
+    };
+    let (major, is_exact) = parse_partial_version(version)?;
+    let major = match major {
+        Some(major) => major,
+        None => return Some((0, u32::MAX)),
+    };
+
+    match op {
+        "" | "=" | "^" | "~" => Some((major, major)),
+        ">=" => Some((major, u32::MAX)),
+        // `>14` excludes all of 14, while `>14.1` allows later 14 releases
+        ">" if is_exact => Some((major, u32::MAX)),
+        ">" => Some((major + 1, u32::MAX)),
+        "<=" => Some((0, major)),
+        // `<16` and `<16.0.0` exclude all of 16
+        "<" if is_exact => Some((0, major)),
+        "<" => Some((0, major.checked_sub(1)?)),
+        _ => None,
+    }
+}
+
+/// The major of a version like `v18`, `18.x`, or `18.12.1`, and whether it has a non-zero minor or patch.
+/// A wildcard major like `*` or `x` is returned as None.
+fn parse_partial_version(version: &str) -> Option<(Option<u32>, bool)> {
+    let version = version.trim_start_matches('v');
+    if version.is_empty() {
+        return Some((None, false));
+    }
+
+    let mut parts = version.split('.');
+    let major = match parts.next()? {
+        "*" | "x" | "X" => None,
+        major => Some(major.parse::<u32>().ok()?),
+    };
+    let is_exact = parts.any(|part| part.parse::<u32>().map_or(false, |n| n > 0));
+
+    Some((major, is_exact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVAILABLE: &[u32] = &[14, 16, 18];
+
+    fn get_major(range: &str) -> Option<u32> {
+        find_matching_major(&parse_major_ranges(range).unwrap(), AVAILABLE, 16)
+    }
+
+    #[test]
+    fn test_exact_versions() {
+        assert_eq!(get_major("18"), Some(18));
+        assert_eq!(get_major("v16.14.2"), Some(16));
+        assert_eq!(get_major("14.x"), Some(14));
+        assert_eq!(get_major("18.X.x"), Some(18));
+        assert_eq!(get_major("15"), None);
+        assert_eq!(get_major("*"), Some(16));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(get_major(">=14.10.3 <16"), Some(14));
+        assert_eq!(get_major(">=12"), Some(16));
+        assert_eq!(get_major(">= 15"), Some(16));
+        assert_eq!(get_major(">16"), Some(18));
+        assert_eq!(get_major(">16.1"), Some(16));
+        assert_eq!(get_major("<16.0.0"), Some(14));
+        assert_eq!(get_major("^18.1.0"), Some(18));
+        assert_eq!(get_major("~16.3"), Some(16));
+        assert_eq!(get_major("14 || 18"), Some(14));
+        assert_eq!(get_major("15 - 17"), Some(16));
+        assert_eq!(get_major(">=20"), None);
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        assert!(parse_major_ranges("lts/*").is_none());
+        assert!(parse_major_ranges("latest").is_none());
+    }
+}
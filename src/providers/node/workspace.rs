@@ -0,0 +1,250 @@
+// Code relating to building a single package of a npm, Yarn, or pnpm workspace
+
+use super::{turborepo::pnpm_workspaces, NodeProvider, PackageJson, Workspaces};
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    messages::{Message, MessageId},
+};
+use anyhow::{bail, Result};
+use path_slash::PathExt;
+use std::collections::BTreeSet;
+
+const NODE_WORKSPACE_ENV_VAR: &str = "NODE_WORKSPACE";
+
+// Files at the root of the workspace that are needed to install any package
+const WORKSPACE_ROOT_FILES: &[&str] = &[
+    "package.json",
+    "pnpm-lock.yaml",
+    "pnpm-workspace.yaml",
+    "yarn.lock",
+    ".npmrc",
+    ".yarnrc",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeWorkspace {
+    pub name: String,
+    /// Directory of the package relative to the app root
+    pub dir: String,
+    pub package_json: PackageJson,
+}
+
+impl NodeWorkspace {
+    /// The workspace package selected with `NIXPACKS_NODE_WORKSPACE`, by package name or directory
+    pub fn get_target(app: &App, env: &Environment) -> Result<Option<NodeWorkspace>> {
+        let target = match env.get_config_variable(NODE_WORKSPACE_ENV_VAR) {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+
+        let workspaces = NodeWorkspace::find_all(app)?;
+        let dir = target.trim_start_matches("./").trim_end_matches('/');
+        match workspaces
+            .iter()
+            .find(|workspace| workspace.name == target || workspace.dir == dir)
+        {
+            Some(workspace) => Ok(Some(workspace.clone())),
+            None => bail!(Message::new(MessageId::NodeWorkspaceNotFound)
+                .arg("name", &target)
+                .arg(
+                    "workspaces",
+                    &workspaces
+                        .iter()
+                        .map(|workspace| workspace.name.clone())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+        }
+    }
+
+    /// All packages matched by the workspace globs of `pnpm-workspace.yaml` or `package.json`
+    pub fn find_all(app: &App) -> Result<Vec<NodeWorkspace>> {
+        let mut workspaces = Vec::new();
+        for glob in NodeWorkspace::get_globs(app)? {
+            for dir in app.find_directories(&glob)? {
+                let package_json_path = dir.join("package.json");
+                if !package_json_path.is_file() {
+                    continue;
+                }
+
+                let package_json: PackageJson =
+                    app.read_json(&package_json_path.to_string_lossy())?;
+                let dir = app.strip_source_path(&dir)?.to_slash_lossy().into_owned();
+                workspaces.push(NodeWorkspace {
+                    name: package_json.name.clone().unwrap_or_else(|| dir.clone()),
+                    dir,
+                    package_json,
+                });
+            }
+        }
+
+        workspaces.sort_by(|a, b| a.dir.cmp(&b.dir));
+        workspaces.dedup_by(|a, b| a.dir == b.dir);
+        Ok(workspaces)
+    }
+
+    fn get_globs(app: &App) -> Result<Vec<String>> {
+        if app.includes_file("pnpm-workspace.yaml") {
+            return pnpm_workspaces(app);
+        }
+
+        let package_json: PackageJson = app.read_json("package.json").unwrap_or_default();
+        let globs = match package_json.workspaces {
+            Some(Workspaces::Array(globs)) => globs,
+            // Yarn also accepts `{ "packages": [...], "nohoist": [...] }`
+            Some(Workspaces::Unknown(value)) => value
+                .get("packages")
+                .and_then(|packages| packages.as_array())
+                .map(|packages| {
+                    packages
+                        .iter()
+                        .filter_map(|glob| glob.as_str().map(ToString::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        Ok(globs)
+    }
+
+    /// Only pnpm and Yarn 1 can install part of a workspace.
+    /// The lockfiles of npm and Yarn 2+ must match every package in the workspace.
+    pub fn can_copy_only_needed_files(app: &App) -> bool {
+        match NodeProvider::get_package_manager(app).as_str() {
+            "pnpm" => true,
+            "yarn" => !NodeProvider::uses_yarn_berry(app),
+            _ => false,
+        }
+    }
+
+    /// The root files and the directories of this package and every workspace package it depends on
+    pub fn get_needed_files(&self, app: &App) -> Result<Vec<String>> {
+        let workspaces = NodeWorkspace::find_all(app)?;
+
+        let mut dirs = BTreeSet::new();
+        let mut queue = vec![self.clone()];
+        while let Some(workspace) = queue.pop() {
+            if !dirs.insert(workspace.dir.clone()) {
+                continue;
+            }
+
+            let deps = NodeProvider::get_deps_from_package_json(&workspace.package_json);
+            queue.extend(
+                workspaces
+                    .iter()
+                    .filter(|other| deps.contains(&other.name))
+                    .cloned(),
+            );
+        }
+
+        let files = WORKSPACE_ROOT_FILES
+            .iter()
+            .filter(|file| app.includes_file(file))
+            .map(ToString::to_string)
+            .chain(dirs.into_iter().map(|dir| format!("{}/", dir)))
+            .collect();
+
+        Ok(files)
+    }
+
+    /// Install only this package and the workspace packages it depends on
+    pub fn get_install_cmd(&self, app: &App, install_cmd: String) -> String {
+        if NodeProvider::get_package_manager(app) == "pnpm" {
+            format!("{} --filter {}...", install_cmd, self.name)
+        } else {
+            install_cmd
+        }
+    }
+
+    /// Run a script from this package's `package.json`
+    pub fn get_script_cmd(&self, app: &App, script: &str) -> Option<String> {
+        let has_script = self
+            .package_json
+            .scripts
+            .as_ref()
+            .map_or(false, |scripts| scripts.contains_key(script));
+        if !has_script {
+            return None;
+        }
+
+        let cmd = match NodeProvider::get_package_manager(app).as_str() {
+            "pnpm" => format!("pnpm --filter {} run {}", self.name, script),
+            "yarn" => format!("yarn workspace {} run {}", self.name, script),
+            "bun" => format!("bun --cwd {} run {}", self.dir, script),
+            _ => format!("npm run {} --workspace {}", script, self.name),
+        };
+
+        Some(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn workspace_env(target: &str) -> Environment {
+        Environment::new(BTreeMap::from([(
+            "NIXPACKS_NODE_WORKSPACE".to_string(),
+            target.to_string(),
+        )]))
+    }
+
+    #[test]
+    fn test_find_workspaces() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-workspace")?;
+        assert_eq!(
+            NodeWorkspace::find_all(&app)?
+                .iter()
+                .map(|workspace| workspace.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["@app/api", "@app/shared", "@app/web"]
+        );
+
+        let app = App::new("./examples/node-monorepo")?;
+        assert_eq!(
+            NodeWorkspace::find_all(&app)?
+                .iter()
+                .map(|workspace| workspace.dir.as_str())
+                .collect::<Vec<_>>(),
+            vec!["packages/client", "packages/server"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_workspace() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-workspace")?;
+
+        let by_name = NodeWorkspace::get_target(&app, &workspace_env("@app/api"))?.unwrap();
+        let by_dir = NodeWorkspace::get_target(&app, &workspace_env("./packages/api/"))?.unwrap();
+        assert_eq!(by_name, by_dir);
+
+        assert!(NodeWorkspace::get_target(&app, &Environment::default())?.is_none());
+        assert!(NodeWorkspace::get_target(&app, &workspace_env("missing")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspace_needed_files() -> Result<()> {
+        let app = App::new("./examples/node-pnpm-workspace")?;
+        let api = NodeWorkspace::get_target(&app, &workspace_env("@app/api"))?.unwrap();
+
+        assert_eq!(
+            api.get_needed_files(&app)?,
+            vec![
+                "package.json",
+                "pnpm-lock.yaml",
+                "pnpm-workspace.yaml",
+                "packages/api/",
+                "packages/shared/"
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -12,10 +12,66 @@ use crate::nixpacks::{
     },
 };
 
-use super::{node::NodeProvider, Provider};
+use super::{node::NodeProvider, tool_versions::ToolVersions, Provider};
 use anyhow::Result;
 
 const DEFAULT_PHP_VERSION: &str = "8.1";
+const AVAILABLE_PHP_VERSIONS: &[&str] = &["7.4", "8.0", "8.1"];
+
+// Extensions that are built into PHP or enabled by default in Nixpkgs
+const DEFAULT_PHP_EXTENSIONS: &[&str] = &[
+    "bcmath",
+    "calendar",
+    "core",
+    "ctype",
+    "curl",
+    "date",
+    "dom",
+    "exif",
+    "fileinfo",
+    "filter",
+    "ftp",
+    "gd",
+    "gettext",
+    "gmp",
+    "hash",
+    "iconv",
+    "imap",
+    "intl",
+    "json",
+    "ldap",
+    "libxml",
+    "mbstring",
+    "mysqli",
+    "mysqlnd",
+    "opcache",
+    "openssl",
+    "pcntl",
+    "pcre",
+    "pdo",
+    "pdo_mysql",
+    "pdo_odbc",
+    "pdo_pgsql",
+    "pdo_sqlite",
+    "pgsql",
+    "posix",
+    "readline",
+    "reflection",
+    "session",
+    "simplexml",
+    "soap",
+    "sockets",
+    "sodium",
+    "spl",
+    "sqlite3",
+    "standard",
+    "tokenizer",
+    "xml",
+    "xmlreader",
+    "xmlwriter",
+    "zip",
+    "zlib",
+];
 
 pub struct PhpProvider;
 
@@ -25,7 +81,10 @@ impl Provider for PhpProvider {
     }
 
     fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
-        Ok(app.includes_file("composer.json") || app.includes_file("index.php"))
+        Ok(app.includes_file("composer.json")
+            || app.includes_file("index.php")
+            || app.includes_file("artisan")
+            || PhpProvider::is_wordpress(app))
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
@@ -42,8 +101,8 @@ impl Provider for PhpProvider {
             Some(start),
         );
 
-        plan.add_static_assets(PhpProvider::static_assets());
-        plan.add_variables(PhpProvider::environment_variables(app));
+        plan.add_static_assets(PhpProvider::static_assets(app)?);
+        plan.add_variables(PhpProvider::environment_variables(app, env));
 
         Ok(Some(plan))
     }
@@ -55,17 +114,33 @@ impl PhpProvider {
             Ok(php_package) => php_package,
             _ => "php".to_string(),
         };
-        let mut pkgs = vec![
-            Pkg::new(&php_pkg),
-            Pkg::new("perl"),
-            Pkg::new("nginx"),
-            Pkg::new(&format!("{}Packages.composer", &php_pkg)),
-        ];
-        if let Ok(php_extensions) = PhpProvider::get_php_extensions(app) {
-            for extension in php_extensions {
-                pkgs.push(Pkg::new(&format!("{}Extensions.{}", &php_pkg, extension)));
-            }
-        }
+        let php_extensions = PhpProvider::get_php_extensions(app).unwrap_or_default();
+
+        let mut pkgs = if php_extensions.is_empty() {
+            vec![
+                Pkg::new(&php_pkg),
+                Pkg::new("perl"),
+                Pkg::new("nginx"),
+                Pkg::new(&format!("{}Packages.composer", &php_pkg)),
+            ]
+        } else {
+            // Composer checks the `ext-*` requirements, so it has to run with the same extensions
+            let php_with_extensions = format!(
+                "({}.withExtensions (pe: pe.enabled ++ [ {} ]))",
+                php_pkg,
+                php_extensions
+                    .iter()
+                    .map(|extension| format!("pe.all.{}", extension))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            vec![
+                Pkg::new(&php_with_extensions),
+                Pkg::new("perl"),
+                Pkg::new("nginx"),
+                Pkg::new(&format!("{}.packages.composer", &php_with_extensions)),
+            ]
+        };
 
         if app.includes_file("package.json") {
             pkgs.append(&mut NodeProvider::get_nix_packages(app, env)?);
@@ -79,7 +154,11 @@ impl PhpProvider {
             "mkdir -p /var/log/nginx && mkdir -p /var/cache/nginx".to_string(),
         ));
         if app.includes_file("composer.json") {
-            install.add_cmd("composer install".to_string());
+            if PhpProvider::is_laravel(app) {
+                install.add_cmd("composer install --no-dev --optimize-autoloader".to_string());
+            } else {
+                install.add_cmd("composer install".to_string());
+            }
         };
         if app.includes_file("package.json") {
             if let Some(install_cmd) = NodeProvider::get_install_command(app) {
@@ -91,67 +170,156 @@ impl PhpProvider {
     }
 
     fn get_build(app: &App) -> Option<Phase> {
+        let mut build = Phase::build(None);
+
         if let Ok(true) = NodeProvider::has_script(app, "prod") {
-            return Some(Phase::build(Some(
-                NodeProvider::get_package_manager(app) + " run prod",
-            )));
+            build.add_cmd(NodeProvider::get_package_manager(app) + " run prod");
         } else if let Ok(true) = NodeProvider::has_script(app, "build") {
-            return Some(Phase::build(Some(
-                NodeProvider::get_package_manager(app) + " run build",
-            )));
+            build.add_cmd(NodeProvider::get_package_manager(app) + " run build");
         }
 
-        None
+        // Cache the routes and views so they are not compiled on each request.
+        // The config is cached when the app starts, so it uses the runtime variables
+        if PhpProvider::is_laravel(app) {
+            build.add_cmd("php artisan route:cache");
+            build.add_cmd("php artisan view:cache");
+        }
+
+        build.cmds.is_some().then(|| build)
     }
 
     fn get_start(app: &App) -> StartPhase {
+        // The directory that php-fpm writes uploads and caches to
+        let writable_dir = if PhpProvider::is_wordpress(app) {
+            "/app/wp-content"
+        } else {
+            "/app/storage"
+        };
+
+        let cache_config = if PhpProvider::is_laravel(app) {
+            "php artisan config:cache && "
+        } else {
+            ""
+        };
+
         StartPhase::new(format!(
-            "([ -e /app/storage ] && chmod -R ugo+w /app/storage); perl {} {} /nginx.conf && echo \"Server starting on port $PORT\" && (php-fpm -y {} & nginx -c /nginx.conf)",
+            "([ -e {dir} ] && chmod -R ugo+w {dir}); {cache_config}perl {} {} /nginx.conf && echo \"Server starting on port $PORT\" && (php-fpm -y {} & nginx -c /nginx.conf)",
             app.asset_path("transform-config.pl"),
             app.asset_path("nginx.template.conf"),
             app.asset_path("php-fpm.conf"),
+            dir = writable_dir,
+            cache_config = cache_config,
         ))
     }
 
-    fn static_assets() -> StaticAssets {
-        static_asset_list! {
+    /// The bundled configs, where an `nginx.template.conf` or `php-fpm.conf` in the app replaces its default
+    fn static_assets(app: &App) -> Result<StaticAssets> {
+        let mut assets = static_asset_list! {
             "nginx.template.conf" => include_str!("nginx.template.conf"),
             "transform-config.pl" => include_str!("transform-config.pl"),
             "php-fpm.conf" => include_str!("php-fpm.conf")
+        };
+
+        for name in ["nginx.template.conf", "php-fpm.conf"] {
+            if app.includes_file(name) {
+                assets.insert(name.to_string(), app.read_file(name)?);
+            }
         }
+
+        Ok(assets)
     }
 
-    fn environment_variables(app: &App) -> EnvironmentVariables {
+    fn environment_variables(app: &App, env: &Environment) -> EnvironmentVariables {
         let mut vars = EnvironmentVariables::new();
         vars.insert("PORT".to_string(), "80".to_string());
-        if app.includes_file("artisan") {
+
+        let root_dir = PhpProvider::get_root_dir(app, env);
+        let fallback_path = if root_dir != "/app" || PhpProvider::is_wordpress(app) {
+            // Requests for missing files go to the front controller, e.g. `public/index.php`,
+            // which also resolves the permalinks of WordPress
+            Some(
+                env.get_config_variable("PHP_FALLBACK_PATH")
+                    .unwrap_or_else(|| "/index.php".to_string()),
+            )
+        } else {
+            env.get_config_variable("PHP_FALLBACK_PATH")
+        };
+        if let Some(fallback_path) = fallback_path {
+            // `$if()` in the nginx template only matches variables that are set to `yes`
+            vars.insert("NIXPACKS_PHP_USE_FALLBACK".to_string(), "yes".to_string());
+            vars.insert("NIXPACKS_PHP_FALLBACK_PATH".to_string(), fallback_path);
+        }
+        vars.insert("NIXPACKS_PHP_ROOT_DIR".to_string(), root_dir);
+
+        if PhpProvider::is_laravel(app) {
             vars.insert("IS_LARAVEL".to_string(), "yes".to_string());
+            vars.insert("APP_ENV".to_string(), "production".to_string());
+            vars.insert("APP_DEBUG".to_string(), "false".to_string());
+            vars.insert("LOG_CHANNEL".to_string(), "stderr".to_string());
         }
         vars
     }
 
+    fn is_laravel(app: &App) -> bool {
+        app.includes_file("artisan")
+    }
+
+    fn is_wordpress(app: &App) -> bool {
+        app.includes_file("wp-config.php")
+            || app.includes_file("wp-config-sample.php")
+            || app.includes_directory("wp-content")
+    }
+
+    /// The document root of nginx, which is `public/` for Laravel, Symfony, and other apps with a front controller
+    fn get_root_dir(app: &App, env: &Environment) -> String {
+        match env.get_config_variable("PHP_ROOT_DIR") {
+            Some(root_dir) if root_dir.starts_with('/') => root_dir,
+            Some(root_dir) => format!("/app/{}", root_dir.trim_start_matches("./")),
+            None if PhpProvider::is_laravel(app) || app.includes_file("public/index.php") => {
+                "/app/public".to_string()
+            }
+            None => "/app".to_string(),
+        }
+    }
+
     fn get_php_package(app: &App) -> Result<String> {
         let version = PhpProvider::get_php_version(app)?;
         Ok(format!("php{}", version.replace('.', "")))
     }
 
     fn get_php_version(app: &App) -> Result<String> {
+        // A version pinned for asdf is installed by its minor version, e.g. 8.1.12 as 8.1
+        if let Some((version, source)) = ToolVersions::from_app(app)?.get("php") {
+            let minor_version = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+            if AVAILABLE_PHP_VERSIONS.contains(&minor_version.as_str()) {
+                return Ok(minor_version);
+            }
+
+            println!(
+                "Warning: PHP version {} from {} is not available, using PHP {}",
+                version, source, DEFAULT_PHP_VERSION
+            );
+            return Ok(DEFAULT_PHP_VERSION.to_string());
+        }
+
         let composer_json: ComposerJson = app.read_json("composer.json")?;
         let version = composer_json.require.get("php").cloned();
 
         let version = if let Some(v) = version {
-            if v.contains("8.0") {
-                "8.0".to_string()
-            } else if v.contains("8.1") {
-                "8.1".to_string()
-            } else if v.contains("7.4") {
-                "7.4".to_string()
-            } else {
-                println!(
-                    "Warning: PHP version {} is not available, using PHP {}",
-                    v, DEFAULT_PHP_VERSION
-                );
-                DEFAULT_PHP_VERSION.to_string()
+            // The newest available version that satisfies the constraint
+            match AVAILABLE_PHP_VERSIONS
+                .iter()
+                .rev()
+                .find(|available| PhpProvider::satisfies_constraint(available, &v))
+            {
+                Some(available) => (*available).to_string(),
+                None => {
+                    println!(
+                        "Warning: PHP version {} is not available, using PHP {}",
+                        v, DEFAULT_PHP_VERSION
+                    );
+                    DEFAULT_PHP_VERSION.to_string()
+                }
             }
         } else {
             println!("Warning: No PHP version specified, using PHP {}; see https://getcomposer.org/doc/04-schema.md#package-links for how to specify a PHP version.", DEFAULT_PHP_VERSION);
@@ -161,24 +329,192 @@ impl PhpProvider {
         Ok(version)
     }
 
+    /// Whether a `major.minor` version satisfies a Composer constraint such as `^7.4 || ^8.0`
+    ///
+    /// Patch versions are ignored, since only the newest patch of each minor version is available.
+    fn satisfies_constraint(version: &str, constraint: &str) -> bool {
+        let version = match parse_version(version) {
+            Some((major, minor, _)) => (major, minor.unwrap_or(0)),
+            None => return false,
+        };
+
+        constraint
+            .split('|')
+            .filter(|c| !c.trim().is_empty())
+            .any(|alternative| {
+                alternative
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|c| !c.is_empty())
+                    .all(|c| {
+                        let c = c.split('@').next().unwrap_or_default();
+                        let (op, rest) = match c.find(|c: char| c.is_ascii_digit() || c == '*') {
+                            Some(index) => c.split_at(index),
+                            None => return c.is_empty(),
+                        };
+                        if rest == "*" {
+                            return true;
+                        }
+                        let (major, minor, patch) = match parse_version(rest) {
+                            Some(parsed) => parsed,
+                            None => return false,
+                        };
+                        let bound = (major, minor.unwrap_or(0));
+
+                        match op.trim_end_matches('v') {
+                            "^" | "~" if minor.is_none() || (op.starts_with('^') && major > 0) => {
+                                version >= bound && version.0 == major
+                            }
+                            "^" => version == bound,
+                            "~" if patch.is_some() => version == bound,
+                            "~" => version >= bound && version.0 == major,
+                            ">=" => version >= bound,
+                            ">" if patch.is_some() => version >= bound,
+                            ">" => version > bound,
+                            "<=" => version <= bound,
+                            "<" if patch.unwrap_or(0) > 0 => version <= bound,
+                            "<" => version < bound,
+                            "" | "=" | "==" if minor.is_none() => version.0 == major,
+                            "" | "=" | "==" => version == bound,
+                            "!=" => version != bound || patch.is_some(),
+                            _ => false,
+                        }
+                    })
+            })
+    }
+
+    /// The `ext-*` requirements that are not already enabled in the PHP of Nixpkgs
     fn get_php_extensions(app: &App) -> Result<Vec<String>> {
-        let composer_json: ComposerJson = app.read_json("composer.json")?;
+        let requirements: Vec<String> = if app.includes_file("composer.json") {
+            let composer_json: ComposerJson = app.read_json("composer.json")?;
+            composer_json.require.into_keys().collect()
+        } else {
+            Vec::new()
+        };
+
         let mut extensions = Vec::new();
-        for extension in composer_json.require.keys() {
-            if extension.starts_with("ext-") {
-                extensions.push(
-                    extension
-                        .strip_prefix("ext-")
-                        .unwrap_or(extension)
-                        .to_string(),
-                );
+        for requirement in &requirements {
+            if let Some(extension) = requirement.strip_prefix("ext-") {
+                let extension = extension.to_lowercase().replace('-', "_");
+                let is_valid = extension
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+                if is_valid
+                    && !DEFAULT_PHP_EXTENSIONS.contains(&extension.as_str())
+                    && !extensions.contains(&extension)
+                {
+                    extensions.push(extension);
+                }
             }
         }
+        extensions.sort();
         Ok(extensions)
     }
 }
 
+/// Splits a version such as `8`, `8.1`, or `8.1.*` into its major, minor, and patch numbers
+fn parse_version(version: &str) -> Option<(u32, Option<u32>, Option<u32>)> {
+    let mut parts = version
+        .split('.')
+        .take_while(|part| *part != "*")
+        .map(|part| part.parse::<u32>().ok());
+
+    let major = parts.next()??;
+    let minor = match parts.next() {
+        Some(minor) => Some(minor?),
+        None => None,
+    };
+    let patch = match parts.next() {
+        Some(patch) => Some(patch?),
+        None => None,
+    };
+
+    Some((major, minor, patch))
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct ComposerJson {
     require: HashMap<String, String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_satisfies_constraint() {
+        assert!(PhpProvider::satisfies_constraint("8.1", "^8.0"));
+        assert!(!PhpProvider::satisfies_constraint("7.4", "^8.0"));
+        assert!(PhpProvider::satisfies_constraint("7.4", "^7.4 || ^8.0"));
+        assert!(PhpProvider::satisfies_constraint("8.0", "~8.0.2"));
+        assert!(!PhpProvider::satisfies_constraint("8.1", "~8.0.2"));
+        assert!(PhpProvider::satisfies_constraint("8.0", ">=7.4 <8.1"));
+        assert!(!PhpProvider::satisfies_constraint("8.1", ">=7.4 <8.1"));
+        assert!(PhpProvider::satisfies_constraint("8.1", "8.*"));
+        assert!(PhpProvider::satisfies_constraint("8.0", ">=8.0.3"));
+        assert!(PhpProvider::satisfies_constraint("8.1", "*"));
+        assert!(!PhpProvider::satisfies_constraint("8.1", "^9.0"));
+    }
+
+    #[test]
+    fn test_php_version() -> Result<()> {
+        assert_eq!(
+            PhpProvider::get_php_version(&App::new("./examples/php-extensions")?)?,
+            "8.1"
+        );
+        assert_eq!(
+            PhpProvider::get_php_version(&App::new("./examples/php-laravel")?)?,
+            "8.1"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_php_extensions() -> Result<()> {
+        assert_eq!(
+            PhpProvider::get_php_extensions(&App::new("./examples/php-extensions")?)?,
+            vec!["redis".to_string()]
+        );
+        assert!(PhpProvider::get_php_extensions(&App::new("./examples/php-laravel")?)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_dir() -> Result<()> {
+        let env = Environment::default();
+        assert_eq!(
+            PhpProvider::get_root_dir(&App::new("./examples/php-laravel")?, &env),
+            "/app/public"
+        );
+        assert_eq!(
+            PhpProvider::get_root_dir(&App::new("./examples/php-vanilla")?, &env),
+            "/app"
+        );
+        assert_eq!(
+            PhpProvider::get_root_dir(
+                &App::new("./examples/php-vanilla")?,
+                &Environment::from_envs(vec!["NIXPACKS_PHP_ROOT_DIR=web"])?
+            ),
+            "/app/web"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_config() -> Result<()> {
+        let dir = TempDir::new("nixpacks-php")?;
+        fs::write(dir.path().join("index.php"), "<?php echo 'hi';")?;
+        fs::write(dir.path().join("nginx.template.conf"), "daemon off;")?;
+        let assets = PhpProvider::static_assets(&App::new(&dir.path().to_string_lossy())?)?;
+
+        assert_eq!(assets["nginx.template.conf"], "daemon off;");
+        assert_eq!(assets["php-fpm.conf"], include_str!("php-fpm.conf"));
+
+        Ok(())
+    }
+}
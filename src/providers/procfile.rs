@@ -1,40 +1,53 @@
-use std::collections::HashMap;
-
 use super::Provider;
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, Ok, Result};
+use regex::Regex;
+
+const RELEASE_PROCESS: &str = "release";
 
 pub struct ProcfileProvider {}
 
 impl Provider for ProcfileProvider {
     fn name(&self) -> &str {
-        "deno"
+        "procfile"
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let mut plan = BuildPlan::default();
 
-        if let Some(release_cmd) = ProcfileProvider::get_release_cmd(app)? {
-            let mut release = Phase::new("release");
-            release.depends_on = Some(vec![
-                "setup".to_owned(),
-                "install".to_owned(),
-                "build".to_owned(),
-            ]);
-            release.cmds = Some(vec!["...".to_string(), release_cmd]);
-            plan.add_phase(release);
+        let procfile = ProcfileProvider::get_processes(app)?;
+        if procfile.is_empty() {
+            return Ok(Some(plan));
+        }
+
+        let process = ProcfileProvider::get_process(&procfile, env)?;
+
+        // A release process that is started itself doesn't also run after the build
+        if let Some(release_cmd) = ProcfileProvider::get_cmd(&procfile, RELEASE_PROCESS) {
+            if process.as_deref() != Some(RELEASE_PROCESS) {
+                let mut release = Phase::new(RELEASE_PROCESS);
+                release.depends_on = Some(vec![
+                    "setup".to_owned(),
+                    "install".to_owned(),
+                    "build".to_owned(),
+                ]);
+                release.cmds = Some(vec!["...".to_string(), release_cmd]);
+                plan.add_phase(release);
+            }
         };
 
-        if let Some(start_cmd) = ProcfileProvider::get_start_cmd(app)? {
-            let start_phase = StartPhase::new(start_cmd);
-            plan.set_start_phase(start_phase);
+        if let Some(start_cmd) =
+            process.and_then(|process| ProcfileProvider::get_cmd(&procfile, &process))
+        {
+            plan.set_start_phase(StartPhase::new(start_cmd));
         }
 
         Ok(Some(plan))
@@ -42,36 +55,136 @@ impl Provider for ProcfileProvider {
 }
 
 impl ProcfileProvider {
-    fn get_start_cmd(app: &App) -> Result<Option<String>> {
-        if app.includes_file("Procfile") {
-            let mut procfile: HashMap<String, String> =
-                app.read_yaml("Procfile").context("Reading Procfile")?;
-            procfile.remove("release");
-
-            if procfile.is_empty() {
-                Ok(None)
-            } else if let Some(cmd) = procfile.get("web") {
-                Ok(Some(cmd.to_string()))
-            } else {
-                let process = procfile.values().collect::<Vec<_>>()[0].to_string();
-                Ok(Some(process))
-            }
-        } else {
-            Ok(None)
+    /// The processes of the Procfile in the order they are listed, e.g. `web: npm start`
+    fn get_processes(app: &App) -> Result<Vec<(String, String)>> {
+        if !app.includes_file("Procfile") {
+            return Ok(Vec::new());
         }
+
+        // Commands can contain colons and quotes, so the Procfile isn't parsed as YAML
+        let process_re = Regex::new(r"^([A-Za-z0-9_-]+)\s*:\s*(.+)$")?;
+        let procfile = app.read_file("Procfile")?;
+        let processes = procfile
+            .lines()
+            .filter_map(|line| process_re.captures(line.trim()))
+            .map(|captures| (captures[1].to_string(), captures[2].trim().to_string()))
+            .collect();
+
+        Ok(processes)
     }
 
-    fn get_release_cmd(app: &App) -> Result<Option<String>> {
-        if app.includes_file("Procfile") {
-            let procfile: HashMap<String, String> =
-                app.read_yaml("Procfile").context("Reading Procfile")?;
-            if let Some(release) = procfile.get("release") {
-                Ok(Some(release.to_string()))
-            } else {
-                Ok(None)
+    /// The process to start, from `NIXPACKS_PROCFILE_PROCESS`. Otherwise this is `web`,
+    /// or the first process other than `release`
+    fn get_process(procfile: &[(String, String)], env: &Environment) -> Result<Option<String>> {
+        if let Some(process) = env.get_config_variable("PROCFILE_PROCESS") {
+            if ProcfileProvider::get_cmd(procfile, &process).is_none() {
+                bail!(Message::new(MessageId::ProcfileProcessNotFound)
+                    .arg("process", &process)
+                    .arg(
+                        "processes",
+                        &procfile
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
             }
-        } else {
-            Ok(None)
+
+            return Ok(Some(process));
+        }
+
+        if ProcfileProvider::get_cmd(procfile, "web").is_some() {
+            return Ok(Some("web".to_string()));
         }
+
+        Ok(procfile
+            .iter()
+            .map(|(name, _)| name)
+            .find(|name| *name != RELEASE_PROCESS)
+            .cloned())
+    }
+
+    fn get_cmd(procfile: &[(String, String)], process: &str) -> Option<String> {
+        procfile
+            .iter()
+            .find(|(name, _)| name == process)
+            .map(|(_, cmd)| cmd.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn get_plan(procfile: &str, envs: Vec<&str>) -> Result<BuildPlan> {
+        let dir = TempDir::new("nixpacks-procfile")?;
+        fs::write(dir.path().join("Procfile"), procfile)?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok(ProcfileProvider {}
+            .get_build_plan(&app, &Environment::from_envs(envs)?)?
+            .unwrap())
+    }
+
+    #[test]
+    fn test_web_process() -> Result<()> {
+        let plan = get_plan(
+            "release: ./migrate.sh\nworker: celery -A app worker\nweb: gunicorn app:app --bind 0.0.0.0:$PORT\n",
+            vec![],
+        )?;
+
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("gunicorn app:app --bind 0.0.0.0:$PORT".to_string())
+        );
+        assert_eq!(
+            plan.phases.unwrap()["release"].cmds,
+            Some(vec!["...".to_string(), "./migrate.sh".to_string()])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_selected_process() -> Result<()> {
+        let procfile = "release: ./migrate.sh\nweb: npm start\nworker: node worker.js\n";
+
+        let plan = get_plan(procfile, vec!["NIXPACKS_PROCFILE_PROCESS=worker"])?;
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("node worker.js".to_string())
+        );
+
+        // The release process is started instead of running after the build
+        let plan = get_plan(procfile, vec!["NIXPACKS_PROCFILE_PROCESS=release"])?;
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("./migrate.sh".to_string())
+        );
+        assert!(plan.phases.unwrap_or_default().get("release").is_none());
+
+        let err = get_plan(procfile, vec!["NIXPACKS_PROCFILE_PROCESS=clock"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Process clock not found in the Procfile. Available processes: release, web, worker"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_first_process() -> Result<()> {
+        let plan = get_plan(
+            "release: ./migrate.sh\nworker: node worker.js\nclock: node clock.js\n",
+            vec![],
+        )?;
+        assert_eq!(
+            plan.start_phase.unwrap().cmd,
+            Some("node worker.js".to_string())
+        );
+
+        Ok(())
     }
 }
@@ -0,0 +1,67 @@
+use super::{node::NodeProvider, Provider};
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::Result;
+
+const SPAGO_CACHE_DIR: &str = "/root/.cache/spago";
+const BUNDLE_OUTPUT: &str = "dist/index.js";
+
+pub struct PureScriptProvider {}
+
+impl Provider for PureScriptProvider {
+    fn name(&self) -> &str {
+        "purescript"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("spago.dhall") || app.includes_file("spago.yaml"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let mut setup = Phase::setup(Some(NodeProvider::get_nix_packages(app, env)?));
+        setup.add_nix_pkgs(&[
+            Pkg::new("purescript"),
+            Pkg::new("spago"),
+            Pkg::new("esbuild"),
+        ]);
+
+        let mut install = Phase::install(NodeProvider::get_install_command(app));
+        install.add_path("/app/node_modules/.bin".to_string());
+
+        let mut build = Phase::build(Some("spago build".to_string()));
+        build.add_cmd(PureScriptProvider::get_bundle_cmd(app));
+        build.add_cache_directory(SPAGO_CACHE_DIR.to_string());
+
+        let start = StartPhase::new(format!("node {}", BUNDLE_OUTPUT));
+
+        let mut plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        plan.add_variables(NodeProvider::get_node_environment_variables());
+        plan.add_build_variables(NodeProvider::get_node_build_variables());
+
+        Ok(Some(plan))
+    }
+}
+
+impl PureScriptProvider {
+    /// Spago 0.93+ (configured with `spago.yaml`) replaced `bundle-app` with `bundle`
+    fn get_bundle_cmd(app: &App) -> String {
+        if app.includes_file("spago.yaml") {
+            format!(
+                "spago bundle --module Main --platform node --outfile {}",
+                BUNDLE_OUTPUT
+            )
+        } else {
+            format!(
+                "spago bundle-app --main Main --platform node --to {}",
+                BUNDLE_OUTPUT
+            )
+        }
+    }
+}
@@ -3,6 +3,7 @@ use crate::{
     nixpacks::{
         app::App,
         environment::{Environment, EnvironmentVariables},
+        messages::{Message, MessageId},
         plan::{
             phase::{Phase, StartPhase},
             BuildPlan,
@@ -14,13 +15,30 @@ use anyhow::{bail, Context, Ok, Result};
 use regex::{Match, Regex};
 use serde::Deserialize;
 use std::result::Result::Ok as OkResult;
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+};
 
-use super::{Provider, ProviderMetadata};
+use super::{tool_versions::ToolVersions, Provider, ProviderMetadata};
 
 const DEFAULT_PYTHON_PKG_NAME: &str = "python38";
+// The Python versions that have a package, with the version of the default package first
+const PYTHON_VERSIONS: &[(u32, u32)] = &[(3, 8), (2, 7), (3, 7), (3, 9), (3, 10), (3, 11)];
 const POETRY_VERSION: &str = "1.1.13";
+const PIPENV_VERSION: &str = "2022.11.11";
+const PDM_VERSION: &str = "2.4.0";
+const UV_VERSION: &str = "0.4.30";
 const PIP_CACHE_DIR: &str = "/root/.cache/pip";
+const POETRY_CACHE_DIR: &str = "/root/.cache/pypoetry";
+const PIPENV_CACHE_DIR: &str = "/root/.cache/pipenv";
+const PDM_CACHE_DIR: &str = "/root/.cache/pdm";
+const UV_CACHE_DIR: &str = "/root/.cache/uv";
+const VENV_LOCATION: &str = "/opt/venv";
+const CONDA_ENV_LOCATION: &str = "/opt/conda";
+const MAMBA_ROOT_PREFIX: &str = "/root/micromamba";
+const CONDA_ENV_FILES: &[&str] = &["environment.yml", "environment.yaml"];
+const PYTHON_PROCESSES: &[&str] = &["web", "worker"];
 
 pub struct PythonProvider {}
 
@@ -32,19 +50,31 @@ impl Provider for PythonProvider {
     fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
         let has_python = app.includes_file("main.py")
             || app.includes_file("requirements.txt")
-            || app.includes_file("pyproject.toml");
+            || app.includes_file("pyproject.toml")
+            || app.includes_file("Pipfile")
+            || PythonProvider::get_conda_env_file(app).is_some();
         Ok(has_python)
     }
 
     fn metadata(&self, app: &App, env: &Environment) -> Result<ProviderMetadata> {
         let is_django = PythonProvider::is_django(app, env)?;
         let is_using_postgres = PythonProvider::is_using_postgres(app, env)?;
-        let is_poetry = app.includes_file("poetry.lock");
+        let is_poetry = PythonProvider::is_poetry(app);
+        let is_pipenv = app.includes_file("Pipfile");
+        let is_pdm = app.includes_file("pdm.lock");
+        let is_uv = app.includes_file("uv.lock");
+        let is_conda = PythonProvider::get_conda_env_file(app).is_some();
+        let is_celery = PythonProvider::uses_dep(app, "celery")?;
 
         Ok(ProviderMetadata::from(vec![
             (is_django, "django"),
             (is_using_postgres, "postgres"),
             (is_poetry, "poetry"),
+            (is_pipenv, "pipenv"),
+            (is_pdm, "pdm"),
+            (is_uv, "uv"),
+            (is_conda, "conda"),
+            (is_celery, "celery"),
         ]))
     }
 
@@ -57,6 +87,10 @@ impl Provider for PythonProvider {
         let install = self.install(app, env)?.unwrap_or_default();
         plan.add_phase(install);
 
+        if let Some(build) = self.build(app, env)? {
+            plan.add_phase(build);
+        }
+
         if let Some(start) = self.start(app, env)? {
             plan.set_start_phase(start);
         }
@@ -66,27 +100,104 @@ impl Provider for PythonProvider {
             "1".to_owned(),
         )]));
 
-        if app.includes_file("poetry.lock") {
-            plan.add_variables(EnvironmentVariables::from([(
+        if PythonProvider::is_poetry(app) {
+            plan.add_build_variables(EnvironmentVariables::from([(
                 "NIXPACKS_POETRY_VERSION".to_string(),
                 POETRY_VERSION.to_string(),
             )]));
+
+            // `poetry run` uses the active virtual environment instead of creating its own
+            plan.add_variables(EnvironmentVariables::from([(
+                "VIRTUAL_ENV".to_string(),
+                VENV_LOCATION.to_string(),
+            )]));
+        }
+
+        if app.includes_file("Pipfile") {
+            plan.add_build_variables(EnvironmentVariables::from([(
+                "NIXPACKS_PIPENV_VERSION".to_string(),
+                PIPENV_VERSION.to_string(),
+            )]));
+        }
+
+        // Variables set in the app's environment take precedence over the module in manage.py
+        if PythonProvider::is_django(app, env)? {
+            if let Some(settings_module) = PythonProvider::get_django_settings_module(app)? {
+                plan.add_variables(EnvironmentVariables::from([(
+                    "DJANGO_SETTINGS_MODULE".to_string(),
+                    settings_module,
+                )]));
+            }
+        }
+
+        if PythonProvider::get_conda_env_file(app).is_some() {
+            plan.add_variables(EnvironmentVariables::from([(
+                "MAMBA_ROOT_PREFIX".to_string(),
+                MAMBA_ROOT_PREFIX.to_string(),
+            )]));
+        }
+
+        if app.includes_file("pdm.lock") {
+            plan.add_build_variables(EnvironmentVariables::from([(
+                "NIXPACKS_PDM_VERSION".to_string(),
+                PDM_VERSION.to_string(),
+            )]));
+        }
+
+        if app.includes_file("uv.lock") {
+            // Sync into the virtual environment with the Nix Python, instead of a `.venv` with a downloaded Python.
+            // The cache is a separate mount, so packages can't be hard linked from it
+            plan.add_build_variables(EnvironmentVariables::from([
+                ("NIXPACKS_UV_VERSION".to_string(), UV_VERSION.to_string()),
+                (
+                    "UV_PROJECT_ENVIRONMENT".to_string(),
+                    VENV_LOCATION.to_string(),
+                ),
+                ("UV_PYTHON_DOWNLOADS".to_string(), "never".to_string()),
+                ("UV_LINK_MODE".to_string(), "copy".to_string()),
+            ]));
         }
 
         Ok(Some(plan))
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+struct Pipfile {
+    pub requires: Option<PipfileRequires>,
+    pub scripts: Option<BTreeMap<String, toml::Value>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct PipfileRequires {
+    pub python_version: Option<String>,
+    pub python_full_version: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[allow(dead_code)]
 struct PyProject {
     pub project: Option<ProjectDecl>,
+    pub tool: Option<ToolDecl>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ToolDecl {
+    pub poetry: Option<PoetryDecl>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct PoetryDecl {
+    pub dependencies: Option<HashMap<String, toml::Value>>,
+    pub scripts: Option<BTreeMap<String, toml::Value>>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 #[allow(dead_code)]
 struct ProjectDecl {
     pub name: Option<String>,
+    #[serde(rename = "requires-python")]
+    pub requires_python: Option<String>,
     pub packages: Option<Vec<String>>,
     pub py_modules: Option<Vec<String>>,
     pub entry_points: Option<HashMap<String, String>>,
@@ -107,6 +218,11 @@ enum EntryPoint {
 
 impl PythonProvider {
     fn setup(&self, app: &App, env: &Environment) -> Result<Option<Phase>> {
+        // Conda environments bring their own Python and libraries
+        if PythonProvider::get_conda_env_file(app).is_some() {
+            return Ok(Some(Phase::setup(Some(vec![Pkg::new("micromamba")]))));
+        }
+
         let mut pkgs: Vec<Pkg> = vec![];
         let python_base_package = PythonProvider::get_nix_python_package(app, env)?;
 
@@ -133,41 +249,116 @@ impl PythonProvider {
     }
 
     fn install(&self, app: &App, _env: &Environment) -> Result<Option<Phase>> {
-        let env_loc = "/opt/venv";
-        let create_env = format!("python -m venv {}", env_loc);
-        let activate_env = format!(". {}/bin/activate", env_loc);
+        let create_env = format!("python -m venv {}", VENV_LOCATION);
+        let activate_env = format!(". {}/bin/activate", VENV_LOCATION);
 
-        if app.includes_file("requirements.txt") {
+        if let Some(env_file) = PythonProvider::get_conda_env_file(app) {
+            // Packages are copied out of the cache mount, since they can't be hard linked across filesystems
+            let mut install_phase = Phase::install(Some(format!(
+                "micromamba create -y --always-copy -p {} -f {}",
+                CONDA_ENV_LOCATION, env_file
+            )));
+
+            install_phase.add_file_dependency(env_file.to_string());
+            install_phase.add_cache_directory(format!("{}/pkgs", MAMBA_ROOT_PREFIX));
+            install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
+
+            return Ok(Some(install_phase));
+        } else if app.includes_file("Pipfile") {
+            // Dependencies are installed into the virtual environment, which is the "system" Python once activated.
+            // Without a lockfile there is nothing to check with `--deploy`
+            let pipenv_install = if app.includes_file("Pipfile.lock") {
+                "pipenv install --deploy --system"
+            } else {
+                "pipenv install --skip-lock --system"
+            };
+            let mut install_phase = Phase::install(Some(format!(
+                "{} && {} && pip install pipenv==$NIXPACKS_PIPENV_VERSION && {}",
+                create_env, activate_env, pipenv_install
+            )));
+
+            install_phase.add_file_dependency("Pipfile".to_string());
+            if app.includes_file("Pipfile.lock") {
+                install_phase.add_file_dependency("Pipfile.lock".to_string());
+            }
+
+            install_phase.add_path(format!("{}/bin", VENV_LOCATION));
+
+            install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
+            install_phase.add_cache_directory(PIPENV_CACHE_DIR.to_string());
+
+            return Ok(Some(install_phase));
+        } else if app.includes_file("requirements.txt") {
             let mut install_phase = Phase::install(Some(format!(
                 "{} && {} && pip install -r requirements.txt",
                 create_env, activate_env
             )));
 
-            install_phase.add_path(format!("{}/bin", env_loc));
+            install_phase.add_path(format!("{}/bin", VENV_LOCATION));
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
             return Ok(Some(install_phase));
         } else if app.includes_file("pyproject.toml") {
-            if app.includes_file("poetry.lock") {
+            if PythonProvider::is_poetry(app) {
                 let install_poetry = "pip install poetry==$NIXPACKS_POETRY_VERSION".to_string();
                 let mut install_phase = Phase::install(Some(format!(
-                    "{} && {} && {} && poetry install --no-dev --no-interaction --no-ansi",
+                    "{} && {} && {} && poetry install --no-dev --no-root --no-interaction --no-ansi",
                     create_env, activate_env, install_poetry
                 )));
 
-                install_phase.add_path(format!("{}/bin", env_loc));
+                // Only install the dependencies again when they change, so the virtual environment is reused
+                install_phase.add_file_dependency("pyproject.toml".to_string());
+                if app.includes_file("poetry.lock") {
+                    install_phase.add_file_dependency("poetry.lock".to_string());
+                }
+
+                install_phase.add_path(format!("{}/bin", VENV_LOCATION));
+
+                install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
+                install_phase.add_cache_directory(POETRY_CACHE_DIR.to_string());
+
+                return Ok(Some(install_phase));
+            }
+
+            // PDM and uv install exactly what is in their lockfile, without the project itself
+            let lockfile_install = if app.includes_file("pdm.lock") {
+                Some((
+                    "pdm.lock",
+                    "pip install pdm==$NIXPACKS_PDM_VERSION && pdm install --prod --frozen-lockfile --no-self",
+                    PDM_CACHE_DIR,
+                ))
+            } else if app.includes_file("uv.lock") {
+                Some((
+                    "uv.lock",
+                    "pip install uv==$NIXPACKS_UV_VERSION && uv sync --frozen --no-dev --no-install-project",
+                    UV_CACHE_DIR,
+                ))
+            } else {
+                None
+            };
+            if let Some((lockfile, install_cmd, cache_dir)) = lockfile_install {
+                let mut install_phase = Phase::install(Some(format!(
+                    "{} && {} && {}",
+                    create_env, activate_env, install_cmd
+                )));
+
+                install_phase.add_file_dependency("pyproject.toml".to_string());
+                install_phase.add_file_dependency(lockfile.to_string());
+                install_phase.add_path(format!("{}/bin", VENV_LOCATION));
 
                 install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
+                install_phase.add_cache_directory(cache_dir.to_string());
 
                 return Ok(Some(install_phase));
             }
+
             let mut install_phase = Phase::install(Some(format!(
                 "{} && {} && pip install --upgrade build setuptools && pip install .",
                 create_env, activate_env
             )));
 
             install_phase.add_file_dependency("pyproject.toml".to_string());
-            install_phase.add_path(format!("{}/bin", env_loc));
+            install_phase.add_path(format!("{}/bin", VENV_LOCATION));
 
             install_phase.add_cache_directory(PIP_CACHE_DIR.to_string());
 
@@ -177,37 +368,289 @@ impl PythonProvider {
         Ok(Some(Phase::install(None)))
     }
 
+    fn build(&self, app: &App, env: &Environment) -> Result<Option<Phase>> {
+        let mut build = Phase::build(None);
+
+        // Poetry scripts run code from the project itself, which is installed once the whole app is copied
+        if !PythonProvider::get_poetry_scripts(app).is_empty() {
+            build.add_cmd("poetry install --no-dev --no-interaction --no-ansi");
+        }
+
+        // collectstatic fails when there is nowhere to collect the static files to
+        if PythonProvider::is_django(app, env)? && PythonProvider::has_django_static_root(app)? {
+            build.add_cmd(PythonProvider::run_in_env(
+                app,
+                "python manage.py collectstatic --noinput",
+            ));
+        }
+
+        Ok(build.cmds.is_some().then(|| build))
+    }
+
     fn start(&self, app: &App, env: &Environment) -> Result<Option<StartPhase>> {
+        let run = |cmd: String| PythonProvider::run_in_env(app, &cmd);
+
+        if PythonProvider::get_process(env)? == "worker" {
+            let celery_app = PythonProvider::get_celery_app(app)?
+                .ok_or_else(|| Message::new(MessageId::CeleryAppNotFound))?;
+            return Ok(Some(StartPhase::new(run(format!(
+                "celery -A {} worker --loglevel=info",
+                celery_app
+            )))));
+        }
+
         if PythonProvider::is_django(app, env)? {
             let app_name = PythonProvider::get_django_app_name(app, env)?;
 
-            return Ok(Some(StartPhase::new(format!(
-                "python manage.py migrate && gunicorn {}",
-                app_name
-            ))));
+            let mut start = StartPhase::new(format!(
+                "{} && {}",
+                run("python manage.py migrate".to_string()),
+                run(format!(
+                    "gunicorn {} --bind 0.0.0.0:${{PORT:-8000}}",
+                    app_name
+                ))
+            ));
+            start.set_port(8000);
+            return Ok(Some(start));
+        }
+
+        if let Some(script) = PythonProvider::get_pipfile_start_script(app)? {
+            return Ok(Some(StartPhase::new(script)));
+        }
+
+        if let Some(script) = PythonProvider::get_poetry_scripts(app).first() {
+            return Ok(Some(StartPhase::new(run(script.clone()))));
+        }
+
+        if let Some(asgi_app) = PythonProvider::get_asgi_app(app)? {
+            let mut start = StartPhase::new(run(format!(
+                "uvicorn {} --host 0.0.0.0 --port ${{PORT:-8000}}",
+                asgi_app
+            )));
+            start.set_port(8000);
+            return Ok(Some(start));
         }
 
         if app.includes_file("pyproject.toml") {
             if let OkResult(meta) = PythonProvider::parse_pyproject(app) {
                 if let Some(entry_point) = meta.entry_point {
-                    return Ok(Some(StartPhase::new(match entry_point {
+                    return Ok(Some(StartPhase::new(run(match entry_point {
                         EntryPoint::Command(cmd) => cmd,
                         EntryPoint::Module(module) => format!("python -m {}", module),
-                    })));
+                    }))));
                 }
             }
         }
         // falls through
         if app.includes_file("main.py") {
-            return Ok(Some(StartPhase::new("python main.py".to_string())));
+            return Ok(Some(StartPhase::new(run("python main.py".to_string()))));
+        }
+
+        Ok(None)
+    }
+
+    /// Apps managed with Poetry have a `poetry.lock` or a `[tool.poetry]` section in pyproject.toml
+    fn is_poetry(app: &App) -> bool {
+        app.includes_file("poetry.lock") || PythonProvider::get_poetry_decl(app).is_some()
+    }
+
+    fn get_poetry_decl(app: &App) -> Option<PoetryDecl> {
+        PythonProvider::read_pyproject(app)
+            .ok()
+            .flatten()
+            .and_then(|pyproject| pyproject.tool)
+            .and_then(|tool| tool.poetry)
+    }
+
+    /// Names of the scripts in `[tool.poetry.scripts]`
+    fn get_poetry_scripts(app: &App) -> Vec<String> {
+        PythonProvider::get_poetry_decl(app)
+            .and_then(|poetry| poetry.scripts)
+            .map(|scripts| scripts.into_keys().collect())
+            .unwrap_or_default()
+    }
+
+    /// The process that the image starts, `web` by default or a Celery `worker`
+    fn get_process(env: &Environment) -> Result<String> {
+        let process = env
+            .get_config_variable("PYTHON_PROCESS")
+            .unwrap_or_else(|| "web".to_string());
+        if !PYTHON_PROCESSES.contains(&process.as_str()) {
+            bail!(Message::new(MessageId::UnknownProcess)
+                .arg("process", &process)
+                .arg("variable", "NIXPACKS_PYTHON_PROCESS"));
         }
 
+        Ok(process)
+    }
+
+    /// Conda and Poetry apps run their commands in the project's environment
+    fn run_in_env(app: &App, cmd: &str) -> String {
+        if PythonProvider::get_conda_env_file(app).is_some() {
+            format!("micromamba run -p {} {}", CONDA_ENV_LOCATION, cmd)
+        } else if PythonProvider::is_poetry(app) {
+            format!("poetry run {}", cmd)
+        } else {
+            cmd.to_string()
+        }
+    }
+
+    /// The conda environment file, e.g. `environment.yml`
+    fn get_conda_env_file(app: &App) -> Option<&'static str> {
+        CONDA_ENV_FILES
+            .iter()
+            .find(|file| app.includes_file(file))
+            .copied()
+    }
+
+    fn read_pipfile(app: &App) -> Result<Option<Pipfile>> {
+        if app.includes_file("Pipfile") {
+            return Ok(Some(app.read_toml("Pipfile").context("Reading Pipfile")?));
+        }
         Ok(None)
     }
 
+    /// The command of the `start` script in the Pipfile, or of its only script.
+    /// Pipenv runs scripts with `pipenv run`, but the dependencies are already on the `PATH`
+    fn get_pipfile_start_script(app: &App) -> Result<Option<String>> {
+        let scripts = PythonProvider::read_pipfile(app)?
+            .and_then(|pipfile| pipfile.scripts)
+            .unwrap_or_default();
+
+        let script = match scripts.get("start") {
+            Some(script) => Some(script),
+            None if scripts.len() == 1 => scripts.values().next(),
+            None => None,
+        };
+
+        Ok(script
+            .and_then(|script| script.as_str())
+            .map(ToString::to_string))
+    }
+
+    /// The Python version in the `[requires]` section of the Pipfile
+    fn get_pipfile_python_version(app: &App) -> Result<Option<String>> {
+        Ok(PythonProvider::read_pipfile(app)?
+            .and_then(|pipfile| pipfile.requires)
+            .and_then(|requires| requires.python_version.or(requires.python_full_version)))
+    }
+
+    /// The version allowed by the `python` dependency of Poetry, e.g. `3.11` for `^3.10`
+    fn get_poetry_python_version(app: &App) -> Result<Option<String>> {
+        let requirement = PythonProvider::get_poetry_decl(app)
+            .and_then(|decl| decl.dependencies)
+            .and_then(|dependencies| {
+                dependencies
+                    .get("python")?
+                    .as_str()
+                    .map(ToString::to_string)
+            });
+
+        match requirement {
+            Some(requirement) => {
+                PythonProvider::resolve_python_requirement(&requirement, "pyproject.toml").map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The version allowed by `requires-python` in the `[project]` table, used by PDM and uv
+    fn get_requires_python_version(app: &App) -> Result<Option<String>> {
+        let requirement = PythonProvider::read_pyproject(app)
+            .ok()
+            .flatten()
+            .and_then(|pyproject| pyproject.project?.requires_python);
+
+        match requirement {
+            Some(requirement) => {
+                PythonProvider::resolve_python_requirement(&requirement, "pyproject.toml").map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The available version that a requirement like `>=3.10,<4` or `^3.10` allows.
+    /// The version of the default package is preferred, and otherwise the newest allowed version is used
+    fn resolve_python_requirement(requirement: &str, source: &str) -> Result<String> {
+        let allowed = PYTHON_VERSIONS
+            .iter()
+            .filter(|version| PythonProvider::allows_python_version(requirement, **version))
+            .collect::<Vec<_>>();
+
+        let version = match allowed.first() {
+            Some(version) if **version == PYTHON_VERSIONS[0] => **version,
+            Some(_) => **allowed.iter().max().unwrap(),
+            None => {
+                let mut versions = PYTHON_VERSIONS.to_vec();
+                versions.sort_unstable();
+                bail!(Message::new(MessageId::PythonVersionUnsatisfiable)
+                    .arg("requirement", requirement)
+                    .arg("source", source)
+                    .arg(
+                        "versions",
+                        &versions
+                            .iter()
+                            .map(|(major, minor)| format!("{}.{}", major, minor))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+            }
+        };
+
+        Ok(format!("{}.{}", version.0, version.1))
+    }
+
+    /// Whether any release of a `major.minor` version satisfies a PEP 440 or Poetry requirement.
+    /// Alternatives are separated by `||`, and the specifiers of an alternative all have to match
+    fn allows_python_version(requirement: &str, (major, minor): (u32, u32)) -> bool {
+        let specifier_re =
+            Regex::new(r"(\^|~=|~|===|==|!=|>=|<=|>|<|=)?\s*(\d+)(?:\.(\d+|\*))?(?:\.(\d+|\*))?")
+                .unwrap();
+        let version = (major, minor);
+
+        requirement.split("||").any(|alternative| {
+            specifier_re.captures_iter(alternative).all(|captures| {
+                let operator = captures.get(1).map_or("==", |operator| operator.as_str());
+                let spec_major = captures[2].parse::<u32>().unwrap_or_default();
+                let spec_minor = captures.get(3).and_then(|m| m.as_str().parse::<u32>().ok());
+                let spec_patch = captures.get(4).and_then(|m| m.as_str().parse::<u32>().ok());
+                let spec = (spec_major, spec_minor.unwrap_or_default());
+
+                match (operator, spec_minor) {
+                    (_, None) if ["==", "===", "=", "~"].contains(&operator) => major == spec_major,
+                    ("!=", None) => major != spec_major,
+                    (">", None) => major > spec_major,
+                    ("<", None) => major < spec_major,
+                    ("<=", None) => major <= spec_major,
+                    ("^" | "~=", None) => major == spec_major,
+                    (">=", _) => version >= spec,
+                    (">", _) if spec_patch.is_some() => version >= spec,
+                    (">", _) => version > spec,
+                    ("<=", _) => version <= spec,
+                    ("<", _) if spec_patch.unwrap_or_default() > 0 => version <= spec,
+                    ("<", _) => version < spec,
+                    // A series is only excluded as a whole, e.g. by `!=3.9` or `!=3.9.*`
+                    ("!=", _) => spec_patch.is_some() || version != spec,
+                    ("^", _) => version >= spec && major == spec_major,
+                    ("~=", _) if spec_patch.is_some() => version == spec,
+                    ("~=", _) => version >= spec && major == spec_major,
+                    _ => version == spec,
+                }
+            })
+        })
+    }
+
     fn is_django(app: &App, _env: &Environment) -> Result<bool> {
         let has_manage = app.includes_file("manage.py");
-        let imports_django = vec!["requirements.txt", "pyproject.toml"].iter().any(|f| {
+        let imports_django = vec![
+            "requirements.txt",
+            "pyproject.toml",
+            "Pipfile",
+            "environment.yml",
+            "environment.yaml",
+        ]
+        .iter()
+        .any(|f| {
             app.read_file(f)
                 .unwrap_or_default()
                 .to_lowercase()
@@ -231,6 +674,72 @@ impl PythonProvider {
         app.find_match(&re, "/**/*.py")
     }
 
+    /// The `module:variable` of a FastAPI or Starlette app served with uvicorn,
+    /// e.g. `app.main:app` for `app = FastAPI()` in app/main.py
+    fn get_asgi_app(app: &App) -> Result<Option<String>> {
+        if !PythonProvider::uses_dep(app, "fastapi")? && !PythonProvider::uses_dep(app, "uvicorn")?
+        {
+            return Ok(None);
+        }
+
+        PythonProvider::find_app_instance(app, &["FastAPI", "Starlette"])
+    }
+
+    /// The `module:variable` of the Celery app, e.g. `mysite.celery:app` for `app = Celery("mysite")` in mysite/celery.py
+    fn get_celery_app(app: &App) -> Result<Option<String>> {
+        if !PythonProvider::uses_dep(app, "celery")? {
+            return Ok(None);
+        }
+
+        PythonProvider::find_app_instance(app, &["Celery"])
+    }
+
+    /// The `module:variable` of the first app created with one of the `classes`, e.g. `app = FastAPI()`
+    fn find_app_instance(app: &App, classes: &[&str]) -> Result<Option<String>> {
+        let re = Regex::new(&format!(
+            r"(?m)^(\w+)\s*(?::[^=]+)?=\s*(?:\w+\.)?(?:{})\(",
+            classes.join("|")
+        ))
+        .unwrap();
+
+        // Prefer files closest to the root, e.g. main.py over app/main.py
+        let mut paths = app
+            .find_files("/**/*.py")?
+            .into_iter()
+            .map(|path| app.strip_source_path(&path))
+            .collect::<Result<Vec<_>>>()?;
+        paths.retain(|path| !path.to_string_lossy().contains("site-packages"));
+        paths.sort_by_key(|path| path.components().count());
+
+        for path in paths {
+            let contents = app.read_file(&path.to_string_lossy())?;
+            if let Some(captures) = re.captures(&contents) {
+                let module = path
+                    .with_extension("")
+                    .iter()
+                    .map(|component| component.to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+                    .join(".");
+                let module = module.trim_end_matches(".__init__");
+                return Ok(Some(format!("{}:{}", module, &captures[1])));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The default `DJANGO_SETTINGS_MODULE` that manage.py sets, e.g. `mysite.settings`
+    fn get_django_settings_module(app: &App) -> Result<Option<String>> {
+        let re = Regex::new(r#"DJANGO_SETTINGS_MODULE["']\s*,\s*["']([\w.]+)["']"#).unwrap();
+        let manage = app.read_file("manage.py")?;
+        Ok(re.captures(&manage).map(|captures| captures[1].to_string()))
+    }
+
+    fn has_django_static_root(app: &App) -> Result<bool> {
+        let re = Regex::new(r"(?m)^\s*STATIC_ROOT\s*=").unwrap();
+        app.find_match(&re, "/**/*.py")
+    }
+
     fn get_django_app_name(app: &App, _env: &Environment) -> Result<String> {
         // Look for the settings.py file
         let paths = app.find_files("/**/*.py").unwrap();
@@ -251,7 +760,7 @@ impl PythonProvider {
                 }
             }
         }
-        bail!("Failed to find django application name!")
+        bail!(Message::new(MessageId::DjangoAppNotFound))
     }
 
     fn get_nix_python_package(app: &App, env: &Environment) -> Result<Pkg> {
@@ -266,13 +775,30 @@ impl PythonProvider {
         // Fetch version from configs
         let mut custom_version = env.get_config_variable("PYTHON_VERSION");
 
-        // If not from configs, get it from the .python-version file
+        // If not from configs, get it from the .python-version, .tool-versions, or runtime.txt file
         if custom_version.is_none() && app.includes_file(".python-version") {
             custom_version = Some(app.read_file(".python-version")?);
-        } else if app.includes_file("runtime.txt") {
+        }
+        if custom_version.is_none() {
+            custom_version = ToolVersions::from_app(app)?
+                .get("python")
+                .map(|(version, _)| version);
+        }
+        if custom_version.is_none() && app.includes_file("runtime.txt") {
             custom_version = Some(app.read_file("runtime.txt")?);
         }
 
+        // Otherwise use the Python version required by Pipenv, Poetry, or `requires-python`
+        if custom_version.is_none() {
+            custom_version = PythonProvider::get_pipfile_python_version(app)?;
+        }
+        if custom_version.is_none() {
+            custom_version = PythonProvider::get_poetry_python_version(app)?;
+        }
+        if custom_version.is_none() {
+            custom_version = PythonProvider::get_requires_python_version(app)?;
+        }
+
         // If it's still none, return default
         if custom_version.is_none() {
             return Ok(Pkg::new(DEFAULT_PYTHON_PKG_NAME));
@@ -348,7 +874,7 @@ impl PythonProvider {
     fn parse_pyproject(app: &App) -> Result<ProjectMeta> {
         Ok(PythonProvider::parse_project(
             &(PythonProvider::read_pyproject(app)?
-                .ok_or_else(|| anyhow::anyhow!("failed to load pyproject.toml"))?),
+                .ok_or_else(|| Message::new(MessageId::PyprojectInvalid))?),
         ))
     }
 
@@ -365,7 +891,15 @@ impl PythonProvider {
                 .to_lowercase()
                 .contains(dep);
 
-        Ok(requirements_usage || pyproject_usage)
+        let pipfile_usage =
+            app.includes_file("Pipfile") && app.read_file("Pipfile")?.to_lowercase().contains(dep);
+
+        let conda_usage = match PythonProvider::get_conda_env_file(app) {
+            Some(env_file) => app.read_file(env_file)?.to_lowercase().contains(dep),
+            None => false,
+        };
+
+        Ok(requirements_usage || pyproject_usage || pipfile_usage || conda_usage)
     }
 }
 
@@ -414,6 +948,151 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_poetry_version() -> Result<()> {
+        assert_eq!(
+            PythonProvider::get_nix_python_package(
+                &App::new("./examples/python-poetry")?,
+                &Environment::default()
+            )?,
+            Pkg::new("python311")
+        );
+        assert_eq!(
+            PythonProvider::get_nix_python_package(
+                &App::new("./examples/python-poetry-scripts")?,
+                &Environment::default()
+            )?,
+            Pkg::new("python311")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipenv() -> Result<()> {
+        let app = App::new("./examples/python-pipenv")?;
+        assert_eq!(
+            PythonProvider::get_nix_python_package(&app, &Environment::default())?,
+            Pkg::new("python39")
+        );
+        assert_eq!(
+            PythonProvider::get_pipfile_start_script(&app)?,
+            Some("python main.py".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_django_static() -> Result<()> {
+        let app = App::new("./examples/python-django-static")?;
+        assert!(PythonProvider::has_django_static_root(&app)?);
+        assert_eq!(
+            PythonProvider::get_django_settings_module(&app)?,
+            Some("mysite.settings".to_string())
+        );
+
+        assert!(!PythonProvider::has_django_static_root(&App::new(
+            "./examples/python-django"
+        )?)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_asgi_app_detection() -> Result<()> {
+        assert_eq!(
+            PythonProvider::get_asgi_app(&App::new("./examples/python-fastapi")?)?,
+            Some("app.main:app".to_string())
+        );
+        assert_eq!(
+            PythonProvider::get_asgi_app(&App::new("./examples/python")?)?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_celery_worker() -> Result<()> {
+        let app = App::new("./examples/python-celery")?;
+        assert_eq!(
+            PythonProvider::get_celery_app(&app)?,
+            Some("tasks:app".to_string())
+        );
+
+        let env = Environment::from_envs(vec!["NIXPACKS_PYTHON_PROCESS=worker"])?;
+        assert_eq!(
+            PythonProvider {}.start(&app, &env)?.unwrap().cmd,
+            Some("celery -A tasks:app worker --loglevel=info".to_string())
+        );
+
+        let env = Environment::from_envs(vec!["NIXPACKS_PYTHON_PROCESS=beat"])?;
+        assert!(PythonProvider {}.start(&app, &env).is_err());
+        let env = Environment::from_envs(vec!["NIXPACKS_PYTHON_PROCESS=worker"])?;
+        assert!(PythonProvider {}
+            .start(&App::new("./examples/python")?, &env)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conda_detection() -> Result<()> {
+        assert_eq!(
+            PythonProvider::get_conda_env_file(&App::new("./examples/python-conda")?),
+            Some("environment.yml")
+        );
+        assert_eq!(
+            PythonProvider::get_conda_env_file(&App::new("./examples/python")?),
+            None
+        );
+        assert!(PythonProvider::uses_dep(
+            &App::new("./examples/python-conda")?,
+            "numpy"
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_python_requirement() -> Result<()> {
+        let resolve =
+            |requirement| PythonProvider::resolve_python_requirement(requirement, "pyproject.toml");
+
+        // The default version when it is allowed, and the newest allowed version otherwise
+        assert_eq!(resolve(">=3.7,<4")?, "3.8");
+        assert_eq!(resolve("~=3.8")?, "3.8");
+        assert_eq!(resolve(">=3.11,<4")?, "3.11");
+        assert_eq!(resolve("^3.9")?, "3.11");
+        assert_eq!(resolve(">=3.9,<3.11")?, "3.10");
+        assert_eq!(resolve("~3.9")?, "3.9");
+        assert_eq!(resolve("==3.10.*")?, "3.10");
+        assert_eq!(resolve(">=3.7, !=3.8.*")?, "3.11");
+        assert_eq!(resolve("~2.7 || ^3.10")?, "3.11");
+
+        let err = resolve(">=3.12").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "No available Python version satisfies >=3.12 from pyproject.toml. Available versions: 2.7, 3.7, 3.8, 3.9, 3.10, 3.11"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_poetry_detection() -> Result<()> {
+        let app = App::new("./examples/python-poetry-scripts")?;
+        assert!(PythonProvider::is_poetry(&app));
+        assert_eq!(PythonProvider::get_poetry_scripts(&app), vec!["hello"]);
+
+        assert!(!PythonProvider::is_poetry(&App::new(
+            "./examples/python-setuptools"
+        )?));
+
+        Ok(())
+    }
+
     #[test]
     fn test_version_from_environment_variable() -> Result<()> {
         assert_eq!(
@@ -0,0 +1,208 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    messages::{Message, MessageId},
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::{bail, Result};
+use regex::Regex;
+
+const RACKET_COMPILE_EXE: &str = "RACKET_COMPILE_EXE";
+const RACKET_MAIN: &str = "RACKET_MAIN";
+const RACKET_MAIN_FILE: &str = "main.rkt";
+const RACKET_INFO_FILE: &str = "info.rkt";
+const RACKET_EXE_NAME: &str = "main";
+
+pub struct RacketProvider {}
+
+impl Provider for RacketProvider {
+    fn name(&self) -> &str {
+        "racket"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file(RACKET_INFO_FILE) || app.includes_file(RACKET_MAIN_FILE))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let main_file = RacketProvider::get_main_file(app, env)?;
+
+        let setup = Phase::setup(Some(vec![Pkg::new("racket")]));
+
+        let mut install = Phase::install(None);
+        if app.includes_file(RACKET_INFO_FILE) {
+            install.add_cmd("raco pkg install --auto --batch");
+        }
+
+        let compile_exe = env.is_config_variable_truthy(RACKET_COMPILE_EXE);
+
+        let mut build = Phase::build(None);
+        if compile_exe {
+            build.add_cmd(format!("raco exe -o {} {}", RACKET_EXE_NAME, main_file));
+        }
+
+        let start = if compile_exe {
+            StartPhase::new(format!("./{}", RACKET_EXE_NAME))
+        } else {
+            StartPhase::new(format!("racket {}", main_file))
+        };
+
+        let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
+        Ok(Some(plan))
+    }
+}
+
+impl RacketProvider {
+    /// The file to start, from `NIXPACKS_RACKET_MAIN`, the first of the `racket-launcher-libraries` in `info.rkt`,
+    /// `main.rkt`, or otherwise the only `#lang` module at the root of the app
+    fn get_main_file(app: &App, env: &Environment) -> Result<String> {
+        let main_file = env
+            .get_config_variable(RACKET_MAIN)
+            .or(RacketProvider::get_launcher_library(app)?);
+        if let Some(main_file) = main_file {
+            if !app.includes_file(&main_file) {
+                bail!(Message::new(MessageId::RacketMainNotFound).arg("file", &main_file));
+            }
+
+            return Ok(main_file);
+        }
+
+        if app.includes_file(RACKET_MAIN_FILE) {
+            return Ok(RACKET_MAIN_FILE.to_string());
+        }
+
+        let mut modules = app
+            .find_files("*.rkt")?
+            .iter()
+            .filter_map(|path| app.strip_source_path(path).ok())
+            .map(|path| path.to_string_lossy().to_string())
+            .filter(|file| file != RACKET_INFO_FILE && RacketProvider::is_module(app, file))
+            .collect::<Vec<_>>();
+        modules.sort();
+
+        match modules.len() {
+            0 => bail!(Message::new(MessageId::RacketMainNotFound).arg("file", RACKET_MAIN_FILE)),
+            1 => Ok(modules.remove(0)),
+            _ => {
+                bail!(Message::new(MessageId::RacketMainAmbiguous).arg("files", &modules.join(", ")))
+            }
+        }
+    }
+
+    /// The first library `raco setup` creates a launcher for, e.g. `(define racket-launcher-libraries '("server.rkt"))`
+    fn get_launcher_library(app: &App) -> Result<Option<String>> {
+        if !app.includes_file(RACKET_INFO_FILE) {
+            return Ok(None);
+        }
+
+        let info = app.read_file(RACKET_INFO_FILE)?;
+        let library = Regex::new(r#"racket-launcher-libraries\s+'\(\s*"([^"]+)""#)?
+            .captures(&info)
+            .map(|captures| captures[1].to_string());
+
+        Ok(library)
+    }
+
+    /// Whether the file is a module that can be started, i.e. starts with a `#lang` other than `info`
+    fn is_module(app: &App, file: &str) -> bool {
+        let contents = app.read_file(file).unwrap_or_default();
+        let lang = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with(';'));
+
+        matches!(lang, Some(lang) if lang.starts_with("#lang") && lang != "#lang info")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn create_app(files: &[(&str, &str)]) -> Result<(TempDir, App)> {
+        let dir = TempDir::new("nixpacks-racket")?;
+        for (name, contents) in files {
+            fs::write(dir.path().join(name), contents)?;
+        }
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok((dir, app))
+    }
+
+    #[test]
+    fn test_main_file() -> Result<()> {
+        let env = Environment::default();
+        assert_eq!(
+            RacketProvider::get_main_file(&App::new("./examples/racket")?, &env)?,
+            "main.rkt"
+        );
+
+        let (_dir, app) = create_app(&[
+            (
+                "info.rkt",
+                "#lang info\n(define racket-launcher-libraries '(\"server.rkt\"))",
+            ),
+            ("server.rkt", "#lang racket"),
+            ("util.rkt", "#lang racket"),
+        ])?;
+        assert_eq!(RacketProvider::get_main_file(&app, &env)?, "server.rkt");
+        assert_eq!(
+            RacketProvider::get_main_file(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_RACKET_MAIN=util.rkt"])?
+            )?,
+            "util.rkt"
+        );
+
+        let (_dir, app) = create_app(&[
+            ("info.rkt", "#lang info"),
+            ("app.rkt", ";; The app\n#lang racket/base"),
+            ("data.rktd", "()"),
+        ])?;
+        assert_eq!(RacketProvider::get_main_file(&app, &env)?, "app.rkt");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_main_file_not_found() -> Result<()> {
+        let env = Environment::default();
+
+        let (_dir, app) = create_app(&[("info.rkt", "#lang info")])?;
+        let err = RacketProvider::get_main_file(&app, &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Message>().map(|message| message.id),
+            Some(MessageId::RacketMainNotFound)
+        );
+
+        let (_dir, app) = create_app(&[
+            ("info.rkt", "#lang info"),
+            ("server.rkt", "#lang racket"),
+            ("worker.rkt", "#lang racket"),
+        ])?;
+        let err = RacketProvider::get_main_file(&app, &env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Found multiple Racket files: server.rkt, worker.rkt. Set NIXPACKS_RACKET_MAIN to the one to start"
+        );
+
+        let err = RacketProvider::get_main_file(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_RACKET_MAIN=cli.rkt"])?,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("Racket file cli.rkt not found"));
+
+        Ok(())
+    }
+}
@@ -1,12 +1,14 @@
-use super::{node::NodeProvider, Provider};
+use super::{node::NodeProvider, tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
+    scripts::INSTALL_RUBY,
 };
 use anyhow::{bail, Ok, Result};
 use regex::Regex;
@@ -14,6 +16,17 @@ use regex::Regex;
 pub struct RubyProvider {}
 
 const BUNDLE_CACHE_DIR: &str = "/root/.bundle/cache";
+const RUBY_PROCESSES: &[&str] = &["web", "worker"];
+// Only the asset cache is mounted, since the bootsnap cache next to it has to end up in the image
+const RAILS_ASSETS_CACHE_DIR: &str = "/app/tmp/cache/assets";
+
+/// Ruby versions that can be installed, with their latest patch release
+const RUBY_VERSIONS: &[(&str, &str)] = &[
+    ("2.7", "2.7.7"),
+    ("3.0", "3.0.5"),
+    ("3.1", "3.1.3"),
+    ("3.2", "3.2.0"),
+];
 
 impl Provider for RubyProvider {
     fn name(&self) -> &str {
@@ -28,7 +41,7 @@ impl Provider for RubyProvider {
         let setup = self.get_setup(app, env)?;
         let install = self.get_install(app)?;
         let build = self.get_build(app)?;
-        let start = self.get_start(app)?;
+        let start = self.get_start(app, env)?;
 
         let mut plan = BuildPlan::new(
             &vec![setup, install, build]
@@ -49,6 +62,7 @@ impl Provider for RubyProvider {
             }
         }
 
+        plan.add_script(&INSTALL_RUBY);
         plan.add_variables(self.get_environment_variables(app)?);
 
         Ok(Some(plan))
@@ -80,19 +94,13 @@ impl RubyProvider {
             setup.add_apt_pkgs(vec![String::from("libicu-dev")]);
         }
 
-        setup.add_cmd(format!(
-            "curl -sSL https://get.rvm.io | bash -s stable \
-            && . /etc/profile.d/rvm.sh \
-            && rvm install {ruby_version} \
-            && rvm --default use {ruby_version} \
-            && gem install {bundler_version} \
-            && rm -rf /usr/local/rvm/src",
-            ruby_version = self.get_ruby_version(app)?,
-            bundler_version = self.get_bundler_version(app)
-        ));
-
         setup.add_cmd("echo 'source /usr/local/rvm/scripts/rvm' >> /root/.profile".to_string());
 
+        setup.add_cmd(
+            INSTALL_RUBY.get_cmd(&[&self.get_ruby_version(app)?, &self.get_bundler_version(app)]),
+        );
+        setup.add_cleanup_cmd("rm -rf /usr/local/rvm/src");
+
         Ok(Some(setup))
     }
 
@@ -119,13 +127,27 @@ impl RubyProvider {
     fn get_build(&self, app: &App) -> Result<Option<Phase>> {
         let mut build = Phase::build(None);
         if self.is_rails_app(app) {
-            build.add_cmd("bundle exec rake assets:precompile".to_string());
+            // Precompiling in production needs a secret key, even though assets don't use it
+            build.add_cmd(
+                "SECRET_KEY_BASE=${SECRET_KEY_BASE:-assets-precompile} bundle exec rails assets:precompile"
+                    .to_string(),
+            );
+            build.add_cache_directory(RAILS_ASSETS_CACHE_DIR.to_string());
+
+            // Compile the app and gems ahead of time so the server boots faster
+            if self.uses_gem_dep(app, "bootsnap") {
+                build.add_cmd("bundle exec bootsnap precompile --gemfile app/ lib/".to_string());
+            }
         }
 
         Ok(Some(build))
     }
 
-    fn get_start(&self, app: &App) -> Result<Option<StartPhase>> {
+    fn get_start(&self, app: &App, env: &Environment) -> Result<Option<StartPhase>> {
+        if self.get_process(env)? == "worker" {
+            return Ok(Some(StartPhase::new(self.get_sidekiq_command(app)?)));
+        }
+
         if let Some(start_cmd) = self.get_start_command(app) {
             Ok(Some(StartPhase::new(start_cmd)))
         } else {
@@ -152,6 +174,7 @@ impl RubyProvider {
         ]);
 
         if self.is_rails_app(app) {
+            env_vars.insert("RAILS_ENV".to_string(), "production".to_string());
             env_vars.insert("RAILS_LOG_TO_STDOUT".to_string(), "enabled".to_string());
             env_vars.insert("RAILS_SERVE_STATIC_FILES".to_string(), "1".to_string());
         }
@@ -159,6 +182,45 @@ impl RubyProvider {
         Ok(env_vars)
     }
 
+    /// The process that the image starts, `web` by default or a Sidekiq `worker`
+    fn get_process(&self, env: &Environment) -> Result<String> {
+        let process = env
+            .get_config_variable("RUBY_PROCESS")
+            .unwrap_or_else(|| "web".to_string());
+        if !RUBY_PROCESSES.contains(&process.as_str()) {
+            bail!(Message::new(MessageId::UnknownProcess)
+                .arg("process", &process)
+                .arg("variable", "NIXPACKS_RUBY_PROCESS"));
+        }
+
+        Ok(process)
+    }
+
+    /// Rails apps are loaded by Sidekiq itself, other apps have to require the file that defines the jobs
+    fn get_sidekiq_command(&self, app: &App) -> Result<String> {
+        if !self.uses_gem_dep(app, "sidekiq") {
+            bail!(Message::new(MessageId::SidekiqNotFound));
+        }
+
+        let mut cmd = "bundle exec sidekiq".to_string();
+        if !self.is_rails_app(app) {
+            let re = Regex::new(r"Sidekiq::(Worker|Job)\b").unwrap();
+            for path in app.find_files("/**/*.rb")? {
+                let path = app.strip_source_path(&path)?;
+                let path = path.to_string_lossy();
+                if re.is_match(&app.read_file(&path)?) {
+                    cmd.push_str(&format!(" -r ./{}", path));
+                    break;
+                }
+            }
+        }
+        if app.includes_file("config/sidekiq.yml") {
+            cmd.push_str(" -C config/sidekiq.yml");
+        }
+
+        Ok(cmd)
+    }
+
     fn get_start_command(&self, app: &App) -> Option<String> {
         if self.is_rails_app(app) {
             if app.includes_file("rails") {
@@ -180,22 +242,47 @@ impl RubyProvider {
         }
     }
 
+    /// The RVM name of the Ruby version to install, e.g. `ruby-3.1.2`, from the first of
+    /// `.ruby-version`, `.tool-versions`, the `ruby` directive in the Gemfile, and Gemfile.lock
     fn get_ruby_version(&self, app: &App) -> Result<String> {
+        let lock_version = self.get_gemfile_lock_ruby_version(app);
+
+        // `.ruby-version` is written for version managers like RVM, so exact versions like `jruby-9.4.0.0` are passed through
         if app.includes_file(".ruby-version") {
-            return Ok(app.read_file(".ruby-version")?.trim().to_string());
+            let version = app.read_file(".ruby-version")?;
+            let version = version.trim();
+            if !is_ruby_requirement(version) {
+                return Ok(version.to_string());
+            }
+
+            return resolve_ruby_version(version, ".ruby-version", lock_version.as_deref());
         }
-        let re_gemfile = Regex::new(r#"ruby (?:'|")(.*)(?:'|")[^>]"#).unwrap();
+
+        if let Some((version, source)) = ToolVersions::from_app(app)?.get("ruby") {
+            return resolve_ruby_version(&version, source, lock_version.as_deref());
+        }
+
+        let re_gemfile = Regex::new(r#"(?m)^\s*ruby\s*\(?\s*(?:'|")([^'"]+)(?:'|")"#).unwrap();
         let gemfile = app.read_file("Gemfile").unwrap_or_default();
         if let Some(value) = re_gemfile.captures(&gemfile) {
-            return Ok(format!("ruby-{}", value.get(1).unwrap().as_str()));
+            return resolve_ruby_version(&value[1], "Gemfile", lock_version.as_deref());
         }
+
+        if let Some(lock_version) = lock_version {
+            return resolve_ruby_version(&lock_version, "Gemfile.lock", None);
+        }
+
+        bail!(Message::new(MessageId::RubyVersionNotFound))
+    }
+
+    /// The Ruby version under `RUBY VERSION` in Gemfile.lock, e.g. `3.1.2` for `ruby 3.1.2p20`
+    fn get_gemfile_lock_ruby_version(&self, app: &App) -> Option<String> {
         let re_gemfile_lock =
             Regex::new(r#"ruby ((?:0|[1-9]\d*)\.(?:0|[1-9]\d*)\.(?:0|[1-9]\d*))[^>]"#).unwrap();
         let gemfile_lock = app.read_file("Gemfile.lock").unwrap_or_default();
-        if let Some(value) = re_gemfile_lock.captures(&gemfile_lock) {
-            return Ok(format!("ruby-{}", value.get(1).unwrap().as_str()));
-        }
-        bail!("Please specify ruby's version in .ruby-version file")
+        re_gemfile_lock
+            .captures(&gemfile_lock)
+            .map(|value| value[1].to_string())
     }
 
     // Loop through Gemfile.lock and find bundler's version (Line below BUNDLED WITH)
@@ -244,6 +331,53 @@ impl RubyProvider {
     }
 }
 
+/// Resolve a version or requirement from `source` to the RVM name of a supported Ruby, e.g. `ruby-3.1.3` for `~> 3.1`.
+/// Requirements use the version in Gemfile.lock when it matches, and the latest patch release otherwise
+fn resolve_ruby_version(
+    requirement: &str,
+    source: &str,
+    lock_version: Option<&str>,
+) -> Result<String> {
+    let re_version = Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
+    let unsupported = || {
+        Message::new(MessageId::RubyVersionUnsupported)
+            .arg("version", requirement)
+            .arg("source", source)
+            .arg(
+                "versions",
+                &RUBY_VERSIONS
+                    .iter()
+                    .map(|(minor, _)| *minor)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+    };
+
+    let captures = re_version.captures(requirement).ok_or_else(unsupported)?;
+    let minor = format!("{}.{}", &captures[1], &captures[2]);
+    let latest = RUBY_VERSIONS
+        .iter()
+        .find(|(supported, _)| *supported == minor)
+        .map(|(_, latest)| *latest)
+        .ok_or_else(unsupported)?;
+
+    // Exact versions, e.g. `3.1.2` or `ruby-3.1.2`, are installed as they are
+    let version = match captures.get(3) {
+        Some(_) if !is_ruby_requirement(requirement) => captures[0].to_string(),
+        _ => lock_version
+            .filter(|lock_version| lock_version.starts_with(&format!("{}.", minor)))
+            .unwrap_or(latest)
+            .to_string(),
+    };
+
+    Ok(format!("ruby-{}", version))
+}
+
+/// Whether a version is a requirement like `~> 3.1.0` or `3.1.x` rather than an exact version
+fn is_ruby_requirement(version: &str) -> bool {
+    version.contains(|c| "~><=x*".contains(c))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -280,4 +414,85 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_rails_build() -> Result<()> {
+        let app = App::new("./examples/ruby-rails-postgres")?;
+        let build = RubyProvider {}.get_build(&app)?.unwrap();
+        let cmds = build.cmds.unwrap();
+
+        assert!(cmds[0].ends_with("bundle exec rails assets:precompile"));
+        assert!(cmds[1].starts_with("bundle exec bootsnap precompile"));
+        assert_eq!(
+            build.cache_directories,
+            Some(vec![RAILS_ASSETS_CACHE_DIR.to_string()])
+        );
+        assert_eq!(
+            RubyProvider {}.get_environment_variables(&app)?["RAILS_ENV"],
+            "production"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sidekiq_worker() -> Result<()> {
+        let app = App::new("./examples/ruby-sidekiq")?;
+        let env = Environment::from_envs(vec!["NIXPACKS_RUBY_PROCESS=worker"])?;
+        assert_eq!(
+            RubyProvider {}.get_start(&app, &env)?.unwrap().cmd,
+            Some("bundle exec sidekiq -r ./worker.rb".to_string())
+        );
+        assert_eq!(
+            RubyProvider {}
+                .get_start(&app, &Environment::default())?
+                .unwrap()
+                .cmd,
+            Some("bundle exec rackup config.ru -p ${PORT:-3000}".to_string())
+        );
+
+        assert!(RubyProvider {}
+            .get_start(&App::new("./examples/ruby")?, &env)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_versions() -> Result<()> {
+        assert_eq!(
+            RubyProvider::get_ruby_version(
+                &RubyProvider {},
+                &App::new("./examples/ruby-tool-versions")?
+            )?,
+            "ruby-3.2.0"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_ruby_version() -> Result<()> {
+        assert_eq!(
+            resolve_ruby_version("ruby-3.1.2", ".ruby-version", None)?,
+            "ruby-3.1.2"
+        );
+        assert_eq!(
+            resolve_ruby_version("~> 3.1.0", "Gemfile", Some("3.1.2"))?,
+            "ruby-3.1.2"
+        );
+        assert_eq!(
+            resolve_ruby_version("3.1.x", "Gemfile", Some("3.0.4"))?,
+            "ruby-3.1.3"
+        );
+        assert_eq!(resolve_ruby_version("3.0", "Gemfile", None)?, "ruby-3.0.5");
+
+        let err = resolve_ruby_version("2.5.1", ".ruby-version", None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Ruby 2.5.1 from .ruby-version is not supported"));
+        assert!(err.contains("2.7, 3.0, 3.1, 3.2"));
+
+        Ok(())
+    }
 }
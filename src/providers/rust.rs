@@ -1,18 +1,22 @@
 use std::env::consts::ARCH;
 use std::fmt::Write as _;
+use std::path::{Component, Path, PathBuf};
 
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::{Environment, EnvironmentVariables},
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
+        resources::BuildResources,
         BuildPlan,
     },
 };
-use anyhow::{Context, Result};
-use cargo_toml::{Manifest, Workspace};
+use anyhow::{bail, Context, Result};
+use cargo_toml::{Manifest, OptionalFile};
+use regex::Regex;
 
 const RUST_OVERLAY: &str = "https://github.com/oxalica/rust-overlay/archive/master.tar.gz";
 const DEFAULT_RUST_PACKAGE: &str = "rust-bin.stable.latest.default";
@@ -20,6 +24,7 @@ const DEFAULT_RUST_PACKAGE: &str = "rust-bin.stable.latest.default";
 const CARGO_GIT_CACHE_DIR: &str = "/root/.cargo/git";
 const CARGO_REGISTRY_CACHE_DIR: &str = "/root/.cargo/registry";
 const CARGO_TARGET_CACHE_DIR: &str = "target";
+const RUST_MIN_MEMORY_MB: u64 = 2048;
 
 pub struct RustProvider {}
 
@@ -33,16 +38,31 @@ impl Provider for RustProvider {
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        if RustProvider::is_static(env) {
+            if let Some(reason) = RustProvider::get_musl_blocker(app, env)? {
+                bail!(Message::new(MessageId::RustStaticBuildUnavailable).arg("reason", &reason));
+            }
+        }
+
         let setup = RustProvider::get_setup(app, env)?;
-        let build = RustProvider::get_build(app, env)?;
+        let install = RustProvider::get_install(app, env)?;
+        let build = RustProvider::get_build(app, env, install.is_some())?;
         let start = RustProvider::get_start(app, env)?;
 
-        let mut plan = BuildPlan::new(&vec![setup, build], start);
-        plan.add_variables(EnvironmentVariables::from([(
+        let phases = match install {
+            Some(install) => vec![setup, install, build],
+            None => vec![setup, build],
+        };
+
+        let mut plan = BuildPlan::new(&phases, start);
+        plan.add_runtime_variables(EnvironmentVariables::from([(
             "ROCKET_ADDRESS".to_string(),
             "0.0.0.0".to_string(),
         )]));
 
+        // Linking release builds regularly needs more memory than small runners have
+        plan.add_resources(BuildResources::new(Some(RUST_MIN_MEMORY_MB), None));
+
         Ok(Some(plan))
     }
 }
@@ -78,52 +98,119 @@ impl RustProvider {
         Ok(setup)
     }
 
-    fn get_build(app: &App, env: &Environment) -> Result<Phase> {
+    /// Compile the dependencies before the source of the app is copied.
+    ///
+    /// The sources are replaced with stubs, so the layer is only rebuilt when `Cargo.lock` or a manifest changes.
+    /// The stub artifacts are cleaned, so that Cargo builds the real sources in the build phase.
+    /// Apps that need more than their manifests to compile the dependencies are built in the build phase only.
+    fn get_install(app: &App, env: &Environment) -> Result<Option<Phase>> {
+        if !app.includes_file("Cargo.lock") {
+            return Ok(None);
+        }
+
+        let binary = match RustProvider::get_binary(app, env)? {
+            Some(binary) => binary,
+            None => return Ok(None),
+        };
+
+        let packages = RustProvider::get_packages(app)?;
+        if !RustProvider::has_plain_layout(app, &packages)? {
+            return Ok(None);
+        }
+
+        let mut install = Phase::install(None);
+        install.add_file_dependency("Cargo.toml");
+        install.add_file_dependency("Cargo.lock");
+        for config_file in [".cargo/config.toml", ".cargo/config"] {
+            if app.includes_file(config_file) {
+                install.add_file_dependency(config_file);
+            }
+        }
+
+        for (dir, manifest) in &packages {
+            if dir != "." {
+                install.add_file_dependency(format!("{}/Cargo.toml", dir));
+            }
+
+            for (path, contents) in RustProvider::get_stub_files(manifest) {
+                let path = if dir == "." {
+                    PathBuf::from(path)
+                } else {
+                    Path::new(dir).join(path)
+                };
+
+                let write_cmd = format!(
+                    "echo {} > {}",
+                    shell_words::quote(contents),
+                    shell_words::quote(&path.to_string_lossy())
+                );
+                match path
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                {
+                    Some(parent) => install.add_cmd(format!(
+                        "mkdir -p {} && {}",
+                        shell_words::quote(&parent.to_string_lossy()),
+                        write_cmd
+                    )),
+                    None => install.add_cmd(write_cmd),
+                }
+            }
+        }
+
+        install.add_cmd(RustProvider::get_build_cmd(app, env, &binary)?);
+
+        let mut clean_cmd = "cargo clean --release".to_string();
+        if let Some(target) = RustProvider::get_target(app, env)? {
+            write!(clean_cmd, " --target {}", target)?;
+        }
+        for (_, manifest) in &packages {
+            if let Some(package) = &manifest.package {
+                write!(clean_cmd, " --package {}", package.name)?;
+            }
+        }
+        install.add_cmd(clean_cmd);
+
+        install.add_cache_directory(CARGO_GIT_CACHE_DIR.to_string());
+        install.add_cache_directory(CARGO_REGISTRY_CACHE_DIR.to_string());
+
+        Ok(Some(install))
+    }
+
+    fn get_build(app: &App, env: &Environment, has_install: bool) -> Result<Phase> {
         let mut build = Phase::build(None);
         if !app.includes_file("Cargo.toml") {
             return Ok(build);
         }
 
         build.add_cmd("mkdir -p bin");
-        build.depends_on = Some(vec!["setup".to_string()]);
-
-        let mut build_cmd = "cargo build --release".to_string();
+        if !has_install {
+            build.depends_on = Some(vec!["setup".to_string()]);
+        }
 
-        if let Some(target) = RustProvider::get_target(app, env)? {
-            if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-                write!(build_cmd, " --package {} --target {}", workspace, target)?;
+        if let Some(binary) = RustProvider::get_binary(app, env)? {
+            build.add_cmd(RustProvider::get_build_cmd(app, env, &binary)?);
 
-                build.add_cmd(build_cmd);
+            if let Some(target) = RustProvider::get_target(app, env)? {
                 build.add_cmd(format!(
                     "cp target/{}/release/{name} bin",
                     target,
-                    name = workspace
+                    name = binary.name
                 ));
             } else {
-                write!(build_cmd, " --target {}", target)?;
-
-                if let Some(name) = RustProvider::get_app_name(app)? {
-                    build.add_cmd(build_cmd);
-                    build.add_cmd(format!(
-                        "cp target/{}/release/{name} bin",
-                        target,
-                        name = name
-                    ));
-                }
+                build.add_cmd(format!("cp target/release/{name} bin", name = binary.name));
+            }
+
+            if RustProvider::is_static(env) {
+                build.add_cmd(format!("strip bin/{}", binary.name));
             }
-        } else if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-            write!(build_cmd, " --package {}", workspace)?;
-            build.add_cmd(build_cmd);
-            build.add_cmd(format!("cp target/release/{name} bin", name = workspace));
-        } else if let Some(name) = RustProvider::get_app_name(app)? {
-            build.add_cmd(build_cmd);
-            build.add_cmd(format!("cp target/release/{name} bin", name = name));
         }
 
         build.add_cache_directory(CARGO_GIT_CACHE_DIR.to_string());
         build.add_cache_directory(CARGO_REGISTRY_CACHE_DIR.to_string());
 
-        if RustProvider::get_app_name(app)?.is_some() {
+        // The compiled dependencies are in the install layer, which a cache mount would hide
+        if RustProvider::get_app_name(app)?.is_some() && !has_install {
             // Cache target directory
             build.add_cache_directory(CARGO_TARGET_CACHE_DIR.to_string());
         }
@@ -131,29 +218,42 @@ impl RustProvider {
         Ok(build)
     }
 
-    fn get_start(app: &App, env: &Environment) -> Result<Option<StartPhase>> {
-        if (RustProvider::get_target(app, env)?).is_some() {
-            if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-                let mut start = StartPhase::new(format!("./{}", workspace));
-                start.run_in_slim_image();
-                start.add_file_dependency(format!("./bin/{}", workspace));
+    fn get_build_cmd(app: &App, env: &Environment, binary: &CargoBinary) -> Result<String> {
+        let mut build_cmd = "cargo build --release".to_string();
 
-                Ok(Some(start))
-            } else if let Some(name) = RustProvider::get_app_name(app)? {
-                let mut start = StartPhase::new(format!("./{}", name));
-                start.run_in_slim_image();
-                start.add_file_dependency(format!("./bin/{}", name));
+        if binary.in_workspace {
+            write!(build_cmd, " --package {}", binary.package)?;
+        }
+
+        if binary.name != binary.package {
+            write!(build_cmd, " --bin {}", binary.name)?;
+        }
+
+        if let Some(target) = RustProvider::get_target(app, env)? {
+            write!(build_cmd, " --target {}", target)?;
+        }
+
+        Ok(build_cmd)
+    }
+
+    fn get_start(app: &App, env: &Environment) -> Result<Option<StartPhase>> {
+        let binary = match RustProvider::get_binary(app, env)? {
+            Some(binary) => binary,
+            None => return Ok(None),
+        };
 
-                Ok(Some(start))
+        if (RustProvider::get_target(app, env)?).is_some() {
+            let mut start = StartPhase::new(format!("./{}", binary.name));
+            if RustProvider::is_static(env) {
+                start.run_in_scratch_image();
             } else {
-                Ok(None)
+                start.run_in_slim_image();
             }
-        } else if let Some(workspace) = RustProvider::resolve_cargo_workspace(app, env)? {
-            Ok(Some(StartPhase::new(format!("./bin/{}", workspace))))
-        } else if let Some(name) = RustProvider::get_app_name(app)? {
-            Ok(Some(StartPhase::new(format!("./bin/{}", name))))
+            start.add_file_dependency(format!("./bin/{}", binary.name));
+
+            Ok(Some(start))
         } else {
-            Ok(None)
+            Ok(Some(StartPhase::new(format!("./bin/{}", binary.name))))
         }
     }
 
@@ -186,6 +286,25 @@ impl RustProvider {
         Ok(None)
     }
 
+    /// The package of a pinned version, which can also be a channel like `stable` or `nightly-2023-01-01`
+    fn version_to_pkg(version: &str) -> Pkg {
+        let date_re = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+        let version_re = Regex::new(r"^\d+\.\d+$").unwrap();
+
+        let release = match version {
+            "stable" | "beta" | "nightly" => format!("{}.latest", version),
+            _ => match version.split_once('-') {
+                Some((channel @ ("beta" | "nightly"), date)) if date_re.is_match(date) => {
+                    format!("{}.\"{}\"", channel, date)
+                }
+                _ if version_re.is_match(version) => format!("stable.\"{}.0\"", version),
+                _ => format!("stable.\"{}\"", version),
+            },
+        };
+
+        Pkg::new(&format!("rust-bin.{}.default", release))
+    }
+
     fn get_rust_toolchain_file(app: &App) -> Option<String> {
         if app.includes_file("rust-toolchain") {
             Some("rust-toolchain".to_string())
@@ -199,10 +318,7 @@ impl RustProvider {
     // Get the rust package version by parsing the `rust-version` field in `Cargo.toml`
     fn get_rust_pkg(app: &App, env: &Environment) -> Result<Pkg> {
         if let Some(version) = env.get_config_variable("RUST_VERSION") {
-            return Ok(Pkg::new(&format!(
-                "rust-bin.stable.\"{}\".default",
-                version
-            )));
+            return Ok(RustProvider::version_to_pkg(&version));
         }
 
         if let Some(toolchain_file) = RustProvider::get_rust_toolchain_file(app) {
@@ -212,6 +328,10 @@ impl RustProvider {
             )));
         }
 
+        if let Some((version, _)) = ToolVersions::from_app(app)?.get("rust") {
+            return Ok(RustProvider::version_to_pkg(&version));
+        }
+
         let pkg = match RustProvider::parse_cargo_toml(app)? {
             Some(toml_file) => toml_file.package.map_or_else(
                 || Pkg::new(DEFAULT_RUST_PACKAGE),
@@ -231,20 +351,30 @@ impl RustProvider {
     }
 
     fn should_use_musl(app: &App, env: &Environment) -> Result<bool> {
+        Ok(RustProvider::get_musl_blocker(app, env)?.is_none())
+    }
+
+    /// Why the app can not be built for the musl target, if it can not
+    fn get_musl_blocker(app: &App, env: &Environment) -> Result<Option<&'static str>> {
         if env.is_config_variable_truthy("NO_MUSL") {
-            return Ok(false);
+            return Ok(Some("NIXPACKS_NO_MUSL is set"));
         }
 
         if RustProvider::get_rust_toolchain_file(app).is_some() {
-            return Ok(false);
+            return Ok(Some("the app has a rust-toolchain file"));
         }
 
         // Do not build for the musl target if using openssl
         if RustProvider::uses_openssl(app)? {
-            return Ok(false);
+            return Ok(Some("the app uses openssl"));
         }
 
-        Ok(true)
+        Ok(None)
+    }
+
+    /// A stripped musl binary that runs in the empty scratch image
+    fn is_static(env: &Environment) -> bool {
+        env.is_config_variable_truthy("RUST_STATIC")
     }
 
     fn uses_openssl(app: &App) -> Result<bool> {
@@ -266,79 +396,289 @@ impl RustProvider {
         Ok(false)
     }
 
-    fn resolve_cargo_workspace(app: &App, env: &Environment) -> Result<Option<String>> {
-        if let Some(name) = env.get_config_variable("CARGO_WORKSPACE") {
-            return Ok(Some(name));
+    /// The binary to build and start.
+    ///
+    /// `NIXPACKS_RUST_BIN` selects a binary from any package of the workspace, and
+    /// `NIXPACKS_CARGO_WORKSPACE` limits the binaries to one package.
+    /// Without them, the app must have a single binary, or set `default-run`.
+    fn get_binary(app: &App, env: &Environment) -> Result<Option<CargoBinary>> {
+        let manifest = match RustProvider::parse_cargo_toml(app)? {
+            Some(manifest) => manifest,
+            None => return Ok(None),
+        };
+
+        let selected_bin = env.get_config_variable("RUST_BIN");
+        let selected_package = env.get_config_variable("CARGO_WORKSPACE");
+
+        let mut binaries = Vec::new();
+        if manifest.package.is_some() {
+            binaries.extend(RustProvider::get_package_binaries(
+                app,
+                "Cargo.toml",
+                manifest.workspace.is_some(),
+            )?);
         }
 
-        if let Some(workspace) =
-            RustProvider::parse_cargo_toml(app)?.and_then(|manifest| manifest.workspace)
-        {
-            if let Some(binary) = RustProvider::find_binary_in_workspace(app, &workspace)? {
-                return Ok(Some(binary));
+        if let Some(workspace) = &manifest.workspace {
+            // Cargo only builds the default members, unless a package is selected
+            let members = if selected_bin.is_none()
+                && selected_package.is_none()
+                && !workspace.default_members.is_empty()
+            {
+                &workspace.default_members
+            } else {
+                &workspace.members
+            };
+
+            for dir in RustProvider::get_member_dirs(app, members, &workspace.exclude)? {
+                binaries.extend(RustProvider::get_package_binaries(
+                    app,
+                    &format!("{}/Cargo.toml", dir),
+                    true,
+                )?);
             }
         }
 
-        Ok(None)
-    }
+        if let Some(package) = selected_package {
+            binaries.retain(|binary| binary.package == package);
+
+            // The package might not be declared in a way we can find, so trust the variable
+            if binaries.is_empty() && selected_bin.is_none() {
+                return Ok(Some(CargoBinary {
+                    name: package.clone(),
+                    package,
+                    in_workspace: true,
+                }));
+            }
+        }
+
+        if let Some(name) = selected_bin {
+            return match binaries.iter().position(|binary| binary.name == name) {
+                Some(index) => Ok(Some(binaries.swap_remove(index))),
+                None => bail!(Message::new(MessageId::RustBinaryNotFound)
+                    .arg("name", &name)
+                    .arg("binaries", &CargoBinary::list(&binaries))),
+            };
+        }
 
-    fn find_binary_in_workspace(app: &App, workspace: &Workspace) -> Result<Option<String>> {
-        let find_binary = |member: &str| -> Result<Option<String>> {
-            let mut manifest = app.read_toml::<Manifest>(&format!("{}/Cargo.toml", member))?;
+        if binaries.len() > 1 {
+            let default_run = manifest
+                .package
+                .and_then(|package| package.default_run)
+                .and_then(|name| binaries.iter().position(|binary| binary.name == name));
+
+            return match default_run {
+                Some(index) => Ok(Some(binaries.swap_remove(index))),
+                None => bail!(Message::new(MessageId::RustBinaryAmbiguous)
+                    .arg("binaries", &CargoBinary::list(&binaries))),
+            };
+        }
 
-            manifest.complete_from_path(&app.source.join(format!("{}/Cargo.toml", member)))?;
+        Ok(binaries.pop())
+    }
 
-            if let Some(package) = manifest.package {
-                if !manifest.bin.is_empty() || manifest.lib.is_none() {
-                    return Ok(Some(package.name));
+    /// Directories of the workspace members, relative to the app
+    fn get_member_dirs(app: &App, members: &[String], exclude: &[String]) -> Result<Vec<String>> {
+        let mut dirs = Vec::new();
+        for member in members.iter().filter(|member| !exclude.contains(member)) {
+            // a member can have globs
+            if member.contains('*') || member.contains('?') {
+                for dir in app.find_directories(member)? {
+                    dirs.push(app.strip_source_path(&dir)?.to_string_lossy().to_string());
                 }
+            } else {
+                dirs.push(member.clone());
             }
+        }
 
-            Ok(None)
+        Ok(dirs)
+    }
+
+    /// The directory and completed manifest of the root package and all workspace members
+    fn get_packages(app: &App) -> Result<Vec<(String, Manifest)>> {
+        let root = match RustProvider::parse_cargo_toml(app)? {
+            Some(manifest) => manifest,
+            None => return Ok(Vec::new()),
         };
 
-        for default_member in workspace
-            .default_members
+        let mut dirs = vec![".".to_string()];
+        if let Some(workspace) = &root.workspace {
+            dirs.extend(RustProvider::get_member_dirs(
+                app,
+                &workspace.members,
+                &workspace.exclude,
+            )?);
+        }
+
+        let mut packages = Vec::new();
+        for dir in dirs {
+            let manifest_path = format!("{}/Cargo.toml", dir);
+            let mut manifest = app.read_toml::<Manifest>(&manifest_path)?;
+            manifest.complete_from_path(&app.source.join(&manifest_path))?;
+
+            if manifest.package.is_some() {
+                packages.push((dir, manifest));
+            }
+        }
+
+        Ok(packages)
+    }
+
+    /// Paths and contents of empty sources for the targets of a package
+    fn get_stub_files(manifest: &Manifest) -> Vec<(String, &'static str)> {
+        let mut files = Vec::new();
+
+        if let Some(lib) = &manifest.lib {
+            files.push((
+                lib.path.clone().unwrap_or_else(|| "src/lib.rs".to_string()),
+                "",
+            ));
+        }
+
+        for bin in &manifest.bin {
+            let path = bin
+                .path
+                .clone()
+                .or_else(|| bin.name.as_ref().map(|name| format!("src/bin/{}.rs", name)));
+            if let Some(path) = path {
+                files.push((path, "fn main() {}"));
+            }
+        }
+
+        files
+    }
+
+    /// Whether the dependencies of the packages can be compiled from their manifests and stub sources alone.
+    ///
+    /// That is not the case with custom target paths, examples, tests, or benches, build scripts,
+    /// path dependencies outside of the workspace, or sources that include other files.
+    fn has_plain_layout(app: &App, packages: &[(String, Manifest)]) -> Result<bool> {
+        let dirs = packages
             .iter()
-            .filter(|default_member| !workspace.exclude.contains(default_member))
-        {
-            // a member can have globs
-            if default_member.contains('*') || default_member.contains('?') {
-                for member in app.find_directories(default_member)? {
-                    if let Some(bin) = find_binary(&member.to_string_lossy())? {
-                        return Ok(Some(bin));
-                    }
+            .map(|(dir, _)| RustProvider::normalize_path(Path::new(dir)))
+            .collect::<Vec<_>>();
+
+        for (dir, _) in packages {
+            // The completed manifests include the targets Cargo discovers, so check what is declared
+            let manifest = app.read_toml::<Manifest>(&format!("{}/Cargo.toml", dir))?;
+
+            let custom_paths = manifest.lib.iter().any(|lib| lib.path.is_some())
+                || manifest.bin.iter().any(|bin| bin.path.is_some())
+                || !manifest.example.is_empty()
+                || !manifest.test.is_empty()
+                || !manifest.bench.is_empty();
+            let build_script = app.includes_file(&format!("{}/build.rs", dir))
+                || matches!(
+                    manifest
+                        .package
+                        .as_ref()
+                        .and_then(|package| package.build.as_ref()),
+                    Some(OptionalFile::Path(_) | OptionalFile::Flag(true))
+                );
+            if custom_paths || build_script {
+                return Ok(false);
+            }
+
+            let deps = manifest
+                .dependencies
+                .values()
+                .chain(manifest.dev_dependencies.values())
+                .chain(manifest.build_dependencies.values())
+                .chain(manifest.target.values().flat_map(|target| {
+                    target
+                        .dependencies
+                        .values()
+                        .chain(target.dev_dependencies.values())
+                        .chain(target.build_dependencies.values())
+                }));
+            for dep_path in deps.filter_map(|dep| dep.detail().and_then(|d| d.path.as_ref())) {
+                let dep_dir = RustProvider::normalize_path(&Path::new(dir).join(dep_path));
+                if !dirs.contains(&dep_dir) {
+                    return Ok(false);
                 }
-            } else if let Some(bin) = find_binary(default_member)? {
-                return Ok(Some(bin));
             }
         }
 
-        for member in workspace
-            .members
-            .iter()
-            .filter(|member| !workspace.exclude.contains(member))
-        {
-            // a member can have globs
-            if member.contains('*') || member.contains('?') {
-                for member in app.find_directories(member)? {
-                    if let Some(bin) = find_binary(&member.to_string_lossy())? {
-                        return Ok(Some(bin));
+        let include_re = Regex::new(r"\binclude(_str|_bytes)?!")?;
+        Ok(!app.find_match(&include_re, "**/*.rs")?)
+    }
+
+    /// Resolve `.` and `..` components of a relative path
+    fn normalize_path(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        normalized.push("..");
                     }
                 }
-            } else if let Some(bin) = find_binary(member)? {
-                return Ok(Some(bin));
+                component => normalized.push(component),
             }
         }
 
-        Ok(None)
+        normalized
+    }
+
+    fn get_package_binaries(
+        app: &App,
+        manifest_path: &str,
+        in_workspace: bool,
+    ) -> Result<Vec<CargoBinary>> {
+        let mut manifest = app.read_toml::<Manifest>(manifest_path)?;
+
+        manifest.complete_from_path(&app.source.join(manifest_path))?;
+
+        let package = match manifest.package {
+            Some(package) => package.name,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut names = manifest
+            .bin
+            .into_iter()
+            .filter_map(|bin| bin.name)
+            .collect::<Vec<_>>();
+        if names.is_empty() && manifest.lib.is_none() {
+            names.push(package.clone());
+        }
+
+        Ok(names
+            .into_iter()
+            .map(|name| CargoBinary {
+                package: package.clone(),
+                name,
+                in_workspace,
+            })
+            .collect())
+    }
+}
+
+/// A binary target of the app, and the package that contains it
+#[derive(Debug, PartialEq, Eq)]
+struct CargoBinary {
+    package: String,
+    name: String,
+    /// Packages of a workspace are built with `--package`
+    in_workspace: bool,
+}
+
+impl CargoBinary {
+    fn list(binaries: &[CargoBinary]) -> String {
+        binaries
+            .iter()
+            .map(|binary| binary.name.clone())
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::collections::BTreeMap;
+    use std::{collections::BTreeMap, fs};
+    use tempdir::TempDir;
 
     #[test]
     fn test_no_version() -> Result<()> {
@@ -391,6 +731,13 @@ mod test {
             )?,
             Pkg::new("rust-bin.stable.\"1.54.0\".default")
         );
+        assert_eq!(
+            RustProvider::get_rust_pkg(
+                &App::new("./examples/rust-custom-toolchain")?,
+                &Environment::from_envs(vec!["NIXPACKS_RUST_VERSION=nightly"])?
+            )?,
+            Pkg::new("rust-bin.nightly.latest.default")
+        );
 
         Ok(())
     }
@@ -406,4 +753,110 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_default_member_binary() -> Result<()> {
+        assert_eq!(
+            RustProvider::get_binary(
+                &App::new("./examples/rust-workspace-bins")?,
+                &Environment::default()
+            )?,
+            Some(CargoBinary {
+                package: "api".to_string(),
+                name: "api".to_string(),
+                in_workspace: true,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_not_found() -> Result<()> {
+        let err = RustProvider::get_binary(
+            &App::new("./examples/rust-workspace-bins")?,
+            &Environment::from_envs(vec!["NIXPACKS_RUST_BIN=cron"])?,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Rust binary cron not found. Available binaries: api, worker"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_binaries() -> Result<()> {
+        let dir = TempDir::new("nixpacks-rust")?;
+        fs::create_dir_all(dir.path().join("src/bin"))?;
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"jobs\"\nversion = \"0.1.0\"\n",
+        )?;
+        fs::write(dir.path().join("src/bin/server.rs"), "fn main() {}")?;
+        fs::write(dir.path().join("src/bin/cron.rs"), "fn main() {}")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        let err = RustProvider::get_binary(&app, &Environment::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Found multiple Rust binaries"));
+        assert!(err.contains("cron"));
+        assert!(err.contains("server"));
+
+        let binary = RustProvider::get_binary(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_RUST_BIN=server"])?,
+        )?
+        .unwrap();
+        assert_eq!(binary.name, "server");
+        assert!(!binary.in_workspace);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_layout() -> Result<()> {
+        let has_plain_layout = |manifest: &str, files: &[(&str, &str)]| -> Result<bool> {
+            let dir = TempDir::new("nixpacks-rust")?;
+            fs::create_dir_all(dir.path().join("src"))?;
+            fs::write(
+                dir.path().join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"app\"\nversion = \"0.1.0\"\n{}",
+                    manifest
+                ),
+            )?;
+            fs::write(dir.path().join("src/main.rs"), "fn main() {}")?;
+            for (path, contents) in files {
+                fs::write(dir.path().join(path), contents)?;
+            }
+
+            let app = App::new(&dir.path().to_string_lossy())?;
+            RustProvider::has_plain_layout(&app, &RustProvider::get_packages(&app)?)
+        };
+
+        assert!(has_plain_layout("", &[])?);
+        assert!(has_plain_layout("[[bin]]\nname = \"app\"\n", &[])?);
+        assert!(!has_plain_layout(
+            "[[bin]]\nname = \"app\"\npath = \"main.rs\"\n",
+            &[]
+        )?);
+        assert!(!has_plain_layout("[[example]]\nname = \"demo\"\n", &[])?);
+        assert!(!has_plain_layout("", &[("build.rs", "fn main() {}")])?);
+        assert!(!has_plain_layout(
+            "[dependencies]\nshared = { path = \"../shared\" }\n",
+            &[]
+        )?);
+        assert!(!has_plain_layout(
+            "",
+            &[(
+                "src/lib.rs",
+                "const SCHEMA: &str = include_str!(\"schema.sql\");"
+            )]
+        )?);
+
+        Ok(())
+    }
 }
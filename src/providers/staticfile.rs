@@ -7,19 +7,52 @@ use crate::nixpacks::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
+    scripts::START_NGINX,
 };
 use anyhow::Result;
 use indoc::formatdoc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Write as _;
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct Staticfile {
     pub root: Option<String>,
-    pub directory: Option<String>,
-    pub gzip: Option<String>,
-    pub status_code: Option<HashMap<u32, String>>,
+    pub directory: Option<StaticfileToggle>,
+    pub gzip: Option<StaticfileToggle>,
+    pub pushstate: Option<StaticfileToggle>,
+    #[serde(alias = "status_codes")]
+    pub status_code: Option<BTreeMap<u32, String>>,
+    pub headers: Option<BTreeMap<String, String>>,
+}
+
+/// An option that can be written as `enabled`, `on`, or `true`, and the opposites
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum StaticfileToggle {
+    Bool(bool),
+    String(String),
+}
+
+impl StaticfileToggle {
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            StaticfileToggle::Bool(enabled) => *enabled,
+            StaticfileToggle::String(value) => matches!(
+                value.trim().to_lowercase().as_str(),
+                "enabled" | "on" | "true" | "yes"
+            ),
+        }
+    }
+
+    /// The value of an nginx `on | off` directive
+    fn to_nginx(toggle: Option<&StaticfileToggle>, default: bool) -> &'static str {
+        if toggle.map_or(default, StaticfileToggle::is_enabled) {
+            "on"
+        } else {
+            "off"
+        }
+    }
 }
 
 pub struct StaticfileProvider {}
@@ -38,18 +71,9 @@ impl Provider for StaticfileProvider {
     }
 
     fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
-        let mut setup = Phase::setup(Some(vec![Pkg::new("nginx")]));
-        setup.add_cmd("mkdir /etc/nginx/ /var/log/nginx/ /var/cache/nginx/");
-
-        // shell command to edit 0.0.0.0:80 to $PORT
-        let shell_cmd = "[[ -z \"${PORT}\" ]] && echo \"Environment variable PORT not found. Using PORT 80\" || sed -i \"s/0.0.0.0:80/$PORT/g\"";
-        let start = StartPhase::new(format!(
-            "{shell_cmd} {conf_location} && nginx -c {conf_location}",
-            shell_cmd = shell_cmd,
-            conf_location = app.asset_path("nginx.conf"),
-        ));
-
-        let static_assets = StaticfileProvider::get_static_assets(app, env)?;
+        let setup = StaticfileProvider::get_setup_phase(vec![]);
+        let start = StaticfileProvider::get_start_phase(app);
+        let static_assets = StaticfileProvider::get_static_assets(app, env, None)?;
 
         let mut plan = BuildPlan::new(&vec![setup], Some(start));
         plan.add_static_assets(static_assets);
@@ -59,6 +83,24 @@ impl Provider for StaticfileProvider {
 }
 
 impl StaticfileProvider {
+    /// Setup phase that installs nginx alongside any additional packages
+    pub fn get_setup_phase(pkgs: Vec<Pkg>) -> Phase {
+        let mut setup = Phase::setup(Some(pkgs));
+        StaticfileProvider::add_nginx(&mut setup);
+        setup
+    }
+
+    /// Install nginx and create the directories it writes to
+    pub fn add_nginx(setup: &mut Phase) {
+        setup.add_nix_pkgs(&[Pkg::new("nginx")]);
+        setup.add_cmd("mkdir /etc/nginx/ /var/log/nginx/ /var/cache/nginx/");
+    }
+
+    /// Start phase that serves the static assets `nginx.conf` with nginx
+    pub fn get_start_phase(app: &App) -> StartPhase {
+        StartPhase::new(START_NGINX.get_cmd(&[&app.asset_path("nginx.conf")]))
+    }
+
     pub fn get_root(app: &App, env: &Environment, staticfile_root: String) -> String {
         let mut root = String::new();
         if let Some(staticfile_root) = env.get_config_variable("STATICFILE_ROOT") {
@@ -76,7 +118,13 @@ impl StaticfileProvider {
         root
     }
 
-    fn get_static_assets(app: &App, env: &Environment) -> Result<StaticAssets> {
+    /// Static assets for nginx and the script that starts it. `default_root` is used when the root is not configured,
+    /// which lets other providers serve a directory that only exists after the build.
+    pub fn get_static_assets(
+        app: &App,
+        env: &Environment,
+        default_root: Option<&str>,
+    ) -> Result<StaticAssets> {
         let mut assets = StaticAssets::new();
 
         let mut mime_types = "include /nix/store/*-user-environment/conf/mime.types;".to_string();
@@ -95,15 +143,44 @@ impl StaticfileProvider {
         }
 
         let staticfile: Staticfile = app.read_yaml("Staticfile").unwrap_or_default();
-        let root = StaticfileProvider::get_root(app, env, staticfile.root.unwrap_or_default());
-        let gzip = staticfile.gzip.unwrap_or_else(|| "on".to_string());
-        let directory = staticfile.directory.unwrap_or_else(|| "off".to_string());
-        let status_code = staticfile.status_code.unwrap_or_default();
+        let root = StaticfileProvider::get_root(
+            app,
+            env,
+            staticfile
+                .root
+                .or_else(|| default_root.map(ToString::to_string))
+                .unwrap_or_default(),
+        );
+        let gzip = StaticfileToggle::to_nginx(staticfile.gzip.as_ref(), true);
+        let directory = StaticfileToggle::to_nginx(staticfile.directory.as_ref(), false);
+
+        // Single page apps route on the client, so unknown paths serve index.html
+        let pushstate = if staticfile
+            .pushstate
+            .as_ref()
+            .map_or(false, StaticfileToggle::is_enabled)
+        {
+            "try_files $uri $uri/ /index.html;"
+        } else {
+            ""
+        };
+
         let mut error_page = String::new();
-        for (key, value) in status_code {
+        for (key, value) in staticfile.status_code.unwrap_or_default() {
             writeln!(error_page, "\terror_page {} {};", key, value)?;
         }
 
+        // `always` also adds the headers to error responses
+        let mut headers = String::new();
+        for (name, value) in staticfile.headers.unwrap_or_default() {
+            writeln!(
+                headers,
+                "\tadd_header {} \"{}\" always;",
+                name,
+                value.replace('"', "\\\"")
+            )?;
+        }
+
         let nginx_conf = formatdoc! {"
         daemon off;
         error_log /dev/stdout info;
@@ -122,10 +199,13 @@ impl StaticfileProvider {
             server {{
                 listen    0.0.0.0:80;
                 gzip  	  {gzip};
+                gzip_types text/plain text/css text/javascript application/javascript application/json image/svg+xml;
                 root	  /app/{root};
+        {headers}
                 location / {{
                     {auth_basic}
                     autoindex {directory};
+                    {pushstate}
                 }}
         {error_page}
             }}
@@ -136,10 +216,49 @@ impl StaticfileProvider {
         root = root,
         auth_basic = auth_basic,
         directory = directory,
+        pushstate = pushstate,
+        headers = headers,
         error_page = error_page
         };
         assets.insert("nginx.conf".to_string(), nginx_conf);
+        assets.extend(START_NGINX.to_static_assets());
 
         Ok(assets)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_staticfile_options() -> Result<()> {
+        let assets = StaticfileProvider::get_static_assets(
+            &App::new("./examples/staticfile-spa")?,
+            &Environment::default(),
+            None,
+        )?;
+        let nginx_conf = &assets["nginx.conf"];
+
+        assert!(nginx_conf.contains("root\t  /app/dist;"));
+        assert!(nginx_conf.contains("try_files $uri $uri/ /index.html;"));
+        assert!(nginx_conf.contains("error_page 404 /404.html;"));
+        assert!(nginx_conf.contains("add_header Cache-Control \"public, max-age=3600\" always;"));
+        assert!(nginx_conf.contains("add_header X-Frame-Options \"DENY\" always;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toggle() {
+        for enabled in ["enabled", "on", "true", "Yes"] {
+            assert!(StaticfileToggle::String(enabled.to_string()).is_enabled());
+        }
+        assert!(!StaticfileToggle::String("off".to_string()).is_enabled());
+        assert_eq!(StaticfileToggle::to_nginx(None, true), "on");
+        assert_eq!(
+            StaticfileToggle::to_nginx(Some(&StaticfileToggle::Bool(false)), true),
+            "off"
+        );
+    }
+}
@@ -2,6 +2,7 @@ use super::Provider;
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
@@ -10,6 +11,7 @@ use crate::nixpacks::{
 };
 use anyhow::{bail, Result};
 use path_slash::PathExt;
+use regex::Regex;
 
 const DEFAULT_SWIFT_VERSION: &str = "5.4.2";
 
@@ -40,7 +42,7 @@ impl Provider for SwiftProvider {
         Ok(app.includes_file("Package.swift"))
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
         let _plan = BuildPlan::default();
 
         let mut setup = Phase::setup(Some(vec![
@@ -52,20 +54,24 @@ impl Provider for SwiftProvider {
         ]));
 
         let swift_version = SwiftProvider::get_swift_version(app)?;
-        let rev = SwiftProvider::version_number_to_rev(&swift_version);
-
-        if let Some(rev) = rev {
-            setup.set_nix_archive(rev);
+        let source = if app.includes_file(".swift-version") {
+            ".swift-version"
         } else {
-            // Safe to unwrap, "5.4.2" exists on `AVAILABLE_SWIFT_VERSIONS`
-            setup.set_nix_archive(
-                AVAILABLE_SWIFT_VERSIONS
-                    .iter()
-                    .find(|(ver, _rev)| *ver == DEFAULT_SWIFT_VERSION)
-                    .unwrap()
-                    .1
-                    .to_string(),
-            );
+            "Package.swift"
+        };
+        match SwiftProvider::version_number_to_rev(&swift_version) {
+            Some(rev) => setup.set_nix_archive(rev),
+            None => bail!(Message::new(MessageId::SwiftVersionUnavailable)
+                .arg("version", &swift_version)
+                .arg("source", source)
+                .arg(
+                    "versions",
+                    &AVAILABLE_SWIFT_VERSIONS
+                        .iter()
+                        .map(|(version, _rev)| *version)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
         }
 
         let mut install = Phase::install(Some("swift package resolve".to_string()));
@@ -74,16 +80,14 @@ impl Provider for SwiftProvider {
             install.add_file_dependency("Package.resolved".to_string());
         }
 
-        let name = SwiftProvider::get_executable_name(app)?;
-        let mut build = Phase::build(Some(
-            "CC=clang++ swift build -c release --static-swift-stdlib".to_string(),
-        ));
-        build.add_cmd(format!(
-            "cp ./.build/release/{name} ./{name} && rm -rf ./.build",
-            name = name
-        ));
+        let name = SwiftProvider::get_product_name(app, env)?;
+        let mut build = Phase::build(Some(format!(
+            "CC=clang++ swift build -c release --static-swift-stdlib --product {}",
+            name
+        )));
+        build.add_cmd(format!("cp ./.build/release/{name} ./{name}", name = name));
+        build.add_cleanup_cmd("rm -rf ./.build");
 
-        let name = SwiftProvider::get_executable_name(app)?;
         let start = StartPhase::new(format!("./{}", name));
 
         let plan = BuildPlan::new(&vec![setup, install, build], Some(start));
@@ -100,22 +104,14 @@ impl SwiftProvider {
 
             match version.first() {
                 Some(v) => Ok(v.trim().to_string()),
-                None => bail!("Your .swift-version file is empty"),
+                None => bail!(Message::new(MessageId::SwiftVersionEmpty)),
             }
         } else if app.includes_file("Package.swift") {
             let contents = app.read_file("Package.swift")?;
-            let version = contents
-                .split('\n')
-                .filter(|&l| l.contains("swift-tools-version:"))
-                .map(|l| {
-                    l.replace("swift-tools-version:", "")
-                        .replace("//", "")
-                        .trim()
-                        .to_string()
-                })
-                .collect::<Vec<_>>()
-                .first()
-                .cloned();
+            // The tools version has to be on the first line, e.g. `// swift-tools-version:5.4`
+            let version = Regex::new(r"^//\s*swift-tools-version\s*:\s*(\d+(?:\.\d+)*)")?
+                .captures(contents.trim_start())
+                .map(|captures| captures[1].to_string());
 
             if let Some(version) = version {
                 Ok(version)
@@ -127,6 +123,55 @@ impl SwiftProvider {
         }
     }
 
+    /// The executable product to build and start.
+    ///
+    /// `NIXPACKS_SWIFT_PRODUCT` selects one of the executable products in `Package.swift`.
+    /// Without it, the package must have a single executable product.
+    fn get_product_name(app: &App, env: &Environment) -> Result<String> {
+        let products = SwiftProvider::get_executable_products(app)?;
+
+        if let Some(selected) = env.get_config_variable("SWIFT_PRODUCT") {
+            if products.is_empty() || products.contains(&selected) {
+                return Ok(selected);
+            }
+
+            bail!(Message::new(MessageId::SwiftProductNotFound)
+                .arg("name", &selected)
+                .arg("products", &products.join(", ")));
+        }
+
+        match products.len() {
+            // Packages with only a main.swift in a regular target still build an executable
+            0 => SwiftProvider::get_executable_name(app),
+            1 => Ok(products[0].clone()),
+            _ => bail!(Message::new(MessageId::SwiftProductAmbiguous)
+                .arg("products", &products.join(", "))),
+        }
+    }
+
+    /// The executable products declared in `Package.swift`. SwiftPM also creates
+    /// a product for every `executableTarget` that isn't part of a declared product.
+    fn get_executable_products(app: &App) -> Result<Vec<String>> {
+        let contents = app.read_file("Package.swift")?;
+
+        let mut products = Vec::new();
+        for pattern in [
+            r#"\.executable\(\s*name:\s*"([^"]+)""#,
+            r#"\.executableTarget\(\s*name:\s*"([^"]+)""#,
+        ] {
+            if !products.is_empty() {
+                break;
+            }
+            for captures in Regex::new(pattern)?.captures_iter(&contents) {
+                if !products.contains(&captures[1].to_string()) {
+                    products.push(captures[1].to_string());
+                }
+            }
+        }
+
+        Ok(products)
+    }
+
     fn get_executable_name(app: &App) -> Result<String> {
         let raw_paths = app.find_files("Sources/**/main.swift")?;
         let paths = raw_paths
@@ -136,7 +181,7 @@ impl SwiftProvider {
 
         let path = match paths.first() {
             Some(path) => path.to_slash().unwrap().to_string(),
-            None => bail!("Your swift app doesn't have a main.swift file"),
+            None => bail!(Message::new(MessageId::SwiftMainNotFound)),
         };
 
         let mut names = path.split('/').collect::<Vec<_>>();
@@ -149,18 +194,52 @@ impl SwiftProvider {
         Ok(names[1].to_string())
     }
 
+    /// The Nixpkgs archive of the requested version. Without an exact match, this is
+    /// the newest patch release of the same minor version, or the next newer release,
+    /// since the tools version is only the minimum needed to build the package
     fn version_number_to_rev(version: &str) -> Option<String> {
         let matched_version = AVAILABLE_SWIFT_VERSIONS
             .iter()
             .find(|(ver, _rev)| *ver == version);
+        if let Some((_ver, rev)) = matched_version {
+            return Some((*rev).to_string());
+        }
+
+        let requested = SwiftProvider::parse_version(version)?;
+        let mut newer = AVAILABLE_SWIFT_VERSIONS
+            .iter()
+            .filter_map(|(ver, rev)| SwiftProvider::parse_version(ver).map(|ver| (ver, *rev)))
+            .filter(|(ver, _rev)| *ver >= requested)
+            .collect::<Vec<_>>();
+        newer.sort();
+
+        let same_minor = newer
+            .iter()
+            .rev()
+            .find(|(ver, _rev)| ver[..2] == requested[..2]);
 
-        matched_version.map(|(_ver, rev)| (*rev).to_string())
+        same_minor
+            .or_else(|| newer.first())
+            .map(|(_ver, rev)| (*rev).to_string())
+    }
+
+    /// `5.4` as `[5, 4, 0]`, so versions with and without a patch compare correctly
+    fn parse_version(version: &str) -> Option<Vec<u32>> {
+        let mut parts = version
+            .split('.')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        parts.resize(3, 0);
+
+        Some(parts)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::fs;
+    use tempdir::TempDir;
 
     #[test]
     fn test_custom_version() -> Result<()> {
@@ -171,4 +250,85 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_tools_version() -> Result<()> {
+        assert_eq!(
+            &SwiftProvider::get_swift_version(&App::new("./examples/swift")?)?,
+            "5.4"
+        );
+
+        // 5.2 isn't available, so the next newer release builds the package
+        assert_eq!(
+            SwiftProvider::version_number_to_rev("5.2"),
+            SwiftProvider::version_number_to_rev("5.4")
+        );
+        assert_eq!(
+            SwiftProvider::version_number_to_rev("4.2"),
+            SwiftProvider::version_number_to_rev("4.2.3")
+        );
+        assert_eq!(SwiftProvider::version_number_to_rev("5.7"), None);
+
+        Ok(())
+    }
+
+    fn create_app(package: &str) -> Result<(TempDir, App)> {
+        let dir = TempDir::new("nixpacks-swift")?;
+        fs::write(dir.path().join("Package.swift"), package)?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        Ok((dir, app))
+    }
+
+    #[test]
+    fn test_products() -> Result<()> {
+        assert_eq!(
+            SwiftProvider::get_product_name(
+                &App::new("./examples/swift-vapor")?,
+                &Environment::default()
+            )?,
+            "Run"
+        );
+
+        let (_dir, app) = create_app(
+            r#"// swift-tools-version:5.4
+let package = Package(
+    name: "jobs",
+    products: [
+        .executable(name: "api", targets: ["Api"]),
+        .executable(name: "worker", targets: ["Worker"]),
+    ],
+    targets: [
+        .executableTarget(name: "Api"),
+        .executableTarget(name: "Worker"),
+    ]
+)"#,
+        )?;
+
+        let err = SwiftProvider::get_product_name(&app, &Environment::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Found multiple Swift executable products: api, worker. Set NIXPACKS_SWIFT_PRODUCT to the one to start"
+        );
+
+        assert_eq!(
+            SwiftProvider::get_product_name(
+                &app,
+                &Environment::from_envs(vec!["NIXPACKS_SWIFT_PRODUCT=worker"])?
+            )?,
+            "worker"
+        );
+
+        let err = SwiftProvider::get_product_name(
+            &app,
+            &Environment::from_envs(vec!["NIXPACKS_SWIFT_PRODUCT=cron"])?,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Swift product cron not found. Available products: api, worker"
+        );
+
+        Ok(())
+    }
 }
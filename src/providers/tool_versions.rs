@@ -0,0 +1,245 @@
+// Code relating to the version managers that pin the version of every tool of an app
+//
+// The `.tool-versions` file of asdf names a plugin and its versions on each line, e.g. `nodejs 18.12.1 16.18.1`.
+// The first version is the one asdf installs, the rest are fallbacks.
+//
+// mise (formerly rtx) reads `.tool-versions` too, and a TOML config with a `[tools]` table
+// that takes precedence, and an `[env]` table of variables.
+
+use crate::nixpacks::{app::App, environment::EnvironmentVariables};
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+pub const TOOL_VERSIONS_FILE: &str = ".tool-versions";
+
+/// The config files of mise, in the order it reads them
+pub const MISE_CONFIG_FILES: &[&str] = &[".mise.toml", "mise.toml", ".rtx.toml"];
+
+/// asdf plugins named differently than the tool, e.g. `nodejs` for `node`
+const PLUGIN_ALIASES: &[(&str, &str)] = &[("nodejs", "node"), ("golang", "go")];
+
+/// The pinned versions of an app by tool name, and the file they were pinned in
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ToolVersions {
+    versions: BTreeMap<String, (String, &'static str)>,
+}
+
+impl ToolVersions {
+    /// The versions pinned in `.tool-versions` and the mise config of the app
+    pub fn from_app(app: &App) -> Result<ToolVersions> {
+        let mut tool_versions = if app.includes_file(TOOL_VERSIONS_FILE) {
+            ToolVersions::parse(&app.read_file(TOOL_VERSIONS_FILE)?)
+        } else {
+            ToolVersions::default()
+        };
+
+        if let Some((config, file)) = MiseConfig::from_app(app)? {
+            for (tool, version) in config.tools {
+                tool_versions
+                    .versions
+                    .insert(get_tool_name(&tool).to_string(), (version, file));
+            }
+        }
+
+        Ok(tool_versions)
+    }
+
+    pub fn parse(contents: &str) -> ToolVersions {
+        let mut versions = BTreeMap::new();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut parts = line.split_whitespace();
+
+            // asdf uses the first line of a plugin, and falls back past versions it doesn't install
+            if let (Some(plugin), Some(version)) = (parts.next(), parts.find(|v| is_installable(v)))
+            {
+                versions
+                    .entry(get_tool_name(plugin).to_string())
+                    .or_insert_with(|| (version.to_string(), TOOL_VERSIONS_FILE));
+            }
+        }
+
+        ToolVersions { versions }
+    }
+
+    /// The pinned version of a tool, such as `node` or `go`, and where it was pinned
+    pub fn get(&self, tool: &str) -> Option<(String, &'static str)> {
+        self.versions.get(get_tool_name(tool)).cloned()
+    }
+}
+
+/// The `[tools]` and `[env]` tables of a mise config file
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MiseConfig {
+    pub tools: BTreeMap<String, String>,
+    pub env: EnvironmentVariables,
+}
+
+impl MiseConfig {
+    /// The first mise config file of the app, and its name.
+    /// A config that mise could not read either is ignored, like a `.tool-versions` line that can't be parsed
+    pub fn from_app(app: &App) -> Result<Option<(MiseConfig, &'static str)>> {
+        match MISE_CONFIG_FILES
+            .iter()
+            .find(|file| app.includes_file(file))
+        {
+            Some(file) => match MiseConfig::parse(&app.read_file(file)?) {
+                Ok(config) => Ok(Some((config, file))),
+                Err(e) => {
+                    println!("Warning: Ignoring {}, which is not valid TOML: {}", file, e);
+                    Ok(None)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<MiseConfig> {
+        let config: toml::Value = toml::from_str(contents)?;
+
+        // Tools are pinned as `node = "18"`, `python = ["3.11", "3.10"]`, or `node = { version = "18" }`
+        let mut tools = BTreeMap::new();
+        if let Some(table) = config.get("tools").and_then(toml::Value::as_table) {
+            for (tool, value) in table {
+                let versions = match value {
+                    toml::Value::Array(versions) => versions.iter().collect(),
+                    toml::Value::Table(options) => options.get("version").into_iter().collect(),
+                    version => vec![version],
+                };
+                if let Some(version) = versions
+                    .into_iter()
+                    .filter_map(toml::Value::as_str)
+                    .find(|version| is_installable(version))
+                {
+                    tools.insert(tool.clone(), version.to_string());
+                }
+            }
+        }
+
+        // Keys starting with `_` are directives, such as `_.file` to load a dotenv file
+        let mut env = EnvironmentVariables::new();
+        if let Some(table) = config.get("env").and_then(toml::Value::as_table) {
+            for (name, value) in table {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                        value.to_string()
+                    }
+                    _ => continue,
+                };
+                if !name.starts_with('_') {
+                    env.insert(name.clone(), value);
+                }
+            }
+        }
+
+        Ok(MiseConfig { tools, env })
+    }
+}
+
+fn get_tool_name(plugin: &str) -> &str {
+    PLUGIN_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == plugin)
+        .map_or(plugin, |(_, tool)| tool)
+}
+
+/// `system` uses the tool outside of the version manager, and `ref:` and `path:` build it from source
+fn is_installable(version: &str) -> bool {
+    version != "system" && !version.starts_with("ref:") && !version.starts_with("path:")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let tool_versions = ToolVersions::parse(
+            "# Pinned for CI\nnodejs 18.12.1 16.18.1\npython system 3.11.0\nruby ref:v3_1_2\ngolang   1.18.3 # latest\nnodejs 14.21.1\n",
+        );
+
+        assert_eq!(
+            tool_versions.get("node"),
+            Some(("18.12.1".to_string(), TOOL_VERSIONS_FILE))
+        );
+        assert_eq!(
+            tool_versions.get("go"),
+            Some(("1.18.3".to_string(), TOOL_VERSIONS_FILE))
+        );
+        assert_eq!(
+            tool_versions.get("python"),
+            Some(("3.11.0".to_string(), TOOL_VERSIONS_FILE))
+        );
+        assert_eq!(tool_versions.get("ruby"), None);
+    }
+
+    #[test]
+    fn test_mise_config() -> Result<()> {
+        let config = MiseConfig::parse(
+            r#"
+[tools]
+node = "18"
+python = ["system", "3.11"]
+go = { version = "1.18.3" }
+
+[env]
+NODE_ENV = "production"
+WORKERS = 4
+_.file = ".env"
+"#,
+        )?;
+
+        assert_eq!(
+            config.tools,
+            BTreeMap::from([
+                ("go".to_string(), "1.18.3".to_string()),
+                ("node".to_string(), "18".to_string()),
+                ("python".to_string(), "3.11".to_string()),
+            ])
+        );
+        assert_eq!(
+            config.env,
+            EnvironmentVariables::from([
+                ("NODE_ENV".to_string(), "production".to_string()),
+                ("WORKERS".to_string(), "4".to_string()),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mise_config_precedence() -> Result<()> {
+        let dir = TempDir::new("nixpacks-tool-versions")?;
+        fs::write(
+            dir.path().join(".tool-versions"),
+            "nodejs 16.18.1\nruby 3.1.2\n",
+        )?;
+        fs::write(dir.path().join(".rtx.toml"), "[tools]\nnode = \"18\"\n")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        let tool_versions = ToolVersions::from_app(&app)?;
+        assert_eq!(
+            tool_versions.get("node"),
+            Some(("18".to_string(), ".rtx.toml"))
+        );
+        assert_eq!(
+            tool_versions.get("ruby"),
+            Some(("3.1.2".to_string(), TOOL_VERSIONS_FILE))
+        );
+
+        // A malformed config is ignored
+        fs::write(dir.path().join(".rtx.toml"), "[tools\nnode = \"18\"\n")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert_eq!(MiseConfig::from_app(&app)?, None);
+        assert_eq!(
+            ToolVersions::from_app(&app)?.get("node"),
+            Some(("16.18.1".to_string(), TOOL_VERSIONS_FILE))
+        );
+
+        Ok(())
+    }
+}
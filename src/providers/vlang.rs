@@ -0,0 +1,73 @@
+use super::Provider;
+use crate::nixpacks::{
+    app::App,
+    environment::Environment,
+    nix::pkg::Pkg,
+    plan::{
+        phase::{Phase, StartPhase},
+        BuildPlan,
+    },
+};
+use anyhow::Result;
+use regex::Regex;
+
+const V_FLAGS: &str = "V_FLAGS";
+const DEFAULT_V_BINARY_NAME: &str = "main";
+
+pub struct VProvider {}
+
+impl Provider for VProvider {
+    fn name(&self) -> &str {
+        "v"
+    }
+
+    fn detect(&self, app: &App, _env: &Environment) -> Result<bool> {
+        Ok(app.includes_file("v.mod"))
+    }
+
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let setup = Phase::setup(Some(vec![Pkg::new("vlang")]));
+
+        let name = VProvider::get_module_name(app)?;
+        let flags = env
+            .get_config_variable(V_FLAGS)
+            .map(|flags| format!("{} ", flags))
+            .unwrap_or_default();
+
+        let mut build = Phase::build(Some(format!("v -prod {}-o {} .", flags, name)));
+        build.depends_on_phase("setup");
+
+        let start = StartPhase::new(format!("./{}", name));
+
+        let plan = BuildPlan::new(&vec![setup, build], Some(start));
+        Ok(Some(plan))
+    }
+}
+
+impl VProvider {
+    /// Read the module name from `v.mod`, which is used as the name of the compiled binary
+    fn get_module_name(app: &App) -> Result<String> {
+        let contents = app.read_file("v.mod")?;
+        let re = Regex::new(r#"name:\s*['"]([^'"]+)['"]"#)?;
+
+        let name = re
+            .captures(&contents)
+            .and_then(|caps| caps.get(1))
+            .map_or(DEFAULT_V_BINARY_NAME, |m| m.as_str());
+
+        Ok(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_module_name() -> Result<()> {
+        let name = VProvider::get_module_name(&App::new("./examples/vlang")?)?;
+        assert_eq!(name, "hello");
+
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
-use super::Provider;
+use super::{tool_versions::ToolVersions, Provider};
 use crate::nixpacks::{
     app::App,
     environment::Environment,
+    messages::{Message, MessageId},
     nix::pkg::Pkg,
     plan::{
         phase::{Phase, StartPhase},
         BuildPlan,
     },
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
+use regex::Regex;
 use std::{env::consts::ARCH, ffi::OsStr};
 
 pub struct ZigProvider;
@@ -16,6 +18,15 @@ pub struct ZigProvider;
 //TODO: CHANGE THIS WHEN ZIG IS UPDATED OR EVERYTHING WILL BREAK!
 const GYRO_VERSION: &str = "0.6.0";
 
+// Zig breaks compatibility between minor versions, so each needs its own package
+const AVAILABLE_ZIG_VERSIONS: &[(&str, &str)] = &[("0.9", "zig")];
+const DEFAULT_ZIG_PKG_NAME: &str = "zig";
+
+const ZIG_ZON_FILE: &str = "build.zig.zon";
+
+const ZIG_OPTIMIZE_MODES: &[&str] = &["Debug", "ReleaseSafe", "ReleaseFast", "ReleaseSmall"];
+const DEFAULT_ZIG_OPTIMIZE_MODE: &str = "ReleaseSafe";
+
 impl Provider for ZigProvider {
     fn name(&self) -> &str {
         "zig"
@@ -25,8 +36,16 @@ impl Provider for ZigProvider {
         Ok(app.has_match("*.zig") || app.has_match("**/*.zig") || app.has_match("gyro.zzz"))
     }
 
-    fn get_build_plan(&self, app: &App, _env: &Environment) -> Result<Option<BuildPlan>> {
-        let mut setup = Phase::setup(Some(vec![Pkg::new("zig")]));
+    fn get_build_plan(&self, app: &App, env: &Environment) -> Result<Option<BuildPlan>> {
+        let version = ZigProvider::get_zig_version(app, env)?;
+        let zig_pkg = match &version {
+            Some((version, source)) if source == ZIG_ZON_FILE => {
+                ZigProvider::get_minimum_zig_pkg(version, source)?
+            }
+            Some((version, source)) => ZigProvider::get_zig_pkg(version, source)?,
+            None => DEFAULT_ZIG_PKG_NAME.to_string(),
+        };
+        let mut setup = Phase::setup(Some(vec![Pkg::new(&zig_pkg)]));
 
         if app.includes_file("gyro.zzz") {
             setup.add_nix_pkgs(&[Pkg::new("wget")]);
@@ -46,7 +65,10 @@ impl Provider for ZigProvider {
             install.add_cmd(format!("{} fetch", gyro_exe_path));
         }
 
-        let build = Phase::build(Some("zig build -Drelease-safe=true".to_string()));
+        let optimize = ZigProvider::get_optimize_flag(env)?;
+        let build = Phase::build(Some(
+            format!("zig build {}", optimize).trim_end().to_string(),
+        ));
 
         let start = StartPhase::new(format!(
             "./zig-out/bin/{}",
@@ -62,6 +84,103 @@ impl Provider for ZigProvider {
 }
 
 impl ZigProvider {
+    /// The requested Zig version from `NIXPACKS_ZIG_VERSION`, `.zig-version`, `.tool-versions`,
+    /// or the `minimum_zig_version` of `build.zig.zon`, and where it was found
+    fn get_zig_version(app: &App, env: &Environment) -> Result<Option<(String, String)>> {
+        if let Some(version) = env.get_config_variable("ZIG_VERSION") {
+            return Ok(Some((version, "NIXPACKS_ZIG_VERSION".to_string())));
+        }
+
+        if app.includes_file(".zig-version") {
+            let version = app.read_file(".zig-version")?.trim().to_string();
+            if !version.is_empty() {
+                return Ok(Some((version, ".zig-version".to_string())));
+            }
+        }
+
+        if let Some((version, source)) = ToolVersions::from_app(app)?.get("zig") {
+            return Ok(Some((version, source.to_string())));
+        }
+
+        if app.includes_file(ZIG_ZON_FILE) {
+            let zon = app.read_file(ZIG_ZON_FILE)?;
+            let version = Regex::new(r#"\.minimum_zig_version\s*=\s*"([^"]+)""#)?
+                .captures(&zon)
+                .map(|captures| (captures[1].to_string(), ZIG_ZON_FILE.to_string()));
+            return Ok(version);
+        }
+
+        Ok(None)
+    }
+
+    /// Patch releases use the package of their minor version, e.g. `0.9.1` uses `0.9`
+    fn get_zig_pkg(version: &str, source: &str) -> Result<String> {
+        let minor_version = version.splitn(3, '.').take(2).collect::<Vec<_>>().join(".");
+
+        match AVAILABLE_ZIG_VERSIONS
+            .iter()
+            .find(|(available, _)| *available == minor_version)
+        {
+            Some((_, pkg)) => Ok((*pkg).to_string()),
+            None => bail!(Message::new(MessageId::ZigVersionUnavailable)
+                .arg("version", version)
+                .arg("source", source)
+                .arg(
+                    "versions",
+                    &AVAILABLE_ZIG_VERSIONS
+                        .iter()
+                        .map(|(version, _)| *version)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+        }
+    }
+
+    /// The package of the oldest available Zig that is at least `version`, since `build.zig.zon` only sets a minimum
+    fn get_minimum_zig_pkg(version: &str, source: &str) -> Result<String> {
+        let parse_minor = |version: &str| {
+            let mut parts = version.split('.').map(str::parse::<u32>);
+            match (parts.next(), parts.next()) {
+                (Some(Ok(major)), Some(Ok(minor))) => Some((major, minor)),
+                _ => None,
+            }
+        };
+
+        let minimum = parse_minor(version);
+        let mut available = AVAILABLE_ZIG_VERSIONS
+            .iter()
+            .filter_map(|(available, pkg)| parse_minor(available).map(|minor| (minor, *pkg)))
+            .filter(|(minor, _)| minimum.map_or(false, |minimum| *minor >= minimum))
+            .collect::<Vec<_>>();
+        available.sort_unstable();
+
+        match available.first() {
+            Some((_, pkg)) => Ok((*pkg).to_string()),
+            None => ZigProvider::get_zig_pkg(version, source),
+        }
+    }
+
+    /// The build option for the `NIXPACKS_ZIG_OPTIMIZE` mode, e.g. `-Drelease-safe=true`
+    fn get_optimize_flag(env: &Environment) -> Result<String> {
+        let mode = env
+            .get_config_variable("ZIG_OPTIMIZE")
+            .unwrap_or_else(|| DEFAULT_ZIG_OPTIMIZE_MODE.to_string());
+        if !ZIG_OPTIMIZE_MODES.contains(&mode.as_str()) {
+            bail!(Message::new(MessageId::ZigOptimizeInvalid)
+                .arg("mode", &mode)
+                .arg("modes", &ZIG_OPTIMIZE_MODES.join(", ")));
+        }
+
+        let flag = match mode.as_str() {
+            "ReleaseSafe" => "-Drelease-safe=true",
+            "ReleaseFast" => "-Drelease-fast=true",
+            "ReleaseSmall" => "-Drelease-small=true",
+            _ => "",
+        };
+
+        Ok(flag.to_string())
+    }
+
     pub fn get_gyro_download_url() -> String {
         let gyro_supported_archs: Vec<&str> = vec!["x86_64", "aarch64", "i386"];
         if gyro_supported_archs.contains(&ARCH) {
@@ -74,3 +193,81 @@ impl ZigProvider {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_zig_version() -> Result<()> {
+        let dir = TempDir::new("nixpacks-zig")?;
+        fs::write(
+            dir.path().join("build.zig.zon"),
+            ".{\n    .name = \"app\",\n    .version = \"0.1.0\",\n    .minimum_zig_version = \"0.11.0\",\n}\n",
+        )?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+
+        assert_eq!(
+            ZigProvider::get_zig_version(&app, &Environment::default())?,
+            Some(("0.11.0".to_string(), "build.zig.zon".to_string()))
+        );
+        let err = ZigProvider::get_minimum_zig_pkg("0.11.0", "build.zig.zon").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Zig 0.11.0 from build.zig.zon is not available. Available versions: 0.9"
+        );
+        assert_eq!(
+            ZigProvider::get_minimum_zig_pkg("0.8.0", "build.zig.zon")?,
+            "zig"
+        );
+
+        fs::write(dir.path().join(".zig-version"), "0.9.1\n")?;
+        let app = App::new(&dir.path().to_string_lossy())?;
+        assert_eq!(
+            ZigProvider::get_zig_version(&app, &Environment::default())?,
+            Some(("0.9.1".to_string(), ".zig-version".to_string()))
+        );
+        assert_eq!(ZigProvider::get_zig_pkg("0.9.1", ".zig-version")?, "zig");
+
+        let err = ZigProvider::get_zig_pkg("0.11.0", ".zig-version").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Zig 0.11.0 from .zig-version is not available. Available versions: 0.9"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimize_flag() -> Result<()> {
+        assert_eq!(
+            ZigProvider::get_optimize_flag(&Environment::default())?,
+            "-Drelease-safe=true"
+        );
+        assert_eq!(
+            ZigProvider::get_optimize_flag(&Environment::from_envs(vec![
+                "NIXPACKS_ZIG_OPTIMIZE=ReleaseFast"
+            ])?)?,
+            "-Drelease-fast=true"
+        );
+        assert_eq!(
+            ZigProvider::get_optimize_flag(&Environment::from_envs(vec![
+                "NIXPACKS_ZIG_OPTIMIZE=Debug"
+            ])?)?,
+            ""
+        );
+
+        let err = ZigProvider::get_optimize_flag(&Environment::from_envs(vec![
+            "NIXPACKS_ZIG_OPTIMIZE=Fast",
+        ])?)
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Zig optimize mode Fast is not valid. Use one of: Debug, ReleaseSafe, ReleaseFast, ReleaseSmall"
+        );
+
+        Ok(())
+    }
+}
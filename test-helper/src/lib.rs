@@ -13,7 +13,10 @@ const IGNORE: &[&str] = &[
     "rust-custom-toolchain",
     "rust-cargo-workspaces",
     "rust-cargo-workspaces-glob",
+    "rust-workspace-bins",
+    "rust-cargo-lock",
     "ruby-no-version",
+    "config-nickel",
 ];
 
 fn get_examples() -> Vec<String> {
@@ -115,3 +118,39 @@ pub fn generate_plan_tests(_tokens: TokenStream) -> TokenStream {
         .collect::<proc_macro2::TokenStream>()
         .into()
 }
+
+#[proc_macro]
+pub fn generate_provider_conformance_tests(_tokens: TokenStream) -> TokenStream {
+    let mut examples = get_examples();
+
+    // First element is always "examples"
+    examples.remove(0);
+
+    examples
+        .into_iter()
+        .map(|example| {
+            let test_name = format_ident!("{}", example.replace('-', "_"));
+            quote! {
+                #[test]
+                fn #test_name() {
+                    let path = format!("./examples/{}", #example);
+                    let raw_env = ::std::fs::read_to_string(format!("{}/test.env", path)).unwrap_or_default();
+                    let env = ::dotenv_parser::parse_dotenv(&raw_env).unwrap();
+                    let envs: Vec<&str> = env
+                        .get("ENVS")
+                        .map(|envs| envs.split(", ").collect())
+                        .unwrap_or_default();
+
+                    for provider in ::nixpacks::get_providers() {
+                        ::nixpacks::providers::conformance::assert_conformance(
+                            *provider,
+                            &path,
+                            envs.clone(),
+                        );
+                    }
+                }
+            }
+        })
+        .collect::<proc_macro2::TokenStream>()
+        .into()
+}
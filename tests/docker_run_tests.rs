@@ -331,6 +331,14 @@ async fn test_deno() {
     assert!(run_image(&name, None).await.contains("Hello from Deno"));
 }
 
+#[tokio::test]
+async fn test_deno_tasks() {
+    let name = simple_build("./examples/deno-tasks").await;
+    assert!(run_image(&name, None)
+        .await
+        .contains("Hello from a Deno task"));
+}
+
 #[tokio::test]
 async fn test_elixir_no_ecto() {
     let rand_64_str: String = thread_rng()
@@ -354,6 +362,20 @@ async fn test_node() {
     assert!(run_image(&name, None).await.contains("Hello from Node"));
 }
 
+#[tokio::test]
+async fn test_node_node_version_file() {
+    let name = simple_build("./examples/node-node-version").await;
+    assert!(run_image(&name, None).await.contains("Hello from Node 18"));
+}
+
+#[tokio::test]
+async fn test_node_mise() {
+    let name = simple_build("./examples/node-mise").await;
+    assert!(run_image(&name, None)
+        .await
+        .contains("Hello from mise on Node v18"));
+}
+
 #[tokio::test]
 async fn test_node_nx_default_app() {
     let name = simple_build("./examples/node-nx").await;
@@ -501,6 +523,29 @@ async fn test_node_turborepo() {
     assert!(run_image(&name, None).await.contains("> next start"));
 }
 
+#[tokio::test]
+async fn test_node_next_standalone() {
+    let name = simple_build("./examples/node-next-standalone").await;
+    assert!(run_image(&name, None)
+        .await
+        .contains("Listening on port 3000"));
+}
+
+#[tokio::test]
+async fn test_node_vite_static() {
+    let name = simple_build("./examples/node-vite-static").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_node_pnpm_workspace() {
+    let name = simple_build("./examples/node-pnpm-workspace").await;
+    let output = run_image(&name, None).await;
+
+    assert!(output.contains("Hello from pnpm workspace"));
+}
+
 #[tokio::test]
 async fn test_yarn_berry() {
     let name = simple_build("./examples/node-yarn-berry").await;
@@ -509,6 +554,13 @@ async fn test_yarn_berry() {
     assert!(output.contains("Hello from Yarn v2+"));
 }
 
+#[tokio::test]
+async fn test_yarn_berry_node_modules() {
+    let name = simple_build("./examples/node-yarn-berry-node-modules").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Yarn Berry"));
+}
+
 #[tokio::test]
 async fn test_yarn_prisma() {
     let name = simple_build("./examples/node-yarn-prisma").await;
@@ -544,6 +596,13 @@ async fn test_pnpm_custom_version() {
     assert!(output.contains("Hello from PNPM"));
 }
 
+#[tokio::test]
+async fn test_pnpm_package_manager() {
+    let name = simple_build("./examples/node-pnpm-package-manager").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from PNPM"));
+}
+
 #[tokio::test]
 async fn test_puppeteer() {
     let name = simple_build("./examples/node-puppeteer").await;
@@ -558,6 +617,14 @@ async fn test_csharp() {
     assert!(output.contains("Hello world from C#"));
 }
 
+#[tokio::test]
+async fn test_csharp_solution() {
+    let name = simple_build("./examples/csharp-solution").await;
+    assert!(run_image(&name, None)
+        .await
+        .contains("Hello from C# solution"));
+}
+
 #[tokio::test]
 async fn test_fsharp() {
     let name = simple_build("./examples/fsharp-cli").await;
@@ -619,6 +686,20 @@ async fn test_django() {
     assert!(output.contains("Running migrations"));
 }
 
+#[tokio::test]
+async fn test_django_static() {
+    let name = simple_build("./examples/python-django-static").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Running migrations"));
+}
+
+#[tokio::test]
+async fn test_python_fastapi() {
+    let name = simple_build("./examples/python-fastapi").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-FastAPI"));
+}
+
 #[tokio::test]
 async fn test_django_mysql() {
     let n = create_network();
@@ -653,6 +734,41 @@ async fn test_python_poetry() {
     assert!(output.contains("Hello from Python-Poetry"));
 }
 
+#[tokio::test]
+async fn test_python_poetry_scripts() {
+    let name = simple_build("./examples/python-poetry-scripts").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python Poetry scripts"));
+}
+
+#[tokio::test]
+async fn test_python_pipenv() {
+    let name = simple_build("./examples/python-pipenv").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-Pipenv"));
+}
+
+#[tokio::test]
+async fn test_python_pdm() {
+    let name = simple_build("./examples/python-pdm").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-pdm"));
+}
+
+#[tokio::test]
+async fn test_python_uv() {
+    let name = simple_build("./examples/python-uv").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-uv"));
+}
+
+#[tokio::test]
+async fn test_python_conda() {
+    let name = simple_build("./examples/python-conda").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Python-Conda with numpy"));
+}
+
 #[tokio::test]
 async fn test_python_numpy() {
     let name = simple_build("./examples/python-numpy").await;
@@ -687,6 +803,24 @@ async fn test_rust_custom_version() {
     assert!(output.contains("cargo 1.56.0"));
 }
 
+#[tokio::test]
+async fn test_rust_static() {
+    let name = build_with_build_time_env_vars(
+        "./examples/rust-cargo-workspaces",
+        vec!["NIXPACKS_RUST_STATIC=1"],
+    )
+    .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from rust"));
+}
+
+#[tokio::test]
+async fn test_rust_cargo_lock() {
+    let name = simple_build("./examples/rust-cargo-lock").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from rust with cached dependencies"));
+}
+
 #[tokio::test]
 async fn test_rust_ring() {
     let name = simple_build("./examples/rust-ring").await;
@@ -722,6 +856,14 @@ async fn test_go() {
     assert!(output.contains("Hello from Go"));
 }
 
+#[tokio::test]
+async fn test_go_cmd() {
+    let name =
+        build_with_build_time_env_vars("./examples/go-cmd", vec!["NIXPACKS_GO_BIN=worker"]).await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Go worker"));
+}
+
 #[tokio::test]
 async fn test_go_custom_version() {
     let name = simple_build("./examples/go-custom-version").await;
@@ -736,6 +878,13 @@ async fn test_haskell_stack() {
     assert!(output.contains("Hello from Haskell"));
 }
 
+#[tokio::test]
+async fn test_haskell_cabal() {
+    let name = simple_build("./examples/haskell-cabal").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Haskell with Cabal"));
+}
+
 #[tokio::test]
 async fn test_crystal() {
     let name = simple_build("./examples/crystal").await;
@@ -762,6 +911,41 @@ async fn test_cowsay() {
     assert!(output.contains("Hello World"));
 }
 
+#[tokio::test]
+async fn test_elm() {
+    let name = simple_build("./examples/elm").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_flutter_web() {
+    let name = simple_build("./examples/flutter-web").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_haxe() {
+    let name = simple_build("./examples/haxe").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Haxe"));
+}
+
+#[tokio::test]
+async fn test_hugo() {
+    let name = simple_build("./examples/hugo").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
+#[tokio::test]
+async fn test_jekyll() {
+    let name = simple_build("./examples/jekyll").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
 #[tokio::test]
 async fn test_staticfile() {
     let name = simple_build("./examples/staticfile").await;
@@ -769,6 +953,13 @@ async fn test_staticfile() {
     assert!(output.contains("start worker process"));
 }
 
+#[tokio::test]
+async fn test_staticfile_spa() {
+    let name = simple_build("./examples/staticfile-spa").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("start worker process"));
+}
+
 #[tokio::test]
 async fn test_swift() {
     let name = Uuid::new_v4().to_string();
@@ -790,9 +981,19 @@ async fn test_swift() {
     assert!(output.contains("Hello from swift"));
 }
 
+#[tokio::test]
+async fn test_node_nix_flakes() {
+    let name =
+        build_with_build_time_env_vars("./examples/node", vec!["NIXPACKS_NIX_FLAKES=1"]).await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Node"));
+}
+
 #[tokio::test]
 async fn test_dart() {
     let name = simple_build("./examples/dart").await;
+
+    // The executable runs in the slim image with only the glibc from Nix it is linked against
     let output = run_image(&name, None).await;
     assert!(output.contains("Hello from Dart"));
 }
@@ -804,6 +1005,66 @@ async fn test_java_maven() {
     assert!(output.contains("Built with Spring Boot"));
 }
 
+#[tokio::test]
+async fn test_java_maven_spring_boot_layered() {
+    let name = simple_build("./examples/java-maven-spring-boot-layered").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from a layered Spring Boot jar"));
+}
+
+#[tokio::test]
+async fn test_java_gradle_spring_boot_layered() {
+    let name = simple_build("./examples/java-gradle-spring-boot-layered").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from a layered Spring Boot jar built with Gradle"));
+}
+
+#[tokio::test]
+async fn test_java_maven_multi_module() {
+    let name = build_with_build_time_env_vars(
+        "./examples/java-maven-multi-module",
+        vec!["NIXPACKS_MAVEN_MODULE=app"],
+    )
+    .await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from a Maven module"));
+}
+
+#[tokio::test]
+async fn test_cmake() {
+    let name = simple_build("./examples/cmake").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from CMake"));
+}
+
+#[tokio::test]
+async fn test_meson() {
+    let name = simple_build("./examples/meson").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Meson"));
+}
+
+#[tokio::test]
+async fn test_purescript() {
+    let name = simple_build("./examples/purescript").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from PureScript"));
+}
+
+#[tokio::test]
+async fn test_racket() {
+    let name = simple_build("./examples/racket").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Racket"));
+}
+
+#[tokio::test]
+async fn test_vlang() {
+    let name = simple_build("./examples/vlang").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from V"));
+}
+
 #[tokio::test]
 async fn test_zig() {
     let name = simple_build("./examples/zig").await;
@@ -826,6 +1087,13 @@ async fn test_ruby_sinatra() {
     assert!(output.contains("Hello from Sinatra"));
 }
 
+#[tokio::test]
+async fn test_ruby_tool_versions() {
+    let name = simple_build("./examples/ruby-tool-versions/").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from Ruby 3.2.0 with .tool-versions"));
+}
+
 #[tokio::test]
 async fn test_ruby_node() {
     let name = simple_build("./examples/ruby-with-node/").await;
@@ -894,6 +1162,20 @@ async fn test_clojure_ring_app() {
     assert_eq!(output, "Started server on port 3000");
 }
 
+#[tokio::test]
+async fn test_clojure_deps() {
+    let name = simple_build("./examples/clojure-deps").await;
+    let output = run_image(&name, None).await;
+    assert_eq!(output, "Hello from Clojure deps.edn");
+}
+
+#[tokio::test]
+async fn test_clojure_babashka() {
+    let name = simple_build("./examples/clojure-babashka").await;
+    let output = run_image(&name, None).await;
+    assert_eq!(output, "Hello from Babashka");
+}
+
 #[tokio::test]
 async fn test_cobol() {
     let name = simple_build("./examples/cobol").await;
@@ -952,6 +1234,14 @@ async fn test_cobol_no_index() {
     );
 }
 
+#[tokio::test]
+async fn test_python_node_assets() {
+    let name = simple_build("./examples/python-node-assets").await;
+    let output = run_image(&name, None).await;
+    assert!(output.contains("Hello from the Node assets"));
+    assert!(output.contains("Hello from Python"));
+}
+
 #[tokio::test]
 async fn test_multiple_providers() {
     let name = simple_build("./examples/multiple-providers").await;
@@ -1,4 +1,11 @@
-use nixpacks::{generate_build_plan, nixpacks::plan::generator::GeneratePlanOptions};
+use nixpacks::{
+    generate_build_plan, generate_build_plan_with_reasons, get_debug_bundle,
+    get_matching_providers, get_plan_providers,
+    nixpacks::{
+        environment::Environment,
+        plan::{generator::GeneratePlanOptions, BuildPlan},
+    },
+};
 use std::env::consts::ARCH;
 
 test_helper::generate_plan_tests!();
@@ -18,6 +25,204 @@ fn test_custom_plan_path() {
     assert_plan_snapshot!(plan);
 }
 
+#[test]
+fn test_config_phases() {
+    let config = r#"
+        [phases.assets]
+        dependsOn = ["install"]
+        cmds = ["npm run assets"]
+
+        [phases.migrate]
+        dependsOn = ["build", "assets"]
+        cmds = ["npm run migrate"]
+
+        [phases.lint]
+        cmds = ["npm run lint"]
+    "#;
+    let plan = generate_build_plan(
+        "./examples/node",
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan::from_toml(config).unwrap()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let phases = plan
+        .get_sorted_phases()
+        .unwrap()
+        .into_iter()
+        .map(|phase| phase.name.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        phases,
+        vec!["setup", "install", "assets", "build", "lint", "migrate"]
+    );
+
+    let err = generate_build_plan(
+        "./examples/node",
+        Vec::new(),
+        &GeneratePlanOptions {
+            plan: Some(BuildPlan::from_toml(config.replace("\"assets\"]", "\"asets\"]")).unwrap()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Phase migrate depends on unknown phase asets. Available phases: assets, build, install, lint, migrate, setup"
+    );
+}
+
+#[test]
+fn test_cmd_overrides() {
+    let plan = generate_build_plan(
+        "./examples/node",
+        vec!["NIXPACKS_INSTALL_CMD=npm ci --omit=dev"],
+        &GeneratePlanOptions {
+            plan: Some(
+                BuildPlan::from_toml(
+                    r#"
+                    [phases.build]
+                    cmds = ['npm run build']
+
+                    [start]
+                    cmd = 'npm start'
+                    "#,
+                )
+                .unwrap(),
+            ),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // The commands are replaced, but the packages and caches of the provider are kept
+    let install = plan.get_phase("install").unwrap();
+    assert_eq!(install.cmds, Some(vec!["npm ci --omit=dev".to_string()]));
+    assert_eq!(
+        install.cache_directories,
+        Some(vec!["/root/.npm".to_string()])
+    );
+
+    let build = plan.get_phase("build").unwrap();
+    assert_eq!(build.cmds, Some(vec!["npm run build".to_string()]));
+    assert_eq!(
+        build.cache_directories,
+        Some(vec!["node_modules/.cache".to_string()])
+    );
+
+    assert!(plan.get_phase("setup").unwrap().nix_pkgs.is_some());
+    assert_eq!(plan.start_phase.unwrap().cmd, Some("npm start".to_string()));
+}
+
+#[test]
+fn test_config_variables_add_packages() {
+    let plan = generate_build_plan(
+        "./examples/node",
+        vec!["NIXPACKS_APT_PKGS=libpq-dev"],
+        &GeneratePlanOptions {
+            plan: Some(
+                BuildPlan::from_toml(
+                    r#"
+                    [variables]
+                    NIXPACKS_PKGS = "ffmpeg"
+                    NIXPACKS_APT_PKGS = "imagemagick"
+                    "#,
+                )
+                .unwrap(),
+            ),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let setup = plan.get_phase("setup").unwrap();
+    assert_eq!(
+        setup.nix_pkgs,
+        Some(vec![
+            "ffmpeg".to_string(),
+            "nodejs-16_x".to_string(),
+            "npm-8_x".to_string()
+        ])
+    );
+    // The environment takes precedence over the variables of the config
+    assert_eq!(setup.apt_pkgs, Some(vec!["libpq-dev".to_string()]));
+}
+
+#[test]
+fn test_matching_providers() {
+    let matching = get_matching_providers(
+        "./examples/ruby-with-node",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+
+    // Only ruby is used, but node detects the app too
+    assert_eq!(
+        matching,
+        vec![
+            ("ruby".to_string(), vec!["Gemfile".to_string()]),
+            ("node".to_string(), vec!["package.json".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn test_provider_reasons() {
+    let (_, reasons) = generate_build_plan_with_reasons(
+        "./examples/ruby-with-node",
+        vec!["NIXPACKS_PROVIDERS=node,..."],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        reasons,
+        vec![
+            ("node".to_string(), "set in providers".to_string()),
+            ("ruby".to_string(), "detected from Gemfile".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_providers_override() {
+    let providers = |envs: Vec<&str>| {
+        get_plan_providers(
+            "./examples/ruby-with-node",
+            envs,
+            &GeneratePlanOptions::default(),
+        )
+        .unwrap()
+    };
+
+    assert_eq!(providers(Vec::new()), vec!["ruby"]);
+    assert_eq!(providers(vec!["NIXPACKS_PROVIDERS=node"]), vec!["node"]);
+    assert_eq!(
+        providers(vec!["NIXPACKS_PROVIDERS=node,ruby"]),
+        vec!["node", "ruby"]
+    );
+    assert_eq!(providers(vec!["NIXPACKS_PROVIDERS=ruby,..."]), vec!["ruby"]);
+
+    let plan = generate_build_plan(
+        "./examples/ruby-with-node",
+        vec!["NIXPACKS_PROVIDERS=node"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let setup = plan.get_phase("setup").unwrap();
+    assert!(setup
+        .nix_pkgs
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        .any(|pkg| pkg.starts_with("nodejs")));
+    assert!(plan.get_phase("ruby:setup").is_none());
+}
+
 #[test]
 fn test_custom_rust_version() {
     let plan = simple_gen_plan("./examples/rust-custom-version");
@@ -90,6 +295,98 @@ fn test_rust_cargo_workspaces() {
     );
 }
 
+#[test]
+fn test_rust_workspace_bin() {
+    let plan = generate_build_plan(
+        "./examples/rust-workspace-bins",
+        vec!["NIXPACKS_RUST_BIN=worker"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let build = plan.get_phase("build").unwrap();
+    let start = plan.start_phase.clone().unwrap();
+
+    assert_eq!(
+        build.clone().cmds.unwrap()[1],
+        format!(
+            "cargo build --release --package worker --target {}-unknown-linux-musl",
+            ARCH
+        )
+    );
+    assert_eq!(start.cmd, Some("./worker".to_string()));
+}
+
+#[test]
+fn test_rust_static() {
+    let plan = generate_build_plan(
+        "./examples/rust-custom-version",
+        vec!["NIXPACKS_RUST_STATIC=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let build = plan.get_phase("build").unwrap();
+    let start = plan.start_phase.clone().unwrap();
+
+    assert_eq!(
+        build.clone().cmds.unwrap()[3],
+        "strip bin/rust-custom-version".to_string()
+    );
+    assert_eq!(start.run_image, Some("scratch".to_string()));
+}
+
+#[test]
+fn test_rust_static_with_openssl() {
+    let err = generate_build_plan(
+        "./examples/rust-openssl",
+        vec!["NIXPACKS_RUST_STATIC=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap_err();
+
+    assert!(format!("{:?}", err).contains("the app uses openssl"));
+}
+
+#[test]
+fn test_rust_cargo_lock() {
+    let plan = simple_gen_plan("./examples/rust-cargo-lock");
+    let install = plan.get_phase("install").unwrap();
+    let build = plan.get_phase("build").unwrap();
+
+    assert_eq!(
+        install.only_include_files,
+        Some(vec!["Cargo.toml".to_string(), "Cargo.lock".to_string()])
+    );
+    assert_eq!(
+        install.cmds,
+        Some(vec![
+            "mkdir -p src && echo 'fn main() {}' > src/main.rs".to_string(),
+            format!("cargo build --release --target {}-unknown-linux-musl", ARCH),
+            format!(
+                "cargo clean --release --target {}-unknown-linux-musl --package rust-cargo-lock",
+                ARCH
+            )
+        ])
+    );
+    assert_eq!(build.depends_on, Some(vec!["install".to_string()]));
+    assert!(!build
+        .cache_directories
+        .clone()
+        .unwrap()
+        .contains(&"target".to_string()));
+}
+
+#[test]
+fn test_java_maven_module_not_found() {
+    let err = generate_build_plan(
+        "./examples/java-maven-multi-module",
+        vec!["NIXPACKS_MAVEN_MODULE=web"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap_err();
+
+    assert!(format!("{:?}", err).contains("Available modules: core, app"));
+}
+
 #[test]
 fn test_haskell_stack() {
     let plan = simple_gen_plan("./examples/haskell-stack");
@@ -105,6 +402,22 @@ fn test_haskell_stack() {
     );
 }
 
+#[test]
+fn test_haskell_cabal() {
+    let plan = simple_gen_plan("./examples/haskell-cabal");
+    let build = plan.get_phase("build").unwrap();
+    let start = plan.start_phase.clone().unwrap();
+
+    assert_eq!(
+        build.cmds.clone().unwrap()[0],
+        "cabal build haskell-cabal".to_string()
+    );
+    assert_eq!(
+        start.cmd,
+        Some("/root/.local/bin/haskell-cabal".to_string())
+    );
+}
+
 #[cfg(any(target_arch = "aarch64", target_arch = "x86_64", target_arch = "i386"))]
 #[test]
 fn test_zig_gyro() {
@@ -136,3 +449,137 @@ fn test_node_turborepo_custom_app() {
     .unwrap();
     assert!(plan.start_phase.unwrap().cmd.unwrap().contains("docs"));
 }
+
+#[test]
+fn test_racket_compile_exe() {
+    let plan = generate_build_plan(
+        "./examples/racket",
+        vec!["NIXPACKS_RACKET_COMPILE_EXE=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let build = plan.get_phase("build").unwrap();
+
+    assert_eq!(
+        build.cmds,
+        Some(vec!["raco exe -o main main.rkt".to_string()])
+    );
+    assert_eq!(plan.start_phase.unwrap().cmd, Some("./main".to_string()));
+}
+
+#[test]
+fn test_go_distroless_start_cmd() {
+    let plan = simple_gen_plan("./examples/go");
+    assert_eq!(
+        plan.start_phase.unwrap().run_image,
+        Some("gcr.io/distroless/static-debian11".to_string())
+    );
+
+    // The distroless image has no shell to run this command
+    let plan = generate_build_plan(
+        "./examples/go",
+        vec!["NIXPACKS_START_CMD=./out --port $PORT"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(plan.start_phase.unwrap().run_image, None);
+}
+
+#[test]
+fn test_django_exposed_port() {
+    let plan = simple_gen_plan("./examples/python-django");
+    assert_eq!(plan.start_phase.unwrap().port, Some(8000));
+    assert!(plan.runtime_variables.is_none());
+
+    let plan = generate_build_plan(
+        "./examples/python-django",
+        vec!["NIXPACKS_EXPOSE_PORT=1"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        plan.runtime_variables.unwrap().get("PORT"),
+        Some(&"8000".to_string())
+    );
+
+    // A port set by the user is kept
+    let plan = generate_build_plan(
+        "./examples/python-django",
+        vec!["NIXPACKS_EXPOSE_PORT=1", "PORT=9000"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    assert!(plan.runtime_variables.is_none());
+    assert_eq!(
+        plan.variables.as_ref().unwrap().get("PORT"),
+        Some(&"9000".to_string())
+    );
+
+    // and is the port that is exposed
+    assert_eq!(plan.get_exposed_port(&Environment::default()), Some(9000));
+}
+
+#[cfg(not(feature = "experimental-config"))]
+#[test]
+fn test_config_nickel_requires_feature() {
+    let err = generate_build_plan(
+        "./examples/config-nickel",
+        vec!["NIXPACKS_CONFIG_FILE=nixpacks.ncl"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap_err();
+
+    assert!(err
+        .chain()
+        .any(|cause| cause.to_string().contains("experimental-config")));
+}
+
+#[test]
+fn test_renamed_nix_pkgs() {
+    let plan = generate_build_plan(
+        "./examples/shell-hello",
+        vec!["NIXPACKS_PKGS=nodejs-10_x ruby_2_6"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let setup = plan.get_phase("setup").unwrap();
+    let pkgs = setup.nix_pkgs.clone().unwrap();
+
+    assert!(pkgs.contains(&"nodejs-12_x".to_string()));
+    assert!(pkgs.contains(&"ruby_2_7".to_string()));
+    assert!(!pkgs.contains(&"nodejs-10_x".to_string()));
+}
+
+#[test]
+fn test_python_celery_worker() {
+    let plan = generate_build_plan(
+        "./examples/python-celery",
+        vec!["NIXPACKS_PYTHON_PROCESS=worker"],
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        plan.start_phase.unwrap().cmd,
+        Some("celery -A tasks:app worker --loglevel=info".to_string())
+    );
+}
+
+#[test]
+fn test_debug_bundle_redacts_config_variables() {
+    let bundle = get_debug_bundle(
+        "./examples/node-pnpm-workspace",
+        Vec::new(),
+        &GeneratePlanOptions::default(),
+    )
+    .unwrap();
+    let plan = BuildPlan::from_json(bundle.get_file("plan.json").unwrap()).unwrap();
+    let variables = plan.variables.unwrap();
+
+    // NIXPACKS_NODE_WORKSPACE is set in nixpacks.toml
+    assert_eq!(
+        variables.get("NIXPACKS_NODE_WORKSPACE"),
+        Some(&"[redacted]".to_string())
+    );
+    assert!(variables.values().all(|value| value == "[redacted]"));
+}
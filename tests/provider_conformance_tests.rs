@@ -0,0 +1,15 @@
+use nixpacks::providers::{node::NodeProvider, staticfile::StaticfileProvider};
+
+test_helper::generate_provider_conformance_tests!();
+
+nixpacks::provider_conformance!(
+    NodeProvider {},
+    provider_conformance_node => "./examples/node",
+    provider_conformance_node_vite_static => "./examples/node-vite-static",
+);
+
+nixpacks::provider_conformance!(
+    StaticfileProvider {},
+    provider_conformance_staticfile => "./examples/staticfile",
+    provider_conformance_staticfile_spa => "./examples/staticfile-spa",
+);